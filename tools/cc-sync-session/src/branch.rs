@@ -0,0 +1,450 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use git2::{Commit, FetchOptions, FetchPrune, Index, IndexEntry, IndexTime, ObjectType, Oid, Repository, Signature, Tree, TreeWalkMode, TreeWalkResult};
+use tempfile::TempDir;
+
+use crate::compression::{decode, is_compressed, Compression, StoreFormat};
+use crate::encryption::{self, Encryption, Identities};
+use crate::filesystem::RealFileSystem;
+use crate::index::{update_index, RedactionRules, INDEX_JSON_FILE, INDEX_MARKDOWN_FILE};
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::merge::merge_transcripts;
+use crate::store::STORE_PATH;
+use crate::sync::is_session_file;
+use crate::verify::STORE_METADATA_FILES;
+
+/// Branch the store is committed to when `[store] branch` is not set.
+pub const DEFAULT_STORE_BRANCH: &str = "ccss/sessions";
+
+const BLOB_MODE: u32 = 0o100644;
+
+/// Files rebuilt after a merge instead of being merged.
+const GENERATED_FILES: &[&str] = &[MANIFEST_FILE, INDEX_JSON_FILE, INDEX_MARKDOWN_FILE];
+
+/// The session store kept on its own branch, outside the working tree.
+///
+/// The branch holds the store at the usual `.claude/ccss_sessions` path. It is
+/// read and written through the object database only, so the checked out
+/// branch, its index and the working tree are never touched.
+pub struct BranchStore {
+    repo: Repository,
+    branch: String,
+}
+
+/// What [`BranchStore::merge`] did.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeResult {
+    UpToDate,
+    /// The branch was missing or behind and now points at the other commit.
+    FastForward(Oid),
+    /// A merge commit was created.
+    Merged {
+        commit: Oid,
+        /// Session files both sides changed, merged record by record.
+        merged_files: Vec<PathBuf>,
+        /// Session files whose records conflict; our version was kept.
+        conflicting_files: Vec<PathBuf>,
+    },
+}
+
+impl BranchStore {
+    pub fn new(repo: Repository, branch: &str) -> Self {
+        Self {
+            repo,
+            branch: branch.to_string(),
+        }
+    }
+
+    pub fn repo(&self) -> &Repository {
+        &self.repo
+    }
+
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    fn reference_name(&self) -> String {
+        format!("refs/heads/{}", self.branch)
+    }
+
+    fn remote_reference_name(&self, remote: &str) -> String {
+        format!("refs/remotes/{}/{}", remote, self.branch)
+    }
+
+    /// The last commit of the branch, or `None` before the first sync.
+    pub fn head(&self) -> Result<Option<Commit<'_>>> {
+        match self.repo.find_reference(&self.reference_name()) {
+            Ok(reference) => Ok(Some(reference.peel_to_commit()
+                .with_context(|| format!("{} is not a commit", self.branch))?)),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the store of the branch head into `store_dir`.
+    ///
+    /// Each file gets the time of the commit that last changed it, so `sync`
+    /// copies only the sessions that changed since, as in the working tree.
+    pub fn check_out(&self, store_dir: &Path) -> Result<()> {
+        fs::create_dir_all(store_dir)
+            .with_context(|| format!("Failed to create {}", store_dir.display()))?;
+        let Some(head) = self.head()? else {
+            return Ok(());
+        };
+
+        let files = self.read_tree(&head.tree()?)?;
+        let times = self.last_changed(&head, &files)?;
+        for (path, content) in files {
+            let seconds = times.get(&path).copied().unwrap_or_else(|| head.time().seconds());
+            // Commit times are whole seconds; files synced during that second are older
+            let time = filetime::FileTime::from(UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64 + 1));
+            let target_path = store_dir.join(&path);
+            if let Some(parent) = target_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target_path, content)
+                .with_context(|| format!("Failed to write {}", target_path.display()))?;
+            filetime::set_file_mtime(&target_path, time)?;
+        }
+
+        Ok(())
+    }
+
+    /// Times of the commits that last changed the store files, following first
+    /// parents from `head`, keyed by store-relative path.
+    fn last_changed(&self, head: &Commit<'_>, files: &BTreeMap<PathBuf, Vec<u8>>) -> Result<HashMap<PathBuf, i64>> {
+        let mut times = HashMap::new();
+        let mut commit = head.clone();
+
+        loop {
+            let parent = commit.parents().next();
+            let parent_tree = parent.as_ref().map(Commit::tree).transpose()?;
+            let diff = self.repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+            for delta in diff.deltas() {
+                let Some(path) = delta.new_file().path().and_then(|path| path.strip_prefix(STORE_PATH).ok()) else {
+                    continue;
+                };
+                if !files.contains_key(path) {
+                    continue;
+                }
+                times.entry(path.to_path_buf()).or_insert_with(|| commit.time().seconds());
+            }
+
+            match parent {
+                Some(parent) if times.len() < files.len() => commit = parent,
+                _ => break,
+            }
+        }
+
+        Ok(times)
+    }
+
+    /// Files of the store in `tree`, keyed by their store-relative path.
+    fn read_tree(&self, tree: &Tree<'_>) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+        let mut files = BTreeMap::new();
+        let Ok(entry) = tree.get_path(Path::new(STORE_PATH)) else {
+            return Ok(files);
+        };
+        let store_tree = entry.to_object(&self.repo)?.peel_to_tree()
+            .with_context(|| format!("{} is not a directory on {}", STORE_PATH, self.branch))?;
+
+        let mut blobs = Vec::new();
+        store_tree.walk(TreeWalkMode::PreOrder, |parent, entry| {
+            if entry.kind() == Some(ObjectType::Blob) {
+                blobs.push((Path::new(parent).join(entry.name().unwrap_or_default()), entry.id()));
+            }
+            TreeWalkResult::Ok
+        })?;
+        for (path, id) in blobs {
+            files.insert(path, self.repo.find_blob(id)?.content().to_vec());
+        }
+
+        Ok(files)
+    }
+
+    /// Commits the files in `store_dir` as the store on the branch.
+    ///
+    /// Returns the new commit, or `None` if the store did not change.
+    pub fn commit(&self, store_dir: &Path, message: &str) -> Result<Option<Oid>> {
+        let head = self.head()?;
+        let parents: Vec<&Commit<'_>> = head.iter().collect();
+        self.commit_with_parents(store_dir, message, &parents)
+    }
+
+    fn commit_with_parents(&self, store_dir: &Path, message: &str, parents: &[&Commit<'_>]) -> Result<Option<Oid>> {
+        // Files outside the store on the branch are kept as they are
+        let mut index = Index::new()?;
+        if let Some(first_parent) = parents.first() {
+            index.read_tree(&first_parent.tree()?)?;
+            index.remove_dir(Path::new(STORE_PATH), 0)?;
+        }
+
+        for entry in walkdir::WalkDir::new(store_dir).sort_by_file_name() {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(store_dir)?;
+            let content = fs::read(entry.path())
+                .with_context(|| format!("Failed to read {}", entry.path().display()))?;
+            let path = Path::new(STORE_PATH).join(relative).to_string_lossy().replace('\\', "/");
+            index.add(&IndexEntry {
+                ctime: IndexTime::new(0, 0),
+                mtime: IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: BLOB_MODE,
+                uid: 0,
+                gid: 0,
+                file_size: content.len() as u32,
+                id: self.repo.blob(&content)?,
+                flags: path.len().min(0xfff) as u16,
+                flags_extended: 0,
+                path: path.into_bytes(),
+            })?;
+        }
+
+        let tree = self.repo.find_tree(index.write_tree_to(&self.repo)?)?;
+        if parents.len() == 1 && parents[0].tree_id() == tree.id() {
+            return Ok(None);
+        }
+
+        let signature = self.signature()?;
+        let commit = self.repo.commit(Some(&self.reference_name()), &signature, &signature, message, &tree, parents)?;
+        Ok(Some(commit))
+    }
+
+    fn signature(&self) -> Result<Signature<'static>> {
+        Ok(match self.repo.signature() {
+            Ok(signature) => signature,
+            Err(_) => Signature::now("cc-sync-session", "cc-sync-session@localhost")?,
+        })
+    }
+
+    /// Fetches the branch from `remote` into `refs/remotes/<remote>/<branch>`.
+    ///
+    /// Returns the fetched commit, or `None` if the remote has no such branch.
+    pub fn fetch(&self, remote: &str) -> Result<Option<Oid>> {
+        let mut remote_handle = self.repo.find_remote(remote)
+            .with_context(|| format!("Unknown remote: {}", remote))?;
+
+        // Pruning drops the remote-tracking branch if the remote no longer has it
+        let refspec = format!("+{}:{}", self.reference_name(), self.remote_reference_name(remote));
+        let mut options = FetchOptions::new();
+        options.prune(FetchPrune::On);
+        remote_handle.fetch(&[refspec.as_str()], Some(&mut options), None)
+            .with_context(|| format!("Failed to fetch {} from {}", self.branch, remote))?;
+
+        match self.repo.refname_to_id(&self.remote_reference_name(remote)) {
+            Ok(id) => Ok(Some(id)),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Pushes the branch to `remote`. Fails if the remote has commits the
+    /// branch lacks; merge them first.
+    pub fn push(&self, remote: &str) -> Result<()> {
+        let mut remote_handle = self.repo.find_remote(remote)
+            .with_context(|| format!("Unknown remote: {}", remote))?;
+        let refspec = format!("{}:{}", self.reference_name(), self.reference_name());
+
+        let mut rejected = None;
+        let mut callbacks = git2::RemoteCallbacks::new();
+        callbacks.push_update_reference(|_, status| {
+            rejected = status.map(str::to_string);
+            Ok(())
+        });
+        let mut options = git2::PushOptions::new();
+        options.remote_callbacks(callbacks);
+        remote_handle.push(&[refspec.as_str()], Some(&mut options))
+            .with_context(|| format!("Failed to push {} to {}; run 'cc-sync-session pull' first", self.branch, remote))?;
+        drop(options);
+
+        if let Some(status) = rejected {
+            anyhow::bail!("{} rejected {}: {}; run 'cc-sync-session pull' first", remote, self.branch, status);
+        }
+        Ok(())
+    }
+
+    /// Merges the commit `theirs` into the branch.
+    ///
+    /// Session files both sides changed are merged record by record, like the
+    /// merge driver does. The manifest, and the index if there is one, are
+    /// rebuilt from the merged files. Encrypted sessions are re-encrypted
+    /// with `encryption`.
+    pub fn merge(&self, theirs: Oid, encryption: Option<&Encryption>, identities: &Identities, redaction: &RedactionRules) -> Result<MergeResult> {
+        let theirs = self.repo.find_commit(theirs)?;
+        let Some(ours) = self.head()? else {
+            self.repo.reference(&self.reference_name(), theirs.id(), true, "cc-sync-session: create from remote")?;
+            return Ok(MergeResult::FastForward(theirs.id()));
+        };
+
+        if ours.id() == theirs.id() || self.repo.graph_descendant_of(ours.id(), theirs.id())? {
+            return Ok(MergeResult::UpToDate);
+        }
+        if self.repo.graph_descendant_of(theirs.id(), ours.id())? {
+            self.repo.reference(&self.reference_name(), theirs.id(), true, "cc-sync-session: fast-forward")?;
+            return Ok(MergeResult::FastForward(theirs.id()));
+        }
+
+        let base = match self.repo.merge_base(ours.id(), theirs.id()) {
+            Ok(base) => self.read_tree(&self.repo.find_commit(base)?.tree()?)?,
+            Err(_) => BTreeMap::new(),
+        };
+        let ours_files = self.read_tree(&ours.tree()?)?;
+        let theirs_files = self.read_tree(&theirs.tree()?)?;
+
+        let mut merged: BTreeMap<PathBuf, Vec<u8>> = BTreeMap::new();
+        let mut merged_files = Vec::new();
+        let mut conflicting_files = Vec::new();
+        let paths: Vec<&PathBuf> = ours_files.keys().chain(theirs_files.keys()).collect();
+
+        for path in paths {
+            if merged.contains_key(path) || is_generated(path) {
+                continue;
+            }
+            let (base_content, ours_content, theirs_content) = (base.get(path), ours_files.get(path), theirs_files.get(path));
+            let content = match (ours_content, theirs_content) {
+                (Some(ours), Some(theirs)) if ours == theirs => ours.clone(),
+                (Some(ours), Some(theirs)) if base_content == Some(theirs) => ours.clone(),
+                (Some(ours), Some(theirs)) if base_content == Some(ours) => theirs.clone(),
+                (Some(ours), Some(theirs)) if is_session_file(path) => {
+                    let decoded = |content: Option<&Vec<u8>>| -> Result<String> {
+                        let content = decode(content.cloned().unwrap_or_default(), identities)
+                            .with_context(|| format!("Failed to read {}", path.display()))?;
+                        Ok(String::from_utf8_lossy(&content).into_owned())
+                    };
+                    let outcome = merge_transcripts(&decoded(base_content)?, &decoded(Some(ours))?, &decoded(Some(theirs))?)
+                        .with_context(|| format!("Failed to merge {}", path.display()))?;
+                    if outcome.is_clean() {
+                        merged_files.push(path.clone());
+                        output_format(ours, encryption, identities)?.encode(outcome.content.as_bytes())?
+                    } else {
+                        conflicting_files.push(path.clone());
+                        ours.clone()
+                    }
+                }
+                (Some(ours), Some(_)) => ours.clone(),
+                // Deleted on one side (e.g. pruned): deleted, unless the other side changed it
+                (Some(kept), None) | (None, Some(kept)) => {
+                    if base_content.is_some_and(|base| base == kept) {
+                        continue;
+                    }
+                    kept.clone()
+                }
+                (None, None) => continue,
+            };
+            merged.insert(path.clone(), content);
+        }
+
+        let checkout = TempDir::new()?;
+        let store_dir = checkout.path();
+        for (path, content) in &merged {
+            let target_path = store_dir.join(path);
+            if let Some(parent) = target_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target_path, content)?;
+        }
+
+        // Generated files are rebuilt where either side had them: at the store
+        // root, or in each namespace of a namespaced store
+        let generated_roots = |name: &str| -> BTreeSet<PathBuf> {
+            ours_files.keys().chain(theirs_files.keys())
+                .filter(|path| path.file_name().is_some_and(|file_name| file_name == name))
+                .map(|path| path.parent().unwrap_or(Path::new("")).to_path_buf())
+                .collect()
+        };
+        let filesystem = RealFileSystem::new();
+        for root in generated_roots(MANIFEST_FILE) {
+            let mut manifest = Manifest::default();
+            for (path, content) in files_below(&merged, &root) {
+                manifest.record(path, content);
+            }
+            manifest.save(&filesystem, &store_dir.join(&root))
+                .with_context(|| format!("Failed to write {}", MANIFEST_FILE))?;
+        }
+        // The index is not encrypted, so an encrypted store drops it
+        let index_roots = match encryption {
+            Some(_) => BTreeSet::new(),
+            None => generated_roots(INDEX_JSON_FILE),
+        };
+        for root in index_roots {
+            let sessions: Vec<PathBuf> = files_below(&merged, &root)
+                .map(|(path, _)| path.to_path_buf())
+                .filter(|path| is_session_file(path))
+                .collect();
+            update_index(&filesystem, &store_dir.join(&root), &sessions, identities, redaction).context("Failed to update the session index")?;
+        }
+
+        let message = format!("Merge {} from {}", self.branch, theirs.id());
+        let commit = self.commit_with_parents(store_dir, &message, &[&ours, &theirs])?
+            .context("Merge produced no commit")?;
+
+        Ok(MergeResult::Merged { commit, merged_files, conflicting_files })
+    }
+}
+
+/// Files of `files` below `root`, keyed relative to it, without the tool's own.
+fn files_below<'a>(files: &'a BTreeMap<PathBuf, Vec<u8>>, root: &'a Path) -> impl Iterator<Item = (&'a Path, &'a Vec<u8>)> {
+    files.iter()
+        .filter_map(move |(path, content)| Some((path.strip_prefix(root).ok()?, content)))
+        .filter(|(path, _)| !STORE_METADATA_FILES.iter().any(|name| *path == Path::new(name)))
+}
+
+/// Whether `path` is a file rebuilt after a merge.
+fn is_generated(path: &Path) -> bool {
+    path.file_name().is_some_and(|file_name| GENERATED_FILES.iter().any(|name| file_name == *name))
+}
+
+/// The format to write a merged session in, the way our side stores it.
+fn output_format(ours: &[u8], encryption: Option<&Encryption>, identities: &Identities) -> Result<StoreFormat> {
+    if !encryption::is_encrypted(ours) {
+        let compression = if is_compressed(ours) { Compression::Zstd } else { Compression::None };
+        return Ok(StoreFormat { compression, encryption: None });
+    }
+
+    let encryption = encryption.context("Merging encrypted sessions needs [encryption] recipients")?;
+    let decrypted = identities.decrypt(ours)?;
+    let compression = if is_compressed(&decrypted) { Compression::Zstd } else { Compression::None };
+    Ok(StoreFormat { compression, encryption: Some(encryption.clone()) })
+}
+
+/// A checkout of a [`BranchStore`] in a temporary directory, for commands
+/// that work on a store directory.
+pub struct BranchCheckout {
+    store: BranchStore,
+    dir: TempDir,
+}
+
+impl BranchCheckout {
+    pub fn new(store: BranchStore) -> Result<Self> {
+        let dir = TempDir::new().context("Failed to create a temporary directory")?;
+        let checkout = Self { store, dir };
+        checkout.store.check_out(&checkout.store_dir())?;
+        Ok(checkout)
+    }
+
+    pub fn store(&self) -> &BranchStore {
+        &self.store
+    }
+
+    /// Stands in for the repository root: the store is at `.claude/ccss_sessions` below it.
+    pub fn root(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn store_dir(&self) -> PathBuf {
+        self.dir.path().join(STORE_PATH)
+    }
+
+    /// Commits the changes made in the checkout; see [`BranchStore::commit`].
+    pub fn commit(&self, message: &str) -> Result<Option<Oid>> {
+        self.store.commit(&self.store_dir(), message)
+    }
+}
@@ -0,0 +1,458 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::compression::StoreFormat;
+use crate::file_path_converter::dir_path_to_claude_code_stype;
+use crate::filesystem::{FileSystem, FileSystemError};
+use crate::manifest::{self, manifest_key, Manifest, ManifestEntry};
+use crate::store::Snapshot;
+use crate::sync::{SessionFilter, SessionPeek};
+
+/// Describes the bundle; the first entry of the archive.
+pub const BUNDLE_METADATA_FILE: &str = "bundle.json";
+
+/// Directory of the archive holding `<encoded-project>/<file>` session files.
+const SESSIONS_DIR: &str = "sessions";
+
+const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum BundleError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    FileSystem(#[from] FileSystemError),
+
+    #[error(transparent)]
+    Manifest(#[from] manifest::ManifestError),
+
+    #[error("Invalid {}: {0}", BUNDLE_METADATA_FILE)]
+    Metadata(#[from] serde_json::Error),
+
+    #[error("Not a session bundle: {} is missing", BUNDLE_METADATA_FILE)]
+    MissingMetadata,
+
+    #[error("Unsupported bundle version {0}; upgrade cc-sync-session")]
+    UnsupportedVersion(u32),
+
+    #[error("Unsafe path in bundle: {0}")]
+    UnsafePath(String),
+
+    #[error("{0} is listed in the bundle but missing from it")]
+    MissingFile(String),
+
+    #[error("{0} does not match its checksum in the bundle")]
+    ChecksumMismatch(String),
+
+    #[error("Cannot relocate {path}: {reason}")]
+    Relocate { path: PathBuf, reason: String },
+}
+
+pub type Result<T> = std::result::Result<T, BundleError>;
+
+/// Content of `bundle.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleMetadata {
+    pub version: u32,
+    pub created: DateTime<Utc>,
+    /// Directory the sessions were exported for, usually the repository root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+    pub projects: Vec<BundleProject>,
+    /// Checksums keyed by `<encoded-project>/<file>`, like the store manifest.
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+/// A Claude Code project directory in the bundle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleProject {
+    /// Directory name under `~/.claude/projects`, e.g. `-home-me-repo`.
+    pub encoded_name: String,
+    /// The directory the name encodes, from the `cwd` of its sessions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleFile {
+    /// `<encoded-project>/<file>`, the file uncompressed and unencrypted.
+    pub path: PathBuf,
+    pub content: Vec<u8>,
+    pub modified: SystemTime,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bundle {
+    pub metadata: BundleMetadata,
+    pub files: Vec<BundleFile>,
+}
+
+impl Bundle {
+    pub fn new(root: Option<PathBuf>) -> Self {
+        Self {
+            metadata: BundleMetadata {
+                version: BUNDLE_VERSION,
+                created: Utc::now(),
+                root,
+                projects: Vec::new(),
+                files: BTreeMap::new(),
+            },
+            files: Vec::new(),
+        }
+    }
+
+    /// Adds a session file, recording its project the first time.
+    pub fn add(&mut self, file: BundleFile) {
+        if let Some(project) = file.path.components().next() {
+            let encoded_name = project.as_os_str().to_string_lossy().into_owned();
+            let cwd = SessionPeek::from_head(&String::from_utf8_lossy(&file.content)).cwd;
+            match self.metadata.projects.iter_mut().find(|project| project.encoded_name == encoded_name) {
+                Some(project) => project.path = project.path.take().or(cwd.map(PathBuf::from)),
+                None => self.metadata.projects.push(BundleProject {
+                    encoded_name,
+                    path: cwd.map(PathBuf::from),
+                }),
+            }
+        }
+
+        self.metadata.files.insert(manifest_key(&file.path), ManifestEntry::for_content(&file.content));
+        self.files.push(file);
+    }
+
+    /// Writes the bundle to a new zstd-compressed tar archive.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = File::options().write(true).create_new(true).open(path)?;
+        let encoder = zstd::Encoder::new(file, 0)?.auto_finish();
+        let mut builder = tar::Builder::new(encoder);
+
+        let metadata = serde_json::to_vec_pretty(&self.metadata)?;
+        append(&mut builder, Path::new(BUNDLE_METADATA_FILE), &metadata, SystemTime::now())?;
+        for file in &self.files {
+            append(&mut builder, &Path::new(SESSIONS_DIR).join(&file.path), &file.content, file.modified)?;
+        }
+
+        builder.into_inner()?;
+        Ok(())
+    }
+
+    /// Reads a bundle, checking every file against its checksum.
+    pub fn read(path: &Path) -> Result<Self> {
+        let decoder = zstd::Decoder::new(File::open(path)?)?;
+        let mut archive = tar::Archive::new(decoder);
+        let mut metadata: Option<BundleMetadata> = None;
+        let mut contents: Vec<(String, Vec<u8>, SystemTime)> = Vec::new();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let entry_path = entry.path()?.into_owned();
+            let modified = UNIX_EPOCH + Duration::from_secs(entry.header().mtime()?);
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+
+            if entry_path == Path::new(BUNDLE_METADATA_FILE) {
+                let parsed: BundleMetadata = serde_json::from_slice(&content)?;
+                if parsed.version > BUNDLE_VERSION {
+                    return Err(BundleError::UnsupportedVersion(parsed.version));
+                }
+                metadata = Some(parsed);
+            } else if let Ok(relative) = entry_path.strip_prefix(SESSIONS_DIR) {
+                if !is_safe(relative) {
+                    return Err(BundleError::UnsafePath(entry_path.display().to_string()));
+                }
+                contents.push((manifest_key(relative), content, modified));
+            }
+        }
+
+        let metadata = metadata.ok_or(BundleError::MissingMetadata)?;
+        let mut files = Vec::new();
+        for (key, content, modified) in contents {
+            match metadata.files.get(&key) {
+                Some(entry) if *entry == ManifestEntry::for_content(&content) => {}
+                Some(_) => return Err(BundleError::ChecksumMismatch(key)),
+                None => {
+                    log::warn!("Ignoring {}, which {} does not list", key, BUNDLE_METADATA_FILE);
+                    continue;
+                }
+            }
+            files.push(BundleFile {
+                path: PathBuf::from(key),
+                content,
+                modified,
+            });
+        }
+        if let Some(missing) = metadata.files.keys().find(|key| !files.iter().any(|file| manifest_key(&file.path) == **key)) {
+            return Err(BundleError::MissingFile(missing.clone()));
+        }
+
+        Ok(Self { metadata, files })
+    }
+}
+
+fn append<W: io::Write>(builder: &mut tar::Builder<W>, path: &Path, content: &[u8], modified: SystemTime) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(modified.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()));
+    header.set_cksum();
+    builder.append_data(&mut header, path, content)
+}
+
+/// Returns `true` if `path` stays inside the directory it is joined to.
+fn is_safe(path: &Path) -> bool {
+    path.components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Bundles the sessions of `snapshot` (keyed by `<encoded-project>/<file>`)
+/// that pass `filter`. `modified` holds their modification times.
+pub fn export_snapshot(
+    snapshot: &Snapshot,
+    modified: &BTreeMap<PathBuf, SystemTime>,
+    filter: &SessionFilter,
+    root: Option<PathBuf>,
+) -> crate::filesystem::Result<Bundle> {
+    let mut bundle = Bundle::new(root);
+
+    for (path, content) in snapshot {
+        let modified = modified.get(path).copied().unwrap_or(UNIX_EPOCH);
+        let peek = || Ok(SessionPeek::from_head(&String::from_utf8_lossy(content)));
+        if !filter.matches_with(path, modified, peek)? {
+            continue;
+        }
+        bundle.add(BundleFile {
+            path: path.clone(),
+            content: content.clone(),
+            modified,
+        });
+    }
+
+    Ok(bundle)
+}
+
+/// Where `import_bundle` writes sessions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportTarget {
+    /// A repository store, written in its format and recorded in its manifest.
+    Store(StoreFormat),
+    /// A Claude Code projects directory such as `~/.claude/projects`.
+    Projects,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub target: ImportTarget,
+    /// The store or projects directory.
+    pub target_dir: PathBuf,
+    /// Directory that replaces the bundle's root, e.g. the repository the
+    /// sessions are imported into. Projects under the old root are moved
+    /// under it, and the `cwd` of their records is rewritten.
+    pub root: Option<PathBuf>,
+    /// Replace files that already exist
+    pub force: bool,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportResult {
+    pub files_imported: usize,
+    /// Files that already exist, without [`ImportOptions::force`]
+    pub files_skipped: usize,
+    /// Projects that got a new encoded name: `(old, new)`
+    pub renamed_projects: Vec<(String, String)>,
+}
+
+/// Writes the files of `bundle` to the target of `options`.
+pub fn import_bundle<FS: FileSystem>(fs: &FS, bundle: &Bundle, options: &ImportOptions) -> Result<ImportResult> {
+    let mut result = ImportResult::default();
+    let relocation = match (&bundle.metadata.root, &options.root) {
+        (Some(old_root), Some(new_root)) if old_root != new_root => Some((old_root, new_root)),
+        _ => None,
+    };
+
+    let mut names: BTreeMap<&str, String> = BTreeMap::new();
+    if let Some((old_root, new_root)) = relocation {
+        for project in &bundle.metadata.projects {
+            let Some(new_path) = project.path.as_deref().and_then(|path| relocate(path, old_root, new_root)) else {
+                continue;
+            };
+            let new_name = dir_path_to_claude_code_stype(new_path.clone())
+                .map_err(|e| BundleError::Relocate { path: new_path, reason: e.to_string() })?;
+            if new_name != project.encoded_name {
+                result.renamed_projects.push((project.encoded_name.clone(), new_name.clone()));
+            }
+            names.insert(&project.encoded_name, new_name);
+        }
+    }
+
+    let mut manifest = match &options.target {
+        ImportTarget::Store(_) if !options.dry_run => Some(Manifest::load(fs, &options.target_dir)?.unwrap_or_default()),
+        _ => None,
+    };
+
+    for file in &bundle.files {
+        if !is_safe(&file.path) {
+            return Err(BundleError::UnsafePath(file.path.display().to_string()));
+        }
+        let mut components = file.path.components();
+        let project = components.next().map(|c| c.as_os_str().to_string_lossy().into_owned()).unwrap_or_default();
+        let relative = components.as_path();
+        let logical_path = Path::new(names.get(project.as_str()).unwrap_or(&project)).join(relative);
+
+        let (stored_path, format) = match &options.target {
+            ImportTarget::Store(format) => (format.stored_path(&logical_path), format),
+            ImportTarget::Projects => (logical_path, &StoreFormat::default()),
+        };
+        let target_path = options.target_dir.join(&stored_path);
+        if fs.exists(&target_path)? && !options.force {
+            result.files_skipped += 1;
+            log::info!("Skipped (exists): {}", target_path.display());
+            continue;
+        }
+
+        result.files_imported += 1;
+        log::info!("Imported: {} -> {}", file.path.display(), target_path.display());
+        if options.dry_run {
+            continue;
+        }
+
+        let rewritten = match relocation {
+            Some((old_root, new_root)) => rewrite_cwd(&file.content, old_root, new_root),
+            None => file.content.clone(),
+        };
+        let stored = format.encode(&rewritten)?;
+        if let Some(parent) = target_path.parent() {
+            if !fs.exists(parent)? {
+                fs.create_directory(parent)?;
+            }
+        }
+        fs.write_file(&target_path, &stored)?;
+        fs.set_modified_time(&target_path, file.modified)?;
+        if let Some(manifest) = &mut manifest {
+            manifest.record(&stored_path, &stored);
+        }
+    }
+
+    if let Some(manifest) = manifest.filter(|_| result.files_imported > 0) {
+        manifest.save(fs, &options.target_dir)?;
+    }
+
+    Ok(result)
+}
+
+/// Moves `path` from under `old_root` to under `new_root`.
+pub fn relocate(path: &Path, old_root: &Path, new_root: &Path) -> Option<PathBuf> {
+    let relative = path.strip_prefix(old_root).ok()?;
+    Some(if relative.as_os_str().is_empty() { new_root.to_path_buf() } else { new_root.join(relative) })
+}
+
+/// Rewrites the `cwd` of session records under `old_root` to `new_root`.
+pub fn rewrite_cwd(content: &[u8], old_root: &Path, new_root: &Path) -> Vec<u8> {
+    let quoted = |root: &Path| serde_json::to_string(&root.to_string_lossy()).unwrap_or_default();
+    let old_quoted = quoted(old_root);
+    let new_quoted = quoted(new_root);
+    // `"cwd":"/old/root` followed by the closing quote or a path separator
+    let old = format!("\"cwd\":{}", &old_quoted[..old_quoted.len() - 1]);
+    let new = format!("\"cwd\":{}", &new_quoted[..new_quoted.len() - 1]);
+
+    let text = String::from_utf8_lossy(content);
+    let mut rewritten = String::with_capacity(text.len());
+    let mut rest = text.as_ref();
+    while let Some(start) = rest.find(&old) {
+        let end = start + old.len();
+        rewritten.push_str(&rest[..start]);
+        if rest[end..].starts_with(['"', '/']) {
+            rewritten.push_str(&new);
+        } else {
+            rewritten.push_str(&old);
+        }
+        rest = &rest[end..];
+    }
+    rewritten.push_str(rest);
+    rewritten.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::{read_session, Compression};
+    use crate::encryption::Identities;
+    use crate::mock::MockFileSystem;
+
+    fn record(cwd: &str) -> Vec<u8> {
+        format!(
+            "{{\"type\":\"user\",\"uuid\":\"u1\",\"sessionId\":\"s1\",\"cwd\":\"{}\",\"gitBranch\":\"main\",\"timestamp\":\"2025-06-01T10:00:00Z\"}}\n",
+            cwd
+        ).into_bytes()
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let modified = UNIX_EPOCH + Duration::from_secs(1_750_000_000);
+        let snapshot = Snapshot::from([
+            (PathBuf::from("-old-repo/s1.jsonl"), record("/old/repo")),
+            (PathBuf::from("-old-repo-sub/s2.jsonl"), record("/old/repo/sub")),
+        ]);
+        let times = snapshot.keys().map(|path| (path.clone(), modified)).collect();
+        let filter = SessionFilter { git_branch: Some("main".to_string()), ..Default::default() };
+
+        let bundle = export_snapshot(&snapshot, &times, &filter, Some(PathBuf::from("/old/repo"))).unwrap();
+        assert_eq!(bundle.metadata.projects[1].path, Some(PathBuf::from("/old/repo/sub")));
+        let path = dir.path().join("sessions.tar.zst");
+        bundle.write(&path).unwrap();
+        assert!(bundle.write(&path).is_err());
+
+        let read = Bundle::read(&path).unwrap();
+        assert_eq!(read.metadata, bundle.metadata);
+        assert_eq!(read.files, bundle.files);
+
+        let filter = SessionFilter { git_branch: Some("other".to_string()), ..Default::default() };
+        assert!(export_snapshot(&snapshot, &times, &filter, None).unwrap().files.is_empty());
+    }
+
+    #[test]
+    fn test_import_relocates_projects() {
+        let fs = MockFileSystem::new();
+        let store_dir = Path::new("/new/repo/.claude/ccss_sessions");
+        fs.add_directory(store_dir);
+        let mut bundle = Bundle::new(Some(PathBuf::from("/old/repo")));
+        for (path, cwd) in [("-old-repo/s1.jsonl", "/old/repo"), ("-old-repo-sub/s2.jsonl", "/old/repo/sub")] {
+            bundle.add(BundleFile { path: PathBuf::from(path), content: record(cwd), modified: UNIX_EPOCH });
+        }
+        let options = ImportOptions {
+            target: ImportTarget::Store(StoreFormat { compression: Compression::Zstd, ..Default::default() }),
+            target_dir: store_dir.to_path_buf(),
+            root: Some(PathBuf::from("/new/repo")),
+            force: false,
+            dry_run: false,
+        };
+
+        let result = import_bundle(&fs, &bundle, &options).unwrap();
+        assert_eq!(result.files_imported, 2);
+        assert_eq!(result.renamed_projects[0], ("-old-repo".to_string(), "-new-repo".to_string()));
+        let imported = read_session(&fs, &store_dir.join("-new-repo-sub/s2.jsonl.zst"), &Identities::default()).unwrap();
+        assert_eq!(imported, record("/new/repo/sub"));
+        let manifest = Manifest::load(&fs, store_dir).unwrap().unwrap();
+        assert!(manifest.get(Path::new("-new-repo/s1.jsonl.zst")).is_some());
+
+        let result = import_bundle(&fs, &bundle, &options).unwrap();
+        assert_eq!((result.files_imported, result.files_skipped), (0, 2));
+    }
+
+    #[test]
+    fn test_rewrite_cwd() {
+        let content = b"{\"cwd\":\"/old/repo\"}\n{\"cwd\":\"/old/repo/sub\"}\n{\"cwd\":\"/old/repository\"}\n";
+        let rewritten = rewrite_cwd(content, Path::new("/old/repo"), Path::new("/new"));
+        assert_eq!(rewritten, b"{\"cwd\":\"/new\"}\n{\"cwd\":\"/new/sub\"}\n{\"cwd\":\"/old/repository\"}\n");
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::encryption::{self, encrypted_path, is_encrypted_path, Encryption, Identities};
+use crate::filesystem::{self, FileSystem, FileSystemError};
+use crate::manifest::{self, Manifest};
+use crate::sync::is_session_file;
+
+/// Extension appended to compressed session files: `<session>.jsonl.zst`.
+pub const COMPRESSED_EXTENSION: &str = "zst";
+
+/// First bytes of every zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How session files are written to the store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Plain `.jsonl`, as Claude Code writes them.
+    #[default]
+    None,
+    /// zstd-compressed `.jsonl.zst`.
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("Unknown compression '{}' (expected none or zstd)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+        })
+    }
+}
+
+impl Compression {
+    /// Compression of a stored session file, by its name.
+    pub fn of_path(path: &Path) -> Self {
+        let path = if is_encrypted_path(path) { path.with_extension("") } else { path.to_path_buf() };
+        if path.extension().is_some_and(|ext| ext == COMPRESSED_EXTENSION) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Path a session at `logical_path` (`<session>.jsonl`) is stored at.
+    pub fn stored_path(&self, logical_path: &Path) -> PathBuf {
+        match self {
+            Compression::None => logical_path.to_path_buf(),
+            Compression::Zstd => {
+                let mut path = logical_path.as_os_str().to_owned();
+                path.push(".");
+                path.push(COMPRESSED_EXTENSION);
+                PathBuf::from(path)
+            }
+        }
+    }
+
+    pub fn encode(&self, content: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(content.to_vec()),
+            Compression::Zstd => zstd::encode_all(content, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+}
+
+/// How session files are written to the store: compressed, then encrypted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StoreFormat {
+    pub compression: Compression,
+    pub encryption: Option<Encryption>,
+}
+
+impl StoreFormat {
+    /// Returns `true` if sessions are stored as Claude Code writes them.
+    pub fn is_plain(&self) -> bool {
+        self.compression == Compression::None && self.encryption.is_none()
+    }
+
+    /// Path a session at `logical_path` (`<session>.jsonl`) is stored at.
+    pub fn stored_path(&self, logical_path: &Path) -> PathBuf {
+        let path = self.compression.stored_path(logical_path);
+        match self.encryption {
+            Some(_) => encrypted_path(&path),
+            None => path,
+        }
+    }
+
+    pub fn encode(&self, content: &[u8]) -> io::Result<Vec<u8>> {
+        let content = self.compression.encode(content)?;
+        match &self.encryption {
+            Some(encryption) => encryption.encrypt(&content).map_err(io::Error::other),
+            None => Ok(content),
+        }
+    }
+}
+
+/// Path of the session a stored file holds, without the compression and
+/// encryption extensions.
+pub fn logical_path(path: &Path) -> PathBuf {
+    let path = if is_encrypted_path(path) { path.with_extension("") } else { path.to_path_buf() };
+    match Compression::of_path(&path) {
+        Compression::Zstd => path.with_extension(""),
+        Compression::None => path,
+    }
+}
+
+/// Returns `true` if `content` is zstd-compressed.
+pub fn is_compressed(content: &[u8]) -> bool {
+    content.starts_with(&ZSTD_MAGIC)
+}
+
+/// Decrypts `content` with `identities` if it is encrypted, then decompresses
+/// it if it is zstd-compressed.
+///
+/// Content is recognized by its magic number rather than by file name, so
+/// the temporary files git hands to drivers are read correctly as well.
+pub fn decode(content: Vec<u8>, identities: &Identities) -> io::Result<Vec<u8>> {
+    let content = if encryption::is_encrypted(&content) {
+        identities.decrypt(&content).map_err(io::Error::other)?
+    } else {
+        content
+    };
+    if is_compressed(&content) {
+        zstd::decode_all(content.as_slice())
+    } else {
+        Ok(content)
+    }
+}
+
+/// Reads, decrypts and decompresses a session file.
+pub fn read_session<FS: FileSystem>(fs: &FS, path: &Path, identities: &Identities) -> filesystem::Result<Vec<u8>> {
+    Ok(decode(fs.read_file(path)?, identities)?)
+}
+
+/// Finds the stored file of the session at the store-relative `logical_path`,
+/// compressed, encrypted or not.
+pub fn find_stored_path<FS: FileSystem>(fs: &FS, store_dir: &Path, logical_path: &Path) -> filesystem::Result<Option<PathBuf>> {
+    for compression in [Compression::None, Compression::Zstd] {
+        let path = compression.stored_path(logical_path);
+        for path in [encrypted_path(&path), path] {
+            if fs.exists(&store_dir.join(&path))? {
+                return Ok(Some(path));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the session at the store-relative `logical_path`, compressed,
+/// encrypted or not.
+pub fn read_stored_session<FS: FileSystem>(
+    fs: &FS,
+    store_dir: &Path,
+    logical_path: &Path,
+    identities: &Identities,
+) -> filesystem::Result<Option<Vec<u8>>> {
+    match find_stored_path(fs, store_dir, logical_path)? {
+        Some(path) => Ok(Some(read_session(fs, &store_dir.join(path), identities)?)),
+        None => Ok(None),
+    }
+}
+
+/// Rewrites the session files of the store that are not stored in `format`.
+///
+/// With `rewrite_all`, every session file is rewritten, e.g. to encrypt it to
+/// a new set of recipients; encrypted files are read with `identities`. Modification times are kept so that `sync` does
+/// not copy the sessions again, and the manifest follows the renamed files.
+/// Returns the number of files converted.
+pub fn convert_store<FS: FileSystem>(
+    fs: &FS,
+    store_dir: &Path,
+    format: &StoreFormat,
+    identities: &Identities,
+    rewrite_all: bool,
+) -> manifest::Result<usize> {
+    let mut manifest = Manifest::load(fs, store_dir)?;
+    let mut converted = 0;
+
+    for entry in fs.walk_files(store_dir)? {
+        if !is_session_file(&entry.path) {
+            continue;
+        }
+        let target_path = format.stored_path(&logical_path(&entry.path));
+        if target_path == entry.path && !rewrite_all {
+            continue;
+        }
+
+        let content = format.encode(&read_session(fs, &entry.path, identities)?).map_err(FileSystemError::from)?;
+        fs.write_file(&target_path, &content)?;
+        fs.set_modified_time(&target_path, entry.modified)?;
+        if target_path != entry.path {
+            fs.remove_file(&entry.path)?;
+        }
+
+        if let Some(manifest) = &mut manifest {
+            let strip = |path: &Path| path.strip_prefix(store_dir).map(Path::to_path_buf)
+                .map_err(|e| FileSystemError::PathError(e.to_string()));
+            manifest.remove(&strip(&entry.path)?);
+            manifest.record(&strip(&target_path)?, &content);
+        }
+        converted += 1;
+    }
+
+    if let Some(manifest) = manifest {
+        manifest.save(fs, store_dir)?;
+    }
+
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::decrypt_with;
+    use crate::mock::MockFileSystem;
+    use age::x25519;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_paths() {
+        let logical = Path::new("p/s1.jsonl");
+        let stored = Compression::Zstd.stored_path(logical);
+
+        assert_eq!(stored, PathBuf::from("p/s1.jsonl.zst"));
+        assert!(is_session_file(&stored));
+        assert_eq!(logical_path(&stored), logical);
+        assert_eq!(logical_path(logical), logical);
+
+        let format = StoreFormat {
+            compression: Compression::Zstd,
+            encryption: Some(Encryption::new(&[x25519::Identity::generate().to_public().to_string()]).unwrap()),
+        };
+        let stored = format.stored_path(logical);
+        assert_eq!(stored, PathBuf::from("p/s1.jsonl.zst.age"));
+        assert_eq!(Compression::of_path(&stored), Compression::Zstd);
+        assert!(is_session_file(&stored));
+        assert_eq!(logical_path(&stored), logical);
+    }
+
+    #[test]
+    fn test_convert_store_round_trip() {
+        let fs = MockFileSystem::new();
+        let store_dir = Path::new("/store");
+        let content = b"{\"type\":\"user\"}\n".to_vec();
+        let modified = SystemTime::now() - Duration::from_secs(60);
+        fs.add_directory(store_dir);
+        fs.add_directory(store_dir.join("p"));
+        fs.add_file(store_dir.join("p/s1.jsonl"), content.clone(), modified);
+
+        let zstd = StoreFormat { compression: Compression::Zstd, ..Default::default() };
+        assert_eq!(convert_store(&fs, store_dir, &zstd, &Identities::default(), false).unwrap(), 1);
+        let compressed = store_dir.join("p/s1.jsonl.zst");
+        assert!(is_compressed(&fs.get_file_content(&compressed).unwrap()));
+        assert_eq!(fs.get_metadata(&compressed).unwrap().modified, modified);
+        assert_eq!(read_stored_session(&fs, store_dir, Path::new("p/s1.jsonl"), &Identities::default()).unwrap(), Some(content.clone()));
+
+        assert_eq!(convert_store(&fs, store_dir, &zstd, &Identities::default(), false).unwrap(), 0);
+        assert_eq!(convert_store(&fs, store_dir, &StoreFormat::default(), &Identities::default(), false).unwrap(), 1);
+        assert_eq!(fs.get_file_content(&store_dir.join("p/s1.jsonl")).unwrap(), content);
+        assert!(!fs.exists(&compressed).unwrap());
+    }
+
+    #[test]
+    fn test_convert_store_encrypts() {
+        let fs = MockFileSystem::new();
+        let store_dir = Path::new("/store");
+        let content = b"{\"type\":\"user\"}\n".to_vec();
+        fs.add_directory(store_dir);
+        fs.add_directory(store_dir.join("p"));
+        fs.add_file(store_dir.join("p/s1.jsonl"), content.clone(), SystemTime::now());
+
+        let identity = x25519::Identity::generate();
+        let format = StoreFormat {
+            compression: Compression::None,
+            encryption: Some(Encryption::new(&[identity.to_public().to_string()]).unwrap()),
+        };
+        assert_eq!(convert_store(&fs, store_dir, &format, &Identities::default(), false).unwrap(), 1);
+        assert!(!fs.exists(&store_dir.join("p/s1.jsonl")).unwrap());
+        let encrypted = fs.get_file_content(&store_dir.join("p/s1.jsonl.age")).unwrap();
+        assert_eq!(decrypt_with(&encrypted, std::slice::from_ref(&identity)).unwrap(), content);
+        assert_eq!(find_stored_path(&fs, store_dir, Path::new("p/s1.jsonl")).unwrap(), Some(PathBuf::from("p/s1.jsonl.age")));
+
+        // Re-encrypting to a new recipient needs an identity for the old files
+        let added = x25519::Identity::generate();
+        let format = StoreFormat {
+            compression: Compression::None,
+            encryption: Some(Encryption::new(&[added.to_public().to_string()]).unwrap()),
+        };
+        assert_eq!(convert_store(&fs, store_dir, &format, &Identities::default(), false).unwrap(), 0);
+        assert!(convert_store(&fs, store_dir, &format, &Identities::default(), true).is_err());
+        assert_eq!(convert_store(&fs, store_dir, &format, &Identities::new(vec![identity]), true).unwrap(), 1);
+        let encrypted = fs.get_file_content(&store_dir.join("p/s1.jsonl.age")).unwrap();
+        assert_eq!(decrypt_with(&encrypted, &[added]).unwrap(), content);
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::branch::DEFAULT_STORE_BRANCH;
+use crate::compression::Compression;
+use crate::encryption::EncryptionConfig;
+use crate::filesystem::{FileSystem, FileSystemError};
+use crate::index::RedactionRules;
+use crate::layout::Layout;
+use crate::prune::RetentionPolicy;
+use crate::sidechain::SidechainMode;
+use crate::sync::{parse_since, parse_until, SessionFilter, SyncPolicy};
+
+/// Repository configuration, relative to the repository root.
+pub const REPO_CONFIG_PATH: &str = ".claude/ccss.toml";
+
+/// User configuration, relative to the XDG config directory.
+pub const USER_CONFIG_PATH: &str = "cc-sync-session/config.toml";
+
+/// Prefix of the environment variables setting a value, as in
+/// `CC_SYNC_SESSION_SYNC_DEDUPLICATE=true` for `[sync] deduplicate`.
+pub const ENV_PREFIX: &str = "CC_SYNC_SESSION_";
+
+/// Tables environment variables can set values of.
+const TABLES: &[&str] = &["source", "sync", "filter", "store", "retention", "encryption", "redaction"];
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error(transparent)]
+    FileSystem(#[from] FileSystemError),
+
+    #[error("Invalid configuration in {path}: {source}")]
+    Parse { path: PathBuf, source: toml::de::Error },
+
+    #[error("Invalid configuration in ${var}: {message}")]
+    Env { var: String, message: String },
+
+    #[error("Invalid {key} ({origin}): {message}")]
+    Value { key: String, origin: Origin, message: String },
+
+    #[error("Cannot edit {path}: {source}")]
+    Edit { path: PathBuf, source: toml_edit::TomlError },
+}
+
+pub type Result<T> = std::result::Result<T, ConfigError>;
+
+/// Settings of cc-sync-session. `.claude/ccss.toml` holds the ones shared by
+/// everyone working on the repository; [`Layers`] adds the others.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RepoConfig {
+    pub source: SourceConfig,
+    pub sync: SyncConfig,
+    pub filter: FilterConfig,
+    pub store: StoreConfig,
+    pub retention: RetentionPolicy,
+    pub encryption: EncryptionConfig,
+    pub redaction: RedactionRules,
+}
+
+/// The `[source]` table: where Claude Code keeps its projects.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceConfig {
+    /// Projects directory (defaults to `~/.claude/projects`)
+    pub dir: Option<PathBuf>,
+}
+
+impl SourceConfig {
+    /// The projects directory, with a leading `~/` expanded.
+    pub fn dir(&self) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        match (dir.strip_prefix("~"), dirs::home_dir()) {
+            (Ok(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(dir.clone()),
+        }
+    }
+}
+
+/// The `[sync]` table: defaults of the `sync` options.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncConfig {
+    pub deduplicate: bool,
+    pub sidechains: SidechainMode,
+    pub index: bool,
+    /// Stage the store in git after syncing
+    pub git_add: bool,
+    /// Only sync sessions started on the checked out branch
+    pub current_branch: bool,
+    /// How `sync` ends when sessions were pending
+    pub policy: SyncPolicy,
+}
+
+/// The `[filter]` table: which sessions `sync` copies.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    /// `YYYY-MM-DD` or RFC 3339
+    pub since: Option<String>,
+    /// `YYYY-MM-DD` (the whole day) or RFC 3339
+    pub until: Option<String>,
+    pub git_branch: Option<String>,
+    /// Session ids (or id prefixes) that are never synced
+    pub private: Vec<String>,
+}
+
+impl FilterConfig {
+    fn session_filter(&self) -> std::result::Result<SessionFilter, (&'static str, String)> {
+        Ok(SessionFilter {
+            session_ids: Vec::new(),
+            since: self.since.as_deref().map(parse_since).transpose().map_err(|e| ("filter.since", e))?,
+            until: self.until.as_deref().map(parse_until).transpose().map_err(|e| ("filter.until", e))?,
+            git_branch: self.git_branch.clone(),
+            private_ids: self.private.clone(),
+        })
+    }
+}
+
+/// The `[store]` table: how and where sessions are stored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    pub compression: Compression,
+    pub mode: StoreMode,
+    /// Branch of [`StoreMode::Branch`] (defaults to `ccss/sessions`)
+    pub branch: Option<String>,
+    /// Layout `init` gives a new store
+    pub layout: Layout,
+}
+
+impl StoreConfig {
+    pub fn branch(&self) -> &str {
+        self.branch.as_deref().unwrap_or(DEFAULT_STORE_BRANCH)
+    }
+}
+
+/// Where `sync` writes the store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreMode {
+    /// `.claude/ccss_sessions` in the working tree, committed with the code.
+    #[default]
+    Worktree,
+    /// Commits on a separate orphan branch; the working tree is not touched.
+    Branch,
+}
+
+/// Where an effective value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    User(PathBuf),
+    Repo(PathBuf),
+    Env(String),
+    CommandLine,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::User(path) => write!(f, "user:{}", path.display()),
+            Origin::Repo(path) => write!(f, "repo:{}", path.display()),
+            Origin::Env(var) => write!(f, "env:{}", var),
+            Origin::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// The configuration sources besides `.claude/ccss.toml`.
+///
+/// Later sources override earlier ones: built-in defaults, the user
+/// configuration, the repository configuration, environment variables and
+/// command line options. Tables merge key by key; arrays are replaced.
+#[derive(Debug, Clone, Default)]
+pub struct Layers {
+    user_config: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    command_line: toml::Table,
+}
+
+impl Layers {
+    /// The user configuration and the environment of this process.
+    pub fn from_environment() -> Self {
+        Self {
+            user_config: user_config_path(),
+            env: std::env::vars().collect(),
+            command_line: toml::Table::new(),
+        }
+    }
+
+    pub fn with_user_config(mut self, path: PathBuf) -> Self {
+        self.user_config = Some(path);
+        self
+    }
+
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Sets `key` (`table.name`) as given on the command line.
+    pub fn with_command_line(mut self, key: &str, value: impl Into<toml::Value>) -> Self {
+        if let Some((table, name)) = key.split_once('.') {
+            let table = self.command_line.entry(table)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if let toml::Value::Table(table) = table {
+                table.insert(name.to_string(), value.into());
+            }
+        }
+        self
+    }
+}
+
+/// `~/.config/cc-sync-session/config.toml`, or its XDG equivalent.
+pub fn user_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(USER_CONFIG_PATH))
+}
+
+/// The effective configuration and where each of its values came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayeredConfig {
+    pub config: RepoConfig,
+    /// Every value by its dotted key, e.g. `sync.deduplicate`.
+    pub values: BTreeMap<String, (toml::Value, Origin)>,
+}
+
+impl RepoConfig {
+    /// Reads the configuration of `repo_dir`; a missing file means defaults.
+    pub fn load<FS: FileSystem>(fs: &FS, repo_dir: &Path) -> Result<Self> {
+        Ok(Self::load_layered(fs, repo_dir, &Layers::default())?.config)
+    }
+
+    /// Reads the configuration of `repo_dir` over and under the other `layers`.
+    pub fn load_layered<FS: FileSystem>(fs: &FS, repo_dir: &Path, layers: &Layers) -> Result<LayeredConfig> {
+        let mut merged = toml::Table::new();
+        let mut origins = BTreeMap::new();
+
+        if let Ok(toml::Value::Table(defaults)) = toml::Value::try_from(RepoConfig::default()) {
+            merge(&mut merged, &defaults, &Origin::Default, "", &mut origins);
+        }
+        if let Some(path) = &layers.user_config {
+            if let Some(table) = read_layer(fs, path)? {
+                merge(&mut merged, &table, &Origin::User(path.clone()), "", &mut origins);
+            }
+        }
+        let path = repo_dir.join(REPO_CONFIG_PATH);
+        if let Some(table) = read_layer(fs, &path)? {
+            merge(&mut merged, &table, &Origin::Repo(path), "", &mut origins);
+        }
+        for (var, table) in env_layers(&layers.env)? {
+            merge(&mut merged, &table, &Origin::Env(var), "", &mut origins);
+        }
+        merge(&mut merged, &layers.command_line, &Origin::CommandLine, "", &mut origins);
+
+        let config = toml::Value::Table(merged.clone()).try_into()
+            .map_err(|e: toml::de::Error| ConfigError::Value {
+                key: "configuration".to_string(),
+                origin: Origin::CommandLine,
+                message: e.message().to_string(),
+            })?;
+        let mut values = BTreeMap::new();
+        flatten(&merged, "", &mut |key, value| {
+            let origin = origins.get(&key).cloned().unwrap_or(Origin::Default);
+            values.insert(key, (value.clone(), origin));
+        });
+
+        Ok(LayeredConfig { config, values })
+    }
+}
+
+impl RepoConfig {
+    /// Whether the session index is kept up to date: never with `[encryption]`
+    /// recipients, as the index lists prompts and branches in plain text.
+    pub fn writes_index(&self) -> bool {
+        self.sync.index && self.encryption.recipients.is_empty()
+    }
+}
+
+impl LayeredConfig {
+    /// Where the value of the dotted `key` came from.
+    pub fn origin(&self, key: &str) -> Origin {
+        self.values.get(key).map(|(_, origin)| origin.clone()).unwrap_or(Origin::Default)
+    }
+
+    /// The `[filter]` settings as a session filter.
+    pub fn session_filter(&self) -> Result<SessionFilter> {
+        self.config.filter.session_filter().map_err(|(key, message)| ConfigError::Value {
+            key: key.to_string(),
+            origin: self.origin(key),
+            message,
+        })
+    }
+}
+
+/// Reads a configuration file as a table, checking it holds valid settings.
+fn read_layer<FS: FileSystem>(fs: &FS, path: &Path) -> Result<Option<toml::Table>> {
+    if !fs.exists(path)? {
+        return Ok(None);
+    }
+
+    let content = String::from_utf8_lossy(&fs.read_file(path)?).into_owned();
+    let parse_error = |source| ConfigError::Parse { path: path.to_path_buf(), source };
+    toml::from_str::<RepoConfig>(&content).map_err(parse_error)?;
+    Ok(Some(toml::from_str(&content).map_err(parse_error)?))
+}
+
+/// One single-value table per `CC_SYNC_SESSION_<TABLE>_<KEY>` variable.
+fn env_layers(env: &[(String, String)]) -> Result<Vec<(String, toml::Table)>> {
+    let mut layers = Vec::new();
+    for (var, value) in env {
+        let Some((table, key)) = var.strip_prefix(ENV_PREFIX).and_then(|name| name.split_once('_')) else {
+            continue;
+        };
+        let table = table.to_ascii_lowercase();
+        if !TABLES.contains(&table.as_str()) {
+            continue;
+        }
+
+        let layer = toml::Table::from_iter([(
+            table,
+            toml::Value::Table(toml::Table::from_iter([(key.to_ascii_lowercase(), env_value(value))])),
+        )]);
+        toml::Value::Table(layer.clone()).try_into::<RepoConfig>()
+            .map_err(|e| ConfigError::Env { var: var.clone(), message: e.message().to_string() })?;
+        layers.push((var.clone(), layer));
+    }
+    layers.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(layers)
+}
+
+/// Booleans, integers and `[...]` arrays are read as TOML, anything else as a string.
+fn env_value(value: &str) -> toml::Value {
+    if let Ok(value) = value.parse::<bool>() {
+        return toml::Value::Boolean(value);
+    }
+    if let Ok(value) = value.parse::<i64>() {
+        return toml::Value::Integer(value);
+    }
+    if value.trim_start().starts_with('[') {
+        if let Ok(mut table) = toml::from_str::<toml::Table>(&format!("value = {}", value)) {
+            if let Some(value) = table.remove("value") {
+                return value;
+            }
+        }
+    }
+    toml::Value::String(value.to_string())
+}
+
+fn merge(into: &mut toml::Table, layer: &toml::Table, origin: &Origin, prefix: &str, origins: &mut BTreeMap<String, Origin>) {
+    for (key, value) in layer {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match (into.get_mut(key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => merge(existing, table, origin, &path, origins),
+            _ => {
+                into.insert(key.clone(), value.clone());
+                match value {
+                    toml::Value::Table(table) => flatten(table, &path, &mut |key, _| {
+                        origins.insert(key, origin.clone());
+                    }),
+                    _ => {
+                        origins.insert(path, origin.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn flatten(table: &toml::Table, prefix: &str, visit: &mut dyn FnMut(String, &toml::Value)) {
+    for (key, value) in table {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            toml::Value::Table(table) => flatten(table, &path, visit),
+            _ => visit(path, value),
+        }
+    }
+}
+
+/// Sets `[store] compression` in the configuration of `repo_dir`, keeping the
+/// rest of the file (comments included) as it is.
+pub fn set_store_compression<FS: FileSystem>(fs: &FS, repo_dir: &Path, compression: Compression) -> Result<()> {
+    edit(fs, repo_dir, |document| {
+        if !document.contains_key("store") {
+            document["store"] = toml_edit::table();
+        }
+        document["store"]["compression"] = toml_edit::value(compression.to_string());
+    })
+}
+
+/// Sets `[store] mode = "branch"` and the branch in the configuration of `repo_dir`.
+pub fn set_store_branch<FS: FileSystem>(fs: &FS, repo_dir: &Path, branch: &str) -> Result<()> {
+    edit(fs, repo_dir, |document| {
+        if !document.contains_key("store") {
+            document["store"] = toml_edit::table();
+        }
+        document["store"]["mode"] = toml_edit::value("branch");
+        document["store"]["branch"] = toml_edit::value(branch);
+    })
+}
+
+/// Sets `[encryption] recipients` in the configuration of `repo_dir`, keeping
+/// the rest of the file as it is.
+pub fn set_encryption_recipients<FS: FileSystem>(fs: &FS, repo_dir: &Path, recipients: &[String]) -> Result<()> {
+    edit(fs, repo_dir, |document| {
+        if !document.contains_key("encryption") {
+            document["encryption"] = toml_edit::table();
+        }
+        let mut array: toml_edit::Array = recipients.iter().map(String::as_str).collect();
+        for value in array.iter_mut() {
+            value.decor_mut().set_prefix("\n    ");
+        }
+        array.set_trailing(",\n");
+        document["encryption"]["recipients"] = toml_edit::value(array);
+    })
+}
+
+/// Adds the session `id` to `[retention] pinned` in the configuration of
+/// `repo_dir`, or removes it if `pinned` is false.
+pub fn set_pinned<FS: FileSystem>(fs: &FS, repo_dir: &Path, id: &str, pinned: bool) -> Result<()> {
+    edit(fs, repo_dir, |document| set_listed(document, "retention", "pinned", id, pinned))
+}
+
+/// Adds the session `id` to `[filter] private` in the configuration of
+/// `repo_dir`, or removes it if `private` is false.
+pub fn set_private<FS: FileSystem>(fs: &FS, repo_dir: &Path, id: &str, private: bool) -> Result<()> {
+    edit(fs, repo_dir, |document| set_listed(document, "filter", "private", id, private))
+}
+
+fn set_listed(document: &mut toml_edit::DocumentMut, table: &str, key: &str, id: &str, listed: bool) {
+    if !document.contains_key(table) {
+        document[table] = toml_edit::table();
+    }
+    if document[table].get(key).and_then(|item| item.as_array()).is_none() {
+        document[table][key] = toml_edit::value(toml_edit::Array::new());
+    }
+    if let Some(array) = document[table][key].as_array_mut() {
+        array.retain(|value| value.as_str() != Some(id));
+        if listed {
+            array.push(id);
+        }
+    }
+}
+
+fn edit<FS: FileSystem>(fs: &FS, repo_dir: &Path, change: impl FnOnce(&mut toml_edit::DocumentMut)) -> Result<()> {
+    let path = repo_dir.join(REPO_CONFIG_PATH);
+    let content = if fs.exists(&path)? {
+        String::from_utf8_lossy(&fs.read_file(&path)?).into_owned()
+    } else {
+        String::new()
+    };
+
+    let mut document: toml_edit::DocumentMut = content.parse()
+        .map_err(|source| ConfigError::Edit { path: path.clone(), source })?;
+    change(&mut document);
+    fs.write_file(&path, document.to_string().as_bytes())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockFileSystem;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_load() {
+        let fs = MockFileSystem::new();
+        let repo_dir = Path::new("/repo");
+        assert_eq!(RepoConfig::load(&fs, repo_dir).unwrap(), RepoConfig::default());
+
+        set_store_compression(&fs, repo_dir, Compression::Zstd).unwrap();
+        assert_eq!(fs.get_file_content(&repo_dir.join(REPO_CONFIG_PATH)).unwrap(), b"[store]\ncompression = \"zstd\"\n");
+
+        fs.add_file(
+            repo_dir.join(REPO_CONFIG_PATH),
+            b"[store]\ncompression = \"zstd\"\n\n[retention]\nkeep_days = 30\npinned = [\"abc\"]\n".to_vec(),
+            SystemTime::now(),
+        );
+        let config = RepoConfig::load(&fs, repo_dir).unwrap();
+        assert_eq!(config.store.compression, Compression::Zstd);
+        assert_eq!(config.store.mode, StoreMode::Worktree);
+        assert_eq!(config.store.branch(), DEFAULT_STORE_BRANCH);
+        assert_eq!(config.retention.keep_days, Some(30));
+        assert_eq!(config.retention.max_per_branch, None);
+        assert_eq!(config.retention.pinned, vec!["abc".to_string()]);
+
+        set_store_compression(&fs, repo_dir, Compression::None).unwrap();
+        let content = String::from_utf8(fs.get_file_content(&repo_dir.join(REPO_CONFIG_PATH)).unwrap()).unwrap();
+        assert!(content.starts_with("[store]\ncompression = \"none\"\n"));
+        assert!(content.contains("keep_days = 30"));
+
+        set_store_branch(&fs, repo_dir, "sessions").unwrap();
+        let config = RepoConfig::load(&fs, repo_dir).unwrap();
+        assert_eq!((config.store.mode, config.store.branch()), (StoreMode::Branch, "sessions"));
+
+        set_encryption_recipients(&fs, repo_dir, &["age1a".to_string(), "age1b".to_string()]).unwrap();
+        let config = RepoConfig::load(&fs, repo_dir).unwrap();
+        assert_eq!(config.encryption.recipients, vec!["age1a".to_string(), "age1b".to_string()]);
+        assert_eq!(config.retention.keep_days, Some(30));
+        // The index would list the encrypted sessions in plain text
+        let config = RepoConfig { sync: SyncConfig { index: true, ..Default::default() }, ..config };
+        assert!(!config.writes_index());
+        assert!(RepoConfig { encryption: EncryptionConfig::default(), ..config }.writes_index());
+
+        fs.add_file(repo_dir.join(REPO_CONFIG_PATH), b"[retention]\nkeep = 1\n".to_vec(), SystemTime::now());
+        assert!(matches!(RepoConfig::load(&fs, repo_dir), Err(ConfigError::Parse { .. })));
+
+        fs.add_file(repo_dir.join(REPO_CONFIG_PATH), b"[retention]\npinned = [\"abc\"]\n".to_vec(), SystemTime::now());
+        set_pinned(&fs, repo_dir, "def", true).unwrap();
+        set_private(&fs, repo_dir, "ghi", true).unwrap();
+        set_private(&fs, repo_dir, "jkl", true).unwrap();
+        set_private(&fs, repo_dir, "ghi", false).unwrap();
+        let config = RepoConfig::load(&fs, repo_dir).unwrap();
+        assert_eq!(config.retention.pinned, vec!["abc".to_string(), "def".to_string()]);
+        assert_eq!(config.filter.private, vec!["jkl".to_string()]);
+    }
+
+    #[test]
+    fn test_load_layered() {
+        let fs = MockFileSystem::new();
+        let repo_dir = Path::new("/repo");
+        let user_config = PathBuf::from("/home/me/.config/cc-sync-session/config.toml");
+        fs.add_file(
+            user_config.clone(),
+            b"[source]\ndir = \"/home/me/projects\"\n\n[sync]\ndeduplicate = true\nindex = true\n".to_vec(),
+            SystemTime::now(),
+        );
+        fs.add_file(
+            repo_dir.join(REPO_CONFIG_PATH),
+            b"[sync]\nindex = false\nsidechains = \"nest\"\n\n[filter]\nsince = \"2025-06-01\"\n".to_vec(),
+            SystemTime::now(),
+        );
+        let env = vec![
+            ("CC_SYNC_SESSION_SYNC_SIDECHAINS".to_string(), "skip".to_string()),
+            ("CC_SYNC_SESSION_SYNC_POLICY".to_string(), "fix-and-pass".to_string()),
+            ("CC_SYNC_SESSION_RETENTION_KEEP_DAYS".to_string(), "30".to_string()),
+            ("CC_SYNC_SESSION_IDENTITY_FILE".to_string(), "/key.txt".to_string()),
+        ];
+        let layers = Layers::default()
+            .with_user_config(user_config.clone())
+            .with_env(env)
+            .with_command_line("source.dir", "/tmp/projects");
+
+        let layered = RepoConfig::load_layered(&fs, repo_dir, &layers).unwrap();
+        let config = &layered.config;
+        assert_eq!(config.source.dir(), Some(PathBuf::from("/tmp/projects")));
+        assert!(config.sync.deduplicate);
+        assert!(!config.sync.index);
+        assert_eq!(config.sync.sidechains, SidechainMode::Skip);
+        assert_eq!(config.sync.policy, SyncPolicy::FixAndPass);
+        assert_eq!(config.retention.keep_days, Some(30));
+        assert!(layered.session_filter().unwrap().since.is_some());
+
+        assert_eq!(layered.origin("source.dir"), Origin::CommandLine);
+        assert_eq!(layered.origin("sync.deduplicate"), Origin::User(user_config));
+        assert_eq!(layered.origin("sync.index"), Origin::Repo(repo_dir.join(REPO_CONFIG_PATH)));
+        assert_eq!(layered.origin("sync.sidechains"), Origin::Env("CC_SYNC_SESSION_SYNC_SIDECHAINS".to_string()));
+        assert_eq!(layered.origin("sync.git_add"), Origin::Default);
+        assert_eq!(layered.values["sync.git_add"].0, toml::Value::Boolean(false));
+
+        let layers = Layers::default().with_env(vec![("CC_SYNC_SESSION_SYNC_DEDUPLICATE".to_string(), "often".to_string())]);
+        assert!(matches!(RepoConfig::load_layered(&fs, repo_dir, &layers), Err(ConfigError::Env { .. })));
+
+        let layers = Layers::default().with_command_line("filter.until", "someday");
+        let error = RepoConfig::load_layered(&fs, repo_dir, &layers).unwrap().session_filter().unwrap_err();
+        assert!(matches!(error, ConfigError::Value { origin: Origin::CommandLine, .. }));
+    }
+}
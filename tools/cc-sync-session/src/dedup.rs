@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::session::SessionRecord;
+use crate::store::Snapshot;
+
+/// Record type of the lines that replace records stored in another session.
+pub const REFERENCE_TYPE: &str = "ccss-reference";
+
+/// Shortest run of duplicated records worth replacing with a reference.
+const MIN_RUN: usize = 2;
+
+#[derive(Debug, Error)]
+pub enum DedupError {
+    #[error("Referenced session {0} is not in the store")]
+    MissingSession(PathBuf),
+
+    #[error("Referenced records starting at {uuid} are missing from {path}")]
+    MissingRecords { path: PathBuf, uuid: String },
+
+    #[error("Invalid reference: {0}")]
+    InvalidReference(String),
+}
+
+pub type Result<T> = std::result::Result<T, DedupError>;
+
+/// A run of records that is stored in another session file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    /// Store-relative path of the session holding the records.
+    pub path: PathBuf,
+    /// uuid of the first record of the run.
+    pub uuid: String,
+    /// Number of records in the run.
+    pub count: usize,
+    /// Session id to put in place of the referenced records' own, for
+    /// resumed sessions that rewrote it.
+    pub session_id: Option<String>,
+}
+
+impl Reference {
+    pub fn parse(record: &SessionRecord) -> Option<Result<Self>> {
+        if record.record_type() != Some(REFERENCE_TYPE) {
+            return None;
+        }
+
+        let value = record.value();
+        let reference = (|| {
+            Some(Self {
+                path: PathBuf::from(value.get("path")?.as_str()?),
+                uuid: value.get("uuid")?.as_str()?.to_string(),
+                count: value.get("count")?.as_u64()? as usize,
+                session_id: value.get("sessionId").and_then(Value::as_str).map(str::to_string),
+            })
+        })();
+
+        Some(reference.ok_or_else(|| DedupError::InvalidReference(value.to_string())))
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut value = json!({
+            "type": REFERENCE_TYPE,
+            "path": self.path.to_string_lossy().replace('\\', "/"),
+            "uuid": self.uuid,
+            "count": self.count,
+        });
+        if let Some(session_id) = &self.session_id {
+            value["sessionId"] = json!(session_id);
+        }
+        value.to_string()
+    }
+}
+
+/// Returns `true` if `content` contains references to other sessions.
+pub fn has_references(content: &str) -> bool {
+    content.lines().any(|line| line.contains(REFERENCE_TYPE) && is_reference_line(line))
+}
+
+/// Store-relative paths of the sessions `content` refers to.
+pub fn referenced_paths(content: &str) -> Vec<PathBuf> {
+    content.lines()
+        .filter(|line| line.contains(REFERENCE_TYPE))
+        .filter_map(|line| SessionRecord::parse(line).ok())
+        .filter_map(|record| Reference::parse(&record)?.ok())
+        .map(|reference| reference.path)
+        .collect()
+}
+
+fn is_reference_line(line: &str) -> bool {
+    SessionRecord::parse(line).is_ok_and(|record| record.record_type() == Some(REFERENCE_TYPE))
+}
+
+/// Rewrites `line` as it appears in a session with id `session_id`.
+fn with_session_id(line: &str, session_id: &str) -> Option<String> {
+    let record = SessionRecord::parse(line).ok()?;
+    let original = record.session_id()?;
+    let from = format!("\"sessionId\":\"{}\"", original);
+    let to = format!("\"sessionId\":\"{}\"", session_id);
+
+    (line.matches(&from).count() == 1).then(|| line.replacen(&from, &to, 1))
+}
+
+/// Literal (not referenced) records of stored sessions, looked up by uuid.
+#[derive(Debug, Default)]
+pub struct RecordIndex {
+    lines: HashMap<PathBuf, Vec<String>>,
+    uuids: HashMap<String, (PathBuf, usize)>,
+}
+
+impl RecordIndex {
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let mut index = Self::default();
+        for (path, content) in snapshot {
+            index.insert(path, &String::from_utf8_lossy(content));
+        }
+        index
+    }
+
+    /// Adds (or replaces) the literal records of the session at `path`.
+    pub fn insert(&mut self, path: &Path, content: &str) {
+        self.remove(path);
+
+        let lines: Vec<String> = content.lines().map(str::to_string).collect();
+        self.index_lines(path, &lines);
+        self.lines.insert(path.to_path_buf(), lines);
+    }
+
+    fn index_lines(&mut self, path: &Path, lines: &[String]) {
+        for (position, line) in lines.iter().enumerate() {
+            let Ok(record) = SessionRecord::parse(line) else { continue };
+            if let (Some(uuid), false) = (record.uuid(), record.record_type() == Some(REFERENCE_TYPE)) {
+                self.uuids.entry(uuid.to_string()).or_insert_with(|| (path.to_path_buf(), position));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        if self.lines.remove(path).is_none() {
+            return;
+        }
+
+        // Records the removed session shadowed may be stored elsewhere too
+        self.uuids.clear();
+        let sessions: Vec<_> = self.lines.iter().map(|(path, lines)| (path.clone(), lines.clone())).collect();
+        for (path, lines) in sessions {
+            self.index_lines(&path, &lines);
+        }
+    }
+
+    /// Finds the longest run of records starting at `lines[0]` that is stored
+    /// literally in a session other than `path`.
+    fn find_run(&self, path: &Path, lines: &[&str]) -> Option<Reference> {
+        let record = SessionRecord::parse(lines[0]).ok()?;
+        let uuid = record.uuid()?;
+        let (other_path, start) = self.uuids.get(uuid)?;
+        if other_path == path {
+            return None;
+        }
+        let other_lines = &self.lines[other_path];
+
+        let session_id = if other_lines[*start] == lines[0] {
+            None
+        } else {
+            let session_id = record.session_id()?;
+            if with_session_id(&other_lines[*start], session_id).as_deref() != Some(lines[0]) {
+                return None;
+            }
+            Some(session_id.to_string())
+        };
+
+        let count = lines.iter()
+            .zip(&other_lines[*start..])
+            .take_while(|(line, other)| {
+                !is_reference_line(other) && match &session_id {
+                    None => line == other,
+                    Some(session_id) => with_session_id(other, session_id).as_deref() == Some(**line),
+                }
+            })
+            .count();
+
+        (count >= MIN_RUN).then(|| Reference {
+            path: other_path.clone(),
+            uuid: uuid.to_string(),
+            count,
+            session_id,
+        })
+    }
+
+    /// Replaces runs of records of the session at `path` that are stored in
+    /// other sessions with references to them.
+    ///
+    /// Returns `None` if nothing is duplicated.
+    pub fn deduplicate(&self, path: &Path, content: &str) -> Option<String> {
+        let lines: Vec<&str> = content.lines().collect();
+        let mut output = String::new();
+        let mut replaced = false;
+        let mut position = 0;
+
+        while position < lines.len() {
+            match self.find_run(path, &lines[position..]) {
+                Some(reference) => {
+                    output.push_str(&reference.to_line());
+                    position += reference.count;
+                    replaced = true;
+                }
+                None => {
+                    output.push_str(lines[position]);
+                    position += 1;
+                }
+            }
+            output.push('\n');
+        }
+
+        replaced.then_some(output)
+    }
+}
+
+/// Rebuilds the full transcript of a deduplicated session.
+///
+/// `read_session` returns the stored content of a store-relative path.
+pub fn expand(content: &str, read_session: &dyn Fn(&Path) -> Option<String>) -> Result<String> {
+    let mut output = String::new();
+
+    for line in content.lines() {
+        let reference = match SessionRecord::parse(line).ok().as_ref().and_then(Reference::parse) {
+            Some(reference) => reference?,
+            None => {
+                output.push_str(line);
+                output.push('\n');
+                continue;
+            }
+        };
+
+        let other = read_session(&reference.path)
+            .ok_or_else(|| DedupError::MissingSession(reference.path.clone()))?;
+        let missing = || DedupError::MissingRecords { path: reference.path.clone(), uuid: reference.uuid.clone() };
+
+        let other_lines: Vec<&str> = other.lines().collect();
+        let start = other_lines.iter()
+            .position(|other| {
+                SessionRecord::parse(other).is_ok_and(|record| {
+                    record.uuid() == Some(reference.uuid.as_str()) && record.record_type() != Some(REFERENCE_TYPE)
+                })
+            })
+            .ok_or_else(missing)?;
+        let run = other_lines.get(start..start + reference.count).ok_or_else(missing)?;
+
+        for other_line in run {
+            let line = match &reference.session_id {
+                Some(session_id) => with_session_id(other_line, session_id).ok_or_else(missing)?,
+                None => other_line.to_string(),
+            };
+            output.push_str(&line);
+            output.push('\n');
+        }
+    }
+
+    Ok(output)
+}
+
+/// Expands every deduplicated session of `snapshot` using the other sessions
+/// of the same snapshot.
+pub fn expand_snapshot(snapshot: &Snapshot) -> Result<Snapshot> {
+    let read_session = |path: &Path| {
+        snapshot.get(path).map(|content| String::from_utf8_lossy(content).into_owned())
+    };
+
+    snapshot.iter()
+        .map(|(path, content)| {
+            let content = String::from_utf8_lossy(content);
+            if has_references(&content) {
+                Ok((path.clone(), expand(&content, &read_session)?.into_bytes()))
+            } else {
+                Ok((path.clone(), content.into_owned().into_bytes()))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(uuid: &str, session: &str, text: &str) -> String {
+        format!(
+            r#"{{"type":"user","uuid":"{}","sessionId":"{}","message":{{"role":"user","content":"{}"}}}}"#,
+            uuid, session, text
+        )
+    }
+
+    fn jsonl(lines: &[String]) -> String {
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    #[test]
+    fn test_deduplicate_resumed_prefix() {
+        let original = jsonl(&[record("a", "s1", "one"), record("b", "s1", "two"), record("c", "s1", "three")]);
+        let resumed = jsonl(&[
+            record("a", "s2", "one"),
+            record("b", "s2", "two"),
+            record("c", "s2", "three"),
+            record("d", "s2", "four"),
+        ]);
+
+        let snapshot = Snapshot::from([
+            (PathBuf::from("p/s1.jsonl"), original.clone().into_bytes()),
+        ]);
+        let index = RecordIndex::from_snapshot(&snapshot);
+
+        let deduplicated = index.deduplicate(Path::new("p/s2.jsonl"), &resumed).unwrap();
+        assert_eq!(deduplicated.lines().count(), 2);
+        assert!(has_references(&deduplicated));
+        assert!(index.deduplicate(Path::new("p/s1.jsonl"), &original).is_none());
+
+        let mut stored = snapshot.clone();
+        stored.insert(PathBuf::from("p/s2.jsonl"), deduplicated.into_bytes());
+        let expanded = expand_snapshot(&stored).unwrap();
+
+        assert_eq!(expanded[Path::new("p/s2.jsonl")], resumed.into_bytes());
+        assert_eq!(expanded[Path::new("p/s1.jsonl")], original.into_bytes());
+    }
+
+    #[test]
+    fn test_expand_reports_missing_session() {
+        let reference = Reference {
+            path: PathBuf::from("p/gone.jsonl"),
+            uuid: "a".to_string(),
+            count: 2,
+            session_id: None,
+        };
+
+        let result = expand(&format!("{}\n", reference.to_line()), &|_| None);
+        assert!(matches!(result, Err(DedupError::MissingSession(_))));
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::PathBuf;
+
+use crate::session::{parse_records_lossy, SessionRecord};
+use crate::sidechain::{link_sidechains, SidechainLink};
+use crate::store::Snapshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Removed,
+}
+
+impl ChangeKind {
+    /// One letter status, as in `git diff --name-status`.
+    pub fn letter(&self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Modified => 'M',
+            ChangeKind::Removed => 'D',
+        }
+    }
+}
+
+/// What happened in the turns a session gained.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TurnSummary {
+    pub user_prompts: Vec<String>,
+    pub assistant_replies: usize,
+    pub tool_calls: BTreeMap<String, usize>,
+    pub files_edited: BTreeSet<String>,
+}
+
+impl TurnSummary {
+    pub fn add_record(&mut self, record: &SessionRecord) {
+        if let Some(prompt) = record.user_prompt() {
+            self.user_prompts.push(prompt);
+        }
+        if record.assistant_text().is_some() {
+            self.assistant_replies += 1;
+        }
+        for tool in record.tool_uses() {
+            *self.tool_calls.entry(tool.name.to_string()).or_default() += 1;
+        }
+        self.files_edited.extend(record.edited_files());
+    }
+
+    pub fn total_tool_calls(&self) -> usize {
+        self.tool_calls.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.user_prompts.is_empty()
+            && self.assistant_replies == 0
+            && self.tool_calls.is_empty()
+            && self.files_edited.is_empty()
+    }
+}
+
+/// A session that differs between two snapshots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionChange {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    pub summary: TurnSummary,
+    /// Parent session and spawning tool call, for subagent sidechains.
+    pub sidechain: Option<SidechainLink>,
+}
+
+/// Records of `new` that do not appear in `old`.
+///
+/// Records are matched by `uuid`; records without one (such as summaries) are
+/// matched by their full content.
+pub fn new_records<'a>(old: &[SessionRecord], new: &'a [SessionRecord]) -> Vec<&'a SessionRecord> {
+    let old_uuids: HashSet<&str> = old.iter().filter_map(SessionRecord::uuid).collect();
+    let old_anonymous: Vec<&SessionRecord> = old.iter().filter(|record| record.uuid().is_none()).collect();
+
+    new.iter()
+        .filter(|record| match record.uuid() {
+            Some(uuid) => !old_uuids.contains(uuid),
+            None => !old_anonymous.contains(record),
+        })
+        .collect()
+}
+
+/// Summarizes the turns `new` gained over `old`.
+pub fn summarize_new_turns(old: &[SessionRecord], new: &[SessionRecord]) -> TurnSummary {
+    let mut summary = TurnSummary::default();
+    for record in new_records(old, new) {
+        summary.add_record(record);
+    }
+    summary
+}
+
+/// Compares two snapshots session by session.
+///
+/// Sidechains are listed right after their parent session when it changed too.
+pub fn diff_snapshots(old: &Snapshot, new: &Snapshot) -> Vec<SessionChange> {
+    let paths: BTreeSet<&PathBuf> = old.keys().chain(new.keys()).collect();
+    let mut links = link_sidechains(old);
+    links.extend(link_sidechains(new));
+    let mut changes = Vec::new();
+
+    for path in paths {
+        let (kind, old_records, new_records) = match (old.get(path), new.get(path)) {
+            (Some(old_content), Some(new_content)) if old_content == new_content => continue,
+            (Some(old_content), Some(new_content)) => (ChangeKind::Modified, parse(old_content), parse(new_content)),
+            (None, Some(new_content)) => (ChangeKind::Added, Vec::new(), parse(new_content)),
+            (Some(_), None) => (ChangeKind::Removed, Vec::new(), Vec::new()),
+            (None, None) => continue,
+        };
+
+        changes.push(SessionChange {
+            path: path.clone(),
+            kind,
+            summary: summarize_new_turns(&old_records, &new_records),
+            sidechain: links.get(path).cloned(),
+        });
+    }
+
+    group_sidechains(changes)
+}
+
+fn group_sidechains(changes: Vec<SessionChange>) -> Vec<SessionChange> {
+    let changed: BTreeSet<PathBuf> = changes.iter().map(|change| change.path.clone()).collect();
+    let parent_of = |change: &SessionChange| {
+        change.sidechain.as_ref()
+            .and_then(|link| link.parent_path.clone())
+            .filter(|parent| changed.contains(parent))
+    };
+
+    let (nested, top_level): (Vec<_>, Vec<_>) = changes.into_iter().partition(|change| parent_of(change).is_some());
+    let mut grouped = Vec::new();
+    for change in top_level {
+        let path = change.path.clone();
+        grouped.push(change);
+        grouped.extend(nested.iter().filter(|sidechain| parent_of(sidechain).as_ref() == Some(&path)).cloned());
+    }
+    grouped
+}
+
+fn parse(content: &[u8]) -> Vec<SessionRecord> {
+    parse_records_lossy(&String::from_utf8_lossy(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(uuid: &str, prompt: &str) -> String {
+        format!(r#"{{"type":"user","uuid":"{}","message":{{"role":"user","content":"{}"}}}}"#, uuid, prompt)
+    }
+
+    fn assistant(uuid: &str, tool: &str) -> String {
+        format!(
+            r#"{{"type":"assistant","uuid":"{}","cwd":"/repo","message":{{"role":"assistant","content":[{{"type":"text","text":"ok"}},{{"type":"tool_use","id":"t","name":"{}","input":{{"file_path":"/repo/a.rs"}}}}]}}}}"#,
+            uuid, tool
+        )
+    }
+
+    #[test]
+    fn test_diff_snapshots_summarizes_new_turns() {
+        let first = format!("{}\n{}\n", user("u1", "first"), assistant("a1", "Read"));
+        let second = format!("{}{}\n{}\n", first, user("u2", "second"), assistant("a2", "Edit"));
+
+        let old = Snapshot::from([
+            (PathBuf::from("p/s1.jsonl"), first.clone().into_bytes()),
+            (PathBuf::from("p/gone.jsonl"), first.clone().into_bytes()),
+            (PathBuf::from("p/same.jsonl"), first.clone().into_bytes()),
+        ]);
+        let new = Snapshot::from([
+            (PathBuf::from("p/s1.jsonl"), second.into_bytes()),
+            (PathBuf::from("p/s2.jsonl"), first.clone().into_bytes()),
+            (PathBuf::from("p/same.jsonl"), first.into_bytes()),
+        ]);
+
+        let changes = diff_snapshots(&old, &new);
+        let kinds: Vec<_> = changes.iter().map(|change| (change.path.clone(), change.kind)).collect();
+        assert_eq!(kinds, vec![
+            (PathBuf::from("p/gone.jsonl"), ChangeKind::Removed),
+            (PathBuf::from("p/s1.jsonl"), ChangeKind::Modified),
+            (PathBuf::from("p/s2.jsonl"), ChangeKind::Added),
+        ]);
+
+        let modified = &changes[1].summary;
+        assert_eq!(modified.user_prompts, vec!["second".to_string()]);
+        assert_eq!(modified.assistant_replies, 1);
+        assert_eq!(modified.tool_calls, BTreeMap::from([("Edit".to_string(), 1)]));
+        assert_eq!(modified.files_edited, BTreeSet::from(["a.rs".to_string()]));
+
+        let added = &changes[2].summary;
+        assert_eq!(added.user_prompts, vec!["first".to_string()]);
+        assert!(added.files_edited.is_empty());
+    }
+}
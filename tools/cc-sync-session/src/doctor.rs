@@ -0,0 +1,475 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use git2::Repository;
+use serde::Serialize;
+
+use crate::file_path_converter::{claude_code_stype_to_file_path, dir_path_to_claude_code_stype};
+use crate::filesystem::FileSystem;
+use crate::hooks::{self, Hook, HookState};
+use crate::store::STORE_PATH;
+use crate::sync::is_session_file;
+use crate::verify;
+
+/// Most near-miss project directories listed for a missing one.
+const MAX_NEAR_MISSES: usize = 5;
+
+/// Most edits between the expected and an existing project directory name
+/// for it to count as similar.
+const MAX_EDIT_DISTANCE: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// The outcome of one check, with how to fix it if it is not ok.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub status: Status,
+    /// What was checked: `config`, `source`, `project`, `store`,
+    /// `gitattributes`, `lfs`, `hooks`, `gitignore` or `permissions`.
+    pub check: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+impl Finding {
+    pub fn ok(check: &'static str, message: impl Into<String>) -> Self {
+        Self { status: Status::Ok, check, message: message.into(), details: Vec::new(), fix: None }
+    }
+
+    pub fn warning(check: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { status: Status::Warning, check, message: message.into(), details: Vec::new(), fix: Some(fix.into()) }
+    }
+
+    pub fn error(check: &'static str, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { status: Status::Error, check, message: message.into(), details: Vec::new(), fix: Some(fix.into()) }
+    }
+
+    pub fn with_details(mut self, details: Vec<String>) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DoctorReport {
+    pub findings: Vec<Finding>,
+}
+
+impl DoctorReport {
+    pub fn errors(&self) -> usize {
+        self.findings.iter().filter(|finding| finding.status == Status::Error).count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.findings.iter().filter(|finding| finding.status == Status::Warning).count()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.errors() == 0
+    }
+}
+
+/// How a project directory name is close to the one `sync` looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NearMissKind {
+    /// Only letter case differs, e.g. on a case-insensitive file system.
+    Case,
+    /// Claude Code also turned characters such as `_` or spaces into `-`.
+    Punctuation,
+    /// A few characters differ.
+    Similar,
+    /// The last path segment is the same, e.g. another checkout.
+    SameName,
+}
+
+impl NearMissKind {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            NearMissKind::Case => "differs in case",
+            NearMissKind::Punctuation => "Claude Code turned more characters than '/' and '.' into '-'",
+            NearMissKind::Similar => "similar name",
+            NearMissKind::SameName => "same directory name at another path",
+        }
+    }
+}
+
+/// A project directory whose name is close to the one `sync` looks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NearMiss {
+    pub name: String,
+    pub kind: NearMissKind,
+}
+
+/// Claude Code turns every character of the path that is not a letter or
+/// digit into `-`, so compare names that way.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The names in `names` that are probably meant by `expected`, the closest first.
+pub fn near_misses(expected: &str, names: &[String]) -> Vec<NearMiss> {
+    let last_segment = expected.rsplit('-').find(|segment| !segment.is_empty()).unwrap_or_default();
+    let mut near_misses: Vec<NearMiss> = names.iter()
+        .filter(|name| name.as_str() != expected)
+        .filter_map(|name| {
+            let kind = if name.to_lowercase() == expected.to_lowercase() {
+                NearMissKind::Case
+            } else if normalize(name) == normalize(expected) {
+                NearMissKind::Punctuation
+            } else if edit_distance(name, expected) <= MAX_EDIT_DISTANCE {
+                NearMissKind::Similar
+            } else if !last_segment.is_empty() && name.ends_with(&format!("-{}", last_segment)) {
+                NearMissKind::SameName
+            } else {
+                return None;
+            };
+            Some(NearMiss { name: name.clone(), kind })
+        })
+        .collect();
+
+    near_misses.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.name.cmp(&b.name)));
+    near_misses.truncate(MAX_NEAR_MISSES);
+    near_misses
+}
+
+/// The directory Claude Code was started in for the project directory
+/// `name`: a sibling of `repo_dir`, or the path the name decodes to.
+fn find_project_path(name: &str, repo_dir: &Path) -> Option<PathBuf> {
+    let siblings = repo_dir.parent()
+        .and_then(|parent| fs::read_dir(parent).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()));
+    siblings.chain(claude_code_stype_to_file_path(name))
+        .find(|path| path.is_dir() && dir_path_to_claude_code_stype(path.clone()).is_ok_and(|encoded| encoded == name))
+}
+
+/// Checks that `source_root` exists and has the project directory of
+/// `repo_dir`, listing near misses if it does not.
+pub fn check_source<FS: FileSystem>(fs: &FS, source_root: &Path, repo_dir: &Path) -> Vec<Finding> {
+    if !fs.exists(source_root).unwrap_or(false) {
+        return vec![Finding::error(
+            "source",
+            format!("Source directory {} does not exist", source_root.display()),
+            "Set [source] dir in .claude/ccss.toml or the user configuration, or CC_SYNC_SESSION_SOURCE_DIR, \
+             to where Claude Code keeps its projects (~/.claude/projects unless CLAUDE_CONFIG_DIR is set)",
+        )];
+    }
+    let mut findings = vec![Finding::ok("source", format!("Source directory {}", source_root.display()))];
+
+    let expected = match dir_path_to_claude_code_stype(repo_dir.to_path_buf()) {
+        Ok(expected) => expected,
+        Err(e) => {
+            findings.push(Finding::error(
+                "project",
+                format!("Cannot compute the project directory of {}: {}", repo_dir.display(), e),
+                "Pass the repository as an absolute path with --repo-dir",
+            ));
+            return findings;
+        }
+    };
+    let entries = match fs.list_directory(source_root) {
+        Ok(entries) => entries,
+        Err(e) => {
+            findings.push(Finding::error(
+                "permissions",
+                format!("Cannot read {}: {}", source_root.display(), e),
+                format!("Make {} readable by this user", source_root.display()),
+            ));
+            return findings;
+        }
+    };
+    let names: Vec<String> = entries.iter()
+        .filter(|entry| entry.is_directory)
+        .filter_map(|entry| entry.path.file_name().map(|name| name.to_string_lossy().into_owned()))
+        .collect();
+
+    if names.contains(&expected) {
+        let project_dir = source_root.join(&expected);
+        let sessions = fs.walk_files(&project_dir)
+            .map(|files| files.iter().filter(|file| is_session_file(&file.path)).count())
+            .unwrap_or_default();
+        findings.push(Finding::ok("project", format!("Project directory {} with {} session files", expected, sessions)));
+        return findings;
+    }
+
+    let near_misses = near_misses(&expected, &names);
+    let mut details = vec![format!("expected {} (from {})", expected, repo_dir.display())];
+    details.extend(near_misses.iter().map(|near_miss| format!("found    {} ({})", near_miss.name, near_miss.kind.reason())));
+    let fix = match near_misses.first() {
+        Some(near_miss) if near_miss.kind == NearMissKind::Case => format!(
+            "Claude Code was started in {} spelled in another case; start it and cc-sync-session from the same spelling",
+            repo_dir.display()
+        ),
+        Some(near_miss) if near_miss.kind == NearMissKind::Punctuation => format!(
+            "Rename {} so that its path only has letters, digits, '/', '.' and '-'; sync cannot find its sessions otherwise",
+            repo_dir.display()
+        ),
+        Some(near_miss) => {
+            let checkout = find_project_path(&near_miss.name, repo_dir)
+                .map_or_else(|| "<that checkout>".to_string(), |path| path.display().to_string());
+            format!(
+                "If Claude Code ran in another checkout of this repository, sync from there with \
+                 'cc-sync-session sync --repo-dir {}'; otherwise start Claude Code in {}",
+                checkout,
+                repo_dir.display()
+            )
+        }
+        None => format!(
+            "Start Claude Code in {} once; its sessions go to {}",
+            repo_dir.display(),
+            source_root.join(&expected).display()
+        ),
+    };
+    findings.push(Finding::error(
+        "project",
+        format!("No project directory {} in {}", expected, source_root.display()),
+        fix,
+    ).with_details(details));
+    findings
+}
+
+/// Checks that `.gitattributes` routes the store through Git LFS, that
+/// Git LFS is set up, and that the drivers it names are configured.
+pub fn check_gitattributes(repo: &Repository, repo_dir: &Path) -> Vec<Finding> {
+    let content = fs::read_to_string(repo_dir.join(".gitattributes")).ok();
+    let mut findings: Vec<Finding> = verify::check_gitattributes(content.as_deref()).into_iter()
+        .chain(verify::check_drivers(repo, content.as_deref().unwrap_or_default()))
+        .map(|finding| Finding::warning("gitattributes", finding.message, "Run 'cc-sync-session init' with the same options"))
+        .collect();
+    if !findings.is_empty() {
+        return findings;
+    }
+    findings.push(Finding::ok("gitattributes", ".gitattributes stores sessions through Git LFS"));
+
+    let lfs_installed = Command::new("git").args(["lfs", "version"]).output()
+        .is_ok_and(|output| output.status.success());
+    if !lfs_installed {
+        findings.push(Finding::warning(
+            "lfs",
+            "Git LFS is not installed; sessions would be committed as regular files",
+            "Install Git LFS (https://git-lfs.com) and run 'git lfs install'",
+        ));
+    } else if repo.config().and_then(|config| config.get_string("filter.lfs.clean")).is_err() {
+        findings.push(Finding::warning(
+            "lfs",
+            "Git LFS is installed but its filters are not configured",
+            "Run 'git lfs install'",
+        ));
+    } else {
+        findings.push(Finding::ok("lfs", "Git LFS is set up"));
+    }
+    findings
+}
+
+/// Checks that the store is not ignored by git; a worktree store that is
+/// ignored is never committed.
+pub fn check_ignored(repo: &Repository) -> Finding {
+    let session_path = Path::new(STORE_PATH).join("session.jsonl");
+    match repo.status_should_ignore(&session_path) {
+        Ok(true) => Finding::error(
+            "gitignore",
+            format!("{} is ignored by git, so synced sessions are never committed", STORE_PATH),
+            format!(
+                "Run 'git check-ignore -v {}' to find the rule, and exclude {}/ from it (e.g. '!{}/' after it)",
+                session_path.display(),
+                STORE_PATH,
+                STORE_PATH
+            ),
+        ),
+        Ok(false) => Finding::ok("gitignore", format!("{} is not ignored by git", STORE_PATH)),
+        Err(e) => Finding::warning("gitignore", format!("Cannot check ignore rules: {}", e), "Check that the repository is readable"),
+    }
+}
+
+/// Whether an executable named `name` is on `PATH`.
+pub fn find_on_path(name: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Checks that the hook syncing sessions is installed: `pre-commit` for a
+/// worktree store, `post-commit` for a store on a branch.
+pub fn check_hooks(repo: &Repository, branch_store: bool) -> Vec<Finding> {
+    let hooks_dir = match hooks::hooks_dir(repo) {
+        Ok(hooks_dir) => hooks_dir,
+        Err(e) => return vec![Finding::warning("hooks", format!("Cannot read core.hooksPath: {}", e), "Fix the git configuration")],
+    };
+    let hook = if branch_store { Hook::PostCommit } else { Hook::PreCommit };
+    let install = format!("Run 'cc-sync-session hooks install' (an existing {} hook is kept and chained to)", hook);
+
+    let mut findings = Vec::new();
+    match hooks::state(&hooks_dir, hook) {
+        Ok(HookState::Installed { .. }) => {
+            if !is_executable(&hooks_dir.join(hook.name())) {
+                findings.push(Finding::error(
+                    "hooks",
+                    format!("The {} hook is installed but not executable, so git skips it", hook),
+                    format!("Run 'chmod +x {}'", hooks_dir.join(hook.name()).display()),
+                ));
+            } else {
+                findings.push(Finding::ok("hooks", format!("The {} hook is installed in {}", hook, hooks_dir.display())));
+            }
+            if find_on_path("cc-sync-session").is_none() {
+                findings.push(Finding::error(
+                    "hooks",
+                    "The hooks run cc-sync-session, but it is not on PATH",
+                    "Install it with 'cargo install --path tools/cc-sync-session', or add its directory to PATH",
+                ));
+            }
+        }
+        Ok(HookState::Foreign) => {
+            let content = fs::read_to_string(hooks_dir.join(hook.name())).unwrap_or_default();
+            if content.contains("cc-sync-session") || uses_pre_commit_framework(repo, &content) {
+                findings.push(Finding::ok("hooks", format!("The {} hook runs cc-sync-session", hook)));
+            } else {
+                findings.push(Finding::warning("hooks", format!("The {} hook does not run cc-sync-session", hook), install));
+            }
+        }
+        Ok(HookState::Missing) => {
+            findings.push(Finding::warning(
+                "hooks",
+                format!("No {} hook in {}; sessions are only synced when 'sync' is run by hand", hook, hooks_dir.display()),
+                install,
+            ));
+        }
+        Err(e) => findings.push(Finding::warning("hooks", format!("Cannot read the {} hook: {}", hook, e), "Check the permissions of the hooks directory")),
+    }
+    findings
+}
+
+/// Whether `hook_content` is the `pre-commit` framework's hook and the
+/// repository's `.pre-commit-config.yaml` uses cc-sync-session.
+fn uses_pre_commit_framework(repo: &Repository, hook_content: &str) -> bool {
+    if !hook_content.contains("pre-commit") {
+        return false;
+    }
+    let Some(workdir) = repo.workdir() else { return false };
+    fs::read_to_string(workdir.join(".pre-commit-config.yaml"))
+        .is_ok_and(|config| config.contains("sync-claude-code-sessions"))
+}
+
+/// Checks that files can be created in `dir`.
+pub fn check_writable(check: &'static str, dir: &Path) -> Finding {
+    match tempfile::Builder::new().prefix(".ccss-doctor").tempfile_in(dir) {
+        Ok(_) => Finding::ok(check, format!("{} is writable", dir.display())),
+        Err(e) => Finding::error(
+            check,
+            format!("Cannot write to {}: {}", dir.display(), e),
+            format!("Make {} writable by this user (e.g. 'chmod u+w {}')", dir.display(), dir.display()),
+        ),
+    }
+}
+
+/// Label of a status in the `doctor` report.
+pub fn label(status: Status) -> &'static str {
+    match status {
+        Status::Ok => "ok",
+        Status::Warning => "warning",
+        Status::Error => "error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockFileSystem;
+    use std::time::SystemTime;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_near_misses() {
+        let near_misses = near_misses("-home-me-my-repo", &names(&[
+            "-home-me-other",
+            "-home-me-my-repo",
+            "-home-me-my-rep",
+            "-mnt-data-my-repo",
+            "-Home-Me-My-Repo",
+        ]));
+        assert_eq!(near_misses.iter().map(|near_miss| near_miss.name.as_str()).collect::<Vec<_>>(), [
+            "-Home-Me-My-Repo",
+            "-home-me-my-rep",
+            "-mnt-data-my-repo",
+        ]);
+        assert_eq!(near_misses[0].kind, NearMissKind::Case);
+
+        // Claude Code writes '-home-me-my-repo' for /home/me/my_repo
+        let near_misses = super::near_misses("-home-me-my_repo", &names(&["-home-me-my-repo"]));
+        assert_eq!(near_misses[0].kind, NearMissKind::Punctuation);
+    }
+
+    #[test]
+    fn test_check_source() {
+        let fs = MockFileSystem::new();
+        let source_root = Path::new("/home/me/.claude/projects");
+        let findings = check_source(&fs, source_root, Path::new("/work/repo"));
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].status, Status::Error);
+
+        fs.add_directory(source_root);
+        fs.add_directory(source_root.join("-work-repo2"));
+        let findings = check_source(&fs, source_root, Path::new("/work/repo"));
+        assert_eq!(findings[1].status, Status::Error);
+        assert_eq!(findings[1].details, [
+            "expected -work-repo (from /work/repo)",
+            "found    -work-repo2 (similar name)",
+        ]);
+
+        fs.add_directory(source_root.join("-work-repo"));
+        fs.add_file(source_root.join("-work-repo/s1.jsonl"), b"{}\n".to_vec(), SystemTime::now());
+        let findings = check_source(&fs, source_root, Path::new("/work/repo"));
+        assert_eq!(findings[1], Finding::ok("project", "Project directory -work-repo with 1 session files"));
+    }
+
+    #[test]
+    fn test_check_ignored() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let repo = Repository::init(temp.path()).unwrap();
+        assert_eq!(check_ignored(&repo).status, Status::Ok);
+
+        fs::write(temp.path().join(".gitignore"), ".claude/\n").unwrap();
+        assert_eq!(check_ignored(&repo).status, Status::Error);
+    }
+}
@@ -0,0 +1,302 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use age::secrecy::ExposeSecret;
+use age::x25519;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Extension appended to encrypted session files: `<session>.jsonl[.zst].age`.
+pub const ENCRYPTED_EXTENSION: &str = "age";
+
+/// Environment variable listing identity files, separated like `PATH`.
+pub const IDENTITY_FILE_ENV: &str = "CC_SYNC_SESSION_IDENTITY_FILE";
+
+/// Header line of every binary age file.
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1\n";
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("Invalid recipient '{recipient}': {reason}")]
+    InvalidRecipient { recipient: String, reason: &'static str },
+
+    #[error("Invalid identity on line {line} of {path}: {reason}")]
+    InvalidIdentity { path: PathBuf, line: usize, reason: &'static str },
+
+    #[error("Cannot read identity file {path}: {source}")]
+    IdentityFile { path: PathBuf, source: io::Error },
+
+    #[error("No identity can decrypt the session; pass --identity or set ${}", IDENTITY_FILE_ENV)]
+    NoIdentity,
+
+    #[error(transparent)]
+    Encrypt(#[from] age::EncryptError),
+
+    #[error(transparent)]
+    Decrypt(#[from] age::DecryptError),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, EncryptionError>;
+
+/// The `[encryption]` table of the repo config.
+///
+/// With recipients, `sync` encrypts every session file to all of them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// age X25519 public keys (`age1...`) of the team members.
+    pub recipients: Vec<String>,
+}
+
+impl EncryptionConfig {
+    /// The encryption to write the store with, or `None` without recipients.
+    pub fn encryption(&self) -> Result<Option<Encryption>> {
+        if self.recipients.is_empty() {
+            return Ok(None);
+        }
+        Encryption::new(&self.recipients).map(Some)
+    }
+}
+
+/// Encrypts session files to a set of X25519 recipients.
+#[derive(Debug, Clone)]
+pub struct Encryption {
+    recipients: Vec<x25519::Recipient>,
+}
+
+impl PartialEq for Encryption {
+    fn eq(&self, other: &Self) -> bool {
+        self.recipients == other.recipients
+    }
+}
+
+impl Encryption {
+    pub fn new<S: AsRef<str>>(recipients: &[S]) -> Result<Self> {
+        let recipients = recipients.iter()
+            .map(|recipient| {
+                let recipient = recipient.as_ref().trim();
+                recipient.parse().map_err(|reason| EncryptionError::InvalidRecipient {
+                    recipient: recipient.to_string(),
+                    reason,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { recipients })
+    }
+
+    pub fn recipients(&self) -> &[x25519::Recipient] {
+        &self.recipients
+    }
+
+    pub fn encrypt(&self, content: &[u8]) -> Result<Vec<u8>> {
+        let encryptor = age::Encryptor::with_recipients(
+            self.recipients.iter().map(|recipient| recipient as &dyn age::Recipient),
+        )?;
+        let mut encrypted = Vec::with_capacity(content.len() + 256);
+        let mut writer = encryptor.wrap_output(&mut encrypted)?;
+        writer.write_all(content)?;
+        writer.finish()?;
+        Ok(encrypted)
+    }
+}
+
+/// Path an encrypted file of `path` is stored at.
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(ENCRYPTED_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Returns `true` if the file name of `path` marks it as encrypted.
+pub fn is_encrypted_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == ENCRYPTED_EXTENSION)
+}
+
+/// Returns `true` if `content` is an age file.
+pub fn is_encrypted(content: &[u8]) -> bool {
+    content.starts_with(AGE_MAGIC)
+}
+
+/// Decrypts `content` with one of `identities`.
+pub fn decrypt_with(content: &[u8], identities: &[x25519::Identity]) -> Result<Vec<u8>> {
+    if identities.is_empty() {
+        return Err(EncryptionError::NoIdentity);
+    }
+
+    let decryptor = age::Decryptor::new_buffered(content)?;
+    let mut reader = decryptor.decrypt(identities.iter().map(|identity| identity as &dyn age::Identity))
+        .map_err(|e| match e {
+            age::DecryptError::NoMatchingKeys => EncryptionError::NoIdentity,
+            e => e.into(),
+        })?;
+    let mut decrypted = Vec::new();
+    reader.read_to_end(&mut decrypted)?;
+    Ok(decrypted)
+}
+
+/// The identities encrypted sessions are decrypted with: those of identity
+/// files, plus any given directly.
+///
+/// The files are only read the first time a session is decrypted, so commands
+/// that never meet an encrypted session do not need them.
+#[derive(Clone, Default)]
+pub struct Identities {
+    files: Vec<PathBuf>,
+    given: Vec<x25519::Identity>,
+    read: OnceLock<Vec<x25519::Identity>>,
+}
+
+impl fmt::Debug for Identities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identities")
+            .field("files", &self.files)
+            .field("given", &self.given.len())
+            .finish()
+    }
+}
+
+impl Identities {
+    pub fn new(identities: Vec<x25519::Identity>) -> Self {
+        Self { given: identities, ..Default::default() }
+    }
+
+    /// The identities of the files in `$CC_SYNC_SESSION_IDENTITY_FILE`, or of
+    /// the default identity file.
+    pub fn local() -> Self {
+        Self { files: identity_file_paths(), ..Default::default() }
+    }
+
+    /// Adds the identities of the file at `path`, e.g. one given with `--identity`.
+    pub fn with_file(mut self, path: &Path) -> Result<Self> {
+        self.given.extend(read_identity_file(path)?);
+        Ok(self)
+    }
+
+    /// Decrypts `content` with one of the identities.
+    pub fn decrypt(&self, content: &[u8]) -> Result<Vec<u8>> {
+        let read = self.read.get_or_init(|| {
+            let mut identities = Vec::new();
+            for path in &self.files {
+                match read_identity_file(path) {
+                    Ok(read) => identities.extend(read),
+                    Err(EncryptionError::IdentityFile { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+                        log::debug!("No identity file at {}", path.display());
+                    }
+                    Err(e) => log::warn!("{}", e),
+                }
+            }
+            identities
+        });
+        let identities: Vec<x25519::Identity> = read.iter().chain(&self.given).cloned().collect();
+        decrypt_with(content, &identities)
+    }
+}
+
+/// Where identities are looked for: `$CC_SYNC_SESSION_IDENTITY_FILE`, or the
+/// default identity file.
+pub fn identity_file_paths() -> Vec<PathBuf> {
+    match std::env::var_os(IDENTITY_FILE_ENV) {
+        Some(paths) => std::env::split_paths(&paths).collect(),
+        None => default_identity_path().into_iter().collect(),
+    }
+}
+
+/// `cc-sync-session/identity.txt` in the user's configuration directory
+/// (`~/.config` on Linux).
+pub fn default_identity_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("cc-sync-session").join("identity.txt"))
+}
+
+/// Reads the X25519 identities (`AGE-SECRET-KEY-1...` lines) of an age
+/// identity file, as written by `age-keygen` or [`generate_identity`].
+pub fn read_identity_file(path: &Path) -> Result<Vec<x25519::Identity>> {
+    let content = fs::read_to_string(path)
+        .map_err(|source| EncryptionError::IdentityFile { path: path.to_path_buf(), source })?;
+
+    content.lines()
+        .enumerate()
+        .map(|(number, line)| (number + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, key)| key.parse().map_err(|reason| EncryptionError::InvalidIdentity {
+            path: path.to_path_buf(),
+            line,
+            reason,
+        }))
+        .collect()
+}
+
+/// Writes a new identity to the file at `path`, which must not exist, and
+/// returns its public key.
+pub fn generate_identity(path: &Path) -> Result<x25519::Recipient> {
+    let identity = x25519::Identity::generate();
+    let recipient = identity.to_public();
+
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::File::options();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)
+        .map_err(|source| EncryptionError::IdentityFile { path: path.to_path_buf(), source })?;
+    writeln!(file, "# created: {}", chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true))?;
+    writeln!(file, "# public key: {}", recipient)?;
+    writeln!(file, "{}", identity.to_string().expose_secret())?;
+
+    Ok(recipient)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let alice = x25519::Identity::generate();
+        let bob = x25519::Identity::generate();
+        let encryption = Encryption::new(&[alice.to_public().to_string(), bob.to_public().to_string()]).unwrap();
+        let content = b"{\"type\":\"user\"}\n";
+
+        let encrypted = encryption.encrypt(content).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(decrypt_with(&encrypted, std::slice::from_ref(&bob)).unwrap(), content);
+        assert_eq!(Identities::new(vec![bob]).decrypt(&encrypted).unwrap(), content);
+        assert!(matches!(Identities::default().decrypt(&encrypted), Err(EncryptionError::NoIdentity)));
+        assert!(matches!(
+            decrypt_with(&encrypted, &[x25519::Identity::generate()]),
+            Err(EncryptionError::NoIdentity)
+        ));
+        assert!(matches!(Encryption::new(&["age1nope"]), Err(EncryptionError::InvalidRecipient { .. })));
+    }
+
+    #[test]
+    fn test_identity_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys/identity.txt");
+
+        let recipient = generate_identity(&path).unwrap();
+        let identities = read_identity_file(&path).unwrap();
+        assert_eq!(identities.len(), 1);
+        assert_eq!(identities[0].to_public(), recipient);
+        assert!(generate_identity(&path).is_err());
+
+        let encrypted = Encryption::new(&[recipient.to_string()]).unwrap().encrypt(b"{}\n").unwrap();
+        let local = Identities { files: vec![dir.path().join("missing.txt"), path.clone()], ..Default::default() };
+        assert_eq!(local.decrypt(&encrypted).unwrap(), b"{}\n");
+        assert_eq!(Identities::default().with_file(&path).unwrap().decrypt(&encrypted).unwrap(), b"{}\n");
+
+        fs::write(&path, "# comment\n\nAGE-SECRET-KEY-1NOPE\n").unwrap();
+        assert!(matches!(read_identity_file(&path), Err(EncryptionError::InvalidIdentity { line: 3, .. })));
+    }
+}
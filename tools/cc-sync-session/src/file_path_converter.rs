@@ -14,7 +14,7 @@ pub fn dir_path_to_claude_code_stype(dir_path: PathBuf) -> anyhow::Result<String
     
     // Remove leading slash and replace path separators with dashes
     let without_leading_slash = path_str.trim_start_matches('/');
-    let with_dashes = without_leading_slash.replace('/', "-").replace('.', "-");
+    let with_dashes = without_leading_slash.replace(['/', '.'], "-");
     
     // Add leading dash
     Ok(format!("-{}", with_dashes))
@@ -39,4 +39,36 @@ pub trait FileSystem: Send + Sync {
     fn exists(&self, path: &Path) -> Result<bool>;
     
     fn set_modified_time(&self, path: &Path, time: SystemTime) -> Result<()>;
+
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Reads at most the first `max_bytes` bytes of a file.
+    fn read_head(&self, path: &Path, max_bytes: usize) -> Result<Vec<u8>> {
+        let mut content = self.read_file(path)?;
+        content.truncate(max_bytes);
+        Ok(content)
+    }
+
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<()>;
+
+    fn remove_file(&self, path: &Path) -> Result<()>;
+
+    /// Recursively lists every file below `dir`, sorted by path.
+    fn walk_files(&self, dir: &Path) -> Result<Vec<EntryMetadata>> {
+        let mut dirs_to_process = vec![dir.to_path_buf()];
+        let mut files = Vec::new();
+
+        while let Some(current_dir) = dirs_to_process.pop() {
+            for entry in self.list_directory(&current_dir)? {
+                if entry.is_directory {
+                    dirs_to_process.push(entry.path);
+                } else {
+                    files.push(entry);
+                }
+            }
+        }
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
 }
\ No newline at end of file
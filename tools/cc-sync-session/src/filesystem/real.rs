@@ -1,11 +1,12 @@
 use std::fs;
+use std::io::Read;
 use std::path::Path;
 use std::time::SystemTime;
 use filetime::{set_file_mtime, FileTime};
 
 use super::{EntryMetadata, FileSystem, Result};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct RealFileSystem;
 
 impl RealFileSystem {
@@ -18,10 +19,7 @@ impl FileSystem for RealFileSystem {
     fn list_directory(&self, path: &Path) -> Result<Vec<EntryMetadata>> {
         let mut results = Vec::new();
         
-        let entries = match fs::read_dir(path) {
-            Ok(entries) => entries,
-            Err(e) => return Err(e.into()),
-        };
+        let entries = fs::read_dir(path)?;
         
         for entry in entries {
             let entry = match entry {
@@ -84,4 +82,28 @@ impl FileSystem for RealFileSystem {
         set_file_mtime(path, file_time)?;
         Ok(())
     }
-}
\ No newline at end of file
+    
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(fs::read(path)?)
+    }
+    
+    fn read_head(&self, path: &Path, max_bytes: usize) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        fs::File::open(path)?.take(max_bytes as u64).read_to_end(&mut content)?;
+        Ok(content)
+    }
+    
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        
+        fs::write(path, content)?;
+        Ok(())
+    }
+    
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
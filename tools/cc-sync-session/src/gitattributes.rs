@@ -0,0 +1,87 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Stores every session file through Git LFS.
+pub const LFS_LINE: &str = ".claude/ccss_sessions/** filter=lfs diff=lfs merge=lfs -text";
+
+/// Name of the diff driver that shows sessions as transcripts.
+pub const DIFF_DRIVER: &str = "ccss-session";
+
+/// Routes session transcripts through the `ccss-session` diff driver.
+///
+/// It comes after [`LFS_LINE`] so that it overrides the `diff=lfs` attribute.
+pub const DIFF_DRIVER_LINE: &str = ".claude/ccss_sessions/**/*.jsonl diff=ccss-session";
+
+/// Routes compressed session transcripts through the `ccss-session` diff driver.
+pub const COMPRESSED_DIFF_DRIVER_LINE: &str = ".claude/ccss_sessions/**/*.jsonl.zst diff=ccss-session";
+
+/// Routes encrypted session transcripts through the `ccss-session` diff driver.
+pub const ENCRYPTED_DIFF_DRIVER_LINE: &str = ".claude/ccss_sessions/**/*.age diff=ccss-session";
+
+/// Name of the merge driver that unions session transcripts.
+pub const MERGE_DRIVER: &str = "ccss-session";
+
+/// Routes session transcripts through the `ccss-session` merge driver.
+pub const MERGE_DRIVER_LINE: &str = ".claude/ccss_sessions/**/*.jsonl merge=ccss-session";
+
+/// Routes compressed session transcripts through the `ccss-session` merge driver.
+pub const COMPRESSED_MERGE_DRIVER_LINE: &str = ".claude/ccss_sessions/**/*.jsonl.zst merge=ccss-session";
+
+/// Routes encrypted session transcripts through the `ccss-session` merge driver.
+pub const ENCRYPTED_MERGE_DRIVER_LINE: &str = ".claude/ccss_sessions/**/*.age merge=ccss-session";
+
+/// Returns `true` if `content` has `line` on a line of its own.
+pub fn contains_line(content: &str, line: &str) -> bool {
+    content.lines().any(|existing| existing.trim() == line)
+}
+
+/// Appends `line` to the `.gitattributes` file at `path` unless it is already there.
+///
+/// Returns `true` if the file was changed.
+pub fn ensure_line(path: &Path, line: &str) -> io::Result<bool> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    if contains_line(&content, line) {
+        return Ok(false);
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+
+    // Check if we need a newline before appending
+    if !content.is_empty() && !content.ends_with('\n') {
+        writeln!(file)?;
+    }
+    writeln!(file, "{}", line)?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_ensure_line() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join(".gitattributes");
+        fs::write(&path, "*.png binary").unwrap();
+
+        assert!(ensure_line(&path, LFS_LINE).unwrap());
+        assert!(!ensure_line(&path, LFS_LINE).unwrap());
+        assert!(ensure_line(&path, DIFF_DRIVER_LINE).unwrap());
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("*.png binary\n{}\n{}\n", LFS_LINE, DIFF_DRIVER_LINE)
+        );
+    }
+}
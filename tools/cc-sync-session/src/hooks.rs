@@ -0,0 +1,291 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use git2::Repository;
+
+/// Line that marks a hook script written by `hooks install`.
+pub const MARKER: &str = "# Installed by cc-sync-session";
+
+/// Extension of a hook script that was there before `hooks install` and is
+/// chained to: `pre-commit.ccss-chained`.
+pub const CHAINED_EXTENSION: &str = "ccss-chained";
+
+/// A git hook `hooks install` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Hook {
+    /// Syncs sessions into a worktree store and stages them.
+    PreCommit,
+    /// Adds `Claude-Session` trailers to the commit message.
+    PrepareCommitMsg,
+    /// Syncs sessions into a store on a branch.
+    PostCommit,
+    /// Pushes the store branch along with the pushed refs.
+    PrePush,
+}
+
+impl Hook {
+    pub const ALL: [Hook; 4] = [Hook::PreCommit, Hook::PrepareCommitMsg, Hook::PostCommit, Hook::PrePush];
+
+    /// File name of the hook in the hooks directory.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hook::PreCommit => "pre-commit",
+            Hook::PrepareCommitMsg => "prepare-commit-msg",
+            Hook::PostCommit => "post-commit",
+            Hook::PrePush => "pre-push",
+        }
+    }
+
+    /// Whether git passes input to the hook on stdin, which has to be handed
+    /// to the chained hook as well.
+    fn reads_stdin(&self) -> bool {
+        matches!(self, Hook::PrePush)
+    }
+
+    /// The hook script, running the chained hook first and
+    /// `cc-sync-session hooks run <hook>` if it succeeded.
+    pub fn script(&self) -> String {
+        let name = self.name();
+        let mut script = format!(
+            "#!/bin/sh\n\
+             {MARKER}; remove with 'cc-sync-session hooks uninstall'.\n\
+             # The {name} hook that was here before, if any, is kept as {name}.{CHAINED_EXTENSION} and runs first.\n\
+             chained=\"$(dirname \"$0\")/{name}.{CHAINED_EXTENSION}\"\n"
+        );
+        if self.reads_stdin() {
+            script.push_str(&format!(
+                "input=$(cat)\n\
+                 if [ -x \"$chained\" ]; then\n\
+                 \x20   printf '%s\\n' \"$input\" | \"$chained\" \"$@\" || exit $?\n\
+                 fi\n\
+                 printf '%s\\n' \"$input\" | cc-sync-session hooks run {name} \"$@\"\n"
+            ));
+        } else {
+            script.push_str(&format!(
+                "if [ -x \"$chained\" ]; then\n\
+                 \x20   \"$chained\" \"$@\" || exit $?\n\
+                 fi\n\
+                 exec cc-sync-session hooks run {name} \"$@\"\n"
+            ));
+        }
+        script
+    }
+}
+
+impl FromStr for Hook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Hook::ALL.into_iter()
+            .find(|hook| hook.name() == s)
+            .ok_or_else(|| format!(
+                "Unknown hook '{}' (expected pre-commit, prepare-commit-msg, post-commit or pre-push)",
+                s
+            ))
+    }
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What is in the hooks directory for a hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookState {
+    /// No hook script.
+    Missing,
+    /// A hook script that `hooks install` did not write.
+    Foreign,
+    /// The `hooks install` script, chaining to the hook that was there before
+    /// if `chained`.
+    Installed { chained: bool },
+}
+
+/// The directory git runs hooks from: `core.hooksPath` if it is set,
+/// `hooks` in the git directory otherwise.
+///
+/// A relative `core.hooksPath` is relative to the working tree, where git
+/// runs hooks from; the hooks of linked worktrees are shared with the main one.
+pub fn hooks_dir(repo: &Repository) -> Result<PathBuf, git2::Error> {
+    let config = repo.config()?;
+    match config.get_path("core.hooksPath") {
+        Ok(path) if path.is_absolute() => Ok(path),
+        Ok(path) => Ok(repo.workdir().unwrap_or_else(|| repo.path()).join(path)),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(common_dir(repo).join("hooks")),
+        Err(e) => Err(e),
+    }
+}
+
+/// The git directory shared by all worktrees, named by the `commondir` file
+/// of a linked worktree's git directory.
+fn common_dir(repo: &Repository) -> PathBuf {
+    let git_dir = repo.path();
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => git_dir.join(common_dir.trim()),
+        Err(_) => git_dir.to_path_buf(),
+    }
+}
+
+/// Why a hook that syncs has nothing to do, if so: the working tree store at
+/// `store_dir` was never initialized, or Claude Code has no project directory
+/// starting with `project_prefix` in `source_root_dir` yet, as for a teammate
+/// who has not used it in the repository. Neither may fail the commit.
+pub fn nothing_to_sync(store_dir: Option<&Path>, source_root_dir: &Path, project_prefix: &str) -> io::Result<Option<String>> {
+    if let Some(store_dir) = store_dir.filter(|dir| !dir.is_dir()) {
+        return Ok(Some(format!("No session store at {}; run 'cc-sync-session init'", store_dir.display())));
+    }
+
+    if source_root_dir.is_dir() {
+        for entry in fs::read_dir(source_root_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && entry.file_name().to_string_lossy().starts_with(project_prefix) {
+                return Ok(None);
+            }
+        }
+    }
+    Ok(Some(format!("No Claude Code sessions of this repository in {}", source_root_dir.display())))
+}
+
+fn chained_path(hooks_dir: &Path, hook: Hook) -> PathBuf {
+    hooks_dir.join(format!("{}.{}", hook.name(), CHAINED_EXTENSION))
+}
+
+/// What is in `hooks_dir` for `hook`.
+pub fn state(hooks_dir: &Path, hook: Hook) -> io::Result<HookState> {
+    let content = match fs::read(hooks_dir.join(hook.name())) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HookState::Missing),
+        Err(e) => return Err(e),
+    };
+    if !String::from_utf8_lossy(&content).lines().any(|line| line.starts_with(MARKER)) {
+        return Ok(HookState::Foreign);
+    }
+    Ok(HookState::Installed { chained: chained_path(hooks_dir, hook).exists() })
+}
+
+/// Writes the `hook` script to `hooks_dir`.
+///
+/// A hook script that is already there is renamed to
+/// `<hook>.ccss-chained` and run first; an installed script is rewritten
+/// and keeps its chained hook. Returns the state before installing.
+pub fn install(hooks_dir: &Path, hook: Hook) -> io::Result<HookState> {
+    let previous = state(hooks_dir, hook)?;
+    let path = hooks_dir.join(hook.name());
+    fs::create_dir_all(hooks_dir)?;
+
+    if previous == HookState::Foreign {
+        let chained = chained_path(hooks_dir, hook);
+        if chained.exists() {
+            // Renaming would lose the hook chained by an earlier install
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!(
+                "{} was replaced after cc-sync-session was installed, and {} is still there",
+                path.display(),
+                chained.display()
+            )));
+        }
+        fs::rename(&path, &chained)?;
+    }
+
+    fs::write(&path, hook.script())?;
+    make_executable(&path)?;
+    Ok(previous)
+}
+
+/// Removes the `hook` script from `hooks_dir`, putting back the hook it
+/// chained to. Hook scripts that `hooks install` did not write are left
+/// alone. Returns the state before uninstalling.
+pub fn uninstall(hooks_dir: &Path, hook: Hook) -> io::Result<HookState> {
+    let previous = state(hooks_dir, hook)?;
+    let path = hooks_dir.join(hook.name());
+    match previous {
+        HookState::Installed { chained: true } => fs::rename(chained_path(hooks_dir, hook), &path)?,
+        HookState::Installed { chained: false } => fs::remove_file(&path)?,
+        HookState::Missing | HookState::Foreign => {}
+    }
+    Ok(previous)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_install_chains_existing_hook() {
+        let temp = TempDir::new().unwrap();
+        let hooks_dir = temp.path().join("hooks");
+        let existing = "#!/bin/sh\necho existing\n";
+        fs::create_dir_all(&hooks_dir).unwrap();
+        fs::write(hooks_dir.join("pre-commit"), existing).unwrap();
+
+        assert_eq!(state(&hooks_dir, Hook::PreCommit).unwrap(), HookState::Foreign);
+        assert_eq!(install(&hooks_dir, Hook::PreCommit).unwrap(), HookState::Foreign);
+        assert_eq!(install(&hooks_dir, Hook::PostCommit).unwrap(), HookState::Missing);
+        assert_eq!(state(&hooks_dir, Hook::PreCommit).unwrap(), HookState::Installed { chained: true });
+        assert_eq!(state(&hooks_dir, Hook::PostCommit).unwrap(), HookState::Installed { chained: false });
+        assert_eq!(fs::read_to_string(hooks_dir.join("pre-commit.ccss-chained")).unwrap(), existing);
+
+        // Installing again keeps the chained hook
+        assert_eq!(install(&hooks_dir, Hook::PreCommit).unwrap(), HookState::Installed { chained: true });
+        assert_eq!(fs::read_to_string(hooks_dir.join("pre-commit.ccss-chained")).unwrap(), existing);
+
+        assert_eq!(uninstall(&hooks_dir, Hook::PreCommit).unwrap(), HookState::Installed { chained: true });
+        assert_eq!(uninstall(&hooks_dir, Hook::PostCommit).unwrap(), HookState::Installed { chained: false });
+        assert_eq!(fs::read_to_string(hooks_dir.join("pre-commit")).unwrap(), existing);
+        assert!(!hooks_dir.join("pre-commit.ccss-chained").exists());
+        assert_eq!(state(&hooks_dir, Hook::PostCommit).unwrap(), HookState::Missing);
+
+        // Hooks written by someone else are left alone
+        assert_eq!(uninstall(&hooks_dir, Hook::PreCommit).unwrap(), HookState::Foreign);
+        assert_eq!(fs::read_to_string(hooks_dir.join("pre-commit")).unwrap(), existing);
+    }
+
+    #[test]
+    fn test_nothing_to_sync() {
+        let temp = TempDir::new().unwrap();
+        let store_dir = temp.path().join("repo/.claude/ccss_sessions");
+        let source_root_dir = temp.path().join("projects");
+
+        assert!(nothing_to_sync(Some(&store_dir), &source_root_dir, "-repo").unwrap().unwrap().contains("No session store"));
+        fs::create_dir_all(&store_dir).unwrap();
+        assert!(nothing_to_sync(Some(&store_dir), &source_root_dir, "-repo").unwrap().unwrap().contains("No Claude Code sessions"));
+        fs::create_dir_all(source_root_dir.join("-other")).unwrap();
+        assert!(nothing_to_sync(Some(&store_dir), &source_root_dir, "-repo").unwrap().is_some());
+
+        // Sessions of a subdirectory are synced too
+        fs::create_dir_all(source_root_dir.join("-repo-sub")).unwrap();
+        assert_eq!(nothing_to_sync(Some(&store_dir), &source_root_dir, "-repo").unwrap(), None);
+        assert_eq!(nothing_to_sync(None, &source_root_dir, "-repo").unwrap(), None);
+    }
+
+    #[test]
+    fn test_script() {
+        assert_eq!("pre-push".parse::<Hook>().unwrap(), Hook::PrePush);
+        assert!("post-merge".parse::<Hook>().is_err());
+
+        let script = Hook::PrePush.script();
+        assert!(script.starts_with("#!/bin/sh\n# Installed by cc-sync-session"));
+        assert!(script.contains("chained=\"$(dirname \"$0\")/pre-push.ccss-chained\"\n"));
+        assert!(script.contains("| cc-sync-session hooks run pre-push \"$@\"\n"));
+        assert!(Hook::PreCommit.script().ends_with("exec cc-sync-session hooks run pre-commit \"$@\"\n"));
+    }
+}
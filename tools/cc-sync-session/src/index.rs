@@ -0,0 +1,419 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::compression::{logical_path, read_session, read_stored_session};
+use crate::dedup::{self, has_references};
+use crate::encryption::Identities;
+use crate::filesystem::{FileSystem, FileSystemError};
+use crate::manifest::manifest_key;
+use crate::session::{parse_records_lossy, shorten};
+use crate::sync::is_session_file;
+
+/// Human-readable index at the store root.
+pub const INDEX_MARKDOWN_FILE: &str = "INDEX.md";
+
+/// Machine-readable index at the store root.
+pub const INDEX_JSON_FILE: &str = "sessions.json";
+
+const INDEX_VERSION: u32 = 1;
+
+/// Longest first prompt kept in the index, in characters.
+const PROMPT_MAX_CHARS: usize = 80;
+
+const REDACTED: &str = "[redacted]";
+
+/// Prefixes of well-known API keys and tokens.
+const SECRET_PREFIXES: &[&str] = &["sk-", "ghp_", "gho_", "ghs_", "ghu_", "github_pat_", "glpat-", "xoxb-", "xoxp-", "AKIA"];
+
+#[derive(Debug, Error)]
+pub enum IndexError {
+    #[error(transparent)]
+    FileSystem(#[from] FileSystemError),
+
+    #[error("Invalid session index: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, IndexError>;
+
+/// What the index says about one session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionEntry {
+    pub id: String,
+    /// Store-relative path, with `/` separators.
+    pub path: String,
+    pub started: Option<DateTime<Utc>>,
+    pub ended: Option<DateTime<Utc>>,
+    pub git_branch: Option<String>,
+    /// First user prompt, shortened and with secrets redacted.
+    pub first_prompt: Option<String>,
+    /// Number of user and assistant messages.
+    pub messages: usize,
+    pub models: BTreeSet<String>,
+    pub files: BTreeSet<String>,
+}
+
+impl SessionEntry {
+    /// Summarizes the (expanded) transcript stored at `relative_path`,
+    /// redacting its first prompt with `rules`.
+    pub fn summarize(relative_path: &Path, content: &str, rules: &RedactionRules) -> Self {
+        let records = parse_records_lossy(content);
+        let timestamps: Vec<DateTime<Utc>> = records.iter().filter_map(|record| record.timestamp()).collect();
+
+        Self {
+            id: records.iter()
+                .find_map(|record| record.session_id())
+                .map(str::to_string)
+                .or_else(|| relative_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+                .unwrap_or_default(),
+            path: manifest_key(relative_path),
+            started: timestamps.iter().min().copied(),
+            ended: timestamps.iter().max().copied(),
+            git_branch: records.iter().find_map(|record| record.git_branch()).map(str::to_string),
+            first_prompt: records.iter()
+                .find_map(|record| record.user_prompt())
+                .map(|prompt| shorten(&redact(&prompt, rules), PROMPT_MAX_CHARS)),
+            messages: records.iter()
+                .filter(|record| matches!(record.record_type(), Some("user") | Some("assistant")))
+                .count(),
+            models: records.iter().filter_map(|record| record.model()).map(str::to_string).collect(),
+            files: records.iter().flat_map(|record| record.edited_files()).collect(),
+        }
+    }
+}
+
+/// Index of every session in the store, sorted by path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionIndex {
+    pub version: u32,
+    pub sessions: Vec<SessionEntry>,
+}
+
+impl Default for SessionIndex {
+    fn default() -> Self {
+        Self {
+            version: INDEX_VERSION,
+            sessions: Vec::new(),
+        }
+    }
+}
+
+impl SessionIndex {
+    /// Reads `sessions.json` of the store at `store_dir`, if it has one.
+    pub fn load<FS: FileSystem>(fs: &FS, store_dir: &Path) -> Result<Option<Self>> {
+        let path = store_dir.join(INDEX_JSON_FILE);
+        if !fs.exists(&path)? {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_slice(&fs.read_file(&path)?)?))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    /// Renders `INDEX.md`, one table per project directory.
+    ///
+    /// Sessions link to their file in `stored_paths`, keyed by their logical
+    /// path, as a compressed or encrypted file has more extensions; the others
+    /// link to their logical path.
+    pub fn to_markdown(&self, stored_paths: &BTreeMap<String, String>) -> String {
+        let mut projects: BTreeMap<&str, Vec<&SessionEntry>> = BTreeMap::new();
+        for session in &self.sessions {
+            let project = session.path.split_once('/').map_or("", |(project, _)| project);
+            projects.entry(project).or_default().push(session);
+        }
+
+        let mut output = String::from("# Claude Code sessions\n\n");
+        output.push_str("<!-- Generated by cc-sync-session sync --index; do not edit. -->\n");
+
+        for (project, sessions) in projects {
+            output.push_str(&format!("\n## {}\n\n", project));
+            output.push_str("| Session | Started | Ended | Branch | Messages | Models | First prompt | Files |\n");
+            output.push_str("|---|---|---|---|---:|---|---|---|\n");
+
+            for session in sessions {
+                let time = |time: &Option<DateTime<Utc>>| {
+                    time.map(|time| time.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default()
+                };
+                let list = |items: &BTreeSet<String>| {
+                    items.iter().map(|item| format!("`{}`", item)).collect::<Vec<_>>().join(", ")
+                };
+                let path = stored_paths.get(&session.path).unwrap_or(&session.path);
+                let link = path.strip_prefix(project).unwrap_or(path).trim_start_matches('/');
+
+                output.push_str(&format!(
+                    "| [{}]({}/{}) | {} | {} | {} | {} | {} | {} | {} |\n",
+                    table_cell(&session.id),
+                    project,
+                    link,
+                    time(&session.started),
+                    time(&session.ended),
+                    table_cell(session.git_branch.as_deref().unwrap_or_default()),
+                    session.messages,
+                    table_cell(&list(&session.models)),
+                    table_cell(session.first_prompt.as_deref().unwrap_or_default()),
+                    table_cell(&list(&session.files)),
+                ));
+            }
+        }
+
+        output
+    }
+}
+
+fn table_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// The `[redaction]` table: secrets to keep out of the index besides the
+/// built-in ones.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactionRules {
+    /// Names of `name=value` settings whose value is a secret.
+    pub keys: Vec<String>,
+    /// Prefixes of tokens, like the built-in `sk-` or `ghp_`.
+    pub prefixes: Vec<String>,
+    /// Words redacted wherever they appear, such as internal host names.
+    pub words: Vec<String>,
+}
+
+/// Replaces likely secrets in `text`: API keys and tokens, e-mail addresses,
+/// the values of `password=`-style assignments and whatever `rules` add.
+pub fn redact(text: &str, rules: &RedactionRules) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (word, tail) = rest.split_at(word_end);
+        output.push_str(&redact_word(word, rules));
+
+        let space_end = tail.find(|c: char| !c.is_whitespace()).unwrap_or(tail.len());
+        output.push_str(&tail[..space_end]);
+        rest = &tail[space_end..];
+    }
+
+    output
+}
+
+fn redact_word(word: &str, rules: &RedactionRules) -> String {
+    let trimmed = word.trim_matches(|c: char| "\"'`()[]{}<>,;.!?".contains(c));
+    if trimmed.is_empty() {
+        return word.to_string();
+    }
+
+    if let Some((key, value)) = trimmed.split_once(['=', ':']) {
+        let key = key.to_ascii_lowercase();
+        let is_secret_key = ["password", "passwd", "secret", "token", "api_key", "apikey"]
+            .iter()
+            .copied()
+            .chain(rules.keys.iter().map(String::as_str))
+            .any(|name| key.ends_with(&name.to_ascii_lowercase()));
+        if is_secret_key && !value.is_empty() {
+            return word.replacen(value, REDACTED, 1);
+        }
+    }
+
+    let is_listed = rules.prefixes.iter().any(|prefix| !prefix.is_empty() && trimmed.starts_with(prefix.as_str()))
+        || rules.words.iter().any(|listed| !listed.is_empty() && trimmed.to_lowercase().contains(&listed.to_lowercase()));
+    if is_listed || is_secret(trimmed) || is_email(trimmed) {
+        return word.replacen(trimmed, REDACTED, 1);
+    }
+
+    word.to_string()
+}
+
+fn is_secret(word: &str) -> bool {
+    if SECRET_PREFIXES.iter().any(|prefix| word.starts_with(prefix) && word.len() >= prefix.len() + 16) {
+        return true;
+    }
+
+    // Long random-looking strings: hex digests, JWTs, base64 keys
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || "-_.+/=".contains(c);
+    word.len() >= 32
+        && word.chars().all(is_token_char)
+        && word.chars().any(|c| c.is_ascii_digit())
+        && word.chars().any(|c| c.is_ascii_alphabetic())
+        && !word.contains("//")
+}
+
+fn is_email(word: &str) -> bool {
+    match word.split_once('@') {
+        Some((user, domain)) => !user.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.'),
+        None => false,
+    }
+}
+
+/// Removes `INDEX.md` and `sessions.json` from the store at `store_dir`.
+/// Returns `true` if there were any.
+pub fn remove_index<FS: FileSystem>(fs: &FS, store_dir: &Path) -> Result<bool> {
+    let mut removed = false;
+    for file in [INDEX_MARKDOWN_FILE, INDEX_JSON_FILE] {
+        let path = store_dir.join(file);
+        if fs.exists(&path)? {
+            fs.remove_file(&path)?;
+            removed = true;
+        }
+    }
+    Ok(removed)
+}
+
+/// Brings the index of the store at `store_dir` up to date.
+///
+/// Entries of sessions not in `changed` are taken from the existing
+/// `sessions.json`; only changed and new sessions are read. The index files are
+/// only written when their content changes. Returns `true` if they were.
+pub fn update_index<FS: FileSystem>(fs: &FS, store_dir: &Path, changed: &[PathBuf], identities: &Identities, rules: &RedactionRules) -> Result<bool> {
+    let previous: BTreeMap<String, SessionEntry> = match SessionIndex::load(fs, store_dir) {
+        Ok(index) => index.unwrap_or_default().sessions,
+        Err(e) => {
+            log::warn!("Rebuilding the session index: {}", e);
+            Vec::new()
+        }
+    }
+    .into_iter()
+    .map(|entry| (entry.path.clone(), entry))
+    .collect();
+    let changed: BTreeSet<String> = changed.iter().map(|path| manifest_key(&logical_path(path))).collect();
+
+    let read_referenced = |path: &Path| {
+        read_stored_session(fs, store_dir, path, identities).ok().flatten().map(|content| String::from_utf8_lossy(&content).into_owned())
+    };
+
+    let mut index = SessionIndex::default();
+    let mut stored_paths = BTreeMap::new();
+    for entry in fs.walk_files(store_dir)? {
+        if !is_session_file(&entry.path) {
+            continue;
+        }
+        let stored_path = entry.path.strip_prefix(store_dir)
+            .map_err(|e| FileSystemError::PathError(e.to_string()))?;
+        let relative_path = logical_path(stored_path);
+        let key = manifest_key(&relative_path);
+        stored_paths.insert(key.clone(), manifest_key(stored_path));
+
+        if let (Some(previous), false) = (previous.get(&key), changed.contains(&key)) {
+            index.sessions.push(previous.clone());
+            continue;
+        }
+
+        let mut content = String::from_utf8_lossy(&read_session(fs, &entry.path, identities)?).into_owned();
+        if has_references(&content) {
+            match dedup::expand(&content, &read_referenced) {
+                Ok(expanded) => content = expanded,
+                Err(e) => log::warn!("Indexing {} without its referenced records: {}", key, e),
+            }
+        }
+        index.sessions.push(SessionEntry::summarize(&relative_path, &content, rules));
+    }
+
+    let json = index.to_json()?;
+    let markdown = index.to_markdown(&stored_paths);
+    let mut written = false;
+    for (file, content) in [(INDEX_JSON_FILE, json), (INDEX_MARKDOWN_FILE, markdown)] {
+        let path = store_dir.join(file);
+        if fs.exists(&path)? && fs.read_file(&path)? == content.as_bytes() {
+            continue;
+        }
+        fs.write_file(&path, content.as_bytes())?;
+        written = true;
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::Compression;
+    use crate::mock::MockFileSystem;
+    use std::time::SystemTime;
+
+    const SESSION: &str = concat!(
+        r#"{"type":"user","uuid":"u1","sessionId":"s1","gitBranch":"main","cwd":"/repo","timestamp":"2025-06-25T10:00:00Z","message":{"role":"user","content":"Deploy with token=abc123 please"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","sessionId":"s1","cwd":"/repo","timestamp":"2025-06-25T10:05:00Z","message":{"role":"assistant","model":"claude-sonnet-4","content":[{"type":"tool_use","id":"t","name":"Edit","input":{"file_path":"/repo/src/main.rs"}}]}}"#, "\n",
+    );
+
+    #[test]
+    fn test_summarize() {
+        let entry = SessionEntry::summarize(Path::new("p/s1.jsonl"), SESSION, &RedactionRules::default());
+
+        assert_eq!(entry.id, "s1");
+        assert_eq!(entry.path, "p/s1.jsonl");
+        assert_eq!(entry.git_branch.as_deref(), Some("main"));
+        assert_eq!(entry.first_prompt.as_deref(), Some("Deploy with token=[redacted] please"));
+        assert_eq!(entry.messages, 2);
+        assert_eq!(entry.models, BTreeSet::from(["claude-sonnet-4".to_string()]));
+        assert_eq!(entry.files, BTreeSet::from(["src/main.rs".to_string()]));
+        assert!(entry.started < entry.ended);
+    }
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("mail me at yuta@example.com.", &RedactionRules::default()), "mail me at [redacted].");
+        assert_eq!(redact("use sk-ant-REDACTED now", &RedactionRules::default()), "use [redacted] now");
+        assert_eq!(redact("db_password=hunter2", &RedactionRules::default()), "db_password=[redacted]");
+        assert_eq!(redact("fix src/main.rs and https://example.com/a", &RedactionRules::default()), "fix src/main.rs and https://example.com/a");
+
+        let rules = RedactionRules {
+            keys: vec!["Cookie".to_string()],
+            prefixes: vec!["acme_".to_string()],
+            words: vec!["db.internal".to_string()],
+        };
+        assert_eq!(
+            redact("cookie=abc acme_1 ssh db.internal:22 done", &rules),
+            "cookie=[redacted] [redacted] ssh [redacted] done"
+        );
+    }
+
+    #[test]
+    fn test_update_index_is_incremental() {
+        let fs = MockFileSystem::new();
+        let store_dir = Path::new("/store");
+        fs.add_directory(store_dir);
+        fs.add_directory(store_dir.join("p"));
+        fs.add_file(store_dir.join("p/s1.jsonl"), SESSION.as_bytes().to_vec(), SystemTime::now());
+
+        assert!(update_index(&fs, store_dir, &[], &Identities::default(), &RedactionRules::default()).unwrap());
+        assert!(!update_index(&fs, store_dir, &[], &Identities::default(), &RedactionRules::default()).unwrap());
+
+        let markdown = String::from_utf8(fs.get_file_content(&store_dir.join(INDEX_MARKDOWN_FILE)).unwrap()).unwrap();
+        assert!(markdown.contains("| [s1](p/s1.jsonl) | 2025-06-25 10:00 | 2025-06-25 10:05 | main | 2 |"));
+
+        // Unchanged sessions are not read again
+        fs.add_file(store_dir.join("p/s1.jsonl"), b"not json\n".to_vec(), SystemTime::now());
+        assert!(!update_index(&fs, store_dir, &[], &Identities::default(), &RedactionRules::default()).unwrap());
+        assert!(update_index(&fs, store_dir, &[PathBuf::from("p/s1.jsonl")], &Identities::default(), &RedactionRules::default()).unwrap());
+
+        let index = SessionIndex::load(&fs, store_dir).unwrap().unwrap();
+        assert_eq!(index.sessions[0].messages, 0);
+
+        assert!(remove_index(&fs, store_dir).unwrap());
+        assert!(!fs.exists(&store_dir.join(INDEX_MARKDOWN_FILE)).unwrap());
+        assert!(!remove_index(&fs, store_dir).unwrap());
+    }
+
+    #[test]
+    fn test_update_index_links_compressed_sessions() {
+        let fs = MockFileSystem::new();
+        let store_dir = Path::new("/store");
+        fs.add_directory(store_dir);
+        fs.add_directory(store_dir.join("p"));
+        let compressed = Compression::Zstd.encode(SESSION.as_bytes()).unwrap();
+        fs.add_file(store_dir.join("p/s1.jsonl.zst"), compressed, SystemTime::now());
+
+        assert!(update_index(&fs, store_dir, &[], &Identities::default(), &RedactionRules::default()).unwrap());
+
+        let index = SessionIndex::load(&fs, store_dir).unwrap().unwrap();
+        assert_eq!(index.sessions[0].path, "p/s1.jsonl");
+        let markdown = String::from_utf8(fs.get_file_content(&store_dir.join(INDEX_MARKDOWN_FILE)).unwrap()).unwrap();
+        assert!(markdown.contains("| [s1](p/s1.jsonl.zst) | 2025-06-25 10:00 |"), "{}", markdown);
+    }
+}
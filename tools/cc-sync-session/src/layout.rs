@@ -0,0 +1,386 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::StoreMode;
+use crate::filesystem::{FileSystem, FileSystemError};
+use crate::namespace::{self, Namespace, NamespaceError};
+
+/// Descriptor at the store root recording how the store is laid out.
+pub const STORE_DESCRIPTOR_FILE: &str = ".ccss.toml";
+
+/// Placeholder that kept the store directory in git before there was a descriptor.
+pub const GITKEEP_FILE: &str = ".gitkeep";
+
+/// Version of stores written before the descriptor existed.
+pub const LEGACY_STORE_VERSION: u32 = 1;
+
+/// Version of the stores this build writes.
+pub const STORE_VERSION: u32 = 2;
+
+#[derive(Debug, Error)]
+pub enum LayoutError {
+    #[error(transparent)]
+    FileSystem(#[from] FileSystemError),
+
+    #[error("Invalid store descriptor {path}: {source}")]
+    Parse { path: PathBuf, source: toml::de::Error },
+
+    #[error(
+        "The store has version {version}, but this cc-sync-session only understands versions {}-{}; \
+         upgrade cc-sync-session or run 'cc-sync-session migrate' with a build that knows it",
+        LEGACY_STORE_VERSION,
+        STORE_VERSION
+    )]
+    UnsupportedVersion { version: u32 },
+
+    #[error("The store has {0} namespaces; only a store with one can be flattened")]
+    SeveralNamespaces(usize),
+
+    #[error(
+        "Store version {} only has the encoded layout, but the store is {0}; \
+         run 'cc-sync-session migrate --layout encoded' first",
+        LEGACY_STORE_VERSION
+    )]
+    LayoutNeedsDescriptor(Layout),
+
+    #[error(transparent)]
+    Namespace(#[from] NamespaceError),
+}
+
+pub type Result<T> = std::result::Result<T, LayoutError>;
+
+/// How session files are placed below the store root.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// `<encoded-project>/<session>.jsonl`, with the project directory named
+    /// as Claude Code names it (`/` and `.` replaced by `-`).
+    #[default]
+    Encoded,
+    /// `<author>/<machine>/<encoded-project>/<session>.jsonl`: every
+    /// namespace is a store of the encoded layout, with its own manifest and
+    /// index, written by one person on one machine.
+    Namespaced,
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Layout::Encoded => "encoded",
+            Layout::Namespaced => "namespaced",
+        })
+    }
+}
+
+impl std::str::FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "encoded" => Ok(Layout::Encoded),
+            "namespaced" => Ok(Layout::Namespaced),
+            _ => Err(format!("Unknown layout '{}' (expected encoded or namespaced)", s)),
+        }
+    }
+}
+
+/// Contents of `.ccss.toml` at the store root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StoreDescriptor {
+    pub version: u32,
+    pub layout: Layout,
+    pub mode: StoreMode,
+}
+
+impl StoreDescriptor {
+    /// The descriptor of a new store kept in `mode`.
+    pub fn new(mode: StoreMode) -> Self {
+        Self {
+            version: STORE_VERSION,
+            layout: Layout::default(),
+            mode,
+        }
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Reads the descriptor of the store at `store_dir`; `None` for a legacy
+    /// store without one.
+    ///
+    /// The version is checked before the rest, so a newer store is reported
+    /// as such rather than as a parse error.
+    pub fn load<FS: FileSystem>(fs: &FS, store_dir: &Path) -> Result<Option<Self>> {
+        let path = store_dir.join(STORE_DESCRIPTOR_FILE);
+        if !fs.exists(&path)? {
+            return Ok(None);
+        }
+
+        let content = String::from_utf8_lossy(&fs.read_file(&path)?).into_owned();
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        let Version { version } = toml::from_str(&content)
+            .map_err(|source| LayoutError::Parse { path: path.clone(), source })?;
+        if !(LEGACY_STORE_VERSION..=STORE_VERSION).contains(&version) {
+            return Err(LayoutError::UnsupportedVersion { version });
+        }
+
+        toml::from_str(&content)
+            .map(Some)
+            .map_err(|source| LayoutError::Parse { path, source })
+    }
+
+    pub fn save<FS: FileSystem>(&self, fs: &FS, store_dir: &Path) -> Result<()> {
+        let content = format!(
+            "# Written by cc-sync-session; change it with 'cc-sync-session migrate'\n{}",
+            toml::to_string(self).expect("descriptor serializes")
+        );
+        fs.write_file(&store_dir.join(STORE_DESCRIPTOR_FILE), content.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Version of the store at `store_dir`, failing for versions this build does
+/// not understand.
+pub fn store_version<FS: FileSystem>(fs: &FS, store_dir: &Path) -> Result<u32> {
+    Ok(StoreDescriptor::load(fs, store_dir)?.map_or(LEGACY_STORE_VERSION, |descriptor| descriptor.version))
+}
+
+/// Layout of the store at `store_dir`; legacy stores are encoded.
+pub fn store_layout<FS: FileSystem>(fs: &FS, store_dir: &Path) -> Result<Layout> {
+    Ok(StoreDescriptor::load(fs, store_dir)?.map_or(Layout::Encoded, |descriptor| descriptor.layout))
+}
+
+/// Writes the files of a new, empty store at `store_dir`.
+pub fn init_store<FS: FileSystem>(fs: &FS, store_dir: &Path, mode: StoreMode) -> Result<()> {
+    init_store_with_layout(fs, store_dir, mode, Layout::default())
+}
+
+pub fn init_store_with_layout<FS: FileSystem>(fs: &FS, store_dir: &Path, mode: StoreMode, layout: Layout) -> Result<()> {
+    fs.create_directory(store_dir)?;
+    StoreDescriptor::new(mode).with_layout(layout).save(fs, store_dir)
+}
+
+/// Moves the files of the store at `store_dir`, which must be at the newest
+/// version, to `layout` and returns the renamed files.
+///
+/// An encoded store moves into `namespace`; a namespaced store can only be
+/// flattened when it has a single namespace.
+pub fn change_layout<FS: FileSystem>(
+    fs: &FS,
+    store_dir: &Path,
+    layout: Layout,
+    namespace: &Namespace,
+    dry_run: bool,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let Some(descriptor) = StoreDescriptor::load(fs, store_dir)?.filter(|descriptor| descriptor.version == STORE_VERSION) else {
+        return Err(LayoutError::UnsupportedVersion { version: store_version(fs, store_dir)? });
+    };
+    if descriptor.layout == layout {
+        return Ok(Vec::new());
+    }
+
+    let renames: Vec<(PathBuf, PathBuf)> = match layout {
+        Layout::Namespaced => fs.walk_files(store_dir)?
+            .into_iter()
+            .filter_map(|entry| Some(entry.path.strip_prefix(store_dir).ok()?.to_path_buf()))
+            .filter(|path| path != Path::new(STORE_DESCRIPTOR_FILE) && path != Path::new(GITKEEP_FILE))
+            .map(|path| (path.clone(), namespace.path().join(path)))
+            .collect(),
+        Layout::Encoded => {
+            let namespaces = namespace::list_namespaces(fs, store_dir)?;
+            if namespaces.len() > 1 {
+                return Err(LayoutError::SeveralNamespaces(namespaces.len()));
+            }
+            let mut renames = Vec::new();
+            for namespace in namespaces {
+                for entry in fs.walk_files(&store_dir.join(namespace.path()))? {
+                    let path = entry.path.strip_prefix(store_dir).unwrap_or(&entry.path).to_path_buf();
+                    let flattened = path.strip_prefix(namespace.path()).unwrap_or(&path).to_path_buf();
+                    renames.push((path, flattened));
+                }
+            }
+            renames
+        }
+    };
+
+    if !dry_run {
+        for (from, to) in &renames {
+            fs.copy_file(&store_dir.join(from), &store_dir.join(to))?;
+            fs.remove_file(&store_dir.join(from))?;
+        }
+        descriptor.with_layout(layout).save(fs, store_dir)?;
+    }
+    Ok(renames)
+}
+
+/// What [`migrate`] changed, or would change on a dry run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub from: u32,
+    pub to: u32,
+    /// Files moved, relative to the store directory.
+    pub renames: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Migration {
+    pub fn is_empty(&self) -> bool {
+        self.from == self.to
+    }
+}
+
+/// Converts the store at `store_dir` to version `to`, one version at a time.
+pub fn migrate<FS: FileSystem>(fs: &FS, store_dir: &Path, to: u32, mode: StoreMode, dry_run: bool) -> Result<Migration> {
+    if !(LEGACY_STORE_VERSION..=STORE_VERSION).contains(&to) {
+        return Err(LayoutError::UnsupportedVersion { version: to });
+    }
+    let from = store_version(fs, store_dir)?;
+    let mut migration = Migration { from, to, renames: Vec::new() };
+
+    let mut version = from;
+    while version != to {
+        let next = if version < to { version + 1 } else { version - 1 };
+        migration.renames.extend(step(fs, store_dir, version, next, mode, dry_run)?);
+        version = next;
+    }
+
+    Ok(migration)
+}
+
+/// Migrates between two adjacent versions and returns the renamed files.
+fn step<FS: FileSystem>(
+    fs: &FS,
+    store_dir: &Path,
+    from: u32,
+    to: u32,
+    mode: StoreMode,
+    dry_run: bool,
+) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let gitkeep = store_dir.join(GITKEEP_FILE);
+    let descriptor = store_dir.join(STORE_DESCRIPTOR_FILE);
+
+    match (from, to) {
+        // The descriptor takes the place of the placeholder; the sessions stay where they are
+        (1, 2) => {
+            let had_gitkeep = fs.exists(&gitkeep)?;
+            if !dry_run {
+                StoreDescriptor::new(mode).save(fs, store_dir)?;
+                if had_gitkeep {
+                    fs.remove_file(&gitkeep)?;
+                }
+            }
+            Ok(if had_gitkeep { vec![(GITKEEP_FILE.into(), STORE_DESCRIPTOR_FILE.into())] } else { Vec::new() })
+        }
+        (2, 1) => {
+            // Without the descriptor, the namespaces would be read as projects
+            let layout = StoreDescriptor::load(fs, store_dir)?.map(|descriptor| descriptor.layout).unwrap_or_default();
+            if layout != Layout::Encoded {
+                return Err(LayoutError::LayoutNeedsDescriptor(layout));
+            }
+            if !dry_run {
+                fs.write_file(&gitkeep, b"")?;
+                fs.remove_file(&descriptor)?;
+            }
+            Ok(vec![(STORE_DESCRIPTOR_FILE.into(), GITKEEP_FILE.into())])
+        }
+        _ => unreachable!("no migration from version {} to {}", from, to),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockFileSystem;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_descriptor_versions() {
+        let fs = MockFileSystem::new();
+        let store_dir = Path::new("/repo/.claude/ccss_sessions");
+        fs.add_file(store_dir.join(GITKEEP_FILE), Vec::new(), SystemTime::now());
+        assert_eq!(store_version(&fs, store_dir).unwrap(), LEGACY_STORE_VERSION);
+
+        init_store(&fs, store_dir, StoreMode::Branch).unwrap();
+        assert_eq!(
+            StoreDescriptor::load(&fs, store_dir).unwrap(),
+            Some(StoreDescriptor { version: STORE_VERSION, layout: Layout::Encoded, mode: StoreMode::Branch })
+        );
+
+        fs.add_file(store_dir.join(STORE_DESCRIPTOR_FILE), b"version = 9\nlayout = \"sharded\"\n".to_vec(), SystemTime::now());
+        assert!(matches!(store_version(&fs, store_dir), Err(LayoutError::UnsupportedVersion { version: 9 })));
+
+        fs.add_file(store_dir.join(STORE_DESCRIPTOR_FILE), b"version = 2\nlayout = \"sharded\"\n".to_vec(), SystemTime::now());
+        assert!(matches!(store_version(&fs, store_dir), Err(LayoutError::Parse { .. })));
+    }
+
+    #[test]
+    fn test_migrate() {
+        let fs = MockFileSystem::new();
+        let store_dir = Path::new("/repo/.claude/ccss_sessions");
+        let session = store_dir.join("-repo/s1.jsonl");
+        fs.add_file(store_dir.join(GITKEEP_FILE), Vec::new(), SystemTime::now());
+        fs.add_file(&session, b"{}\n".to_vec(), SystemTime::now());
+
+        let dry_run = migrate(&fs, store_dir, STORE_VERSION, StoreMode::Worktree, true).unwrap();
+        assert_eq!(dry_run.renames, vec![(PathBuf::from(GITKEEP_FILE), PathBuf::from(STORE_DESCRIPTOR_FILE))]);
+        assert_eq!(store_version(&fs, store_dir).unwrap(), LEGACY_STORE_VERSION);
+
+        let migration = migrate(&fs, store_dir, STORE_VERSION, StoreMode::Worktree, false).unwrap();
+        assert_eq!((migration.from, migration.to), (LEGACY_STORE_VERSION, STORE_VERSION));
+        assert_eq!(store_version(&fs, store_dir).unwrap(), STORE_VERSION);
+        assert!(!fs.exists(&store_dir.join(GITKEEP_FILE)).unwrap());
+        assert!(fs.exists(&session).unwrap());
+        assert!(migrate(&fs, store_dir, STORE_VERSION, StoreMode::Worktree, false).unwrap().is_empty());
+
+        migrate(&fs, store_dir, LEGACY_STORE_VERSION, StoreMode::Worktree, false).unwrap();
+        assert!(fs.exists(&store_dir.join(GITKEEP_FILE)).unwrap());
+        assert!(!fs.exists(&store_dir.join(STORE_DESCRIPTOR_FILE)).unwrap());
+
+        assert!(matches!(
+            migrate(&fs, store_dir, 3, StoreMode::Worktree, false),
+            Err(LayoutError::UnsupportedVersion { version: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_change_layout() {
+        let fs = MockFileSystem::new();
+        let store_dir = Path::new("/repo/.claude/ccss_sessions");
+        let alice = Namespace::new("alice", "laptop");
+        fs.add_directory(store_dir);
+        fs.add_directory(store_dir.join("-repo"));
+        fs.add_file(store_dir.join("-repo/s1.jsonl"), b"{}\n".to_vec(), SystemTime::now());
+        fs.add_file(store_dir.join(crate::manifest::MANIFEST_FILE), b"{}".to_vec(), SystemTime::now());
+        assert!(matches!(
+            change_layout(&fs, store_dir, Layout::Namespaced, &alice, false),
+            Err(LayoutError::UnsupportedVersion { version: LEGACY_STORE_VERSION })
+        ));
+
+        init_store(&fs, store_dir, StoreMode::Worktree).unwrap();
+        let renames = change_layout(&fs, store_dir, Layout::Namespaced, &alice, false).unwrap();
+        assert_eq!(renames, vec![
+            (PathBuf::from("-repo/s1.jsonl"), PathBuf::from("alice/laptop/-repo/s1.jsonl")),
+            (PathBuf::from(crate::manifest::MANIFEST_FILE), PathBuf::from("alice/laptop/.ccss-manifest.json")),
+        ]);
+        assert_eq!(store_layout(&fs, store_dir).unwrap(), Layout::Namespaced);
+        assert!(fs.exists(&store_dir.join("alice/laptop/-repo/s1.jsonl")).unwrap());
+        assert!(!fs.exists(&store_dir.join("-repo/s1.jsonl")).unwrap());
+
+        // A namespaced store has to be flattened before it loses its descriptor
+        for dry_run in [true, false] {
+            let error = migrate(&fs, store_dir, LEGACY_STORE_VERSION, StoreMode::Worktree, dry_run).unwrap_err();
+            assert!(matches!(error, LayoutError::LayoutNeedsDescriptor(Layout::Namespaced)));
+            assert!(error.to_string().contains("migrate --layout encoded"));
+        }
+        assert_eq!(store_version(&fs, store_dir).unwrap(), STORE_VERSION);
+        assert!(fs.exists(&store_dir.join(STORE_DESCRIPTOR_FILE)).unwrap());
+    }
+}
@@ -1,5 +1,32 @@
 pub mod filesystem;
 pub mod sync;
+pub mod session;
+pub mod store;
+pub mod diff;
+pub mod render;
+pub mod merge;
+pub mod dedup;
+pub mod sidechain;
+pub mod gitattributes;
+pub mod trailers;
+pub mod manifest;
+pub mod verify;
+pub mod index;
+pub mod config;
+pub mod prune;
+pub mod compression;
+pub mod encryption;
+pub mod bundle;
+pub mod branch;
+pub mod layout;
+pub mod namespace;
+pub mod status;
+pub mod watch;
+pub mod hooks;
+pub mod doctor;
+pub mod list;
+pub mod tui;
+pub mod registry;
 
 pub mod mock;
 pub mod file_path_converter;
@@ -0,0 +1,314 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::index::{RedactionRules, SessionEntry};
+use crate::session::shorten;
+use crate::sidechain;
+use crate::store::Snapshot;
+use crate::sync::{SessionFilter, SessionPeek};
+
+/// Longest first prompt shown in the `list` table, in characters.
+const PROMPT_WIDTH: usize = 60;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ListError {
+    #[error("No session matches '{0}'")]
+    NotFound(String),
+
+    #[error("'{id}' matches several sessions: {}", matches.join(", "))]
+    Ambiguous { id: String, matches: Vec<String> },
+}
+
+/// A session in the `list` table.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ListedSession {
+    /// Session file name without extension, as `show` takes it.
+    pub id: String,
+    /// `<author>/<machine>` of a namespaced store.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Size of the transcript, in bytes.
+    pub size: u64,
+    /// When the session file was last written.
+    pub modified: DateTime<Utc>,
+    #[serde(flatten)]
+    pub entry: SessionEntry,
+}
+
+impl ListedSession {
+    /// Summarizes the session file at `path`, last written at `modified`,
+    /// redacting its first prompt with `rules`.
+    pub fn new(path: &Path, content: &str, modified: SystemTime, namespace: Option<&str>, rules: &RedactionRules) -> Self {
+        ListedSession {
+            id: session_id(path),
+            namespace: namespace.map(str::to_string),
+            size: content.len() as u64,
+            modified: modified.into(),
+            entry: SessionEntry::summarize(path, content, rules),
+        }
+    }
+
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        Some(self.entry.ended? - self.entry.started?)
+    }
+}
+
+/// Order of the `list` table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// Latest start first.
+    #[default]
+    Started,
+    /// Longest first.
+    Duration,
+    /// Most messages first.
+    Messages,
+    /// Largest first.
+    Size,
+    /// By id.
+    Id,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "started" => Ok(SortKey::Started),
+            "duration" => Ok(SortKey::Duration),
+            "messages" => Ok(SortKey::Messages),
+            "size" => Ok(SortKey::Size),
+            "id" => Ok(SortKey::Id),
+            _ => Err(format!("Unknown sort key '{}' (expected started, duration, messages, size or id)", s)),
+        }
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SortKey::Started => "started",
+            SortKey::Duration => "duration",
+            SortKey::Messages => "messages",
+            SortKey::Size => "size",
+            SortKey::Id => "id",
+        })
+    }
+}
+
+fn session_id(path: &Path) -> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Summarizes the sessions of `snapshot` that pass `filter`, leaving out
+/// subagent sidechains unless `sidechains` is set. First prompts are
+/// redacted with `rules`.
+///
+/// `modified` has the last write time of each file, for `--since`.
+pub fn list_sessions(
+    snapshot: &Snapshot,
+    modified: &dyn Fn(&Path) -> SystemTime,
+    filter: &SessionFilter,
+    sidechains: bool,
+    namespace: Option<&str>,
+    rules: &RedactionRules,
+) -> Vec<ListedSession> {
+    snapshot.iter()
+        .filter_map(|(path, content)| {
+            let content = String::from_utf8_lossy(content);
+            if !sidechains && sidechain::detect(&content).is_some() {
+                return None;
+            }
+            let modified = modified(path);
+            // The whole transcript is at hand, so the filter never fails to read it
+            let matches = filter.matches_with(path, modified, || Ok(SessionPeek::from_head(&content)));
+            if !matches.unwrap_or(false) {
+                return None;
+            }
+            Some(ListedSession::new(path, &content, modified, namespace, rules))
+        })
+        .collect()
+}
+
+pub fn sort_sessions(sessions: &mut [ListedSession], key: SortKey, reverse: bool) {
+    match key {
+        SortKey::Started => sessions.sort_by_key(|session| Reverse(session.entry.started)),
+        SortKey::Duration => sessions.sort_by_key(|session| Reverse(session.duration())),
+        SortKey::Messages => sessions.sort_by_key(|session| Reverse(session.entry.messages)),
+        SortKey::Size => sessions.sort_by_key(|session| Reverse(session.size)),
+        SortKey::Id => sessions.sort_by(|a, b| a.id.cmp(&b.id)),
+    }
+    if reverse {
+        sessions.reverse();
+    }
+}
+
+/// Finds the session whose id is `id`, or starts with it.
+pub fn find_session<'a>(snapshot: &'a Snapshot, id: &str) -> Result<(&'a PathBuf, &'a Vec<u8>), ListError> {
+    if let Some(found) = snapshot.iter().find(|(path, _)| session_id(path) == id) {
+        return Ok(found);
+    }
+
+    let matches: Vec<_> = snapshot.iter().filter(|(path, _)| session_id(path).starts_with(id)).collect();
+    match matches.as_slice() {
+        [found] => Ok(*found),
+        [] => Err(ListError::NotFound(id.to_string())),
+        _ => Err(ListError::Ambiguous {
+            id: id.to_string(),
+            matches: matches.iter().map(|(path, _)| session_id(path)).collect(),
+        }),
+    }
+}
+
+/// Duration like `1h 05m`, `12m` or `40s`.
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let seconds = duration.num_seconds().max(0);
+    match (seconds / 3600, seconds / 60 % 60) {
+        (0, 0) => format!("{}s", seconds),
+        (0, minutes) => format!("{}m", minutes),
+        (hours, minutes) => format!("{}h {:02}m", hours, minutes),
+    }
+}
+
+/// Size like `512 B`, `3.4 KB` or `1.2 MB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Renders the `list` table, with the start times in `timezone`.
+pub fn render_table<Tz: chrono::TimeZone>(sessions: &[ListedSession], timezone: &Tz) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    let rows: Vec<[String; 7]> = sessions.iter()
+        .map(|session| [
+            match &session.namespace {
+                Some(namespace) => format!("{}/{}", namespace, session.id),
+                None => session.id.clone(),
+            },
+            session.entry.started
+                .map(|started| started.with_timezone(timezone).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default(),
+            session.duration().map(format_duration).unwrap_or_default(),
+            session.entry.git_branch.clone().unwrap_or_default(),
+            session.entry.messages.to_string(),
+            format_size(session.size),
+            shorten(session.entry.first_prompt.as_deref().unwrap_or_default(), PROMPT_WIDTH),
+        ])
+        .collect();
+
+    let header = ["ID", "STARTED", "DURATION", "BRANCH", "MESSAGES", "SIZE", "FIRST PROMPT"].map(str::to_string);
+    let mut widths = [0; 7];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut output = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let mut line = String::new();
+        for (column, (cell, width)) in row.iter().zip(widths).enumerate() {
+            // Numbers line up on the right
+            if matches!(column, 4 | 5) {
+                line.push_str(&format!("{:>width$}  ", cell, width = width));
+            } else {
+                line.push_str(&format!("{:<width$}  ", cell, width = width));
+            }
+        }
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn user(session_id: &str, timestamp: &str, prompt: &str) -> String {
+        format!(
+            r#"{{"type":"user","sessionId":"{}","gitBranch":"main","timestamp":"{}","message":{{"role":"user","content":"{}"}}}}"#,
+            session_id, timestamp, prompt
+        )
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot::from([
+            (PathBuf::from("p/aaa111.jsonl"), format!(
+                "{}\n{}\n",
+                user("aaa111", "2025-06-01T10:00:00Z", "Fix the build"),
+                user("aaa111", "2025-06-01T11:30:00Z", "Thanks"),
+            ).into_bytes()),
+            (PathBuf::from("p/aab222.jsonl"), format!("{}\n", user("aab222", "2025-06-02T09:00:00Z", "Add a test")).into_bytes()),
+            (PathBuf::from("p/agent-1.jsonl"), concat!(
+                r#"{"type":"user","isSidechain":true,"sessionId":"aaa111","message":{"role":"user","content":"Search"}}"#,
+                "\n",
+            ).as_bytes().to_vec()),
+        ])
+    }
+
+    #[test]
+    fn test_list_sessions() {
+        let snapshot = snapshot();
+        let modified = |_: &Path| SystemTime::now();
+        let mut sessions = list_sessions(&snapshot, &modified, &SessionFilter::default(), false, None, &RedactionRules::default());
+        assert_eq!(sessions.len(), 2);
+
+        sort_sessions(&mut sessions, SortKey::Started, false);
+        assert_eq!(sessions[0].id, "aab222");
+        sort_sessions(&mut sessions, SortKey::Duration, false);
+        assert_eq!(sessions[0].id, "aaa111");
+        assert_eq!(sessions[0].duration().map(format_duration).as_deref(), Some("1h 30m"));
+        assert_eq!(sessions[0].entry.messages, 2);
+
+        let table = render_table(&sessions, &Utc);
+        assert_eq!(table.lines().next(), Some("ID      STARTED           DURATION  BRANCH  MESSAGES   SIZE  FIRST PROMPT"));
+        assert!(table.lines().nth(1).unwrap().starts_with("aaa111  2025-06-01 10:00  1h 30m    main"));
+
+        let filter = SessionFilter { session_ids: vec!["aab".to_string()], ..Default::default() };
+        assert_eq!(list_sessions(&snapshot, &modified, &filter, false, None, &RedactionRules::default()).len(), 1);
+        assert_eq!(list_sessions(&snapshot, &modified, &SessionFilter::default(), true, None, &RedactionRules::default()).len(), 3);
+
+        let old = |_: &Path| SystemTime::now() - Duration::from_secs(86400 * 2);
+        let filter = SessionFilter { since: Some(Utc::now() - chrono::Duration::days(1)), ..Default::default() };
+        assert!(list_sessions(&snapshot, &old, &filter, false, None, &RedactionRules::default()).is_empty());
+    }
+
+    #[test]
+    fn test_find_session() {
+        let snapshot = snapshot();
+        assert_eq!(find_session(&snapshot, "aab").unwrap().0, &PathBuf::from("p/aab222.jsonl"));
+        assert_eq!(find_session(&snapshot, "aaa111").unwrap().0, &PathBuf::from("p/aaa111.jsonl"));
+        assert_eq!(find_session(&snapshot, "zzz"), Err(ListError::NotFound("zzz".to_string())));
+        assert!(matches!(find_session(&snapshot, "aa"), Err(ListError::Ambiguous { matches, .. }) if matches.len() == 2));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_duration(chrono::Duration::seconds(40)), "40s");
+        assert_eq!(format_duration(chrono::Duration::seconds(750)), "12m");
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(3482), "3.4 KB");
+        assert_eq!(format_size(1_300_000), "1.2 MB");
+    }
+}
@@ -1,12 +1,40 @@
 use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use cc_sync_session::{RealFileSystem, SessionSyncer, SyncOptions};
+use clap::{Args, Parser, Subcommand};
+use cc_sync_session::{FileSystem, RealFileSystem, SessionSyncer, SyncOptions};
+use cc_sync_session::sync::{self as session_sync, parse_since, parse_until, SessionFilter, SyncPolicy};
+use chrono::{DateTime, Utc};
 use log::warn;
 use cc_sync_session::file_path_converter::dir_path_to_claude_code_stype;
-use git2::Repository;
+use cc_sync_session::diff::{self, ChangeKind, TurnSummary};
+use cc_sync_session::sidechain::SidechainMode;
+use cc_sync_session::{dedup, gitattributes, merge, render, session, store, trailers, verify};
+use cc_sync_session::render::RenderOptions;
+use cc_sync_session::branch::{BranchCheckout, BranchStore, MergeResult, DEFAULT_STORE_BRANCH};
+use cc_sync_session::bundle::{self, Bundle, ImportOptions, ImportTarget};
+use cc_sync_session::compression::{self, Compression, StoreFormat};
+use cc_sync_session::encryption::{self, Encryption, Identities};
+use cc_sync_session::hooks::{self, Hook, HookState};
+use cc_sync_session::doctor::{self, DoctorReport, Finding};
+use cc_sync_session::list::{self, ListError, SortKey};
+use cc_sync_session::config::{self, Layers, LayeredConfig, RepoConfig, StoreMode};
+use cc_sync_session::index::{self, RedactionRules, INDEX_JSON_FILE};
+use cc_sync_session::layout::{self, Layout, STORE_DESCRIPTOR_FILE, STORE_VERSION};
+use cc_sync_session::namespace::{self, AuthorMap, Namespace, NamespaceFilter};
+use cc_sync_session::manifest::Manifest;
+use cc_sync_session::prune::{self, KeepReason};
+use cc_sync_session::registry::{self, Registry, RepoState};
+use cc_sync_session::status::{self, GitState, SyncState};
+use cc_sync_session::watch;
+use cc_sync_session::tui::{self, Action};
+use ratatui::crossterm::{self, event::{self, Event, KeyEventKind}};
+use notify::{RecursiveMode, Watcher};
+use git2::{ConfigLevel, Repository};
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{IsTerminal, Write};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Parser, Debug)]
 #[command(name = "cc-sync-session")]
@@ -18,6 +46,154 @@ struct Cli {
     /// Enable verbose output
     #[arg(short, long, global = true)]
     verbose: bool,
+
+    /// age identity file to decrypt an encrypted store with; can be given more than once
+    /// (in addition to $CC_SYNC_SESSION_IDENTITY_FILE or ~/.config/cc-sync-session/identity.txt)
+    #[arg(short, long, global = true, value_name = "FILE")]
+    identity: Vec<PathBuf>,
+}
+
+/// Options selecting sessions, shared by the commands that take them
+#[derive(Args, Debug)]
+struct FilterArgs {
+    /// Only this session (id or id prefix); can be given more than once
+    #[arg(long = "session", value_name = "ID")]
+    sessions: Vec<String>,
+
+    /// Only sessions active since this time (YYYY-MM-DD or RFC 3339)
+    #[arg(long, value_parser = parse_since)]
+    since: Option<DateTime<Utc>>,
+
+    /// Only sessions started until this time (YYYY-MM-DD or RFC 3339)
+    #[arg(long, value_parser = parse_until)]
+    until: Option<DateTime<Utc>>,
+
+    /// Only sessions started on this git branch
+    #[arg(long, value_name = "BRANCH")]
+    git_branch: Option<String>,
+}
+
+impl From<FilterArgs> for SessionFilter {
+    fn from(args: FilterArgs) -> Self {
+        SessionFilter {
+            session_ids: args.sessions,
+            since: args.since,
+            until: args.until,
+            git_branch: args.git_branch,
+            private_ids: Vec::new(),
+        }
+    }
+}
+
+/// Options selecting the namespaces of a namespaced store
+#[derive(Args, Debug)]
+struct NamespaceArgs {
+    /// Only sessions of this author (a name or an email mapped in .claude/ccss-authors.toml)
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Only sessions synced from this machine
+    #[arg(long)]
+    machine: Option<String>,
+}
+
+impl NamespaceArgs {
+    fn filter(&self, repo_dir: &Path) -> Result<NamespaceFilter> {
+        let authors = AuthorMap::load(&RealFileSystem::new(), repo_dir)?;
+        Ok(NamespaceFilter::new(self.author.as_deref(), self.machine.as_deref(), &authors))
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print every effective setting, from defaults, the user configuration,
+    /// .claude/ccss.toml, CC_SYNC_SESSION_* variables and options in that order
+    Show {
+        /// Print where each value comes from
+        #[arg(long)]
+        origin: bool,
+
+        /// Repository directory (defaults to current directory or parent with .git)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct HookArgs {
+    /// Only this hook: pre-commit, prepare-commit-msg, post-commit or pre-push
+    /// (can be given more than once; defaults to all of them)
+    #[arg(long = "hook", value_name = "HOOK")]
+    hooks: Vec<Hook>,
+
+    /// Repository directory (defaults to current directory or parent with .git)
+    #[arg(short = 'r', long)]
+    repo_dir: Option<PathBuf>,
+}
+
+impl HookArgs {
+    fn hooks(&self) -> Vec<Hook> {
+        if self.hooks.is_empty() {
+            Hook::ALL.to_vec()
+        } else {
+            self.hooks.clone()
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum RegistryCommands {
+    /// Show the repositories init registered, and whether sync --all can still sync them
+    List {
+        /// Print the repositories as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Unregister repositories, e.g. ones that were deleted
+    Remove {
+        /// Repository directories to unregister
+        #[arg(required_unless_present = "stale")]
+        paths: Vec<PathBuf>,
+
+        /// Unregister every repository that is gone, no longer a git repository
+        /// or no longer initialized
+        #[arg(long)]
+        stale: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HooksCommands {
+    /// Write git hooks running cc-sync-session, chaining to the hooks already there
+    Install {
+        #[command(flatten)]
+        hooks: HookArgs,
+    },
+
+    /// Remove the git hooks written by install, putting back the hooks they chained to
+    Uninstall {
+        #[command(flatten)]
+        hooks: HookArgs,
+    },
+
+    /// Show which git hooks are installed
+    Status {
+        /// Repository directory (defaults to current directory or parent with .git)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+    },
+
+    /// Run what an installed hook does (called by the hook scripts)
+    #[command(hide = true)]
+    Run {
+        /// The hook git runs
+        hook: Hook,
+
+        /// Arguments git passes to the hook
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -27,12 +203,29 @@ enum Commands {
         /// Repository directory (defaults to current directory or parent with .git)
         #[arg(short = 'r', long)]
         repo_dir: Option<PathBuf>,
+
+        /// Register the textconv diff driver so git diff shows sessions as transcripts
+        #[arg(long)]
+        diff_driver: bool,
+
+        /// Register the merge driver that unions session transcripts by message uuid
+        #[arg(long)]
+        merge_driver: bool,
+
+        /// Keep the store on this orphan branch instead of the working tree
+        /// (ccss/sessions if no branch is given)
+        #[arg(long, value_name = "BRANCH", num_args = 0..=1, default_missing_value = DEFAULT_STORE_BRANCH)]
+        store_branch: Option<String>,
+
+        /// Lay a new store out as <author>/<machine> namespaces ([store] layout)
+        #[arg(long)]
+        namespaced: bool,
     },
     
     /// Sync session files to the repository
     Sync {
         /// Source directory containing Claude Code sessions
-        /// (defaults to $CC_SYNC_SESSION_SOURCE_DIR or ~/.claude/projects/)
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
         #[arg(short, long)]
         source_dir: Option<PathBuf>,
         
@@ -40,244 +233,2952 @@ enum Commands {
         /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
         #[arg(short = 'r', long)]
         repo_dir: Option<PathBuf>,
+
+        /// Sync every repository registered by init, one after the other, and
+        /// report on all of them (exit code: the most severe of theirs)
+        #[arg(long, conflicts_with = "repo_dir")]
+        all: bool,
         
         /// Run in dry-run mode (show what would be done without making changes)
         #[arg(short, long)]
         dry_run: bool,
         
-        /// Add .claude/ccss_sessions to git after sync
+        /// Add .claude/ccss_sessions to git after sync ([sync] git_add)
+        #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+        git_add: Option<bool>,
+
+        /// Store records already stored in another session (e.g. the history a
+        /// resumed session repeats) once, replacing the copies with references
+        /// ([sync] deduplicate)
+        #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+        deduplicate: Option<bool>,
+
+        /// What to do with subagent sidechain transcripts: sync (copy as is),
+        /// nest (store under <parent-session-id>/sidechains/) or skip
+        /// ([sync] sidechains, defaults to sync)
+        #[arg(long)]
+        sidechains: Option<SidechainMode>,
+
+        /// Keep a generated INDEX.md and sessions.json at the store root ([sync] index)
+        #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+        index: Option<bool>,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        /// Only sync sessions started on the branch checked out in the repository
+        /// ([sync] current_branch)
+        #[arg(long, value_name = "BOOL", num_args = 0..=1, require_equals = true, default_missing_value = "true", conflicts_with = "git_branch")]
+        current_branch: Option<bool>,
+
+        /// How to end when sessions were pending: check (sync nothing, exit 1),
+        /// fix-and-fail (sync, exit 3) or fix-and-pass (sync and stage, exit 0)
+        /// ([sync] policy, defaults to fix-and-fail)
+        #[arg(long, value_name = "POLICY")]
+        policy: Option<SyncPolicy>,
+    },
+
+    /// Keep syncing sessions as Claude Code writes them, until interrupted
+    Watch {
+        /// Source directory containing Claude Code sessions
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
+        #[arg(short, long)]
+        source_dir: Option<PathBuf>,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        /// Seconds without changes before a burst of changes is synced
+        #[arg(long, value_name = "SECONDS", default_value_t = 2.0)]
+        debounce: f64,
+
+        /// Seconds a change waits at most while a session keeps being written
+        #[arg(long, value_name = "SECONDS", default_value_t = 30.0)]
+        max_delay: f64,
+
+        /// Print a systemd user unit running watch for the repository and exit
+        #[arg(long)]
+        systemd_unit: bool,
+    },
+
+    /// List the sessions in the store, or in Claude Code's projects
+    List {
+        /// List the sessions in Claude Code's projects instead of the store
+        #[arg(long)]
+        source: bool,
+
+        /// Source directory containing Claude Code sessions
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
+        #[arg(short, long)]
+        source_dir: Option<PathBuf>,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        #[command(flatten)]
+        namespaces: NamespaceArgs,
+
+        /// Include subagent sidechain transcripts
+        #[arg(long)]
+        sidechains: bool,
+
+        /// Order by started (latest first), duration, messages, size (largest first) or id
+        #[arg(long, value_name = "KEY", default_value_t = SortKey::Started)]
+        sort: SortKey,
+
+        /// Reverse the order
+        #[arg(long)]
+        reverse: bool,
+
+        /// Show at most this many sessions
+        #[arg(short = 'n', long, value_name = "N")]
+        limit: Option<usize>,
+
+        /// Print the sessions as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the conversation of a session
+    Show {
+        /// Session id, or the start of it
+        id: String,
+
+        /// Look the session up in Claude Code's projects instead of the store
+        #[arg(long)]
+        source: bool,
+
+        /// Source directory containing Claude Code sessions
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
+        #[arg(short, long)]
+        source_dir: Option<PathBuf>,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        namespaces: NamespaceArgs,
+
+        /// Show tool inputs and results in full
+        #[arg(long)]
+        tools: bool,
+
+        /// Print the records as pretty-printed JSON
+        #[arg(long, conflicts_with = "tools")]
+        raw: bool,
+
+        /// Print directly instead of through $PAGER
+        #[arg(long)]
+        no_pager: bool,
+    },
+
+    /// Browse sessions, compare them with the store and act on them interactively
+    Tui {
+        /// Source directory containing Claude Code sessions
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
+        #[arg(short, long)]
+        source_dir: Option<PathBuf>,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+
+    /// Summarize new session turns between the source and the store, or between store revisions
+    Diff {
+        /// Store revision to compare from (without it, the source is compared with the store)
+        from: Option<String>,
+
+        /// Store revision to compare to (defaults to the store in the working tree)
+        to: Option<String>,
+
+        /// Source directory containing Claude Code sessions
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
+        #[arg(short, long)]
+        source_dir: Option<PathBuf>,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        namespaces: NamespaceArgs,
+    },
+
+    /// Print a session file as a readable transcript (git textconv driver)
+    Textconv {
+        /// Session file to convert
+        file: PathBuf,
+    },
+
+    /// Merge three versions of a session file (git merge driver)
+    MergeDriver {
+        /// Common ancestor version (%O)
+        base: PathBuf,
+
+        /// Current version (%A); receives the merge result
+        ours: PathBuf,
+
+        /// Other branch's version (%B)
+        theirs: PathBuf,
+
+        /// Path of the merged file in the repository (%P)
+        path: Option<String>,
+    },
+
+    /// Append Claude-Session trailers to a commit message (prepare-commit-msg hook)
+    Trailers {
+        /// Commit message file passed to the prepare-commit-msg hook
+        message_file: PathBuf,
+
+        /// Source of the commit message (message, template, merge, squash or commit)
+        /// (defaults to $PRE_COMMIT_COMMIT_MSG_SOURCE)
+        commit_source: Option<String>,
+
+        /// Commit object name given with the "commit" source, e.g. HEAD on amend
+        /// (defaults to $PRE_COMMIT_COMMIT_OBJECT_NAME)
+        commit_sha: Option<String>,
+
+        /// Source directory containing Claude Code sessions
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
+        #[arg(short, long)]
+        source_dir: Option<PathBuf>,
+
+        /// Repository directory (defaults to current directory or parent with .git)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+    },
+
+    /// Remove sessions from the store according to the [retention] rules in .claude/ccss.toml
+    Prune {
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        /// Show what would be pruned without changing anything
+        #[arg(short, long)]
+        dry_run: bool,
+
+        /// Write the pruned sessions to this new zstd-compressed tar archive before removing them
+        #[arg(long, value_name = "FILE")]
+        archive: Option<PathBuf>,
+
+        /// Stage the removals (and the updated manifest and index) in git
         #[arg(long)]
         git_add: bool,
     },
+
+    /// Convert the session store to another compression and make it the store's setting
+    Convert {
+        /// Compression to convert the store to: zstd (.jsonl.zst) or none (.jsonl)
+        compression: Compression,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+    },
+
+    /// Convert the store to another layout version and stage the renamed files
+    Migrate {
+        /// Store version to convert to (defaults to the newest)
+        #[arg(long, default_value_t = STORE_VERSION)]
+        to: u32,
+
+        /// Also move the files to this layout (encoded or namespaced); existing
+        /// files move into the namespace of the current user and machine
+        #[arg(long, value_name = "LAYOUT")]
+        layout: Option<Layout>,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        /// Show what would change without changing anything
+        #[arg(short = 'd', long)]
+        dry_run: bool,
+    },
+
+    /// Create an age identity for decrypting an encrypted store and print its public key
+    Keygen {
+        /// Identity file to create (defaults to ~/.config/cc-sync-session/identity.txt)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Encrypt every session in the store to the [encryption] recipients in .claude/ccss.toml
+    ReEncrypt {
+        /// Add this recipient (age1...) to the configuration first; can be given more than once
+        #[arg(long, value_name = "RECIPIENT")]
+        add: Vec<String>,
+
+        /// Remove this recipient from the configuration first; can be given more than once
+        #[arg(long, value_name = "RECIPIENT")]
+        remove: Vec<String>,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+    },
+
+    /// Write sessions to a portable bundle (a tar.zst archive) for another machine or repository
+    Export {
+        /// Bundle file to create
+        bundle: PathBuf,
+
+        /// Export the sessions in the repository's store instead of Claude Code's projects
+        #[arg(long)]
+        from_store: bool,
+
+        /// Source directory containing Claude Code sessions
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
+        #[arg(short, long)]
+        source_dir: Option<PathBuf>,
+
+        /// Repository directory (defaults to current directory or parent with .git)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        #[command(flatten)]
+        namespaces: NamespaceArgs,
+    },
+
+    /// Import the sessions of a bundle into the repository's store or Claude Code's projects
+    Import {
+        /// Bundle file to read
+        bundle: PathBuf,
+
+        /// Import into Claude Code's projects directory instead of the repository's store
+        #[arg(long)]
+        to_projects: bool,
+
+        /// Claude Code projects directory to import into with --to-projects
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
+        #[arg(short, long)]
+        source_dir: Option<PathBuf>,
+
+        /// Repository directory; projects of the exported repository are moved under it
+        /// (defaults to current directory or parent with .git)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        /// Replace files that already exist
+        #[arg(long)]
+        force: bool,
+
+        /// Show what would be imported without writing anything
+        #[arg(short, long)]
+        dry_run: bool,
+    },
+
+    /// Install, remove or inspect the git hooks running cc-sync-session
+    Hooks {
+        #[command(subcommand)]
+        command: HooksCommands,
+    },
+
+    /// Show or clean up the repositories sync --all goes through
+    Registry {
+        #[command(subcommand)]
+        command: RegistryCommands,
+    },
+
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Show sessions that differ between the source and the store, without copying
+    /// (exit code 0: nothing to sync or commit, 1: changes pending, 2: could not run)
+    Status {
+        /// Source directory containing Claude Code sessions
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
+        #[arg(short, long)]
+        source_dir: Option<PathBuf>,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        /// One line per session: sync state (A, M, D, C) and git state (S, U, ?)
+        #[arg(long, conflicts_with = "json")]
+        short: bool,
+
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check the session store for corrupt, truncated or tampered files
+    /// (exit code 0: clean or warnings only, 2: could not run, 4: errors found)
+    Verify {
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Diagnose the sync setup: configuration, source and project directories,
+    /// store, .gitattributes and Git LFS, hooks, ignore rules and permissions
+    /// (exit code 0: no errors, 2: could not run, 4: errors found)
+    Doctor {
+        /// Source directory containing Claude Code sessions
+        /// (defaults to [source] dir of the configuration, else ~/.claude/projects/)
+        #[arg(short, long)]
+        source_dir: Option<PathBuf>,
+
+        /// Repository directory (defaults to current directory or parent with .git)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Fetch the store branch from a remote and merge it into the local one
+    Pull {
+        /// Remote to fetch from
+        #[arg(default_value = "origin")]
+        remote: String,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+    },
+
+    /// Push the store branch to a remote
+    Push {
+        /// Remote to push to
+        #[arg(default_value = "origin")]
+        remote: String,
+
+        /// Repository directory
+        /// (defaults to current directory or parent with .git and .claude/ccss_sessions)
+        #[arg(short = 'r', long)]
+        repo_dir: Option<PathBuf>,
+    },
+}
+
+/// Find a repository directory by looking for .git and .claude/ccss_sessions
+/// (or .claude/ccss.toml, for a store kept on a branch)
+fn find_repo_dir(start: &Path) -> Option<PathBuf> {
+    let mut current = start.to_path_buf();
+    
+    loop {
+        let git_dir = current.join(".git");
+        let ccss_dir = current.join(".claude").join("ccss_sessions");
+
+        log::debug!("Checking directory: {} git: {}, ccss: {}",
+                    current.display(),
+                    git_dir.exists(),
+                    ccss_dir.exists());
+
+        if git_dir.exists() && (ccss_dir.exists() || current.join(config::REPO_CONFIG_PATH).exists()) {
+            return Some(current);
+        }
+
+        if !current.pop() {
+            break;
+        }
+    }
+    
+    None
+}
+
+/// Find a git repository directory by looking for .git
+fn find_git_repo(start: &Path) -> Option<PathBuf> {
+    let mut current = start.to_path_buf();
+    
+    loop {
+        let git_dir = current.join(".git");
+        
+        if git_dir.exists() {
+            return Some(current);
+        }
+        
+        if !current.pop() {
+            break;
+        }
+    }
+    
+    None
+}
+
+/// Resolve the git repository directory, searching upwards from the current directory
+fn resolve_git_repo_dir(repo_dir: Option<PathBuf>) -> Result<PathBuf> {
+    match repo_dir {
+        Some(dir) => Ok(dir),
+        None => {
+            let current_dir = std::env::current_dir()
+                .context("Failed to get current directory")?;
+            find_git_repo(&current_dir)
+                .context("No git repository found in current directory or parent directories")
+        }
+    }
+}
+
+/// Resolve the root directory holding Claude Code projects: the option, else
+/// `[source] dir` of the configuration, else ~/.claude/projects
+fn resolve_source_root_dir(source_dir: Option<PathBuf>, repo_dir: &Path) -> Result<PathBuf> {
+    if let Some(dir) = source_dir {
+        return Ok(dir);
+    }
+    if let Some(dir) = load_config(repo_dir)?.source.dir() {
+        return Ok(dir);
+    }
+    let home = dirs::home_dir()
+        .context("Failed to get home directory")?;
+    Ok(home.join(".claude").join("projects"))
+}
+
+/// The configuration of `repo_dir` with the user configuration and environment
+fn load_config(repo_dir: &Path) -> Result<RepoConfig> {
+    Ok(load_layered_config(repo_dir, &Layers::from_environment())?.config)
+}
+
+fn load_layered_config(repo_dir: &Path, layers: &Layers) -> Result<LayeredConfig> {
+    Ok(RepoConfig::load_layered(&RealFileSystem::new(), repo_dir, layers)?)
+}
+
+/// Register a git driver in .gitattributes and the repository's git config
+fn register_driver(repo_dir: &Path, gitattributes_path: &Path, attributes_lines: &[&str], settings: &[(String, &str)]) -> Result<()> {
+    for attributes_line in attributes_lines {
+        if gitattributes::ensure_line(gitattributes_path, attributes_line)
+            .context("Failed to update .gitattributes file")?
+        {
+            println!("Added '{}' to .gitattributes", attributes_line);
+        }
+    }
+
+    let repo = Repository::open(repo_dir)
+        .context("Failed to open git repository")?;
+    let mut config = repo.config()
+        .and_then(|config| config.open_level(ConfigLevel::Local))
+        .context("Failed to open repository git config")?;
+
+    for (name, value) in settings {
+        config.set_str(name, value)
+            .with_context(|| format!("Failed to set {} in git config", name))?;
+        println!("Registered {} in git config", name);
+    }
+
+    Ok(())
+}
+
+fn init_command(
+    repo_dir: Option<PathBuf>,
+    diff_driver: bool,
+    merge_driver: bool,
+    store_branch: Option<String>,
+    namespaced: bool,
+) -> Result<()> {
+    let repo_dir = resolve_git_repo_dir(repo_dir)?;
+    init_store(&repo_dir, diff_driver, merge_driver, store_branch, namespaced)?;
+
+    // The repository is usable without the registry, only sync --all misses it
+    if let Err(e) = register_repo(&repo_dir) {
+        warn!("Failed to register {} for 'sync --all': {:#}", repo_dir.display(), e);
+    }
+    Ok(())
+}
+
+/// Record the repository in the user-level registry that `sync --all` goes through
+fn register_repo(repo_dir: &Path) -> Result<()> {
+    let repo_dir = repo_dir.canonicalize()
+        .with_context(|| format!("Failed to resolve {}", repo_dir.display()))?;
+    let registry_path = registry::registry_path()
+        .context("Failed to get the data directory")?;
+    let filesystem = RealFileSystem::new();
+    let mut registry = Registry::load(&filesystem, &registry_path)?;
+    if registry.add(&repo_dir, Utc::now()) {
+        registry.save(&filesystem, &registry_path)?;
+        println!("Registered {} in {}", repo_dir.display(), registry_path.display());
+    }
+    Ok(())
+}
+
+fn init_store(
+    repo_dir: &Path,
+    diff_driver: bool,
+    merge_driver: bool,
+    store_branch: Option<String>,
+    namespaced: bool,
+) -> Result<()> {
+    let layout = if namespaced { Layout::Namespaced } else { load_config(repo_dir)?.store.layout };
+
+    if let Some(branch) = store_branch {
+        // Blobs on the store branch are written directly, so LFS and the drivers are not involved
+        config::set_store_branch(&RealFileSystem::new(), repo_dir, &branch)
+            .context("Failed to update .claude/ccss.toml")?;
+        println!("Set [store] mode = \"branch\" in {}", config::REPO_CONFIG_PATH);
+        println!("Sessions will be committed to the branch {}; the working tree is not touched", branch);
+
+        if layout == Layout::Namespaced {
+            let repo = Repository::open(repo_dir)
+                .context("Failed to open git repository")?;
+            let checkout = BranchCheckout::new(BranchStore::new(repo, &branch))
+                .with_context(|| format!("Failed to check out the store branch {}", branch))?;
+            if checkout.store().head()?.is_some() {
+                println!("The store branch already exists; run 'cc-sync-session migrate --layout namespaced' to namespace it");
+                return Ok(());
+            }
+            let location = StoreLocation::Branch(checkout);
+            layout::init_store_with_layout(&RealFileSystem::new(), &location.dir(), StoreMode::Branch, layout)?;
+            location.commit("Initialize the session store")?;
+        }
+        return Ok(());
+    }
+    
+    // Create .claude/ccss_sessions directory with its descriptor
+    let filesystem = RealFileSystem::new();
+    let ccss_dir = repo_dir.join(".claude").join("ccss_sessions");
+    let descriptor_path = ccss_dir.join(STORE_DESCRIPTOR_FILE);
+    let is_legacy_store = ccss_dir.is_dir() && !descriptor_path.exists()
+        && fs::read_dir(&ccss_dir).context("Failed to read .claude/ccss_sessions directory")?.next().is_some();
+    if is_legacy_store {
+        println!("{} predates store versions; run 'cc-sync-session migrate' to add {}", ccss_dir.display(), STORE_DESCRIPTOR_FILE);
+    } else {
+        layout::store_version(&filesystem, &ccss_dir)?;
+        if !descriptor_path.exists() {
+            layout::init_store_with_layout(&filesystem, &ccss_dir, StoreMode::Worktree, layout)
+                .context("Failed to create .claude/ccss_sessions directory")?;
+        } else if layout == Layout::Namespaced && layout::store_layout(&filesystem, &ccss_dir)? != layout {
+            println!("The store is not namespaced yet; run 'cc-sync-session migrate --layout namespaced' to namespace it");
+        }
+    }
+    
+    // Handle .gitattributes for Git LFS
+    let gitattributes_path = repo_dir.join(".gitattributes");
+    if gitattributes::ensure_line(&gitattributes_path, gitattributes::LFS_LINE)
+        .context("Failed to update .gitattributes file")?
+    {
+        println!("Added Git LFS configuration to .gitattributes");
+    } else {
+        println!("Git LFS configuration already exists in .gitattributes");
+    }
+
+    if diff_driver {
+        register_driver(repo_dir, &gitattributes_path, &[
+            gitattributes::DIFF_DRIVER_LINE,
+            gitattributes::COMPRESSED_DIFF_DRIVER_LINE,
+            gitattributes::ENCRYPTED_DIFF_DRIVER_LINE,
+        ], &[
+            (format!("diff.{}.textconv", gitattributes::DIFF_DRIVER), "cc-sync-session textconv"),
+        ])?;
+    }
+
+    if merge_driver {
+        register_driver(repo_dir, &gitattributes_path, &[
+            gitattributes::MERGE_DRIVER_LINE,
+            gitattributes::COMPRESSED_MERGE_DRIVER_LINE,
+            gitattributes::ENCRYPTED_MERGE_DRIVER_LINE,
+        ], &[
+            (format!("merge.{}.name", gitattributes::MERGE_DRIVER), "Claude Code session union merge"),
+            (format!("merge.{}.driver", gitattributes::MERGE_DRIVER), "cc-sync-session merge-driver %O %A %B %P"),
+        ])?;
+    }
+    
+    println!("Initialized session sync directory at: {}", ccss_dir.display());
+    if !is_legacy_store {
+        println!("Created: {}", descriptor_path.display());
+    }
+    
+    Ok(())
+}
+
+/// Resolve the repository directory holding the session store
+fn resolve_store_repo_dir(repo_dir: Option<PathBuf>) -> Result<PathBuf> {
+    match repo_dir {
+        Some(dir) => Ok(dir),
+        None => {
+            let current_dir = std::env::current_dir()
+                .context("Failed to get current directory")?;
+            find_repo_dir(&current_dir)
+                .context("No repository with .claude/ccss_sessions found. Run 'cc-sync-session init' first")
+        }
+    }
+}
+
+/// The session store a command works on
+enum StoreLocation {
+    /// .claude/ccss_sessions in the working tree
+    Worktree(PathBuf),
+    /// A checkout of the store branch, committed back by `commit`
+    Branch(BranchCheckout),
+}
+
+impl StoreLocation {
+    /// Opens the store, refusing store versions this build does not understand
+    fn open(repo_dir: &Path, config: &RepoConfig) -> Result<Self> {
+        let filesystem = RealFileSystem::new();
+        let location = match config.store.mode {
+            StoreMode::Worktree => Self::Worktree(store::store_dir(repo_dir)),
+            StoreMode::Branch => {
+                let repo = Repository::open(repo_dir)
+                    .context("Failed to open git repository")?;
+                let checkout = BranchCheckout::new(BranchStore::new(repo, config.store.branch()))
+                    .with_context(|| format!("Failed to check out the store branch {}", config.store.branch()))?;
+                // The first commit to the branch starts a new store
+                if checkout.store().head()?.is_none() {
+                    layout::init_store(&filesystem, &checkout.store_dir(), StoreMode::Branch)?;
+                }
+                Self::Branch(checkout)
+            }
+        };
+
+        layout::store_version(&filesystem, &location.dir())
+            .context("Cannot use the session store")?;
+        Ok(location)
+    }
+
+    fn dir(&self) -> PathBuf {
+        match self {
+            Self::Worktree(dir) => dir.clone(),
+            Self::Branch(checkout) => checkout.store_dir(),
+        }
+    }
+
+    fn is_branch(&self) -> bool {
+        matches!(self, Self::Branch(_))
+    }
+
+    fn layout(&self) -> Result<Layout> {
+        Ok(layout::store_layout(&RealFileSystem::new(), &self.dir())?)
+    }
+
+    /// Directory `sync` and `import` write to: the store, or the namespace of
+    /// the current user and machine in a namespaced store
+    fn own_dir(&self, repo_dir: &Path) -> Result<PathBuf> {
+        Ok(match self.layout()? {
+            Layout::Encoded => self.dir(),
+            Layout::Namespaced => self.dir().join(own_namespace(repo_dir)?.path()),
+        })
+    }
+
+    /// Stores of the encoded layout to read: the store itself, or the
+    /// namespaces `filter` selects
+    fn sub_stores(&self, filter: &NamespaceFilter) -> Result<Vec<(Option<Namespace>, PathBuf)>> {
+        let dir = self.dir();
+        match self.layout()? {
+            Layout::Encoded => {
+                if !filter.is_empty() {
+                    anyhow::bail!("--author and --machine need a namespaced store; see 'cc-sync-session migrate --layout'");
+                }
+                Ok(vec![(None, dir)])
+            }
+            Layout::Namespaced => Ok(namespace::list_namespaces(&RealFileSystem::new(), &dir)?
+                .into_iter()
+                .filter(|namespace| filter.matches(namespace))
+                .map(|namespace| {
+                    let namespace_dir = dir.join(namespace.path());
+                    (Some(namespace), namespace_dir)
+                })
+                .collect()),
+        }
+    }
+
+    /// Commit the changes to the store branch; the working tree store is left to git
+    fn commit(&self, message: &str) -> Result<()> {
+        if let Self::Branch(checkout) = self {
+            let branch = checkout.store().branch();
+            match checkout.commit(message).with_context(|| format!("Failed to commit to {}", branch))? {
+                Some(commit) => println!("Committed {} to {}", &commit.to_string()[..7], branch),
+                None => println!("No changes to commit to {}", branch),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The namespace of the current user and machine in the repository
+fn own_namespace(repo_dir: &Path) -> Result<Namespace> {
+    let repo = Repository::open(repo_dir)
+        .context("Failed to open git repository")?;
+    let authors = AuthorMap::load(&RealFileSystem::new(), repo_dir)?;
+    Ok(Namespace::resolve(&repo, &authors)?)
+}
+
+fn sync_command(
+    repo_dir: Option<PathBuf>,
+    layers: Layers,
+    session_ids: Vec<String>,
+    dry_run: bool,
+    verbose: bool,
+    identities: &Identities,
+) -> Result<i32> {
+    // Determine repository directory
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    Ok(sync_repo(&repo_dir, &layers, session_ids, dry_run, verbose, identities)?.exit_code)
+}
+
+/// Claude Code project directories of the repository and its subdirectories
+fn project_dirs(source_root_dir: &Path, repo_dir_cc_style: &str) -> Result<Vec<PathBuf>> {
+    if !source_root_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    SessionSyncer::new(RealFileSystem::new()).find_project_dirs(source_root_dir, repo_dir_cc_style)
+        .with_context(|| format!("Failed to read {}", source_root_dir.display()))
+}
+
+/// What syncing one repository did
+struct RepoSync {
+    exit_code: i32,
+    files_copied: usize,
+    errors: usize,
+    /// Nothing was copied, by --dry-run or the check policy
+    dry_run: bool,
+}
+
+/// The options `sync` stores sessions with, from the configuration
+fn sync_options(layered: &LayeredConfig, identities: &Identities) -> Result<SyncOptions> {
+    let config = &layered.config;
+    Ok(SyncOptions {
+        dry_run: false,
+        verbose: false,
+        deduplicate: config.sync.deduplicate,
+        sidechains: config.sync.sidechains,
+        index: config.writes_index(),
+        filter: layered.session_filter()?,
+        format: StoreFormat {
+            compression: config.store.compression,
+            encryption: config.encryption.encryption()
+                .with_context(|| format!("Invalid [encryption] recipients in {}", config::REPO_CONFIG_PATH))?,
+        },
+        identities: identities.clone(),
+        redaction: config.redaction.clone(),
+        changed_files: None,
+    })
+}
+
+/// The branch checked out in `repo_dir`, for `[sync] current_branch`
+fn current_branch(repo_dir: &Path) -> Result<String> {
+    let repo = Repository::open(repo_dir)
+        .context("Failed to open git repository")?;
+    let head = repo.head().context("Failed to resolve HEAD")?;
+    let branch = head.shorthand()
+        .filter(|_| head.is_branch())
+        .context("HEAD is not on a branch")?;
+    Ok(branch.to_string())
+}
+
+fn sync_repo(
+    repo_dir: &Path,
+    layers: &Layers,
+    session_ids: Vec<String>,
+    dry_run: bool,
+    verbose: bool,
+    identities: &Identities,
+) -> Result<RepoSync> {
+    let repo_dir = repo_dir.to_path_buf();
+    let layered = load_layered_config(&repo_dir, layers)?;
+    let config = &layered.config;
+    let policy = config.sync.policy;
+    let mut options = sync_options(&layered, identities)?;
+    // Checking only looks at what would be copied
+    options.dry_run = dry_run || policy == SyncPolicy::Check;
+    options.verbose = verbose;
+    options.filter.session_ids = session_ids;
+    if config.sync.current_branch {
+        options.filter.git_branch = Some(current_branch(&repo_dir)?);
+    }
+    if config.sync.index && !config.writes_index() {
+        eprintln!("Warning: not writing the session index, it would list encrypted sessions in plain text");
+    }
+    // Passing without staging would commit without the sessions
+    let git_add = config.sync.git_add || policy == SyncPolicy::FixAndPass;
+
+    log::info!("Using repository directory: {}", repo_dir.display());
+    let repo_dir_cc_style = dir_path_to_claude_code_stype(repo_dir.clone())?;
+    log::debug!("Converted repository directory to Claude Code style: {}", repo_dir_cc_style);
+
+    // Determine source directory
+    let source_root_dir = resolve_source_root_dir(config.source.dir(), &repo_dir)?;
+
+    let source_dir = source_root_dir.join(&repo_dir_cc_style);
+
+    log::info!("Using source directory: {}", source_dir.display());
+
+    
+    // Target directory is .claude/ccss_sessions, or a checkout of the store branch,
+    // or the namespace of this user and machine in either
+    let location = StoreLocation::open(&repo_dir, config)?;
+    let target_dir = location.own_dir(&repo_dir)?;
+    
+    // Validate directories; sessions of subdirectories are synced without the repository's own
+    if !source_dir.exists() && project_dirs(&source_root_dir, &repo_dir_cc_style)?.is_empty() {
+        anyhow::bail!("Source directory does not exist: {}", source_dir.display());
+    }
+    
+    if source_dir.exists() && !source_dir.is_dir() {
+        anyhow::bail!("Source path is not a directory: {}", source_dir.display());
+    }
+    
+    if !location.dir().exists() {
+        anyhow::bail!("Target directory does not exist: {}. Run 'cc-sync-session init' first", location.dir().display());
+    }
+    if !options.dry_run {
+        fs::create_dir_all(&target_dir)
+            .with_context(|| format!("Failed to create {}", target_dir.display()))?;
+    }
+    
+    // Print operation summary
+    println!("Syncing Claude Code sessions:");
+    println!("  Source: {}", source_dir.display());
+    match &location {
+        StoreLocation::Worktree(_) => println!("  Target: {}", target_dir.display()),
+        StoreLocation::Branch(checkout) => {
+            let namespace = target_dir.strip_prefix(location.dir()).unwrap_or(Path::new(""));
+            println!("  Target: branch {} {}", checkout.store().branch(), Path::new(store::STORE_PATH).join(namespace).display());
+        }
+    }
+    if options.dry_run {
+        println!("  Mode: DRY RUN (no changes will be made)");
+    }
+    println!();
+    
+    // Create syncer and run sync
+    let filesystem = RealFileSystem::new();
+    let syncer = SessionSyncer::new(filesystem);
+    
+    let result = syncer.sync(&source_root_dir, repo_dir_cc_style.as_str(), &target_dir, &options)
+        .context("Failed to sync sessions")?;
+    
+    // Print results
+    println!("\nSync completed:");
+    println!("  Files copied: {}", result.files_copied);
+    println!("  Files skipped: {}", result.files_skipped);
+    if !options.filter.is_empty() {
+        println!("  Files filtered out: {}", result.files_filtered);
+    }
+    if options.deduplicate {
+        println!("  Files deduplicated: {}", result.files_deduplicated);
+    }
+    println!("  Directories created: {}", result.directories_created);
+    if options.index {
+        println!("  Index updated: {}", if result.index_updated { "yes" } else { "no" });
+    }
+    
+    if !result.errors.is_empty() {
+        println!("\nErrors encountered:");
+        for error in &result.errors {
+            warn!("{}", error);
+            eprintln!("  - {}", error);
+        }
+    }
+    
+    if location.is_branch() {
+        if !options.dry_run && result.files_copied > 0 {
+            println!();
+            location.commit(&format!("Sync {} session files", result.files_copied))?;
+            // Nothing is left to stage in the working tree
+            return Ok(RepoSync { exit_code: 0, files_copied: result.files_copied, errors: result.errors.len(), dry_run: false });
+        }
+    } else if git_add && !options.dry_run && result.files_copied > 0 {
+        // Perform git add if requested and not in dry-run mode
+        let ccss_sessions_path = git_add_store(&repo_dir, &target_dir)?;
+        println!("\nAdded {} to git index", ccss_sessions_path.display());
+    }
+
+    let exit_code = policy.exit_code(result.files_copied, options.dry_run);
+    match exit_code {
+        session_sync::EXIT_PENDING if policy == SyncPolicy::Check => {
+            println!("\n{} session files are not synced; run 'cc-sync-session sync'", result.files_copied);
+        }
+        session_sync::EXIT_SYNCED if git_add => {
+            println!("\nSynced {} session files; commit again to include them", result.files_copied);
+        }
+        session_sync::EXIT_SYNCED => {
+            println!("\nSynced {} session files; stage them and commit again to include them", result.files_copied);
+        }
+        _ => {}
+    }
+
+    Ok(RepoSync { exit_code, files_copied: result.files_copied, errors: result.errors.len(), dry_run: options.dry_run })
+}
+
+/// How `sync --all` ended for one registered repository
+enum RepoOutcome {
+    Synced(RepoSync),
+    /// Claude Code has no project directory for it
+    NoSessions,
+    Skipped(RepoState),
+    Failed(anyhow::Error),
+}
+
+/// Sync every repository in the registry, then report on all of them. The exit
+/// code is the most severe of the repositories' (see [`session_sync::combined_exit_code`])
+fn sync_all_command(layers: Layers, session_ids: Vec<String>, dry_run: bool, verbose: bool, identities: &Identities) -> Result<i32> {
+    let (registry_path, registry) = load_registry()?;
+    let filesystem = RealFileSystem::new();
+    if registry.repos.is_empty() {
+        println!("No repositories registered in {}; run 'cc-sync-session init' in one", registry_path.display());
+        return Ok(0);
+    }
+
+    let mut outcomes = Vec::new();
+    for repo in &registry.repos {
+        let outcome = match registry::repo_state(&filesystem, &repo.path) {
+            Ok(state) if !state.is_ok() => RepoOutcome::Skipped(state),
+            Ok(_) => {
+                let has_sessions = load_layered_config(&repo.path, &layers)
+                    .and_then(|layered| resolve_source_root_dir(layered.config.source.dir(), &repo.path))
+                    .and_then(|source_root_dir| {
+                        let repo_dir_cc_style = dir_path_to_claude_code_stype(repo.path.clone())?;
+                        Ok(!project_dirs(&source_root_dir, &repo_dir_cc_style)?.is_empty())
+                    });
+                match has_sessions {
+                    Ok(false) => RepoOutcome::NoSessions,
+                    Ok(true) => {
+                        println!("== {}", repo.path.display());
+                        let result = sync_repo(&repo.path, &layers, session_ids.clone(), dry_run, verbose, identities);
+                        println!();
+                        match result {
+                            Ok(sync) => RepoOutcome::Synced(sync),
+                            Err(e) => RepoOutcome::Failed(e),
+                        }
+                    }
+                    Err(e) => RepoOutcome::Failed(e),
+                }
+            }
+            Err(e) => RepoOutcome::Failed(e.into()),
+        };
+        outcomes.push((repo.path.as_path(), outcome));
+    }
+
+    let copied = |dry_run: bool| if dry_run { "would be copied" } else { "copied" };
+    let all_dry_run = outcomes.iter().all(|(_, outcome)| !matches!(outcome, RepoOutcome::Synced(sync) if !sync.dry_run));
+    println!("Repositories in {}:", registry_path.display());
+    let width = outcomes.iter().map(|(path, _)| path.display().to_string().len()).max().unwrap_or(0);
+    let (mut files_copied, mut synced, mut skipped, mut failed) = (0, 0, 0, 0);
+    for (path, outcome) in &outcomes {
+        let description = match outcome {
+            RepoOutcome::Synced(sync) => {
+                files_copied += sync.files_copied;
+                synced += 1;
+                match sync.errors {
+                    0 => format!("{} files {}", sync.files_copied, copied(sync.dry_run)),
+                    errors => format!("{} files {}, {} errors", sync.files_copied, copied(sync.dry_run), errors),
+                }
+            }
+            RepoOutcome::NoSessions => "no sessions".to_string(),
+            RepoOutcome::Skipped(state) => {
+                skipped += 1;
+                format!("skipped: {}", state)
+            }
+            RepoOutcome::Failed(e) => {
+                failed += 1;
+                format!("failed: {:#}", e)
+            }
+        };
+        println!("  {:<width$}  {}", path.display().to_string(), description, width = width);
+    }
+    println!("Total: {} files {} in {} repositories, {} skipped, {} failed", files_copied, copied(all_dry_run), synced, skipped, failed);
+    if skipped > 0 {
+        println!("Run 'cc-sync-session registry remove --stale' to drop repositories that are gone");
+    }
+
+    Ok(session_sync::combined_exit_code(outcomes.iter().map(|(_, outcome)| match outcome {
+        RepoOutcome::Synced(sync) => sync.exit_code,
+        RepoOutcome::Failed(_) => session_sync::EXIT_ERROR,
+        RepoOutcome::NoSessions | RepoOutcome::Skipped(_) => 0,
+    })))
+}
+
+/// Add all files under the store directory `target_dir` to the git index
+fn git_add_store(repo_dir: &Path, target_dir: &Path) -> Result<PathBuf> {
+    let repo = Repository::open(repo_dir)
+        .context("Failed to open git repository")?;
+
+    let ccss_sessions_path = target_dir.strip_prefix(repo_dir)
+        .unwrap_or(target_dir);
+
+    let mut index = repo.index()
+        .context("Failed to get repository index")?;
+
+    // Add all files under .claude/ccss_sessions recursively
+    index.add_all([ccss_sessions_path], git2::IndexAddOption::DEFAULT, None)
+        .context("Failed to add .claude/ccss_sessions to git index")?;
+
+    index.write()
+        .context("Failed to write git index")?;
+
+    Ok(ccss_sessions_path.to_path_buf())
+}
+
+enum WatchMessage {
+    Event(notify::Result<notify::Event>),
+    Stop,
+}
+
+fn watch_command(
+    source_dir: Option<PathBuf>,
+    repo_dir: Option<PathBuf>,
+    debounce: f64,
+    max_delay: f64,
+    systemd_unit: bool,
+    identities: &Identities,
+) -> Result<()> {
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    if systemd_unit {
+        let exe = std::env::current_exe().context("Failed to find the cc-sync-session executable")?;
+        let repo_dir = repo_dir.canonicalize().unwrap_or(repo_dir);
+        print!("{}", watch::systemd_unit(&exe, &repo_dir));
+        return Ok(());
+    }
+
+    let mut layers = Layers::from_environment();
+    if let Some(dir) = source_dir {
+        layers = layers.with_command_line("source.dir", dir.to_string_lossy().into_owned());
+    }
+    let layered = load_layered_config(&repo_dir, &layers)?;
+    let config = &layered.config;
+    let source_root_dir = resolve_source_root_dir(config.source.dir(), &repo_dir)?;
+    let repo_dir_cc_style = dir_path_to_claude_code_stype(repo_dir.clone())?;
+    let mut options = sync_options(&layered, identities)?;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let events = sender.clone();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = events.send(WatchMessage::Event(event));
+    }).context("Failed to start watching files")?;
+    ctrlc::set_handler(move || {
+        let _ = sender.send(WatchMessage::Stop);
+    }).context("Failed to handle signals")?;
+
+    // New project directories show up in the projects directory; sessions are watched inside them
+    if !source_root_dir.is_dir() {
+        anyhow::bail!("Source directory does not exist: {}", source_root_dir.display());
+    }
+    watcher.watch(&source_root_dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", source_root_dir.display()))?;
+    let syncer = SessionSyncer::new(RealFileSystem::new());
+    let mut project_dirs = BTreeSet::new();
+    let watch_projects = |watcher: &mut notify::RecommendedWatcher, project_dirs: &mut BTreeSet<PathBuf>| -> Result<()> {
+        for dir in syncer.find_project_dirs(&source_root_dir, &repo_dir_cc_style)? {
+            if project_dirs.insert(dir.clone()) {
+                watcher.watch(&dir, RecursiveMode::Recursive)
+                    .with_context(|| format!("Failed to watch {}", dir.display()))?;
+                println!("Watching {}", dir.display());
+            }
+        }
+        Ok(())
+    };
+    watch_projects(&mut watcher, &mut project_dirs)?;
+
+    let sync_batch = |options: &SyncOptions| -> Result<()> {
+        let location = StoreLocation::open(&repo_dir, config)?;
+        let target_dir = location.own_dir(&repo_dir)?;
+        if !location.dir().exists() {
+            anyhow::bail!("Target directory does not exist: {}. Run 'cc-sync-session init' first", location.dir().display());
+        }
+        fs::create_dir_all(&target_dir)
+            .with_context(|| format!("Failed to create {}", target_dir.display()))?;
+
+        let result = syncer.sync(&source_root_dir, &repo_dir_cc_style, &target_dir, options)
+            .context("Failed to sync sessions")?;
+        for error in &result.errors {
+            warn!("{}", error);
+            eprintln!("  - {}", error);
+        }
+        if result.files_copied == 0 {
+            return Ok(());
+        }
+
+        println!("[{}] Synced {} session files", chrono::Local::now().format("%H:%M:%S"), result.files_copied);
+        if location.is_branch() {
+            location.commit(&format!("Sync {} session files", result.files_copied))?;
+        } else if config.sync.git_add {
+            git_add_store(&repo_dir, &target_dir)?;
+        }
+        Ok(())
+    };
+    // Whatever changed while nobody was watching
+    sync_batch(&options)?;
+    println!("Syncing changes to {} (Ctrl-C to stop)", repo_dir.display());
+
+    let mut debouncer = watch::Debouncer::new(
+        Duration::from_secs_f64(debounce.max(0.0)),
+        Duration::from_secs_f64(max_delay.max(0.0)),
+    );
+    loop {
+        let message = match debouncer.timeout(Instant::now()) {
+            Some(timeout) => receiver.recv_timeout(timeout),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match message {
+            Ok(WatchMessage::Event(Ok(event))) => {
+                if event.paths.iter().any(|path| path.parent() == Some(source_root_dir.as_path())) {
+                    watch_projects(&mut watcher, &mut project_dirs)?;
+                }
+                for path in watch::changed_paths(&event) {
+                    if project_dirs.iter().any(|dir| path.starts_with(dir)) {
+                        debouncer.add(path.clone(), Instant::now());
+                    }
+                }
+            }
+            Ok(WatchMessage::Event(Err(e))) => warn!("Watch error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(WatchMessage::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if let Some(changed) = debouncer.take_ready(Instant::now()) {
+            options.changed_files = Some(changed);
+            // A failed batch is retried with the next change
+            if let Err(e) = sync_batch(&options) {
+                eprintln!("Error: {:#}", e);
+            }
+        }
+    }
+
+    // Changes still waiting for a quiet period are not lost
+    if !debouncer.is_empty() {
+        options.changed_files = Some(debouncer.take());
+        sync_batch(&options)?;
+    }
+    println!("Stopped watching");
+
+    Ok(())
+}
+
+/// Sessions read for `list` and `show`, by namespace
+struct SessionGroup {
+    namespace: Option<Namespace>,
+    /// Expanded transcripts keyed by their path relative to the store or source root
+    snapshot: store::Snapshot,
+    modified: BTreeMap<PathBuf, SystemTime>,
+    /// The `[redaction]` rules of the repository
+    redaction: RedactionRules,
+}
+
+/// Reads the sessions of the store, or of the source if `from_source`
+fn read_session_groups(
+    from_source: bool,
+    source_dir: Option<PathBuf>,
+    repo_dir: Option<PathBuf>,
+    namespaces: &NamespaceArgs,
+    identities: &Identities,
+) -> Result<Vec<SessionGroup>> {
+    let filesystem = RealFileSystem::new();
+    if from_source {
+        let repo_dir = resolve_git_repo_dir(repo_dir)?;
+        let redaction = load_config(&repo_dir)?.redaction;
+        let repo_dir_cc_style = dir_path_to_claude_code_stype(repo_dir.clone())?;
+        let source_root_dir = resolve_source_root_dir(source_dir, &repo_dir)?;
+        let entries = SessionSyncer::new(filesystem.clone())
+            .find_session_files(&source_root_dir, &repo_dir_cc_style)
+            .context("Failed to list session files")?;
+        let snapshot = store::read_snapshot(&filesystem, &source_root_dir, &entries, &Identities::default())
+            .context("Failed to read session files")?;
+        let modified = entries.into_iter()
+            .filter_map(|entry| Some((entry.path.strip_prefix(&source_root_dir).ok()?.to_path_buf(), entry.modified)))
+            .collect();
+        return Ok(vec![SessionGroup { namespace: None, snapshot, modified, redaction }]);
+    }
+
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    let config = load_config(&repo_dir)?;
+    let location = StoreLocation::open(&repo_dir, &config)?;
+    let mut groups = Vec::new();
+    for (namespace, target_dir) in location.sub_stores(&namespaces.filter(&repo_dir)?)? {
+        let snapshot = store::read_store_snapshot(&filesystem, &target_dir, identities)
+            .context("Failed to read the session store")?;
+        let snapshot = dedup::expand_snapshot(&snapshot)
+            .context("Failed to rebuild deduplicated sessions")?;
+        let modified = filesystem.walk_files(&target_dir)
+            .context("Failed to list the session store")?
+            .into_iter()
+            .filter_map(|entry| {
+                let relative = entry.path.strip_prefix(&target_dir).ok()?;
+                Some((compression::logical_path(relative), entry.modified))
+            })
+            .collect();
+        groups.push(SessionGroup { namespace, snapshot, modified, redaction: config.redaction.clone() });
+    }
+    Ok(groups)
+}
+
+/// How `list` selects and orders sessions
+struct ListOptions {
+    sidechains: bool,
+    sort: SortKey,
+    reverse: bool,
+    limit: Option<usize>,
+    json: bool,
+}
+
+fn list_command(
+    from_source: bool,
+    source_dir: Option<PathBuf>,
+    repo_dir: Option<PathBuf>,
+    filter: SessionFilter,
+    namespaces: NamespaceArgs,
+    options: ListOptions,
+    identities: &Identities,
+) -> Result<()> {
+    let mut sessions = Vec::new();
+    for group in read_session_groups(from_source, source_dir, repo_dir, &namespaces, identities)? {
+        let modified = |path: &Path| group.modified.get(path).copied().unwrap_or(UNIX_EPOCH);
+        let namespace = group.namespace.as_ref().map(|namespace| namespace.path().to_string_lossy().into_owned());
+        sessions.extend(list::list_sessions(&group.snapshot, &modified, &filter, options.sidechains, namespace.as_deref(), &group.redaction));
+    }
+    list::sort_sessions(&mut sessions, options.sort, options.reverse);
+    if let Some(limit) = options.limit {
+        sessions.truncate(limit);
+    }
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&sessions).context("Failed to serialize the sessions")?);
+    } else if sessions.is_empty() {
+        println!("No sessions");
+    } else {
+        print!("{}", list::render_table(&sessions, &chrono::Local));
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show_command(
+    id: String,
+    from_source: bool,
+    source_dir: Option<PathBuf>,
+    repo_dir: Option<PathBuf>,
+    namespaces: NamespaceArgs,
+    tools: bool,
+    raw: bool,
+    no_pager: bool,
+    identities: &Identities,
+) -> Result<()> {
+    let groups = read_session_groups(from_source, source_dir, repo_dir, &namespaces, identities)?;
+    let mut found = Vec::new();
+    for group in &groups {
+        match list::find_session(&group.snapshot, &id) {
+            Ok((path, content)) => found.push((group.namespace.as_ref(), path, content)),
+            Err(ListError::NotFound(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let (namespace, path, content) = match found.as_slice() {
+        [found] => *found,
+        [] => return Err(ListError::NotFound(id).into()),
+        _ => anyhow::bail!(
+            "'{}' matches sessions in several namespaces: {}; pick one with --author and --machine",
+            id,
+            found.iter().filter_map(|(namespace, _, _)| namespace.map(|namespace| namespace.path().display().to_string())).collect::<Vec<_>>().join(", ")
+        ),
+    };
+
+    let content = String::from_utf8_lossy(content);
+    let output = if raw {
+        render::render_raw(&content)
+    } else {
+        let path = namespace.map_or_else(|| path.clone(), |namespace| namespace.path().join(path));
+        format!(
+            "Session {}\n\n{}",
+            path.display(),
+            render::render_transcript_with(&content, &RenderOptions { expand_tools: tools })
+        )
+    };
+
+    if no_pager {
+        print!("{}", output);
+        return Ok(());
+    }
+    page(&output)
+}
+
+/// Prints `output` through `$PAGER` (`less -FRX` if it is not set) when
+/// standard output is a terminal
+fn page(output: &str) -> Result<()> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -FRX".to_string());
+    if !std::io::stdout().is_terminal() || pager.trim().is_empty() || pager == "cat" {
+        print!("{}", output);
+        return Ok(());
+    }
+
+    let child = std::process::Command::new("sh")
+        .args(["-c", &pager])
+        .stdin(std::process::Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            log::warn!("Failed to start the pager '{}': {}", pager, e);
+            print!("{}", output);
+            return Ok(());
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager closes its input when it is quit early
+        match stdin.write_all(output.as_bytes()) {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e).context("Failed to write to the pager"),
+            _ => {}
+        }
+    }
+    child.wait().context("Failed to wait for the pager")?;
+
+    Ok(())
+}
+
+/// The sessions of the source and of the store of the current user, for the TUI
+fn load_tui_sessions(repo_dir: &Path, layers: &Layers, filter: &SessionFilter, identities: &Identities) -> Result<Vec<tui::TuiSession>> {
+    let filesystem = RealFileSystem::new();
+    let layered = load_layered_config(repo_dir, layers)?;
+    let config = &layered.config;
+    let location = StoreLocation::open(repo_dir, config)?;
+    let own_dir = location.own_dir(repo_dir)?;
+
+    let mut modified = BTreeMap::new();
+    let stored = if own_dir.is_dir() {
+        let stored = store::read_store_snapshot(&filesystem, &own_dir, identities)
+            .context("Failed to read the session store")?;
+        modified.extend(filesystem.walk_files(&own_dir)
+            .context("Failed to list the session store")?
+            .into_iter()
+            .filter_map(|entry| {
+                let relative = entry.path.strip_prefix(&own_dir).ok()?;
+                Some((compression::logical_path(relative), entry.modified))
+            }));
+        dedup::expand_snapshot(&stored).context("Failed to rebuild deduplicated sessions")?
+    } else {
+        store::Snapshot::new()
+    };
+
+    let repo_dir_cc_style = dir_path_to_claude_code_stype(repo_dir.to_path_buf())?;
+    let source_root_dir = resolve_source_root_dir(config.source.dir(), repo_dir)?;
+    let entries = SessionSyncer::new(filesystem.clone())
+        .find_session_files(&source_root_dir, &repo_dir_cc_style)
+        .context("Failed to list session files")?;
+    let source = store::read_snapshot(&filesystem, &source_root_dir, &entries, &Identities::default())
+        .context("Failed to read session files")?;
+    // The source's times win for sessions in both
+    modified.extend(entries.into_iter()
+        .filter_map(|entry| Some((entry.path.strip_prefix(&source_root_dir).ok()?.to_path_buf(), entry.modified))));
+
+    Ok(tui::collect_sessions(&source, &stored, &modified, filter, &config.retention.pinned, &config.filter.private, &config.redaction))
+}
+
+fn tui_command(source_dir: Option<PathBuf>, repo_dir: Option<PathBuf>, filter: SessionFilter, identities: &Identities) -> Result<()> {
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    let mut layers = Layers::from_environment();
+    if let Some(dir) = &source_dir {
+        layers = layers.with_command_line("source.dir", dir.to_string_lossy().into_owned());
+    }
+    let mut app = tui::App::new(load_tui_sessions(&repo_dir, &layers, &filter, identities)?);
+
+    // Log lines would be drawn over the screen
+    let log_level = log::max_level();
+    log::set_max_level(log::LevelFilter::Off);
+    let mut terminal = ratatui::try_init().context("Failed to set up the terminal")?;
+    let result = run_tui(&mut terminal, &mut app, &repo_dir, &layers, &filter, log_level, identities);
+    ratatui::restore();
+    log::set_max_level(log_level);
+
+    result
+}
+
+fn run_tui(
+    terminal: &mut ratatui::DefaultTerminal,
+    app: &mut tui::App,
+    repo_dir: &Path,
+    layers: &Layers,
+    filter: &SessionFilter,
+    log_level: log::LevelFilter,
+    identities: &Identities,
+) -> Result<()> {
+    let filesystem = RealFileSystem::new();
+    loop {
+        terminal.draw(|frame| tui::draw(frame, app)).context("Failed to draw the terminal")?;
+        let Event::Key(key) = event::read().context("Failed to read from the terminal")? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match app.handle_key(key) {
+            None => continue,
+            Some(Action::Quit) => return Ok(()),
+            Some(Action::Reload) => {}
+            Some(Action::SetPinned { id, pinned }) => match config::set_pinned(&filesystem, repo_dir, &id, pinned) {
+                Ok(()) if pinned => app.set_message(format!("Pinned {}; prune keeps it", id)),
+                Ok(()) => app.set_message(format!("Unpinned {}", id)),
+                Err(e) => app.set_message(format!("Failed to update {}: {}", config::REPO_CONFIG_PATH, e)),
+            },
+            Some(Action::SetPrivate { id, private }) => match config::set_private(&filesystem, repo_dir, &id, private) {
+                Ok(()) if private => app.set_message(format!(
+                    "Marked {} private; sync leaves it out, and a copy already stored stays until it is removed",
+                    id
+                )),
+                Ok(()) => app.set_message(format!("{} is synced again", id)),
+                Err(e) => app.set_message(format!("Failed to update {}: {}", config::REPO_CONFIG_PATH, e)),
+            },
+            Some(Action::Sync { id }) => outside_tui(terminal, log_level, || {
+                sync_command(Some(repo_dir.to_path_buf()), layers.clone(), vec![id], false, false, identities).map(|_| ())
+            })?,
+            Some(Action::Restore { id, force }) => outside_tui(terminal, log_level, || {
+                restore_session(repo_dir, layers, &id, force, identities)
+            })?,
+            Some(Action::Export { id, from_store, path }) => outside_tui(terminal, log_level, || {
+                let source_dir = load_layered_config(repo_dir, layers)?.config.source.dir();
+                let filter = SessionFilter { session_ids: vec![id], ..Default::default() };
+                let namespaces = NamespaceArgs { author: None, machine: None };
+                export_command(path, from_store, source_dir, Some(repo_dir.to_path_buf()), filter, namespaces, identities)
+            })?,
+        }
+
+        match load_tui_sessions(repo_dir, layers, filter, identities) {
+            Ok(sessions) => app.set_sessions(sessions),
+            Err(e) => app.set_message(format!("Failed to reload the sessions: {:#}", e)),
+        }
+    }
+}
+
+/// Leaves the TUI to run `command`, which prints to the terminal, and comes
+/// back once Enter is pressed
+fn outside_tui(
+    terminal: &mut ratatui::DefaultTerminal,
+    log_level: log::LevelFilter,
+    command: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    ratatui::restore();
+    log::set_max_level(log_level);
+    if let Err(e) = command() {
+        eprintln!("Error: {:#}", e);
+    }
+    print!("\nPress Enter to return");
+    std::io::stdout().flush().context("Failed to write to the terminal")?;
+    std::io::stdin().read_line(&mut String::new()).context("Failed to read from the terminal")?;
+
+    log::set_max_level(log::LevelFilter::Off);
+    crossterm::terminal::enable_raw_mode().context("Failed to set up the terminal")?;
+    crossterm::execute!(std::io::stdout(), crossterm::terminal::EnterAlternateScreen)
+        .context("Failed to set up the terminal")?;
+    terminal.clear().context("Failed to draw the terminal")?;
+
+    Ok(())
+}
+
+/// Copies the stored session `id`, with its sidechains, into Claude Code's
+/// projects, replacing the files there if `force`
+fn restore_session(repo_dir: &Path, layers: &Layers, id: &str, force: bool, identities: &Identities) -> Result<()> {
+    let filesystem = RealFileSystem::new();
+    let layered = load_layered_config(repo_dir, layers)?;
+    let location = StoreLocation::open(repo_dir, &layered.config)?;
+    let own_dir = location.own_dir(repo_dir)?;
+
+    let stored = store::read_store_snapshot(&filesystem, &own_dir, identities)
+        .context("Failed to read the session store")?;
+    let stored = dedup::expand_snapshot(&stored)
+        .context("Failed to rebuild deduplicated sessions")?;
+    let modified = filesystem.walk_files(&own_dir)
+        .context("Failed to list the session store")?
+        .into_iter()
+        .filter_map(|entry| {
+            let relative = entry.path.strip_prefix(&own_dir).ok()?;
+            Some((compression::logical_path(relative), entry.modified))
+        })
+        .collect();
+
+    // Going through a bundle moves the session to where the repository is now
+    let filter = SessionFilter { session_ids: vec![id.to_string()], ..Default::default() };
+    let bundle = bundle::export_snapshot(&stored, &modified, &filter, Some(repo_dir.to_path_buf()))
+        .context("Failed to read the session store")?;
+    let options = ImportOptions {
+        target: ImportTarget::Projects,
+        target_dir: resolve_source_root_dir(layered.config.source.dir(), repo_dir)?,
+        root: Some(repo_dir.to_path_buf()),
+        force,
+        dry_run: false,
+    };
+    let result = bundle::import_bundle(&filesystem, &bundle, &options)
+        .context("Failed to restore the session")?;
+
+    println!(
+        "Restored {} session files into {} ({} already there)",
+        result.files_imported,
+        options.target_dir.display(),
+        result.files_skipped
+    );
+
+    Ok(())
+}
+
+fn diff_command(
+    from: Option<String>,
+    to: Option<String>,
+    source_dir: Option<PathBuf>,
+    repo_dir: Option<PathBuf>,
+    namespaces: NamespaceArgs,
+    identities: &Identities,
+) -> Result<()> {
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    let filesystem = RealFileSystem::new();
+    let location = StoreLocation::open(&repo_dir, &load_config(&repo_dir)?)?;
+    let target_dir = location.dir();
+    let filter = namespaces.filter(&repo_dir)?;
+    let is_namespaced = location.layout()? == Layout::Namespaced;
+    if !filter.is_empty() && (!is_namespaced || from.is_none()) {
+        anyhow::bail!("--author and --machine select namespaces of store revisions; give FROM and use a namespaced store");
+    }
+
+    let read_working_tree_store = || {
+        store::read_store_snapshot(&filesystem, &target_dir, identities)
+            .context("Failed to read session store")
+    };
+    // References of deduplicated sessions are relative to their namespace
+    let expand = |snapshot: store::Snapshot| -> Result<store::Snapshot> {
+        if !is_namespaced {
+            return dedup::expand_snapshot(&snapshot).context("Failed to rebuild deduplicated sessions");
+        }
+        let mut namespaces = namespace::split_snapshot(&snapshot);
+        namespaces.retain(|namespace, _| filter.matches(namespace));
+        for (namespace, snapshot) in namespaces.iter_mut() {
+            *snapshot = dedup::expand_snapshot(snapshot)
+                .with_context(|| format!("Failed to rebuild deduplicated sessions of {}", namespace))?;
+        }
+        Ok(namespace::join_snapshots(&namespaces))
+    };
+
+    let (old, new, compares_source) = match (from, to) {
+        (None, _) => {
+            let repo_dir_cc_style = dir_path_to_claude_code_stype(repo_dir.clone())?;
+            let source_root_dir = resolve_source_root_dir(source_dir, &repo_dir)?;
+            let syncer = SessionSyncer::new(filesystem.clone());
+            let files = syncer.find_session_files(&source_root_dir, &repo_dir_cc_style)
+                .context("Failed to list session files")?;
+            let source = store::read_snapshot(&filesystem, &source_root_dir, &files, &Identities::default())
+                .context("Failed to read session files")?;
+
+            let own_dir = location.own_dir(&repo_dir)?;
+            let stored = store::read_store_snapshot(&filesystem, &own_dir, identities)
+                .context("Failed to read session store")?;
+            let stored = dedup::expand_snapshot(&stored)
+                .context("Failed to rebuild deduplicated sessions")?;
+
+            println!("Session changes from store to source:");
+            (stored, source, true)
+        }
+        (Some(from), to) => {
+            let repo = Repository::open(&repo_dir)
+                .context("Failed to open git repository")?;
+            let old = store::read_revision_snapshot(&repo, &from, identities)?;
+            let new = match &to {
+                Some(to) => store::read_revision_snapshot(&repo, to, identities)?,
+                None => read_working_tree_store()?,
+            };
+
+            println!("Session changes from {} to {}:", from, to.as_deref().unwrap_or("working tree"));
+            (expand(old)?, expand(new)?, false)
+        }
+    };
+
+    // Sync never deletes from the store, so sessions missing from the source are not pending changes
+    let changes: Vec<_> = diff::diff_snapshots(&old, &new)
+        .into_iter()
+        .filter(|change| !(compares_source && change.kind == ChangeKind::Removed))
+        .collect();
+
+    if changes.is_empty() {
+        println!("  No session changes");
+        return Ok(());
+    }
+
+    for change in &changes {
+        println!();
+        println!("{} {}", change.kind.letter(), change.path.display());
+        if let Some(link) = &change.sidechain {
+            let parent = link.parent_path.as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| link.parent_session_id.clone());
+            match &link.tool_use_id {
+                Some(tool_use_id) => println!("    Sidechain of {} (spawned by {})", parent, tool_use_id),
+                None => println!("    Sidechain of {}", parent),
+            }
+        }
+        print_turn_summary(&change.summary);
+    }
+
+    Ok(())
+}
+
+fn print_turn_summary(summary: &TurnSummary) {
+    if summary.is_empty() {
+        println!("    No new turns");
+        return;
+    }
+
+    let mut counts = format!(
+        "    {} user prompts, {} assistant replies, {} tool calls",
+        summary.user_prompts.len(),
+        summary.assistant_replies,
+        summary.total_tool_calls(),
+    );
+    if !summary.tool_calls.is_empty() {
+        let tools: Vec<String> = summary.tool_calls.iter()
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect();
+        counts.push_str(&format!(" ({})", tools.join(", ")));
+    }
+    println!("{}", counts);
+
+    if !summary.files_edited.is_empty() {
+        let files: Vec<&str> = summary.files_edited.iter().map(String::as_str).collect();
+        println!("    Files edited: {}", files.join(", "));
+    }
+
+    for prompt in &summary.user_prompts {
+        println!("    > {}", session::shorten(prompt, 72));
+    }
+}
+
+fn textconv_command(file: PathBuf, identities: &Identities) -> Result<()> {
+    let mut content = read_driver_input(&file, identities)?;
+
+    // References of deduplicated sessions are resolved against the store in the working tree
+    if dedup::has_references(&content) {
+        let repo = Repository::discover(".")
+            .context("Deduplicated sessions can only be shown inside the repository")?;
+        let target_dir = store::store_dir(repo.workdir().context("Repository has no working tree")?);
+        // References are relative to their namespace, which the driver is not told
+        let filesystem = RealFileSystem::new();
+        let mut sub_store_dirs = vec![target_dir.clone()];
+        sub_store_dirs.extend(namespace::list_namespaces(&filesystem, &target_dir).unwrap_or_default()
+            .into_iter()
+            .map(|namespace| target_dir.join(namespace.path())));
+        content = dedup::expand(&content, &|path| {
+            let (sub_store_dir, stored_path) = sub_store_dirs.iter().find_map(|dir| {
+                Some((dir, compression::find_stored_path(&filesystem, dir, path).ok()??))
+            })?;
+            let stored = fs::read(sub_store_dir.join(stored_path)).ok()?;
+            let stored = store::resolve_lfs_pointer(&repo, &stored).ok()?;
+            let stored = compression::decode(stored, identities).ok()?;
+            Some(String::from_utf8_lossy(&stored).into_owned())
+        }).context("Failed to rebuild deduplicated session")?;
+    }
+
+    let transcript = render::render_transcript(&content);
+    std::io::stdout().write_all(transcript.as_bytes())
+        .context("Failed to write transcript")?;
+
+    Ok(())
+}
+
+/// Read a file handed to a git driver, resolving LFS pointers
+fn read_driver_bytes(path: &Path) -> Result<Vec<u8>> {
+    let content = fs::read(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    // Blobs of an LFS-tracked store reach drivers as pointers
+    if !store::is_lfs_pointer(&content) {
+        return Ok(content);
+    }
+    let repo = Repository::discover(".")
+        .context("LFS pointers can only be resolved inside the repository")?;
+    store::resolve_lfs_pointer(&repo, &content)
+        .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Read a session file handed to a git driver, resolving LFS pointers,
+/// decrypting and decompressing it
+fn read_driver_input(path: &Path, identities: &Identities) -> Result<String> {
+    let content = compression::decode(read_driver_bytes(path)?, identities)
+        .with_context(|| format!("Failed to decode {}", path.display()))?;
+
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
+
+/// Merges a session file for git and returns the exit code:
+/// [`merge::EXIT_CONFLICT`] if records conflict
+fn merge_driver_command(base: PathBuf, ours: PathBuf, theirs: PathBuf, path: Option<String>, identities: &Identities) -> Result<i32> {
+    let path = path.unwrap_or_else(|| ours.display().to_string());
+    let outcome = merge::merge_transcripts(
+        &read_driver_input(&base, identities)?,
+        &read_driver_input(&ours, identities)?,
+        &read_driver_input(&theirs, identities)?,
+    ).with_context(|| format!("Failed to merge {}", path))?;
+
+    // The result is stored the way our side was. Git takes it as the blob
+    // without running the LFS filter, so a pointer comes back as a pointer
+    let mut merged = driver_output_format(&read_driver_bytes(&ours)?, identities)?
+        .encode(outcome.content.as_bytes())?;
+    let ours_content = fs::read(&ours)
+        .with_context(|| format!("Failed to read {}", ours.display()))?;
+    if store::is_lfs_pointer(&ours_content) {
+        let repo = Repository::discover(".")
+            .context("LFS pointers can only be written inside the repository")?;
+        merged = store::write_lfs_object(&repo, &merged)?;
+    }
+    fs::write(&ours, &merged)
+        .with_context(|| format!("Failed to write merge result to {}", ours.display()))?;
+
+    if !outcome.is_clean() {
+        eprintln!("Conflicting session records in {}:", path);
+        for uuid in &outcome.conflicts {
+            eprintln!("  - {}", uuid);
+        }
+        return Ok(merge::EXIT_CONFLICT);
+    }
+
+    Ok(0)
+}
+
+/// The format of a session file handed to a git driver, to write a result
+/// the same way. Encrypted files are encrypted to the current recipients.
+fn driver_output_format(content: &[u8], identities: &Identities) -> Result<StoreFormat> {
+    if !encryption::is_encrypted(content) {
+        let compression = if compression::is_compressed(content) { Compression::Zstd } else { Compression::None };
+        return Ok(StoreFormat { compression, encryption: None });
+    }
+
+    let repo = Repository::discover(".")
+        .context("Encrypted sessions can only be merged inside the repository")?;
+    let workdir = repo.workdir().context("Repository has no working tree")?;
+    let recipients = load_config(workdir)?.encryption.recipients;
+    let encryption = Encryption::new(&recipients)
+        .with_context(|| format!("Invalid [encryption] recipients in {}", config::REPO_CONFIG_PATH))?;
+    let decrypted = identities.decrypt(content).context("Failed to decrypt the merged session")?;
+    let compression = if compression::is_compressed(&decrypted) { Compression::Zstd } else { Compression::None };
+
+    Ok(StoreFormat { compression, encryption: Some(encryption) })
+}
+
+fn trailers_command(
+    message_file: PathBuf,
+    commit_source: Option<String>,
+    commit_sha: Option<String>,
+    source_dir: Option<PathBuf>,
+    repo_dir: Option<PathBuf>,
+) -> Result<()> {
+    // pre-commit passes the hook arguments through environment variables
+    let commit_source = commit_source.or_else(|| std::env::var("PRE_COMMIT_COMMIT_MSG_SOURCE").ok());
+    let commit_sha = commit_sha.or_else(|| std::env::var("PRE_COMMIT_COMMIT_OBJECT_NAME").ok());
+
+    if trailers::is_generated_message(commit_source.as_deref()) {
+        log::info!("Leaving {} message untouched", commit_source.unwrap_or_default());
+        return Ok(());
+    }
+
+    let repo_dir = resolve_git_repo_dir(repo_dir)?;
+    let repo = Repository::open(&repo_dir)
+        .context("Failed to open git repository")?;
+
+    // Sessions count as active when they were written after the previous commit
+    // on the branch. When amending, that is the parent of the amended commit.
+    let head = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let amended = match (&head, commit_source.as_deref(), commit_sha.as_deref()) {
+        (Some(head), Some("commit"), Some(sha)) => repo.revparse_single(sha)
+            .ok()
+            .is_some_and(|object| object.id() == head.id()),
+        _ => false,
+    };
+    let previous_commit = if amended {
+        head.and_then(|head| head.parent(0).ok())
+    } else {
+        head
+    };
+    let since = previous_commit
+        .map(|commit| UNIX_EPOCH + Duration::from_secs(commit.time().seconds().max(0) as u64));
+
+    let repo_dir_cc_style = dir_path_to_claude_code_stype(repo_dir.clone())?;
+    let source_root_dir = resolve_source_root_dir(source_dir, &repo_dir)?;
+    if !source_root_dir.is_dir() {
+        log::warn!("Source directory does not exist: {}", source_root_dir.display());
+        return Ok(());
+    }
+
+    let private = load_config(&repo_dir)?.filter.private;
+    let syncer = SessionSyncer::new(RealFileSystem::new());
+    let session_ids: Vec<String> = syncer.find_session_files(&source_root_dir, &repo_dir_cc_style)
+        .context("Failed to list session files")?
+        .into_iter()
+        .filter(|session| since.is_none_or(|since| session.modified > since))
+        .filter_map(|session| session.path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+        .filter(|id| !private.iter().any(|private| id.starts_with(private.as_str())))
+        .collect();
+
+    if session_ids.is_empty() {
+        log::info!("No sessions active since the previous commit");
+        return Ok(());
+    }
+
+    let message = fs::read_to_string(&message_file)
+        .with_context(|| format!("Failed to read commit message file {}", message_file.display()))?;
+    let updated = trailers::append_session_trailers(&message, &session_ids);
+
+    if updated != message {
+        fs::write(&message_file, updated)
+            .with_context(|| format!("Failed to write commit message file {}", message_file.display()))?;
+    }
+
+    Ok(())
+}
+
+fn prune_command(repo_dir: Option<PathBuf>, dry_run: bool, archive: Option<PathBuf>, git_add: bool, identities: &Identities) -> Result<()> {
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    let filesystem = RealFileSystem::new();
+
+    let config = load_config(&repo_dir)?;
+    if config.retention.is_empty() {
+        println!("No retention rules in .claude/ccss.toml; nothing to prune");
+        return Ok(());
+    }
+    let location = StoreLocation::open(&repo_dir, &config)?;
+    let target_dir = location.dir();
+
+    let committed_ids = match Repository::open(&repo_dir) {
+        Ok(repo) => store::committed_session_ids(&repo).unwrap_or_else(|e| {
+            warn!("Could not read session trailers from git history: {}", e);
+            Default::default()
+        }),
+        Err(_) => Default::default(),
+    };
+
+    // Each namespace of a namespaced store is pruned as a store of its own
+    let mut plans = Vec::new();
+    for (namespace, sub_store_dir) in location.sub_stores(&NamespaceFilter::default())? {
+        let snapshot = store::read_store_snapshot(&filesystem, &sub_store_dir, identities)
+            .context("Failed to read the session store")?;
+        let plan = prune::plan_prune(&snapshot, &config.retention, &committed_ids, chrono::Utc::now());
+        let prefix = namespace.map(|namespace| namespace.path()).unwrap_or_default();
+
+        for decision in &plan.decisions {
+            let kept = match decision.kept_by {
+                None => None,
+                Some(KeepReason::Pinned) => Some("pinned"),
+                Some(KeepReason::Committed) => Some("linked to a commit"),
+                Some(KeepReason::Referenced) => Some("referenced by a deduplicated session"),
+            };
+            match kept {
+                Some(kept) => println!("keep  {} ({}; {})", decision.session.id, decision.reason, kept),
+                None => {
+                    println!("prune {} ({})", decision.session.id, decision.reason);
+                    for path in &decision.session.paths {
+                        println!("        {}", prefix.join(path).display());
+                    }
+                }
+            }
+        }
+        plans.push((prefix, sub_store_dir, snapshot, plan));
+    }
+
+    let pruned: usize = plans.iter().map(|(_, _, _, plan)| plan.pruned().count()).sum();
+    let sessions_total: usize = plans.iter().map(|(_, _, _, plan)| plan.sessions_total).sum();
+    if dry_run || pruned == 0 {
+        println!(
+            "\n{} {} of {} sessions",
+            if dry_run { "Would prune" } else { "Pruned" },
+            pruned,
+            sessions_total
+        );
+        return Ok(());
+    }
+
+    if let Some(archive) = &archive {
+        let mut archived = store::Snapshot::new();
+        let mut paths = Vec::new();
+        for (prefix, _, snapshot, plan) in &plans {
+            archived.extend(snapshot.iter().map(|(path, content)| (prefix.join(path), content.clone())));
+            paths.extend(plan.pruned_paths().iter().map(|path| prefix.join(path)));
+        }
+        // An encrypted store is archived encrypted, not in plain text next to the repository
+        let encryption = config.encryption.encryption()
+            .with_context(|| format!("Invalid [encryption] recipients in {}", config::REPO_CONFIG_PATH))?;
+        prune::write_archive(archive, &archived, &paths, encryption.as_ref())
+            .with_context(|| format!("Failed to write archive {}", archive.display()))?;
+        println!("\nArchived {} files to {}", paths.len(), archive.display());
+    }
+
+    for (_, sub_store_dir, _, plan) in &plans {
+        let removed = prune::remove_pruned(&filesystem, sub_store_dir, &plan.pruned_paths())
+            .context("Failed to remove pruned sessions")?;
+
+        if let Some(mut manifest) = Manifest::load(&filesystem, sub_store_dir)? {
+            for path in &removed {
+                manifest.remove(path);
+            }
+            manifest.save(&filesystem, sub_store_dir)?;
+        }
+        if !config.encryption.recipients.is_empty() {
+            index::remove_index(&filesystem, sub_store_dir)?;
+        } else if sub_store_dir.join(INDEX_JSON_FILE).exists() {
+            index::update_index(&filesystem, sub_store_dir, &[], identities, &config.redaction)?;
+        }
+    }
+
+    println!("\nPruned {} of {} sessions", pruned, sessions_total);
+    location.commit(&format!("Prune {} sessions", pruned))?;
+
+    if git_add && !location.is_branch() {
+        let repo = Repository::open(&repo_dir)
+            .context("Failed to open git repository")?;
+        let ccss_sessions_path = target_dir.strip_prefix(&repo_dir).unwrap_or(&target_dir);
+        let mut index = repo.index()
+            .context("Failed to get repository index")?;
+        index.update_all([ccss_sessions_path], None)
+            .context("Failed to stage removed sessions")?;
+        index.add_all([ccss_sessions_path], git2::IndexAddOption::DEFAULT, None)
+            .context("Failed to stage .claude/ccss_sessions")?;
+        index.write()
+            .context("Failed to write git index")?;
+
+        println!("Staged the removals in {}", ccss_sessions_path.display());
+    }
+
+    Ok(())
+}
+
+fn convert_command(compression: Compression, repo_dir: Option<PathBuf>, identities: &Identities) -> Result<()> {
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    let filesystem = RealFileSystem::new();
+    let config = load_config(&repo_dir)?;
+    let location = StoreLocation::open(&repo_dir, &config)?;
+
+    let format = StoreFormat {
+        compression,
+        encryption: config.encryption.encryption()
+            .with_context(|| format!("Invalid [encryption] recipients in {}", config::REPO_CONFIG_PATH))?,
+    };
+    let mut converted = 0;
+    for (_, sub_store_dir) in location.sub_stores(&NamespaceFilter::default())? {
+        converted += compression::convert_store(&filesystem, &sub_store_dir, &format, identities, false)
+            .context("Failed to convert the session store")?;
+    }
+    config::set_store_compression(&filesystem, &repo_dir, compression)
+        .context("Failed to update .claude/ccss.toml")?;
+
+    println!("Converted {} session files to compression '{}'", converted, compression);
+    println!("Set [store] compression = \"{}\" in {}", compression, config::REPO_CONFIG_PATH);
+    location.commit(&format!("Convert the store to compression '{}'", compression))?;
+    if compression == Compression::Zstd && !location.is_branch() && !diff_driver_covers(&repo_dir, gitattributes::COMPRESSED_DIFF_DRIVER_LINE) {
+        println!("Run 'cc-sync-session init --diff-driver --merge-driver' to diff and merge compressed sessions");
+    }
+
+    Ok(())
+}
+
+/// Whether .gitattributes has the diff driver line for a kind of session files
+fn diff_driver_covers(repo_dir: &Path, line: &str) -> bool {
+    fs::read_to_string(repo_dir.join(".gitattributes"))
+        .is_ok_and(|content| gitattributes::contains_line(&content, line))
+}
+
+fn export_command(
+    bundle_path: PathBuf,
+    from_store: bool,
+    source_dir: Option<PathBuf>,
+    repo_dir: Option<PathBuf>,
+    filter: SessionFilter,
+    namespaces: NamespaceArgs,
+    identities: &Identities,
+) -> Result<()> {
+    let filesystem = RealFileSystem::new();
+    let (repo_dir, snapshot, files) = if from_store {
+        let repo_dir = resolve_store_repo_dir(repo_dir)?;
+        let location = StoreLocation::open(&repo_dir, &load_config(&repo_dir)?)?;
+
+        // The namespaces of a namespaced store are merged into one set of projects
+        let mut snapshots = BTreeMap::new();
+        let mut files: Vec<(PathBuf, SystemTime)> = Vec::new();
+        for (namespace, target_dir) in location.sub_stores(&namespaces.filter(&repo_dir)?)? {
+            let snapshot = store::read_store_snapshot(&filesystem, &target_dir, identities)
+                .context("Failed to read the session store")?;
+            let snapshot = dedup::expand_snapshot(&snapshot)
+                .context("Failed to rebuild deduplicated sessions")?;
+            snapshots.insert(namespace.unwrap_or_else(|| Namespace::new("", "")), snapshot);
+            files.extend(filesystem.walk_files(&target_dir)
+                .context("Failed to list the session store")?
+                .into_iter()
+                .filter_map(|entry| {
+                    let relative = entry.path.strip_prefix(&target_dir).ok()?;
+                    Some((compression::logical_path(relative), entry.modified))
+                }));
+        }
+        // Files in several namespaces keep their latest time
+        files.sort_by_key(|(_, modified)| *modified);
+        (repo_dir, namespace::aggregate(&snapshots), files)
+    } else {
+        let repo_dir = resolve_git_repo_dir(repo_dir)?;
+        let repo_dir_cc_style = dir_path_to_claude_code_stype(repo_dir.clone())?;
+        let source_root_dir = resolve_source_root_dir(source_dir, &repo_dir)?;
+        let entries = SessionSyncer::new(filesystem.clone())
+            .find_session_files(&source_root_dir, &repo_dir_cc_style)
+            .context("Failed to list session files")?;
+        let snapshot = store::read_snapshot(&filesystem, &source_root_dir, &entries, &Identities::default())
+            .context("Failed to read session files")?;
+        let files = entries.into_iter()
+            .filter_map(|entry| Some((entry.path.strip_prefix(&source_root_dir).ok()?.to_path_buf(), entry.modified)))
+            .collect();
+        (repo_dir, snapshot, files)
+    };
+
+    let bundle = bundle::export_snapshot(&snapshot, &files.into_iter().collect(), &filter, Some(repo_dir))
+        .context("Failed to read session files")?;
+    bundle.write(&bundle_path)
+        .with_context(|| format!("Failed to write bundle {}", bundle_path.display()))?;
+
+    println!(
+        "Exported {} session files of {} projects to {}",
+        bundle.files.len(),
+        bundle.metadata.projects.len(),
+        bundle_path.display()
+    );
+
+    Ok(())
+}
+
+fn import_command(
+    bundle_path: PathBuf,
+    to_projects: bool,
+    source_dir: Option<PathBuf>,
+    repo_dir: Option<PathBuf>,
+    force: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let filesystem = RealFileSystem::new();
+    let bundle = Bundle::read(&bundle_path)
+        .with_context(|| format!("Failed to read bundle {}", bundle_path.display()))?;
+
+    let mut location = None;
+    let (repo_dir, target, target_dir) = if to_projects {
+        let repo_dir = resolve_git_repo_dir(repo_dir)?;
+        let projects_dir = resolve_source_root_dir(source_dir, &repo_dir)?;
+        (repo_dir, ImportTarget::Projects, projects_dir)
+    } else {
+        let repo_dir = resolve_store_repo_dir(repo_dir)?;
+        let config = load_config(&repo_dir)?;
+        let format = StoreFormat {
+            compression: config.store.compression,
+            encryption: config.encryption.encryption()
+                .with_context(|| format!("Invalid [encryption] recipients in {}", config::REPO_CONFIG_PATH))?,
+        };
+        let target_dir = location.insert(StoreLocation::open(&repo_dir, &config)?).own_dir(&repo_dir)?;
+        (repo_dir, ImportTarget::Store(format), target_dir)
+    };
+
+    let options = ImportOptions {
+        target,
+        target_dir,
+        root: Some(repo_dir),
+        force,
+        dry_run,
+    };
+    let result = bundle::import_bundle(&filesystem, &bundle, &options)
+        .context("Failed to import the bundle")?;
+
+    if let Some(root) = &bundle.metadata.root {
+        println!("Bundle of {} created {}", root.display(), bundle.metadata.created.format("%Y-%m-%d %H:%M UTC"));
+    }
+    for (old, new) in &result.renamed_projects {
+        println!("  {} -> {}", old, new);
+    }
+    let target_name = match &location {
+        Some(StoreLocation::Branch(checkout)) => format!("branch {}", checkout.store().branch()),
+        _ => options.target_dir.display().to_string(),
+    };
+    println!(
+        "{} {} session files into {} ({} already there)",
+        if dry_run { "Would import" } else { "Imported" },
+        result.files_imported,
+        target_name,
+        result.files_skipped
+    );
+    if let Some(location) = location.filter(|_| !dry_run && result.files_imported > 0) {
+        location.commit(&format!("Import {} session files", result.files_imported))?;
+    }
+    if result.files_skipped > 0 && !force {
+        println!("Use --force to replace existing files");
+    }
+
+    Ok(())
+}
+
+fn migrate_command(to: u32, layout: Option<Layout>, repo_dir: Option<PathBuf>, dry_run: bool) -> Result<()> {
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    let filesystem = RealFileSystem::new();
+    let config = load_config(&repo_dir)?;
+    let location = StoreLocation::open(&repo_dir, &config)?;
+    let target_dir = location.dir();
+
+    if layout.is_some() && to != STORE_VERSION {
+        anyhow::bail!("Store layouts need store version {}", STORE_VERSION);
+    }
+
+    let migration = layout::migrate(&filesystem, &target_dir, to, config.store.mode, dry_run)
+        .context("Failed to migrate the session store")?;
+    for (from, to) in &migration.renames {
+        println!("rename {} -> {}", from.display(), to.display());
+    }
+    if migration.is_empty() {
+        if layout.is_none() {
+            println!("The store is already at version {}", migration.to);
+        }
+    } else {
+        println!(
+            "{} the store from version {} to {}",
+            if dry_run { "Would migrate" } else { "Migrated" },
+            migration.from,
+            migration.to
+        );
+    }
+
+    let mut layout_changed = false;
+    if let Some(layout) = layout {
+        // A dry run has not written the descriptor the layout change needs
+        let current = if dry_run && !migration.is_empty() { Layout::Encoded } else { location.layout()? };
+        if current == layout {
+            println!("The store already uses the {} layout", layout);
+        } else if dry_run && !migration.is_empty() {
+            println!("Would move the files to the {} layout", layout);
+        } else {
+            let renames = layout::change_layout(&filesystem, &target_dir, layout, &own_namespace(&repo_dir)?, dry_run)
+                .context("Failed to change the store layout")?;
+            for (from, to) in &renames {
+                println!("rename {} -> {}", from.display(), to.display());
+            }
+            println!("{} {} files to the {} layout", if dry_run { "Would move" } else { "Moved" }, renames.len(), layout);
+            layout_changed = true;
+        }
+    }
+    if dry_run || (migration.is_empty() && !layout_changed) {
+        return Ok(());
+    }
+
+    match &location {
+        StoreLocation::Worktree(_) => {
+            let repo = Repository::open(&repo_dir)
+                .context("Failed to open git repository")?;
+            let ccss_sessions_path = target_dir.strip_prefix(&repo_dir).unwrap_or(&target_dir);
+            let mut index = repo.index()
+                .context("Failed to get repository index")?;
+            index.update_all([ccss_sessions_path], None)
+                .context("Failed to stage the migrated store")?;
+            index.add_all([ccss_sessions_path], git2::IndexAddOption::DEFAULT, None)
+                .context("Failed to stage the migrated store")?;
+            index.write()
+                .context("Failed to write git index")?;
+            println!("Staged the changes in {}", ccss_sessions_path.display());
+        }
+        StoreLocation::Branch(_) => match layout.filter(|_| layout_changed) {
+            Some(layout) => location.commit(&format!("Move the store to the {} layout", layout))?,
+            None => location.commit(&format!("Migrate the store to version {}", migration.to))?,
+        },
+    }
+
+    Ok(())
+}
+
+fn keygen_command(output: Option<PathBuf>) -> Result<()> {
+    let path = match output {
+        Some(path) => path,
+        None => encryption::default_identity_path().context("Failed to find the configuration directory")?,
+    };
+
+    let recipient = encryption::generate_identity(&path)
+        .with_context(|| format!("Failed to create identity file {}", path.display()))?;
+
+    println!("Created identity file {}", path.display());
+    println!("Public key: {}", recipient);
+    println!("Add the public key to [encryption] recipients in {} and run 'cc-sync-session re-encrypt'", config::REPO_CONFIG_PATH);
+
+    Ok(())
+}
+
+fn re_encrypt_command(add: Vec<String>, remove: Vec<String>, repo_dir: Option<PathBuf>, identities: &Identities) -> Result<()> {
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    let filesystem = RealFileSystem::new();
+    let config = load_config(&repo_dir)?;
+    let location = StoreLocation::open(&repo_dir, &config)?;
+
+    let mut recipients = config.encryption.recipients.clone();
+    if !add.is_empty() || !remove.is_empty() {
+        // Check the new keys before writing them
+        Encryption::new(&add)?;
+        recipients.retain(|recipient| !remove.contains(recipient));
+        for recipient in add {
+            if !recipients.contains(&recipient) {
+                recipients.push(recipient);
+            }
+        }
+        config::set_encryption_recipients(&filesystem, &repo_dir, &recipients)
+            .context("Failed to update .claude/ccss.toml")?;
+        println!("Set [encryption] recipients in {} ({} recipients)", config::REPO_CONFIG_PATH, recipients.len());
+    }
+
+    let encryption = if recipients.is_empty() {
+        None
+    } else {
+        Some(Encryption::new(&recipients)
+            .with_context(|| format!("Invalid [encryption] recipients in {}", config::REPO_CONFIG_PATH))?)
+    };
+    let format = StoreFormat {
+        compression: config.store.compression,
+        encryption,
+    };
+
+    let sub_store_dirs: Vec<PathBuf> = location.sub_stores(&NamespaceFilter::default())?
+        .into_iter()
+        .map(|(_, dir)| dir)
+        .collect();
+    let mut converted = 0;
+    let mut index_removed = false;
+    for sub_store_dir in &sub_store_dirs {
+        converted += compression::convert_store(&filesystem, sub_store_dir, &format, identities, true)
+            .context("Failed to re-encrypt the session store")?;
+        // The index is not encrypted
+        if format.encryption.is_some() {
+            index_removed |= index::remove_index(&filesystem, sub_store_dir)
+                .context("Failed to remove the session index")?;
+        }
+    }
+    if format.encryption.is_some() {
+        println!("Encrypted {} session files to {} recipients", converted, recipients.len());
+    } else {
+        println!("No recipients; decrypted {} session files", converted);
+    }
+    if index_removed {
+        println!("Removed the session index ({} and {}), which would list the sessions in plain text", index::INDEX_MARKDOWN_FILE, INDEX_JSON_FILE);
+    }
+    location.commit(&format!("Re-encrypt {} session files", converted))?;
+
+    if format.encryption.is_some() && !location.is_branch() && !diff_driver_covers(&repo_dir, gitattributes::ENCRYPTED_DIFF_DRIVER_LINE) {
+        println!("Run 'cc-sync-session init --diff-driver --merge-driver' to diff and merge encrypted sessions");
+    }
+
+    Ok(())
 }
 
-/// Find a repository directory by looking for .git and .claude/ccss_sessions
-fn find_repo_dir(start: &Path) -> Option<PathBuf> {
-    let mut current = start.to_path_buf();
-    
-    loop {
-        let git_dir = current.join(".git");
-        let ccss_dir = current.join(".claude").join("ccss_sessions");
+fn config_show_command(origin: bool, repo_dir: Option<PathBuf>) -> Result<()> {
+    let repo_dir = resolve_git_repo_dir(repo_dir)?;
+    let layered = load_layered_config(&repo_dir, &Layers::from_environment())?;
 
-        log::debug!("Checking directory: {} git: {}, ccss: {}",
-                    current.display(),
-                    git_dir.exists(),
-                    ccss_dir.exists());
+    let origins: Vec<String> = layered.values.values().map(|(_, origin)| origin.to_string()).collect();
+    let width = origins.iter().map(String::len).max().unwrap_or(0);
+    for ((key, (value, _)), value_origin) in layered.values.iter().zip(&origins) {
+        if origin {
+            println!("{:<width$}  {} = {}", value_origin, key, value, width = width);
+        } else {
+            println!("{} = {}", key, value);
+        }
+    }
 
-        if git_dir.exists() && ccss_dir.exists() {
-            return Some(current);
+    Ok(())
+}
+
+/// Prints the status and returns the exit code: 0 if there is nothing to sync
+/// or commit, [`session_sync::EXIT_PENDING`] otherwise
+fn status_command(source_dir: Option<PathBuf>, repo_dir: Option<PathBuf>, short: bool, json: bool, identities: &Identities) -> Result<i32> {
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    let filesystem = RealFileSystem::new();
+    let mut layers = Layers::from_environment();
+    if let Some(dir) = source_dir {
+        layers = layers.with_command_line("source.dir", dir.to_string_lossy().into_owned());
+    }
+    let layered = load_layered_config(&repo_dir, &layers)?;
+    let config = &layered.config;
+    let location = StoreLocation::open(&repo_dir, config)?;
+    let own_dir = location.own_dir(&repo_dir)?;
+
+    // Only what sync would store is pending, at the path sync would store it at
+    let repo_dir_cc_style = dir_path_to_claude_code_stype(repo_dir.clone())?;
+    let source_root_dir = resolve_source_root_dir(config.source.dir(), &repo_dir)?;
+    let mut options = sync_options(&layered, identities)?;
+    if config.sync.current_branch {
+        options.filter.git_branch = Some(current_branch(&repo_dir)?);
+    }
+    let sessions = SessionSyncer::new(filesystem.clone()).find_synced_sessions(&source_root_dir, &repo_dir_cc_style, &options)
+        .context("Failed to list session files")?;
+    let mut source = store::Snapshot::new();
+    for (entry, stored_path) in sessions {
+        let content = filesystem.read_file(&entry.path)
+            .with_context(|| format!("Failed to read {}", entry.path.display()))?;
+        source.insert(stored_path, content);
+    }
+
+    let stored = if own_dir.is_dir() {
+        let stored = store::read_store_snapshot(&filesystem, &own_dir, identities)
+            .context("Failed to read session store")?;
+        dedup::expand_snapshot(&stored).context("Failed to rebuild deduplicated sessions")?
+    } else {
+        store::Snapshot::new()
+    };
+
+    // The store branch is committed by every command, so only a store in the working tree has git states
+    let git_states = match &location {
+        StoreLocation::Worktree(_) => {
+            let repo = Repository::open(&repo_dir)
+                .context("Failed to open git repository")?;
+            status::git_states(&repo, &own_dir).context("Failed to read git status")?
         }
+        StoreLocation::Branch(_) => BTreeMap::new(),
+    };
+    let sync_states = status::compare(&source, &stored);
+    let statuses = status::file_statuses(&sync_states, &git_states);
+    let clean = statuses.iter().all(|file| !file.sync.is_some_and(|state| state.is_pending()) && file.git.is_none());
+    let exit_code = if clean { 0 } else { session_sync::EXIT_PENDING };
 
-        if !current.pop() {
-            break;
+    let store_path = match own_dir.strip_prefix(location.dir()) {
+        Ok(namespace) if !namespace.as_os_str().is_empty() => Path::new(store::STORE_PATH).join(namespace),
+        _ => PathBuf::from(store::STORE_PATH),
+    };
+    if json {
+        let report = serde_json::json!({
+            "store": store_path,
+            "branch": if let StoreLocation::Branch(checkout) = &location { Some(checkout.store().branch()) } else { None },
+            "clean": clean,
+            "files": statuses,
+        });
+        println!("{}", serde_json::to_string_pretty(&report).context("Failed to serialize the status")?);
+        return Ok(exit_code);
+    }
+    if short {
+        for file in &statuses {
+            println!(
+                "{}{} {}",
+                file.sync.map_or(' ', |state| state.letter()),
+                file.git.map_or(' ', |state| state.letter()),
+                file.path.display()
+            );
         }
+        return Ok(exit_code);
     }
-    
-    None
+
+    match &location {
+        StoreLocation::Worktree(_) => println!("Store: {}", store_path.display()),
+        StoreLocation::Branch(checkout) => println!("Store: {} on branch {}", store_path.display(), checkout.store().branch()),
+    }
+
+    let print_section = |title: &str, hint: &str, lines: Vec<(String, &Path)>| {
+        if lines.is_empty() {
+            return;
+        }
+        println!("\n{}", title);
+        println!("  ({})", hint);
+        for (label, path) in lines {
+            if label.is_empty() {
+                println!("        {}", path.display());
+            } else {
+                println!("        {:<11}{}", label, path.display());
+            }
+        }
+    };
+    let with_sync = |wanted: &dyn Fn(SyncState) -> bool| -> Vec<(String, &Path)> {
+        statuses.iter()
+            .filter_map(|file| Some((file.sync.filter(|state| wanted(*state))?, file.path.as_path())))
+            .map(|(state, path)| (format!("{}:", state.label()), path))
+            .collect()
+    };
+    let with_git = |wanted: GitState| -> Vec<(String, &Path)> {
+        statuses.iter()
+            .filter(|file| file.git == Some(wanted))
+            .map(|file| (String::new(), file.path.as_path()))
+            .collect()
+    };
+
+    print_section(
+        "Sessions not synced yet:",
+        "use \"cc-sync-session sync\" to copy them to the store",
+        with_sync(&|state| state.is_pending()),
+    );
+    print_section(
+        "Sessions no longer in the source:",
+        "sync keeps them in the store",
+        with_sync(&|state| !state.is_pending()),
+    );
+    print_section(
+        "Store changes to be committed:",
+        "use \"git commit\" to record them",
+        with_git(GitState::Staged),
+    );
+    print_section(
+        "Store changes not staged for commit:",
+        "use \"git add\" or \"cc-sync-session sync --git-add\" to stage them",
+        with_git(GitState::Unstaged),
+    );
+    print_section(
+        "Untracked session files:",
+        "use \"git add\" to include them",
+        with_git(GitState::Untracked),
+    );
+
+    if statuses.is_empty() {
+        println!("\nNothing to sync; the store is up to date");
+    } else if clean {
+        println!("\nNothing to sync or commit");
+    }
+
+    Ok(exit_code)
 }
 
-/// Find a git repository directory by looking for .git
-fn find_git_repo(start: &Path) -> Option<PathBuf> {
-    let mut current = start.to_path_buf();
-    
-    loop {
-        let git_dir = current.join(".git");
-        
-        if git_dir.exists() {
-            return Some(current);
+fn verify_command(repo_dir: Option<PathBuf>, json: bool, identities: &Identities) -> Result<i32> {
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    let filesystem = RealFileSystem::new();
+    let report = match StoreLocation::open(&repo_dir, &load_config(&repo_dir)?)? {
+        StoreLocation::Worktree(target_dir) => {
+            if !target_dir.is_dir() {
+                anyhow::bail!("Store directory does not exist: {}. Run 'cc-sync-session init' first", target_dir.display());
+            }
+
+            let mut report = verify::verify_store(&filesystem, &repo_dir, identities);
+            if let Ok(repo) = Repository::open(&repo_dir) {
+                let gitattributes_content = fs::read_to_string(repo_dir.join(".gitattributes")).unwrap_or_default();
+                report.findings.extend(verify::check_drivers(&repo, &gitattributes_content));
+            }
+            report
         }
-        
-        if !current.pop() {
-            break;
+        StoreLocation::Branch(checkout) => {
+            // Blobs on the store branch never go through .gitattributes
+            let mut report = verify::verify_store(&filesystem, checkout.root(), identities);
+            report.findings.retain(|finding| finding.check != verify::Check::Gitattributes);
+            report
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).context("Failed to serialize the report")?);
+    } else {
+        for finding in &report.findings {
+            let label = match finding.severity {
+                verify::Severity::Error => "error",
+                verify::Severity::Warning => "warning",
+            };
+            match &finding.path {
+                Some(path) => println!("{}: {}: {}", label, path.display(), finding.message),
+                None => println!("{}: {}", label, finding.message),
+            }
         }
+        println!(
+            "Checked {} files: {} errors, {} warnings",
+            report.files_checked,
+            report.errors(),
+            report.warnings()
+        );
     }
-    
-    None
+
+    Ok(if report.is_ok() { 0 } else { session_sync::EXIT_CHECK_FAILED })
 }
 
-fn init_command(repo_dir: Option<PathBuf>) -> Result<()> {
-    let repo_dir = match repo_dir {
-        Some(dir) => dir,
-        None => {
-            let current_dir = std::env::current_dir()
-                .context("Failed to get current directory")?;
-            find_git_repo(&current_dir)
-                .context("No git repository found in current directory or parent directories")?
+fn doctor_command(source_dir: Option<PathBuf>, repo_dir: Option<PathBuf>, json: bool) -> Result<i32> {
+    let repo_dir = resolve_git_repo_dir(repo_dir)?;
+    let repo = Repository::open(&repo_dir)
+        .context("Failed to open git repository")?;
+    let filesystem = RealFileSystem::new();
+    let mut report = DoctorReport::default();
+
+    let mut layers = Layers::from_environment();
+    if let Some(dir) = &source_dir {
+        layers = layers.with_command_line("source.dir", dir.to_string_lossy().into_owned());
+    }
+    let config = match load_layered_config(&repo_dir, &layers) {
+        Ok(layered) => {
+            // Values that are only checked when they are used
+            let check_values = || -> Result<()> {
+                layered.session_filter()?;
+                layered.config.encryption.encryption()
+                    .with_context(|| format!("Invalid [encryption] recipients in {}", config::REPO_CONFIG_PATH))?;
+                Ok(())
+            };
+            match check_values() {
+                Ok(_) => report.findings.push(Finding::ok("config", "Configuration is valid")),
+                Err(e) => report.findings.push(Finding::error(
+                    "config",
+                    format!("{:#}", e),
+                    "Fix the value; 'cc-sync-session config show --origin' shows where it comes from",
+                )),
+            }
+            layered.config
+        }
+        Err(e) => {
+            report.findings.push(Finding::error(
+                "config",
+                e.to_string(),
+                "Fix the file or variable named above; the checks below use the defaults",
+            ));
+            RepoConfig::default()
         }
     };
-    
-    // Create .claude/ccss_sessions directory
-    let ccss_dir = repo_dir.join(".claude").join("ccss_sessions");
-    std::fs::create_dir_all(&ccss_dir)
-        .context("Failed to create .claude/ccss_sessions directory")?;
-    
-    // Create .gitkeep file
-    let gitkeep_path = ccss_dir.join(".gitkeep");
-    std::fs::write(&gitkeep_path, "")
-        .context("Failed to create .gitkeep file")?;
-    
-    // Handle .gitattributes for Git LFS
-    let gitattributes_path = repo_dir.join(".gitattributes");
-    let lfs_line = ".claude/ccss_sessions/** filter=lfs diff=lfs merge=lfs -text";
-    
-    // Check if .gitattributes exists and read its content
-    let mut content = String::new();
-    let mut needs_newline = false;
-    
-    if gitattributes_path.exists() {
-        let mut file = fs::File::open(&gitattributes_path)
-            .context("Failed to open .gitattributes file")?;
-        file.read_to_string(&mut content)
-            .context("Failed to read .gitattributes file")?;
-        
-        // Check if LFS line already exists
-        if content.lines().any(|line| line.trim() == lfs_line) {
-            println!("Git LFS configuration already exists in .gitattributes");
+
+    // Not resolve_source_root_dir: the configuration may not load
+    let source_root_dir = source_dir
+        .or_else(|| config.source.dir())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".claude").join("projects")));
+    match source_root_dir {
+        Some(source_root_dir) => report.findings.extend(doctor::check_source(&filesystem, &source_root_dir, &repo_dir)),
+        None => report.findings.push(Finding::error(
+            "source",
+            "Cannot find the home directory",
+            "Set [source] dir in the configuration or CC_SYNC_SESSION_SOURCE_DIR",
+        )),
+    }
+
+    let branch_store = config.store.mode == StoreMode::Branch;
+    if branch_store {
+        // Blobs on the store branch never go through .gitattributes or ignore rules
+        let store = BranchStore::new(Repository::open(&repo_dir)?, config.store.branch());
+        let head = store.head().map(|head| head.is_some());
+        match head {
+            Ok(true) => report.findings.push(Finding::ok("store", format!("Store branch {}", store.branch()))),
+            Ok(false) => report.findings.push(Finding::warning(
+                "store",
+                format!("The store branch {} does not exist yet", store.branch()),
+                "Run 'cc-sync-session sync' to create it",
+            )),
+            Err(e) => report.findings.push(Finding::error(
+                "store",
+                format!("Cannot read the store branch {}: {:#}", store.branch(), e),
+                "Run 'git fsck' on the repository",
+            )),
+        }
+    } else {
+        let store_dir = store::store_dir(&repo_dir);
+        if !store_dir.is_dir() {
+            report.findings.push(Finding::error(
+                "store",
+                format!("No store at {}", store::STORE_PATH),
+                "Run 'cc-sync-session init'",
+            ));
         } else {
-            // Check if we need a newline before appending
-            needs_newline = !content.is_empty() && !content.ends_with('\n');
+            let finding = match layout::store_version(&filesystem, &store_dir) {
+                Ok(version) if version == STORE_VERSION => {
+                    let layout = layout::store_layout(&filesystem, &store_dir).unwrap_or_default();
+                    Finding::ok("store", format!("Store version {} with the {} layout", version, layout))
+                }
+                Ok(version) if version < STORE_VERSION => Finding::warning(
+                    "store",
+                    format!("The store is at version {}, this cc-sync-session writes version {}", version, STORE_VERSION),
+                    "Run 'cc-sync-session migrate'",
+                ),
+                Ok(version) => Finding::error(
+                    "store",
+                    format!("The store is at version {}, newer than this cc-sync-session supports ({})", version, STORE_VERSION),
+                    "Update cc-sync-session",
+                ),
+                Err(e) => Finding::error(
+                    "store",
+                    format!("Cannot read {}: {}", Path::new(store::STORE_PATH).join(STORE_DESCRIPTOR_FILE).display(), e),
+                    format!("Fix or remove {} and run 'cc-sync-session migrate'", STORE_DESCRIPTOR_FILE),
+                ),
+            };
+            report.findings.push(finding);
+            report.findings.push(doctor::check_writable("permissions", &store_dir));
         }
+        report.findings.extend(doctor::check_gitattributes(&repo, &repo_dir));
+        report.findings.push(doctor::check_ignored(&repo));
     }
-    
-    // Add LFS line if it doesn't exist
-    if !gitattributes_path.exists() || !content.lines().any(|line| line.trim() == lfs_line) {
-        let mut file = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&gitattributes_path)
-            .context("Failed to open .gitattributes file for writing")?;
-        
-        if needs_newline {
-            writeln!(file)?;
+    report.findings.extend(doctor::check_hooks(&repo, branch_store));
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).context("Failed to serialize the report")?);
+    } else {
+        for finding in &report.findings {
+            println!("{:<8} {}", doctor::label(finding.status), finding.message);
+            for detail in &finding.details {
+                println!("{:<8} {}", "", detail);
+            }
+            if let Some(fix) = &finding.fix {
+                println!("{:<8} fix: {}", "", fix);
+            }
         }
-        writeln!(file, "{}", lfs_line)
-            .context("Failed to write to .gitattributes file")?;
-        
-        println!("Added Git LFS configuration to .gitattributes");
+        println!("{} errors, {} warnings", report.errors(), report.warnings());
     }
-    
-    println!("Initialized session sync directory at: {}", ccss_dir.display());
-    println!("Created: {}", gitkeep_path.display());
-    
+
+    Ok(if report.is_ok() { 0 } else { session_sync::EXIT_CHECK_FAILED })
+}
+
+/// Opens the store branch of `repo_dir`, which must be configured for it
+fn open_branch_store(repo_dir: Option<PathBuf>) -> Result<(BranchStore, RepoConfig)> {
+    let repo_dir = resolve_store_repo_dir(repo_dir)?;
+    let config = load_config(&repo_dir)?;
+    if config.store.mode != StoreMode::Branch {
+        anyhow::bail!(
+            "The store is not kept on a branch; set [store] mode = \"branch\" in {} or run 'cc-sync-session init --store-branch'",
+            config::REPO_CONFIG_PATH
+        );
+    }
+
+    let repo = Repository::open(&repo_dir)
+        .context("Failed to open git repository")?;
+    Ok((BranchStore::new(repo, config.store.branch()), config))
+}
+
+fn pull_command(remote: String, repo_dir: Option<PathBuf>, identities: &Identities) -> Result<()> {
+    let (store, config) = open_branch_store(repo_dir)?;
+    let encryption = config.encryption.encryption()
+        .with_context(|| format!("Invalid [encryption] recipients in {}", config::REPO_CONFIG_PATH))?;
+
+    let Some(theirs) = store.fetch(&remote)? else {
+        println!("{} has no branch {}", remote, store.branch());
+        return Ok(());
+    };
+
+    match store.merge(theirs, encryption.as_ref(), identities, &config.redaction)? {
+        MergeResult::UpToDate => println!("{} is up to date with {}", store.branch(), remote),
+        MergeResult::FastForward(commit) => {
+            println!("Fast-forwarded {} to {}", store.branch(), &commit.to_string()[..7]);
+        }
+        MergeResult::Merged { commit, merged_files, conflicting_files } => {
+            for path in &merged_files {
+                println!("merged   {}", path.display());
+            }
+            for path in &conflicting_files {
+                println!("conflict {} (kept the local version)", path.display());
+            }
+            println!(
+                "Merged {}/{} into {} as {}",
+                remote,
+                store.branch(),
+                store.branch(),
+                &commit.to_string()[..7]
+            );
+        }
+    }
+
     Ok(())
 }
 
-fn sync_command(source_dir: Option<PathBuf>, repo_dir: Option<PathBuf>, dry_run: bool, git_add: bool, verbose: bool) -> Result<()> {
-    // Determine repository directory
-    let repo_dir = match repo_dir {
-        Some(dir) => dir,
-        None => {
-            let current_dir = std::env::current_dir()
-                .context("Failed to get current directory")?;
-            find_repo_dir(&current_dir)
-                .context("No repository with .claude/ccss_sessions found. Run 'cc-sync-session init' first")?
+fn push_command(remote: String, repo_dir: Option<PathBuf>) -> Result<()> {
+    let (store, _) = open_branch_store(repo_dir)?;
+    if store.head()?.is_none() {
+        anyhow::bail!("There is no branch {} to push yet; run 'cc-sync-session sync' first", store.branch());
+    }
+
+    store.push(&remote)?;
+    println!("Pushed {} to {}", store.branch(), remote);
+
+    Ok(())
+}
+
+/// The hooks directory of the git repository at `repo_dir`
+fn resolve_hooks_dir(repo_dir: Option<PathBuf>) -> Result<PathBuf> {
+    let repo_dir = resolve_git_repo_dir(repo_dir)?;
+    let repo = Repository::open(&repo_dir)
+        .context("Failed to open git repository")?;
+    hooks::hooks_dir(&repo).context("Failed to read core.hooksPath")
+}
+
+fn hooks_install_command(hooks: Vec<Hook>, repo_dir: Option<PathBuf>) -> Result<()> {
+    let hooks_dir = resolve_hooks_dir(repo_dir)?;
+
+    for hook in hooks {
+        let previous = hooks::install(&hooks_dir, hook)
+            .with_context(|| format!("Failed to install the {} hook", hook))?;
+        match previous {
+            HookState::Missing => println!("Installed {}", hook),
+            HookState::Foreign => println!("Installed {}, chaining to the existing hook (kept as {}.{})", hook, hook, hooks::CHAINED_EXTENSION),
+            HookState::Installed { .. } => println!("Updated {}", hook),
         }
-    };
+    }
+    println!("Hooks are in {}", hooks_dir.display());
 
-    log::info!("Using repository directory: {}", repo_dir.display());
-    let repo_dir_cc_style = dir_path_to_claude_code_stype(repo_dir.clone())?;
-    log::debug!("Converted repository directory to Claude Code style: {}", repo_dir_cc_style);
+    Ok(())
+}
 
-    // Determine source directory
-    let source_root_dir = match source_dir {
-        Some(dir) => dir,
-        None => {
-            // Check environment variable first
-            if let Ok(env_source) = std::env::var("CC_SYNC_SESSION_SOURCE_DIR") {
-                PathBuf::from(env_source)
-            } else {
-                let home = dirs::home_dir()
-                    .context("Failed to get home directory")?;
-                home.join(".claude").join("projects")
-            }
+fn hooks_uninstall_command(hooks: Vec<Hook>, repo_dir: Option<PathBuf>) -> Result<()> {
+    let hooks_dir = resolve_hooks_dir(repo_dir)?;
+
+    for hook in hooks {
+        let previous = hooks::uninstall(&hooks_dir, hook)
+            .with_context(|| format!("Failed to uninstall the {} hook", hook))?;
+        match previous {
+            HookState::Missing => {}
+            HookState::Foreign => println!("Left {} alone: it was not installed by cc-sync-session", hook),
+            HookState::Installed { chained: true } => println!("Removed {}, restoring the hook it chained to", hook),
+            HookState::Installed { chained: false } => println!("Removed {}", hook),
         }
-    };
+    }
 
-    let source_dir = source_root_dir.join(&repo_dir_cc_style);
+    Ok(())
+}
 
-    log::info!("Using source directory: {}", source_dir.display());
+/// The registry file and its repositories
+fn load_registry() -> Result<(PathBuf, Registry)> {
+    let registry_path = registry::registry_path()
+        .context("Failed to get the data directory")?;
+    let registry = Registry::load(&RealFileSystem::new(), &registry_path)?;
+    Ok((registry_path, registry))
+}
 
-    
-    // Target directory is .claude/ccss_sessions
-    let target_dir = repo_dir.join(".claude").join("ccss_sessions");
-    
-    // Validate directories
-    if !source_dir.exists() {
-        anyhow::bail!("Source directory does not exist: {}", source_dir.display());
+fn registry_list_command(json: bool) -> Result<()> {
+    let (registry_path, registry) = load_registry()?;
+    let filesystem = RealFileSystem::new();
+    let mut repos = Vec::new();
+    for repo in &registry.repos {
+        repos.push((repo, registry::repo_state(&filesystem, &repo.path)?));
     }
-    
-    if !source_dir.is_dir() {
-        anyhow::bail!("Source path is not a directory: {}", source_dir.display());
+
+    if json {
+        let report: Vec<_> = repos.iter()
+            .map(|(repo, state)| serde_json::json!({ "path": repo.path, "added": repo.added, "state": state }))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&report).context("Failed to serialize the registry")?);
+        return Ok(());
     }
-    
-    if !target_dir.exists() {
-        anyhow::bail!("Target directory does not exist: {}. Run 'cc-sync-session init' first", target_dir.display());
+
+    println!("Registry: {}", registry_path.display());
+    if repos.is_empty() {
+        println!("No repositories registered; 'cc-sync-session init' registers the repository it runs in");
+        return Ok(());
     }
-    
-    // Print operation summary
-    println!("Syncing Claude Code sessions:");
-    println!("  Source: {}", source_dir.display());
-    println!("  Target: {}", target_dir.display());
-    if dry_run {
-        println!("  Mode: DRY RUN (no changes will be made)");
+    let width = repos.iter().map(|(repo, _)| repo.path.display().to_string().len()).max().unwrap_or(0);
+    for (repo, state) in &repos {
+        println!("  {:<width$}  {}  {}", repo.path.display().to_string(), repo.added.format("%Y-%m-%d"), state, width = width);
     }
-    println!();
-    
-    // Create syncer and run sync
+    if repos.iter().any(|(_, state)| !state.is_ok()) {
+        println!("Run 'cc-sync-session registry remove --stale' to unregister the repositories that are not ok");
+    }
+    Ok(())
+}
+
+fn registry_remove_command(paths: Vec<PathBuf>, stale: bool) -> Result<()> {
+    let (registry_path, mut registry) = load_registry()?;
     let filesystem = RealFileSystem::new();
-    let syncer = SessionSyncer::new(filesystem);
-    
-    let options = SyncOptions {
-        dry_run,
-        verbose,
-    };
-    
-    let result = syncer.sync(&source_root_dir, repo_dir_cc_style.as_str(), &target_dir, &options)
-        .context("Failed to sync sessions")?;
-    
-    // Print results
-    println!("\nSync completed:");
-    println!("  Files copied: {}", result.files_copied);
-    println!("  Files skipped: {}", result.files_skipped);
-    println!("  Directories created: {}", result.directories_created);
-    
-    if !result.errors.is_empty() {
-        println!("\nErrors encountered:");
-        for error in &result.errors {
-            warn!("{}", error);
-            eprintln!("  - {}", error);
+    let mut removed = Vec::new();
+
+    for path in paths {
+        // Deleted repositories cannot be canonicalized, so they are looked up as given too
+        let resolved = path.canonicalize().ok().filter(|resolved| registry.contains(resolved));
+        let path = resolved.unwrap_or_else(|| std::path::absolute(&path).unwrap_or(path));
+        if !registry.remove(&path) {
+            anyhow::bail!("{} is not registered in {}", path.display(), registry_path.display());
         }
+        removed.push((path, None));
     }
-    
-    // Perform git add if requested and not in dry-run mode
-    if git_add && !dry_run && result.files_copied > 0 {
-        let repo = Repository::open(&repo_dir)
-            .context("Failed to open git repository")?;
-        
-        let ccss_sessions_path = target_dir.strip_prefix(&repo_dir)
-            .unwrap_or(&target_dir);
-        
-        let mut index = repo.index()
-            .context("Failed to get repository index")?;
-        
-        // Add all files under .claude/ccss_sessions recursively
-        index.add_all([ccss_sessions_path], git2::IndexAddOption::DEFAULT, None)
-            .context("Failed to add .claude/ccss_sessions to git index")?;
-        
-        index.write()
-            .context("Failed to write git index")?;
-        
-        println!("\nAdded {} to git index", ccss_sessions_path.display());
+    if stale {
+        for repo in registry.repos.clone() {
+            let state = registry::repo_state(&filesystem, &repo.path)?;
+            if !state.is_ok() {
+                registry.remove(&repo.path);
+                removed.push((repo.path, Some(state)));
+            }
+        }
     }
-    
-    if result.files_copied != 0 {
-        // コピーが行われた場合は、コマンドとしては失敗とする(pre-commitフックなどでの使用を想定)
-        std::process::exit(-1);
+
+    if removed.is_empty() {
+        println!("No stale repositories in {}", registry_path.display());
+        return Ok(());
+    }
+    registry.save(&filesystem, &registry_path)?;
+    for (path, state) in &removed {
+        match state {
+            Some(state) => println!("Unregistered {} ({})", path.display(), state),
+            None => println!("Unregistered {}", path.display()),
+        }
+    }
+    Ok(())
+}
+
+fn hooks_status_command(repo_dir: Option<PathBuf>) -> Result<()> {
+    let hooks_dir = resolve_hooks_dir(repo_dir)?;
+    println!("Hooks directory: {}", hooks_dir.display());
+
+    for hook in Hook::ALL {
+        let state = hooks::state(&hooks_dir, hook)
+            .with_context(|| format!("Failed to read the {} hook", hook))?;
+        let description = match state {
+            HookState::Missing => "not installed",
+            HookState::Foreign => "not installed (another hook is there; install chains to it)",
+            HookState::Installed { chained: true } => "installed, chaining to the previous hook",
+            HookState::Installed { chained: false } => "installed",
+        };
+        println!("  {:<20} {}", hook.name(), description);
     }
 
     Ok(())
 }
 
+fn hooks_run_command(hook: Hook, args: Vec<String>, verbose: bool, identities: &Identities) -> Result<i32> {
+    // A hook runs in the working tree of the repository being committed to
+    let repo_dir = resolve_git_repo_dir(None)?;
+    let config = load_config(&repo_dir)?;
+    let branch_store = config.store.mode == StoreMode::Branch;
+
+    // Only a sync that runs and fails may stop the commit
+    if matches!((hook, branch_store), (Hook::PreCommit, false) | (Hook::PostCommit, true)) {
+        let source_root_dir = resolve_source_root_dir(config.source.dir(), &repo_dir)?;
+        let worktree_store = store::store_dir(&repo_dir);
+        let store_dir = (!branch_store).then_some(worktree_store.as_path());
+        let reason = hooks::nothing_to_sync(store_dir, &source_root_dir, &dir_path_to_claude_code_stype(repo_dir.clone())?)
+            .with_context(|| format!("Failed to read {}", source_root_dir.display()))?;
+        if let Some(reason) = reason {
+            eprintln!("Note: {}; not syncing sessions", reason);
+            return Ok(0);
+        }
+    }
+
+    match hook {
+        // A store in the working tree is synced and staged with the commit
+        Hook::PreCommit if !branch_store => {
+            let layers = Layers::from_environment().with_command_line("sync.git_add", true);
+            sync_command(Some(repo_dir), layers, Vec::new(), false, verbose, identities)
+        }
+        Hook::PrepareCommitMsg => {
+            let mut args = args.into_iter();
+            let message_file = args.next().context("prepare-commit-msg needs the commit message file")?;
+            trailers_command(PathBuf::from(message_file), args.next(), args.next(), None, Some(repo_dir))?;
+            Ok(0)
+        }
+        // A store on a branch is committed to on its own, once the commit is done
+        Hook::PostCommit if branch_store => {
+            sync_command(Some(repo_dir), Layers::from_environment(), Vec::new(), false, verbose, identities)
+        }
+        Hook::PrePush if branch_store => {
+            let remote = args.first().context("pre-push needs the remote name")?;
+            let (store, _) = open_branch_store(Some(repo_dir))?;
+            if store.head()?.is_none() {
+                return Ok(0);
+            }
+            // The push of the commits goes on when the store branch cannot follow
+            match store.push(remote) {
+                Ok(()) => println!("Pushed {} to {}", store.branch(), remote),
+                Err(e) => eprintln!("Warning: {:#}", e),
+            }
+            Ok(0)
+        }
+        Hook::PreCommit | Hook::PostCommit | Hook::PrePush => Ok(0),
+    }
+}
+
+/// Exits with the exit code of a command that has its own, or with
+/// [`session_sync::EXIT_ERROR`] if it failed
+fn exit_with(result: Result<i32>) -> Result<()> {
+    match result {
+        Ok(0) => Ok(()),
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(session_sync::EXIT_ERROR);
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
     
@@ -291,11 +3192,101 @@ fn main() -> Result<()> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level))
         .target(env_logger::Target::Stderr)
         .init();
+
+    let mut identities = Identities::local();
+    for path in &cli.identity {
+        identities = identities.with_file(path)
+            .with_context(|| format!("Failed to read identity file {}", path.display()))?;
+    }
     
     match cli.command {
-        Commands::Init { repo_dir } => init_command(repo_dir),
-        Commands::Sync { source_dir, repo_dir, dry_run, git_add } => {
-            sync_command(source_dir, repo_dir, dry_run, git_add, cli.verbose)
+        Commands::Init { repo_dir, diff_driver, merge_driver, store_branch, namespaced } => {
+            init_command(repo_dir, diff_driver, merge_driver, store_branch, namespaced)
+        }
+        Commands::Sync {
+            source_dir, repo_dir, all, dry_run, git_add, deduplicate, sidechains, index,
+            filter, current_branch, policy,
+        } => {
+            // Options given on the command line override the configuration
+            let mut settings: Vec<(&str, toml::Value)> = [
+                ("sync.git_add", git_add),
+                ("sync.deduplicate", deduplicate),
+                ("sync.index", index),
+                ("sync.current_branch", current_branch),
+            ].into_iter()
+                .filter_map(|(key, value)| Some((key, toml::Value::Boolean(value?))))
+                .collect();
+            settings.extend([
+                ("source.dir", source_dir.map(|dir| dir.to_string_lossy().into_owned())),
+                ("sync.sidechains", sidechains.map(|mode| mode.to_string())),
+                ("sync.policy", policy.map(|policy| policy.to_string())),
+                ("filter.since", filter.since.map(|time| time.to_rfc3339())),
+                ("filter.until", filter.until.map(|time| time.to_rfc3339())),
+                ("filter.git_branch", filter.git_branch.clone()),
+            ].into_iter().filter_map(|(key, value)| Some((key, toml::Value::String(value?)))));
+            if filter.git_branch.is_some() {
+                settings.push(("sync.current_branch", toml::Value::Boolean(false)));
+            }
+
+            let layers = settings.into_iter()
+                .fold(Layers::from_environment(), |layers, (key, value)| layers.with_command_line(key, value));
+            if all {
+                exit_with(sync_all_command(layers, filter.sessions, dry_run, cli.verbose, &identities))
+            } else {
+                exit_with(sync_command(repo_dir, layers, filter.sessions, dry_run, cli.verbose, &identities))
+            }
+        }
+        Commands::Watch { source_dir, repo_dir, debounce, max_delay, systemd_unit } => {
+            watch_command(source_dir, repo_dir, debounce, max_delay, systemd_unit, &identities)
+        }
+        Commands::List {
+            source, source_dir, repo_dir, filter, namespaces, sidechains, sort, reverse, limit, json,
+        } => {
+            let options = ListOptions { sidechains, sort, reverse, limit, json };
+            list_command(source, source_dir, repo_dir, filter.into(), namespaces, options, &identities)
+        }
+        Commands::Show { id, source, source_dir, repo_dir, namespaces, tools, raw, no_pager } => {
+            show_command(id, source, source_dir, repo_dir, namespaces, tools, raw, no_pager, &identities)
+        }
+        Commands::Tui { source_dir, repo_dir, filter } => tui_command(source_dir, repo_dir, filter.into(), &identities),
+        Commands::Diff { from, to, source_dir, repo_dir, namespaces } => {
+            diff_command(from, to, source_dir, repo_dir, namespaces, &identities)
+        }
+        Commands::Textconv { file } => textconv_command(file, &identities),
+        Commands::MergeDriver { base, ours, theirs, path } => {
+            exit_with(merge_driver_command(base, ours, theirs, path, &identities))
+        }
+        Commands::Trailers { message_file, commit_source, commit_sha, source_dir, repo_dir } => {
+            trailers_command(message_file, commit_source, commit_sha, source_dir, repo_dir)
+        }
+        Commands::Prune { repo_dir, dry_run, archive, git_add } => prune_command(repo_dir, dry_run, archive, git_add, &identities),
+        Commands::Convert { compression, repo_dir } => convert_command(compression, repo_dir, &identities),
+        Commands::Export { bundle, from_store, source_dir, repo_dir, filter, namespaces } => {
+            export_command(bundle, from_store, source_dir, repo_dir, filter.into(), namespaces, &identities)
+        }
+        Commands::Import { bundle, to_projects, source_dir, repo_dir, force, dry_run } => {
+            import_command(bundle, to_projects, source_dir, repo_dir, force, dry_run)
+        }
+        Commands::Migrate { to, layout, repo_dir, dry_run } => migrate_command(to, layout, repo_dir, dry_run),
+        Commands::Keygen { output } => keygen_command(output),
+        Commands::ReEncrypt { add, remove, repo_dir } => re_encrypt_command(add, remove, repo_dir, &identities),
+        Commands::Pull { remote, repo_dir } => pull_command(remote, repo_dir, &identities),
+        Commands::Push { remote, repo_dir } => push_command(remote, repo_dir),
+        Commands::Config { command: ConfigCommands::Show { origin, repo_dir } } => config_show_command(origin, repo_dir),
+        Commands::Registry { command } => match command {
+            RegistryCommands::List { json } => registry_list_command(json),
+            RegistryCommands::Remove { paths, stale } => registry_remove_command(paths, stale),
+        },
+        Commands::Hooks { command } => match command {
+            HooksCommands::Install { hooks } => hooks_install_command(hooks.hooks(), hooks.repo_dir),
+            HooksCommands::Uninstall { hooks } => hooks_uninstall_command(hooks.hooks(), hooks.repo_dir),
+            HooksCommands::Status { repo_dir } => hooks_status_command(repo_dir),
+            HooksCommands::Run { hook, args } => exit_with(hooks_run_command(hook, args, cli.verbose, &identities)),
+        },
+        Commands::Status { source_dir, repo_dir, short, json } => {
+            exit_with(status_command(source_dir, repo_dir, short, json, &identities))
         }
+        Commands::Doctor { source_dir, repo_dir, json } => exit_with(doctor_command(source_dir, repo_dir, json)),
+        Commands::Verify { repo_dir, json } => exit_with(verify_command(repo_dir, json, &identities)),
     }
 }
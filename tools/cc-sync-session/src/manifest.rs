@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::filesystem::{FileSystem, FileSystemError};
+
+/// Manifest file at the store root, listing every synced file with its hash.
+pub const MANIFEST_FILE: &str = ".ccss-manifest.json";
+
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum ManifestError {
+    #[error(transparent)]
+    FileSystem(#[from] FileSystemError),
+
+    #[error("Invalid manifest: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ManifestError>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl ManifestEntry {
+    pub fn for_content(content: &[u8]) -> Self {
+        Self {
+            sha256: sha256_hex(content),
+            size: content.len() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Entries keyed by store-relative path, with `/` separators.
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+impl Manifest {
+    /// Reads the manifest of the store at `store_dir`, if it has one.
+    pub fn load<FS: FileSystem>(fs: &FS, store_dir: &Path) -> Result<Option<Self>> {
+        let path = store_dir.join(MANIFEST_FILE);
+        if !fs.exists(&path)? {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_slice(&fs.read_file(&path)?)?))
+    }
+
+    pub fn save<FS: FileSystem>(&self, fs: &FS, store_dir: &Path) -> Result<()> {
+        let mut content = serde_json::to_vec_pretty(self)?;
+        content.push(b'\n');
+        fs.write_file(&store_dir.join(MANIFEST_FILE), &content)?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, relative_path: &Path, content: &[u8]) {
+        self.files.insert(manifest_key(relative_path), ManifestEntry::for_content(content));
+    }
+
+    pub fn remove(&mut self, relative_path: &Path) -> Option<ManifestEntry> {
+        self.files.remove(&manifest_key(relative_path))
+    }
+
+    pub fn get(&self, relative_path: &Path) -> Option<&ManifestEntry> {
+        self.files.get(&manifest_key(relative_path))
+    }
+}
+
+/// Manifest key of a store-relative path.
+pub fn manifest_key(relative_path: &Path) -> String {
+    relative_path.to_string_lossy().replace('\\', "/")
+}
+
+pub fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockFileSystem;
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let fs = MockFileSystem::new();
+        let store_dir = Path::new("/store");
+        fs.add_directory(store_dir);
+
+        assert!(Manifest::load(&fs, store_dir).unwrap().is_none());
+
+        let mut manifest = Manifest::default();
+        manifest.record(Path::new("p/s1.jsonl"), b"abc");
+        manifest.save(&fs, store_dir).unwrap();
+
+        let loaded = Manifest::load(&fs, store_dir).unwrap().unwrap();
+        assert_eq!(loaded, manifest);
+        assert_eq!(loaded.get(Path::new("p/s1.jsonl")).unwrap(), &ManifestEntry {
+            sha256: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+            size: 3,
+        });
+    }
+}
@@ -0,0 +1,245 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use crate::session::{Result, SessionError, SessionRecord};
+
+/// Exit code of the merge driver when records conflict, so that git leaves
+/// the file conflicted.
+pub const EXIT_CONFLICT: i32 = 1;
+
+/// Result of merging three versions of a session transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeOutcome {
+    /// Merged JSONL, with conflict markers around conflicting records.
+    pub content: String,
+    /// Keys (usually uuids) of records both sides changed differently.
+    pub conflicts: Vec<String>,
+}
+
+impl MergeOutcome {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// A transcript line together with its parsed record.
+#[derive(Debug, Clone)]
+struct Line {
+    text: String,
+    record: SessionRecord,
+}
+
+impl Line {
+    /// Records are identified by `uuid`; records without one (e.g. summaries)
+    /// by their content.
+    fn key(&self) -> String {
+        match self.record.uuid() {
+            Some(uuid) => uuid.to_string(),
+            None => self.record.value().to_string(),
+        }
+    }
+}
+
+enum Resolution {
+    Take(Line),
+    Conflict { ours: Line, theirs: Line },
+}
+
+/// Three-way union of session transcripts keyed by record uuid.
+///
+/// Records added on either side are kept, records deleted on one side and
+/// unchanged on the other are dropped. Only a record with the same uuid but
+/// different content on both sides is a conflict. The result is ordered along
+/// the `parentUuid` chain, then by timestamp.
+pub fn merge_transcripts(base: &str, ours: &str, theirs: &str) -> Result<MergeOutcome> {
+    let base = index_lines(parse_lines(base)?);
+    let ours = parse_lines(ours)?;
+    let theirs = parse_lines(theirs)?;
+
+    let ours_by_key = index_lines(ours.clone());
+    let theirs_by_key = index_lines(theirs.clone());
+
+    // Keys in first-seen order: ours, then records only theirs has
+    let mut keys = Vec::new();
+    let mut seen = HashSet::new();
+    for line in ours.iter().chain(theirs.iter()) {
+        let key = line.key();
+        if seen.insert(key.clone()) {
+            keys.push(key);
+        }
+    }
+
+    let mut resolved = Vec::new();
+    for key in keys {
+        let in_base = base.get(&key);
+        let resolution = match (ours_by_key.get(&key), theirs_by_key.get(&key)) {
+            (Some(ours), Some(theirs)) if ours.record == theirs.record => Resolution::Take(ours.clone()),
+            (Some(ours), Some(theirs)) => match in_base {
+                Some(base) if base.record == ours.record => Resolution::Take(theirs.clone()),
+                Some(base) if base.record == theirs.record => Resolution::Take(ours.clone()),
+                _ => Resolution::Conflict { ours: ours.clone(), theirs: theirs.clone() },
+            },
+            (Some(only), None) | (None, Some(only)) => match in_base {
+                // Deleted on the other side and unchanged here
+                Some(base) if base.record == only.record => continue,
+                _ => Resolution::Take(only.clone()),
+            },
+            (None, None) => continue,
+        };
+        resolved.push((key, resolution));
+    }
+
+    let mut content = String::new();
+    let mut conflicts = Vec::new();
+    for index in chain_order(&resolved) {
+        let (key, resolution) = &resolved[index];
+        match resolution {
+            Resolution::Take(line) => {
+                content.push_str(&line.text);
+                content.push('\n');
+            }
+            Resolution::Conflict { ours, theirs } => {
+                content.push_str(&format!("<<<<<<< ours\n{}\n=======\n{}\n>>>>>>> theirs\n", ours.text, theirs.text));
+                conflicts.push(key.clone());
+            }
+        }
+    }
+
+    Ok(MergeOutcome { content, conflicts })
+}
+
+fn parse_lines(content: &str) -> Result<Vec<Line>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            SessionRecord::parse(line)
+                .map(|record| Line { text: line.to_string(), record })
+                .map_err(|source| SessionError::InvalidRecord { line: index + 1, source })
+        })
+        .collect()
+}
+
+fn index_lines(lines: Vec<Line>) -> HashMap<String, Line> {
+    lines.into_iter().map(|line| (line.key(), line)).collect()
+}
+
+/// Orders records so that every record follows its parent; among records
+/// that are ready, the earliest timestamp (then the first seen) goes first.
+fn chain_order(resolved: &[(String, Resolution)]) -> Vec<usize> {
+    let record = |index: usize| match &resolved[index].1 {
+        Resolution::Take(line) | Resolution::Conflict { ours: line, .. } => &line.record,
+    };
+
+    let positions: HashMap<&str, usize> = resolved.iter()
+        .enumerate()
+        .map(|(index, (key, _))| (key.as_str(), index))
+        .collect();
+
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut ready = BinaryHeap::new();
+    let sort_key = |index: usize| -> Reverse<(Option<DateTime<Utc>>, usize)> {
+        Reverse((record(index).timestamp(), index))
+    };
+
+    for index in 0..resolved.len() {
+        let parent = record(index).parent_uuid()
+            .and_then(|parent| positions.get(parent))
+            .filter(|&&parent| parent != index);
+        match parent {
+            Some(&parent) => children.entry(parent).or_default().push(index),
+            None => ready.push(sort_key(index)),
+        }
+    }
+
+    let mut order = Vec::with_capacity(resolved.len());
+    while let Some(Reverse((_, index))) = ready.pop() {
+        order.push(index);
+        for &child in children.get(&index).into_iter().flatten() {
+            ready.push(sort_key(child));
+        }
+    }
+
+    // Records caught in a parent cycle keep their first-seen order
+    if order.len() < resolved.len() {
+        let mut emitted = vec![false; resolved.len()];
+        for &index in &order {
+            emitted[index] = true;
+        }
+        order.extend((0..resolved.len()).filter(|&index| !emitted[index]));
+    }
+
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(uuid: &str, parent: Option<&str>, time: &str, text: &str) -> String {
+        let parent = parent.map(|parent| format!("\"{}\"", parent)).unwrap_or_else(|| "null".to_string());
+        format!(
+            r#"{{"type":"user","uuid":"{}","parentUuid":{},"timestamp":"2025-06-25T10:00:{}Z","message":{{"role":"user","content":"{}"}}}}"#,
+            uuid, parent, time, text
+        )
+    }
+
+    fn jsonl(lines: &[&String]) -> String {
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    #[test]
+    fn test_union_of_appended_records() {
+        let a = record("a", None, "00", "start");
+        let b = record("b", Some("a"), "01", "ours");
+        let c = record("c", Some("a"), "02", "theirs");
+        let d = record("d", Some("c"), "03", "theirs again");
+
+        let outcome = merge_transcripts(&jsonl(&[&a]), &jsonl(&[&a, &b]), &jsonl(&[&a, &c, &d])).unwrap();
+
+        assert!(outcome.is_clean());
+        assert_eq!(outcome.content, jsonl(&[&a, &b, &c, &d]));
+    }
+
+    #[test]
+    fn test_children_follow_parents() {
+        let a = record("a", None, "00", "start");
+        let b = record("b", Some("a"), "05", "late parent");
+        let c = record("c", Some("b"), "01", "child with skewed clock");
+
+        let outcome = merge_transcripts("", &jsonl(&[&c]), &jsonl(&[&a, &b])).unwrap();
+
+        assert_eq!(outcome.content, jsonl(&[&a, &b, &c]));
+    }
+
+    #[test]
+    fn test_one_sided_change_and_delete() {
+        let a = record("a", None, "00", "start");
+        let b = record("b", Some("a"), "01", "old");
+        let b_changed = record("b", Some("a"), "01", "new");
+
+        let changed = merge_transcripts(&jsonl(&[&a, &b]), &jsonl(&[&a, &b]), &jsonl(&[&a, &b_changed])).unwrap();
+        assert_eq!(changed.content, jsonl(&[&a, &b_changed]));
+
+        let deleted = merge_transcripts(&jsonl(&[&a, &b]), &jsonl(&[&a]), &jsonl(&[&a, &b])).unwrap();
+        assert_eq!(deleted.content, jsonl(&[&a]));
+    }
+
+    #[test]
+    fn test_conflicting_records() {
+        let a = record("a", None, "00", "start");
+        let ours = record("b", Some("a"), "01", "ours");
+        let theirs = record("b", Some("a"), "01", "theirs");
+
+        let outcome = merge_transcripts(&jsonl(&[&a]), &jsonl(&[&a, &ours]), &jsonl(&[&a, &theirs])).unwrap();
+
+        assert_eq!(outcome.conflicts, vec!["b".to_string()]);
+        assert_eq!(
+            outcome.content,
+            format!("{}\n<<<<<<< ours\n{}\n=======\n{}\n>>>>>>> theirs\n", a, ours, theirs)
+        );
+    }
+}
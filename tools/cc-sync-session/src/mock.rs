@@ -50,6 +50,12 @@ impl MockFileSystem {
     }
 }
 
+impl Default for MockFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FileSystem for MockFileSystem {
     fn list_directory(&self, path: &Path) -> Result<Vec<EntryMetadata>> {
         let files = self.files.lock().unwrap();
@@ -124,7 +130,10 @@ impl FileSystem for MockFileSystem {
     }
     
     fn create_directory(&self, path: &Path) -> Result<()> {
-        self.add_directory(path);
+        // Like create_dir_all
+        for ancestor in path.ancestors().filter(|ancestor| !ancestor.as_os_str().is_empty()) {
+            self.add_directory(ancestor);
+        }
         Ok(())
     }
     
@@ -145,4 +154,21 @@ impl FileSystem for MockFileSystem {
             Err(FileSystemError::NotFound(path.to_path_buf()))
         }
     }
-}
\ No newline at end of file
+    
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        self.get_file_content(path)
+            .ok_or_else(|| FileSystemError::NotFound(path.to_path_buf()))
+    }
+    
+    fn write_file(&self, path: &Path, content: &[u8]) -> Result<()> {
+        self.add_file(path, content.to_vec(), SystemTime::now());
+        Ok(())
+    }
+    
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        let mut files = self.files.lock().unwrap();
+        files.remove(path)
+            .map(|_| ())
+            .ok_or_else(|| FileSystemError::NotFound(path.to_path_buf()))
+    }
+}
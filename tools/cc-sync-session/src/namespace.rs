@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use git2::Repository;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::filesystem::{FileSystem, FileSystemError};
+use crate::store::Snapshot;
+
+/// Identity mapping file, relative to the repository root.
+pub const AUTHORS_FILE: &str = ".claude/ccss-authors.toml";
+
+/// Git config key overriding the author of the namespace sessions are synced to.
+pub const AUTHOR_GIT_CONFIG: &str = "ccss.author";
+
+/// Git config key overriding the machine name, which defaults to the host name.
+pub const MACHINE_GIT_CONFIG: &str = "ccss.machine";
+
+#[derive(Debug, Error)]
+pub enum NamespaceError {
+    #[error(transparent)]
+    FileSystem(#[from] FileSystemError),
+
+    #[error("Invalid identity mapping in {path}: {source}")]
+    Parse { path: PathBuf, source: toml::de::Error },
+
+    #[error("No author for the session namespace; set git config user.email or {}", AUTHOR_GIT_CONFIG)]
+    NoAuthor,
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+pub type Result<T> = std::result::Result<T, NamespaceError>;
+
+/// The `<author>/<machine>` directory one person's sessions from one machine
+/// are synced to in a namespaced store.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Namespace {
+    pub author: String,
+    pub machine: String,
+}
+
+impl Namespace {
+    /// A namespace, with both names made safe to use as a directory name.
+    pub fn new(author: &str, machine: &str) -> Self {
+        Self {
+            author: sanitize(author),
+            machine: sanitize(machine),
+        }
+    }
+
+    /// The namespace of the current user and machine in `repo`.
+    ///
+    /// The author is git config `ccss.author`, or else `user.email`, mapped to
+    /// a canonical name through `authors`. The machine is git config
+    /// `ccss.machine`, or else the host name.
+    pub fn resolve(repo: &Repository, authors: &AuthorMap) -> Result<Self> {
+        let config = repo.config()?;
+        let author = config.get_string(AUTHOR_GIT_CONFIG)
+            .or_else(|_| config.get_string("user.email"))
+            .map_err(|_| NamespaceError::NoAuthor)?;
+        let machine = config.get_string(MACHINE_GIT_CONFIG)
+            .unwrap_or_else(|_| gethostname::gethostname().to_string_lossy().into_owned());
+
+        Ok(Self::new(&authors.canonical(&author), &machine))
+    }
+
+    /// Path of the namespace relative to the store root.
+    pub fn path(&self) -> PathBuf {
+        Path::new(&self.author).join(&self.machine)
+    }
+
+    /// Splits a store-relative path into its namespace and the path inside it.
+    pub fn split(path: &Path) -> Option<(Self, PathBuf)> {
+        let mut components = path.components();
+        let (Some(Component::Normal(author)), Some(Component::Normal(machine))) = (components.next(), components.next()) else {
+            return None;
+        };
+        let rest = components.as_path();
+        if rest.as_os_str().is_empty() {
+            return None;
+        }
+        Some((
+            Self { author: author.to_string_lossy().into_owned(), machine: machine.to_string_lossy().into_owned() },
+            rest.to_path_buf(),
+        ))
+    }
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.author, self.machine)
+    }
+}
+
+/// Makes `name` a single, visible path component.
+pub fn sanitize(name: &str) -> String {
+    let name: String = name.trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || "._@+-".contains(c) { c } else { '-' })
+        .collect();
+    let name = name.trim_start_matches('.');
+    if name.is_empty() {
+        "unknown".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Canonical author names of the emails people commit with, read from
+/// `.claude/ccss-authors.toml`:
+///
+/// ```toml
+/// [authors]
+/// alice = ["alice@example.com", "alice@users.noreply.github.com"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuthorMap {
+    by_email: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AuthorsFile {
+    #[serde(default)]
+    authors: BTreeMap<String, Vec<String>>,
+}
+
+impl AuthorMap {
+    /// Reads the mapping of `repo_dir`; a missing file maps nothing.
+    pub fn load<FS: FileSystem>(fs: &FS, repo_dir: &Path) -> Result<Self> {
+        let path = repo_dir.join(AUTHORS_FILE);
+        if !fs.exists(&path)? {
+            return Ok(Self::default());
+        }
+
+        let file: AuthorsFile = toml::from_str(&String::from_utf8_lossy(&fs.read_file(&path)?))
+            .map_err(|source| NamespaceError::Parse { path, source })?;
+        Ok(Self::from_authors(file.authors))
+    }
+
+    pub fn from_authors(authors: BTreeMap<String, Vec<String>>) -> Self {
+        let by_email = authors.into_iter()
+            .flat_map(|(name, emails)| emails.into_iter().map(move |email| (email.to_lowercase(), name.clone())))
+            .collect();
+        Self { by_email }
+    }
+
+    /// The canonical name of `author` (an email or a name), or `author` itself.
+    pub fn canonical(&self, author: &str) -> String {
+        let author = author.trim();
+        self.by_email.get(&author.to_lowercase())
+            .cloned()
+            .unwrap_or_else(|| author.to_string())
+    }
+}
+
+/// Selects namespaces by author and machine; an empty filter selects all.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NamespaceFilter {
+    pub author: Option<String>,
+    pub machine: Option<String>,
+}
+
+impl NamespaceFilter {
+    /// A filter whose author (an email or a name) is mapped through `authors`.
+    pub fn new(author: Option<&str>, machine: Option<&str>, authors: &AuthorMap) -> Self {
+        Self {
+            author: author.map(|author| sanitize(&authors.canonical(author))),
+            machine: machine.map(sanitize),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.author.is_none() && self.machine.is_none()
+    }
+
+    pub fn matches(&self, namespace: &Namespace) -> bool {
+        self.author.as_ref().is_none_or(|author| author.eq_ignore_ascii_case(&namespace.author))
+            && self.machine.as_ref().is_none_or(|machine| machine.eq_ignore_ascii_case(&namespace.machine))
+    }
+}
+
+/// The `<author>/<machine>` directories of the namespaced store at `store_dir`.
+pub fn list_namespaces<FS: FileSystem>(fs: &FS, store_dir: &Path) -> Result<Vec<Namespace>> {
+    let visible_dirs = |dir: &Path| -> Result<Vec<String>> {
+        let mut names: Vec<String> = fs.list_directory(dir)?
+            .into_iter()
+            .filter(|entry| entry.is_directory)
+            .filter_map(|entry| entry.path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .filter(|name| !name.starts_with('.'))
+            .collect();
+        names.sort();
+        Ok(names)
+    };
+
+    let mut namespaces = Vec::new();
+    if !fs.exists(store_dir)? {
+        return Ok(namespaces);
+    }
+    for author in visible_dirs(store_dir)? {
+        for machine in visible_dirs(&store_dir.join(&author))? {
+            namespaces.push(Namespace { author: author.clone(), machine });
+        }
+    }
+    Ok(namespaces)
+}
+
+/// Splits a snapshot of a namespaced store into one snapshot per namespace,
+/// keyed by the path inside the namespace.
+pub fn split_snapshot(snapshot: &Snapshot) -> BTreeMap<Namespace, Snapshot> {
+    let mut namespaces: BTreeMap<Namespace, Snapshot> = BTreeMap::new();
+    for (path, content) in snapshot {
+        if let Some((namespace, path)) = Namespace::split(path) {
+            namespaces.entry(namespace).or_default().insert(path, content.clone());
+        }
+    }
+    namespaces
+}
+
+/// Joins per-namespace snapshots back into one keyed by store-relative path.
+pub fn join_snapshots(namespaces: &BTreeMap<Namespace, Snapshot>) -> Snapshot {
+    namespaces.iter()
+        .flat_map(|(namespace, snapshot)| {
+            snapshot.iter().map(move |(path, content)| (namespace.path().join(path), content.clone()))
+        })
+        .collect()
+}
+
+/// Merges per-namespace snapshots into one keyed like an encoded store.
+///
+/// A session synced from several namespaces (e.g. resumed on another
+/// machine) keeps its longest copy, which has the most records.
+pub fn aggregate(namespaces: &BTreeMap<Namespace, Snapshot>) -> Snapshot {
+    let mut aggregated = Snapshot::new();
+    for snapshot in namespaces.values() {
+        for (path, content) in snapshot {
+            if aggregated.get(path).is_none_or(|existing: &Vec<u8>| existing.len() < content.len()) {
+                aggregated.insert(path.clone(), content.clone());
+            }
+        }
+    }
+    aggregated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockFileSystem;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_author_map_and_filter() {
+        let fs = MockFileSystem::new();
+        let repo_dir = Path::new("/repo");
+        assert_eq!(AuthorMap::load(&fs, repo_dir).unwrap(), AuthorMap::default());
+
+        fs.add_file(
+            repo_dir.join(AUTHORS_FILE),
+            b"[authors]\nalice = [\"alice@example.com\", \"Alice@Home.example\"]\n".to_vec(),
+            SystemTime::now(),
+        );
+        let authors = AuthorMap::load(&fs, repo_dir).unwrap();
+        assert_eq!(authors.canonical("alice@home.example"), "alice");
+        assert_eq!(authors.canonical("bob@example.com"), "bob@example.com");
+
+        let namespace = Namespace::new(&authors.canonical("alice@example.com"), "laptop.local");
+        assert_eq!(namespace.path(), Path::new("alice/laptop.local"));
+        assert_eq!(Namespace::new("a/b", "../x").path(), Path::new("a-b/-x"));
+
+        assert!(NamespaceFilter::default().matches(&namespace));
+        assert!(NamespaceFilter::new(Some("alice@home.example"), None, &authors).matches(&namespace));
+        assert!(!NamespaceFilter::new(Some("alice"), Some("desktop"), &authors).matches(&namespace));
+
+        fs.add_file(repo_dir.join(AUTHORS_FILE), b"alice = 1\n".to_vec(), SystemTime::now());
+        assert!(matches!(AuthorMap::load(&fs, repo_dir), Err(NamespaceError::Parse { .. })));
+    }
+
+    #[test]
+    fn test_split_and_aggregate() {
+        let mut snapshot = Snapshot::new();
+        snapshot.insert(PathBuf::from("alice/laptop/-repo/s1.jsonl"), b"1\n".to_vec());
+        snapshot.insert(PathBuf::from("alice/desktop/-repo/s1.jsonl"), b"1\n2\n".to_vec());
+        snapshot.insert(PathBuf::from("bob/laptop/-repo/s2.jsonl"), b"3\n".to_vec());
+
+        let namespaces = split_snapshot(&snapshot);
+        assert_eq!(namespaces.len(), 3);
+        assert_eq!(namespaces[&Namespace::new("bob", "laptop")].keys().collect::<Vec<_>>(), vec![Path::new("-repo/s2.jsonl")]);
+        assert_eq!(join_snapshots(&namespaces), snapshot);
+
+        let aggregated = aggregate(&namespaces);
+        assert_eq!(aggregated.len(), 2);
+        assert_eq!(aggregated[Path::new("-repo/s1.jsonl")], b"1\n2\n");
+    }
+
+    #[test]
+    fn test_list_namespaces() {
+        let fs = MockFileSystem::new();
+        let store_dir = Path::new("/store");
+        for dir in ["", "bob", "bob/laptop", "alice", "alice/laptop", "alice/desktop"] {
+            fs.add_directory(store_dir.join(dir));
+        }
+        fs.add_file(store_dir.join(".ccss.toml"), Vec::new(), SystemTime::now());
+
+        assert_eq!(list_namespaces(&fs, store_dir).unwrap(), vec![
+            Namespace::new("alice", "desktop"),
+            Namespace::new("alice", "laptop"),
+            Namespace::new("bob", "laptop"),
+        ]);
+    }
+}
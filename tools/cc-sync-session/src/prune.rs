@@ -0,0 +1,345 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::compression::find_stored_path;
+use crate::dedup::{expand_snapshot, referenced_paths};
+use crate::encryption::{encrypted_path, Encryption};
+use crate::filesystem::{self, FileSystem};
+use crate::session::SessionRecord;
+use crate::store::Snapshot;
+use crate::sync::SessionPeek;
+
+/// Retention rules for the store, from the `[retention]` table of the repo config.
+///
+/// Without `keep_days` and `max_per_branch` nothing is pruned.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionPolicy {
+    /// Prune sessions whose last record is older than this many days.
+    pub keep_days: Option<u32>,
+    /// Keep at most this many sessions per git branch, the most recent ones.
+    pub max_per_branch: Option<usize>,
+    /// Session ids that are never pruned.
+    pub pinned: Vec<String>,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.keep_days.is_none() && self.max_per_branch.is_none()
+    }
+}
+
+/// Why a session is pruned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PruneReason {
+    /// Last activity is older than `keep_days`.
+    Expired { last_activity: DateTime<Utc> },
+    /// More than `max_per_branch` newer sessions on the same branch.
+    BranchLimit { branch: Option<String> },
+}
+
+impl std::fmt::Display for PruneReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PruneReason::Expired { last_activity } => {
+                write!(f, "last active {}", last_activity.format("%Y-%m-%d"))
+            }
+            PruneReason::BranchLimit { branch } => {
+                write!(f, "over the limit for branch {}", branch.as_deref().unwrap_or("(none)"))
+            }
+        }
+    }
+}
+
+/// Why a session that breaks a rule is kept anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepReason {
+    Pinned,
+    /// Recorded in a `Claude-Session` trailer of a commit.
+    Committed,
+    /// Deduplicated sessions that are kept refer to its records.
+    Referenced,
+}
+
+/// All files of one session id: the transcript, its sidechains and nested sidechains.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredSession {
+    pub id: String,
+    pub paths: Vec<PathBuf>,
+    pub git_branch: Option<String>,
+    pub last_activity: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PruneDecision {
+    pub session: StoredSession,
+    pub reason: PruneReason,
+    /// Set if the session is kept despite `reason`.
+    pub kept_by: Option<KeepReason>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrunePlan {
+    /// Sessions breaking a rule, pruned unless `kept_by` is set.
+    pub decisions: Vec<PruneDecision>,
+    pub sessions_total: usize,
+}
+
+impl PrunePlan {
+    pub fn pruned(&self) -> impl Iterator<Item = &PruneDecision> {
+        self.decisions.iter().filter(|decision| decision.kept_by.is_none())
+    }
+
+    /// Store-relative paths of every pruned file.
+    pub fn pruned_paths(&self) -> Vec<PathBuf> {
+        self.pruned().flat_map(|decision| decision.session.paths.iter().cloned()).collect()
+    }
+}
+
+/// Groups the files of a store snapshot by session id.
+pub fn stored_sessions(snapshot: &Snapshot) -> Vec<StoredSession> {
+    let expanded = expand_snapshot(snapshot).unwrap_or_else(|e| {
+        log::warn!("Reading deduplicated sessions without their references: {}", e);
+        snapshot.clone()
+    });
+    let mut sessions: BTreeMap<String, StoredSession> = BTreeMap::new();
+
+    for (path, content) in &expanded {
+        let content = String::from_utf8_lossy(content);
+        let peek = SessionPeek::from_head(&content);
+        let last_activity = content.lines()
+            .rev()
+            .filter_map(|line| SessionRecord::parse(line).ok())
+            .find_map(|record| record.timestamp());
+        let id = peek.session_id.clone()
+            .or_else(|| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+
+        let session = sessions.entry(id.clone()).or_insert_with(|| StoredSession {
+            id,
+            paths: Vec::new(),
+            git_branch: None,
+            last_activity: None,
+        });
+        session.paths.push(path.clone());
+        session.git_branch = session.git_branch.take().or(peek.git_branch);
+        session.last_activity = session.last_activity.max(last_activity);
+    }
+
+    sessions.into_values().collect()
+}
+
+/// Decides which sessions of `snapshot` the policy prunes.
+///
+/// Pinned sessions, sessions in `committed_ids` and sessions whose records
+/// kept deduplicated sessions refer to are kept.
+pub fn plan_prune(snapshot: &Snapshot, policy: &RetentionPolicy, committed_ids: &BTreeSet<String>, now: DateTime<Utc>) -> PrunePlan {
+    let sessions = stored_sessions(snapshot);
+    let mut plan = PrunePlan {
+        sessions_total: sessions.len(),
+        ..Default::default()
+    };
+    let mut breaking: BTreeMap<String, PruneReason> = BTreeMap::new();
+
+    if let Some(keep_days) = policy.keep_days {
+        let cutoff = now - Duration::days(i64::from(keep_days));
+        for session in &sessions {
+            if let Some(last_activity) = session.last_activity.filter(|time| *time < cutoff) {
+                breaking.insert(session.id.clone(), PruneReason::Expired { last_activity });
+            }
+        }
+    }
+
+    if let Some(max_per_branch) = policy.max_per_branch {
+        let mut by_branch: BTreeMap<Option<&str>, Vec<&StoredSession>> = BTreeMap::new();
+        for session in &sessions {
+            by_branch.entry(session.git_branch.as_deref()).or_default().push(session);
+        }
+        for (branch, mut branch_sessions) in by_branch {
+            branch_sessions.sort_by(|a, b| b.last_activity.cmp(&a.last_activity).then_with(|| a.id.cmp(&b.id)));
+            for session in branch_sessions.into_iter().skip(max_per_branch) {
+                breaking.entry(session.id.clone())
+                    .or_insert_with(|| PruneReason::BranchLimit { branch: branch.map(str::to_string) });
+            }
+        }
+    }
+
+    let mut decisions: Vec<PruneDecision> = sessions.into_iter()
+        .filter_map(|session| {
+            let reason = breaking.remove(&session.id)?;
+            let kept_by = if policy.pinned.iter().any(|pinned| session.id.starts_with(pinned.as_str())) {
+                Some(KeepReason::Pinned)
+            } else if committed_ids.contains(&session.id) {
+                Some(KeepReason::Committed)
+            } else {
+                None
+            };
+            Some(PruneDecision { session, reason, kept_by })
+        })
+        .collect();
+
+    // Keep what kept sessions refer to, until nothing changes
+    loop {
+        let pruned: BTreeSet<&PathBuf> = decisions.iter()
+            .filter(|decision| decision.kept_by.is_none())
+            .flat_map(|decision| &decision.session.paths)
+            .collect();
+        let referenced: BTreeSet<PathBuf> = snapshot.iter()
+            .filter(|(path, _)| !pruned.contains(path))
+            .flat_map(|(_, content)| referenced_paths(&String::from_utf8_lossy(content)))
+            .collect();
+
+        let mut changed = false;
+        for decision in decisions.iter_mut().filter(|decision| decision.kept_by.is_none()) {
+            if decision.session.paths.iter().any(|path| referenced.contains(path)) {
+                decision.kept_by = Some(KeepReason::Referenced);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    plan.decisions = decisions;
+    plan
+}
+
+/// Writes the pruned files of `snapshot` to a new zstd-compressed tar archive.
+///
+/// With `encryption`, as for an encrypted store, each file is encrypted and
+/// archived under its `.age` path.
+pub fn write_archive(archive_path: &Path, snapshot: &Snapshot, paths: &[PathBuf], encryption: Option<&Encryption>) -> std::io::Result<()> {
+    let file = File::options().write(true).create_new(true).open(archive_path)?;
+    let encoder = zstd::Encoder::new(file, 0)?.auto_finish();
+    let mut builder = tar::Builder::new(encoder);
+    let mtime = Utc::now().timestamp().max(0) as u64;
+
+    for path in paths {
+        let Some(content) = snapshot.get(path) else { continue };
+        let (path, content) = match encryption {
+            Some(encryption) => (encrypted_path(path), encryption.encrypt(content).map_err(std::io::Error::other)?),
+            None => (path.clone(), content.clone()),
+        };
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        builder.append_data(&mut header, path, content.as_slice())?;
+    }
+
+    builder.into_inner()?;
+    Ok(())
+}
+
+/// Removes the pruned files from the store in `store_dir`.
+///
+/// Returns the store-relative paths of the removed files, which end in
+/// `.zst` for compressed sessions.
+pub fn remove_pruned<FS: FileSystem>(fs: &FS, store_dir: &Path, paths: &[PathBuf]) -> filesystem::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    for path in paths {
+        if let Some(stored_path) = find_stored_path(fs, store_dir, path)? {
+            fs.remove_file(&store_dir.join(&stored_path))?;
+            removed.push(stored_path);
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dedup::Reference;
+
+    fn session(id: &str, branch: &str, day: u32) -> Vec<u8> {
+        format!(
+            "{{\"type\":\"user\",\"uuid\":\"u-{}\",\"sessionId\":\"{}\",\"gitBranch\":\"{}\",\"timestamp\":\"2025-06-{:02}T10:00:00Z\",\"message\":{{\"role\":\"user\",\"content\":\"hi\"}}}}\n",
+            id, id, branch, day
+        ).into_bytes()
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2025-06-30T00:00:00Z".parse().unwrap()
+    }
+
+    fn pruned_ids(plan: &PrunePlan) -> Vec<&str> {
+        plan.pruned().map(|decision| decision.session.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_plan_prune_rules() {
+        let snapshot = Snapshot::from([
+            (PathBuf::from("p/old.jsonl"), session("old", "main", 1)),
+            (PathBuf::from("p/pinned.jsonl"), session("pinned", "main", 2)),
+            (PathBuf::from("p/committed.jsonl"), session("committed", "main", 3)),
+            (PathBuf::from("p/a.jsonl"), session("a", "feature", 27)),
+            (PathBuf::from("p/b.jsonl"), session("b", "feature", 28)),
+            (PathBuf::from("p/c.jsonl"), session("c", "feature", 29)),
+        ]);
+        let policy = RetentionPolicy {
+            keep_days: Some(14),
+            max_per_branch: Some(2),
+            pinned: vec!["pin".to_string()],
+        };
+
+        let plan = plan_prune(&snapshot, &policy, &BTreeSet::from(["committed".to_string()]), now());
+
+        assert_eq!(plan.sessions_total, 6);
+        assert_eq!(pruned_ids(&plan), vec!["a", "old"]);
+        let kept: Vec<_> = plan.decisions.iter().filter_map(|decision| decision.kept_by).collect();
+        assert_eq!(kept, vec![KeepReason::Committed, KeepReason::Pinned]);
+
+        assert!(plan_prune(&snapshot, &RetentionPolicy::default(), &BTreeSet::new(), now()).decisions.is_empty());
+    }
+
+    #[test]
+    fn test_plan_prune_keeps_referenced_sessions() {
+        let reference = Reference {
+            path: PathBuf::from("p/old.jsonl"),
+            uuid: "u-old".to_string(),
+            count: 1,
+            session_id: Some("new".to_string()),
+        };
+        let mut resumed = format!("{}\n", reference.to_line()).into_bytes();
+        resumed.extend(session("new", "main", 29));
+        let snapshot = Snapshot::from([
+            (PathBuf::from("p/old.jsonl"), session("old", "main", 1)),
+            (PathBuf::from("p/new.jsonl"), resumed),
+        ]);
+        let policy = RetentionPolicy {
+            keep_days: Some(14),
+            ..Default::default()
+        };
+
+        let plan = plan_prune(&snapshot, &policy, &BTreeSet::new(), now());
+        assert!(pruned_ids(&plan).is_empty());
+        assert_eq!(plan.decisions[0].kept_by, Some(KeepReason::Referenced));
+    }
+
+    #[test]
+    fn test_write_archive_encrypted() {
+        let identity = age::x25519::Identity::generate();
+        let encryption = Encryption::new(&[identity.to_public().to_string()]).unwrap();
+        let snapshot = Snapshot::from([(PathBuf::from("p/old.jsonl"), session("old", "main", 1))]);
+        let dir = tempfile::TempDir::new().unwrap();
+        let archive_path = dir.path().join("pruned.tar.zst");
+
+        write_archive(&archive_path, &snapshot, &[PathBuf::from("p/old.jsonl")], Some(&encryption)).unwrap();
+
+        let archive = zstd::decode_all(File::open(&archive_path).unwrap()).unwrap();
+        assert!(!String::from_utf8_lossy(&archive).contains("gitBranch"));
+        let mut entries = tar::Archive::new(archive.as_slice());
+        let mut entry = entries.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.path().unwrap(), Path::new("p/old.jsonl.age"));
+        let mut content = Vec::new();
+        std::io::Read::read_to_end(&mut entry, &mut content).unwrap();
+        let identities = crate::encryption::Identities::new(vec![identity]);
+        assert_eq!(identities.decrypt(&content).unwrap(), snapshot[Path::new("p/old.jsonl")]);
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::REPO_CONFIG_PATH;
+use crate::filesystem::{FileSystem, FileSystemError};
+use crate::store::STORE_PATH;
+
+/// Environment variable naming the registry file, instead of
+/// [`default_registry_path`].
+pub const REGISTRY_FILE_ENV: &str = "CC_SYNC_SESSION_REGISTRY_FILE";
+
+/// First lines of the registry file.
+const HEADER: &str = "# Repositories initialized with cc-sync-session, synced by 'cc-sync-session sync --all'.\n\
+                      # Clean up with 'cc-sync-session registry remove'.\n\n";
+
+#[derive(Debug, Error)]
+pub enum RegistryError {
+    #[error(transparent)]
+    FileSystem(#[from] FileSystemError),
+
+    #[error("Invalid registry {path}: {source}")]
+    Parse { path: PathBuf, source: toml::de::Error },
+
+    #[error("Cannot write the registry: {0}")]
+    Serialize(#[from] toml::ser::Error),
+}
+
+pub type Result<T> = std::result::Result<T, RegistryError>;
+
+/// A repository `init` was run in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisteredRepo {
+    /// Absolute path of the working tree.
+    pub path: PathBuf,
+    /// When it was first registered.
+    pub added: DateTime<Utc>,
+}
+
+/// The repositories of the user, kept in [`registry_path`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Registry {
+    #[serde(default, rename = "repo")]
+    pub repos: Vec<RegisteredRepo>,
+}
+
+/// Whether a registered repository can still be synced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepoState {
+    Ok,
+    /// The directory is gone.
+    Missing,
+    /// The directory is no longer a git working tree.
+    NotARepository,
+    /// There is neither a store nor a configuration, e.g. after a fresh clone.
+    NotInitialized,
+}
+
+impl RepoState {
+    pub fn is_ok(&self) -> bool {
+        *self == RepoState::Ok
+    }
+}
+
+impl fmt::Display for RepoState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RepoState::Ok => "ok",
+            RepoState::Missing => "missing",
+            RepoState::NotARepository => "not a git repository",
+            RepoState::NotInitialized => "not initialized",
+        })
+    }
+}
+
+/// `cc-sync-session/repos.toml` in the user's data directory
+/// (`~/.local/share` on Linux).
+pub fn default_registry_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("cc-sync-session").join("repos.toml"))
+}
+
+/// `$CC_SYNC_SESSION_REGISTRY_FILE`, or [`default_registry_path`].
+pub fn registry_path() -> Option<PathBuf> {
+    match std::env::var_os(REGISTRY_FILE_ENV) {
+        Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ => default_registry_path(),
+    }
+}
+
+impl Registry {
+    /// Reads the registry at `path`; a missing file is an empty registry.
+    pub fn load<FS: FileSystem>(fs: &FS, path: &Path) -> Result<Self> {
+        if !fs.exists(path)? {
+            return Ok(Self::default());
+        }
+        let content = String::from_utf8_lossy(&fs.read_file(path)?).into_owned();
+        toml::from_str(&content).map_err(|source| RegistryError::Parse { path: path.to_path_buf(), source })
+    }
+
+    pub fn save<FS: FileSystem>(&self, fs: &FS, path: &Path) -> Result<()> {
+        let content = format!("{}{}", HEADER, toml::to_string(self)?);
+        fs.write_file(path, content.as_bytes())?;
+        Ok(())
+    }
+
+    /// Registers the repository at `path`. Returns `false` if it already was.
+    pub fn add(&mut self, path: &Path, now: DateTime<Utc>) -> bool {
+        if self.contains(path) {
+            return false;
+        }
+        self.repos.push(RegisteredRepo { path: path.to_path_buf(), added: now });
+        self.repos.sort_by(|a, b| a.path.cmp(&b.path));
+        true
+    }
+
+    /// Unregisters the repository at `path`. Returns `false` if it was not registered.
+    pub fn remove(&mut self, path: &Path) -> bool {
+        let before = self.repos.len();
+        self.repos.retain(|repo| repo.path != path);
+        self.repos.len() != before
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.repos.iter().any(|repo| repo.path == path)
+    }
+}
+
+/// Whether the repository at `path` can still be synced: a git working tree
+/// with a store or a configuration, as commands look for.
+pub fn repo_state<FS: FileSystem>(fs: &FS, path: &Path) -> Result<RepoState> {
+    if !fs.exists(path)? {
+        return Ok(RepoState::Missing);
+    }
+    if !fs.exists(&path.join(".git"))? {
+        return Ok(RepoState::NotARepository);
+    }
+    if !fs.exists(&path.join(STORE_PATH))? && !fs.exists(&path.join(REPO_CONFIG_PATH))? {
+        return Ok(RepoState::NotInitialized);
+    }
+    Ok(RepoState::Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockFileSystem;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_registry() {
+        let fs = MockFileSystem::new();
+        let path = Path::new("/home/me/.local/share/cc-sync-session/repos.toml");
+        let now = DateTime::parse_from_rfc3339("2025-06-01T10:00:00Z").unwrap().with_timezone(&Utc);
+
+        let mut registry = Registry::load(&fs, path).unwrap();
+        assert!(registry.repos.is_empty());
+        assert!(registry.add(Path::new("/work/b"), now));
+        assert!(registry.add(Path::new("/work/a"), now));
+        assert!(!registry.add(Path::new("/work/a"), now));
+        registry.save(&fs, path).unwrap();
+
+        let content = String::from_utf8(fs.get_file_content(path).unwrap()).unwrap();
+        assert!(content.starts_with("# Repositories initialized with cc-sync-session"));
+        assert!(content.contains("[[repo]]\npath = \"/work/a\"\nadded = \"2025-06-01T10:00:00Z\"\n"));
+
+        let mut registry = Registry::load(&fs, path).unwrap();
+        assert_eq!(registry.repos.iter().map(|repo| repo.path.as_path()).collect::<Vec<_>>(), vec![Path::new("/work/a"), Path::new("/work/b")]);
+        assert!(registry.remove(Path::new("/work/a")));
+        assert!(!registry.remove(Path::new("/work/a")));
+        assert_eq!(registry.repos.len(), 1);
+
+        fs.add_file(path, b"[[repo]]\npath = 1\n".to_vec(), SystemTime::now());
+        assert!(matches!(Registry::load(&fs, path), Err(RegistryError::Parse { .. })));
+    }
+
+    #[test]
+    fn test_repo_state() {
+        let fs = MockFileSystem::new();
+        for dir in ["/work/app", "/work/clone", "/work/plain"] {
+            fs.add_directory(dir);
+        }
+        fs.add_directory("/work/app/.git");
+        fs.add_directory("/work/app/.claude/ccss_sessions");
+        fs.add_directory("/work/clone/.git");
+
+        assert_eq!(repo_state(&fs, Path::new("/work/app")).unwrap(), RepoState::Ok);
+        assert_eq!(repo_state(&fs, Path::new("/work/clone")).unwrap(), RepoState::NotInitialized);
+        assert_eq!(repo_state(&fs, Path::new("/work/plain")).unwrap(), RepoState::NotARepository);
+        assert_eq!(repo_state(&fs, Path::new("/work/gone")).unwrap(), RepoState::Missing);
+
+        fs.add_file("/work/clone/.claude/ccss.toml", b"[store]\nmode = \"branch\"\n".to_vec(), SystemTime::now());
+        assert_eq!(repo_state(&fs, Path::new("/work/clone")).unwrap(), RepoState::Ok);
+    }
+}
@@ -0,0 +1,280 @@
+use serde_json::Value;
+
+use crate::session::{shorten, SessionRecord, ToolUse};
+
+/// Longest tool input or result line shown in a transcript.
+const DETAIL_WIDTH: usize = 100;
+
+/// Indentation of expanded tool inputs and results.
+const DETAIL_INDENT: &str = "     ";
+
+/// How much of a transcript [`render_transcript_with`] shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Show tool inputs and results in full rather than one line each.
+    pub expand_tools: bool,
+}
+
+/// Renders a session transcript as plain text.
+///
+/// The output only depends on the file content (timestamps are printed in UTC),
+/// so it is stable enough to be diffed, e.g. by a git textconv driver.
+pub fn render_transcript(content: &str) -> String {
+    render_transcript_with(content, &RenderOptions::default())
+}
+
+/// Renders a session transcript as plain text, as [`render_transcript`] does
+/// with the default options.
+pub fn render_transcript_with(content: &str, options: &RenderOptions) -> String {
+    let mut output = String::new();
+
+    for record in render_records(content) {
+        for part in &record.parts {
+            render_part(part, options, &mut output);
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+/// A piece of a rendered record.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Part {
+    /// `== User [2025-06-25 10:00:00 UTC]` and the like.
+    Heading(String),
+    /// Prompt or reply text, possibly several lines.
+    Text(String),
+    /// A tool call, with its input pretty-printed.
+    ToolUse { name: String, summary: String, input: String },
+    /// The full text of a tool result.
+    ToolResult(String),
+}
+
+impl Part {
+    /// Whether the part is shown on one line unless tools are expanded.
+    pub fn is_tool(&self) -> bool {
+        matches!(self, Part::ToolUse { .. } | Part::ToolResult(_))
+    }
+}
+
+/// A record of a transcript, as [`render_transcript_with`] prints it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedRecord {
+    /// Line of the record in the session file, from 1.
+    pub line: usize,
+    pub uuid: Option<String>,
+    pub parts: Vec<Part>,
+}
+
+/// Splits a session transcript into the records that are shown, leaving out
+/// record types without a rendering.
+pub fn render_records(content: &str) -> Vec<RenderedRecord> {
+    let mut records = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (uuid, parts) = match SessionRecord::parse(line) {
+            Ok(record) => match record_parts(&record) {
+                Some(parts) => (record.uuid().map(str::to_string), parts),
+                None => continue,
+            },
+            Err(_) => (None, vec![Part::Text(format!("[invalid record on line {}]", index + 1))]),
+        };
+        records.push(RenderedRecord { line: index + 1, uuid, parts });
+    }
+
+    records
+}
+
+/// Pretty-prints every record of a session transcript, separated by blank
+/// lines; lines that are not JSON are kept as they are.
+pub fn render_raw(content: &str) -> String {
+    let mut output = String::new();
+
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str::<Value>(line) {
+            Ok(value) => output.push_str(&serde_json::to_string_pretty(&value).unwrap_or_else(|_| line.to_string())),
+            Err(_) => output.push_str(line),
+        }
+        output.push_str("\n\n");
+    }
+
+    output
+}
+
+fn record_parts(record: &SessionRecord) -> Option<Vec<Part>> {
+    let mut parts = Vec::new();
+    match record.record_type() {
+        Some("user") => {
+            if let Some(prompt) = record.user_prompt() {
+                parts.push(Part::Heading(heading("User", record)));
+                parts.push(Part::Text(prompt));
+            } else {
+                parts.extend(tool_results(record).into_iter().map(Part::ToolResult));
+            }
+        }
+        Some("assistant") => {
+            parts.push(Part::Heading(heading("Assistant", record)));
+            if let Some(text) = record.assistant_text() {
+                parts.push(Part::Text(text));
+            }
+            for tool in record.tool_uses() {
+                parts.push(Part::ToolUse {
+                    name: tool.name.to_string(),
+                    summary: describe_tool_use(&tool),
+                    input: serde_json::to_string_pretty(tool.input).unwrap_or_default(),
+                });
+            }
+        }
+        Some("summary") => {
+            let summary = record.value().get("summary").and_then(Value::as_str).unwrap_or_default();
+            parts.push(Part::Heading(format!("== Summary: {}", summary)));
+        }
+        _ => return None,
+    }
+    Some(parts)
+}
+
+/// Appends `part` to `output` as [`render_transcript_with`] prints it.
+pub fn render_part(part: &Part, options: &RenderOptions, output: &mut String) {
+    match part {
+        Part::Heading(heading) => {
+            output.push_str(heading);
+            output.push('\n');
+        }
+        Part::Text(text) => push_lines(output, text),
+        Part::ToolUse { name, input, .. } if options.expand_tools => {
+            output.push_str(&format!("  -> {}\n", name));
+            push_indented(output, input);
+        }
+        Part::ToolUse { summary, .. } => output.push_str(&format!("  -> {}\n", summary)),
+        Part::ToolResult(text) if options.expand_tools => {
+            output.push_str("  <-\n");
+            push_indented(output, text);
+        }
+        Part::ToolResult(text) => output.push_str(&format!("  <- {}\n", shorten(text, DETAIL_WIDTH))),
+    }
+}
+
+fn heading(role: &str, record: &SessionRecord) -> String {
+    let mut heading = format!("== {}", role);
+    if let Some(timestamp) = record.timestamp() {
+        heading.push_str(&format!(" [{}]", timestamp.format("%Y-%m-%d %H:%M:%S UTC")));
+    }
+    if let Some(model) = record.model() {
+        heading.push_str(&format!(" ({})", model));
+    }
+    if record.is_sidechain() {
+        heading.push_str(" (sidechain)");
+    }
+    heading
+}
+
+fn push_lines(output: &mut String, text: &str) {
+    for line in text.lines() {
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+}
+
+fn push_indented(output: &mut String, text: &str) {
+    for line in text.lines() {
+        output.push_str(DETAIL_INDENT);
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+}
+
+/// Text of the tool results in a user record.
+fn tool_results(record: &SessionRecord) -> Vec<String> {
+    record.value()
+        .pointer("/message/content")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|block| block.get("type").and_then(Value::as_str) == Some("tool_result"))
+        .map(|block| {
+            let text = match block.get("content") {
+                Some(Value::String(text)) => text.clone(),
+                Some(Value::Array(parts)) => parts.iter()
+                    .filter_map(|part| part.get("text").and_then(Value::as_str))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                _ => String::new(),
+            };
+            text
+        })
+        .collect()
+}
+
+/// One line description of a tool call, e.g. `Bash: cargo test`.
+pub fn describe_tool_use(tool: &ToolUse) -> String {
+    const DETAIL_FIELDS: &[&str] = &["file_path", "notebook_path", "command", "pattern", "url", "description", "prompt"];
+
+    let detail = DETAIL_FIELDS.iter()
+        .find_map(|field| tool.input.get(*field).and_then(Value::as_str))
+        .map(str::to_string)
+        .unwrap_or_else(|| tool.input.to_string());
+
+    format!("{}: {}", tool.name, shorten(&detail, DETAIL_WIDTH))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_transcript() {
+        let content = [
+            r#"{"type":"user","uuid":"u1","timestamp":"2025-06-25T10:00:00.000Z","message":{"role":"user","content":"Run the tests"}}"#,
+            r#"{"type":"assistant","uuid":"a1","message":{"model":"claude-sonnet-4","role":"assistant","content":[{"type":"text","text":"Running them."},{"type":"tool_use","id":"t1","name":"Bash","input":{"command":"cargo test"}}]}}"#,
+            r#"{"type":"user","uuid":"r1","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"test result: ok"}]}}"#,
+            r#"{"type":"assis"#,
+        ].join("\n");
+
+        assert_eq!(
+            render_transcript(&content),
+            concat!(
+                "== User [2025-06-25 10:00:00 UTC]\n",
+                "Run the tests\n",
+                "\n",
+                "== Assistant (claude-sonnet-4)\n",
+                "Running them.\n",
+                "  -> Bash: cargo test\n",
+                "\n",
+                "  <- test result: ok\n",
+                "\n",
+                "[invalid record on line 4]\n",
+                "\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_transcript_expanded() {
+        let content = [
+            r#"{"type":"assistant","uuid":"a1","message":{"role":"assistant","content":[{"type":"tool_use","id":"t1","name":"Bash","input":{"command":"cargo test"}}]}}"#,
+            r#"{"type":"user","uuid":"r1","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"running 2 tests\ntest result: ok"}]}}"#,
+        ].join("\n");
+
+        assert_eq!(
+            render_transcript_with(&content, &RenderOptions { expand_tools: true }),
+            concat!(
+                "== Assistant\n",
+                "  -> Bash\n",
+                "     {\n",
+                "       \"command\": \"cargo test\"\n",
+                "     }\n",
+                "\n",
+                "  <-\n",
+                "     running 2 tests\n",
+                "     test result: ok\n",
+                "\n",
+            )
+        );
+        assert!(render_raw(&content).starts_with("{\n  \"message\": {"));
+    }
+}
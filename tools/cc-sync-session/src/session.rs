@@ -0,0 +1,260 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use thiserror::Error;
+
+/// Tools whose `file_path` (or `notebook_path`) input is a file the session edited.
+const EDIT_TOOLS: &[&str] = &["Edit", "MultiEdit", "Write", "NotebookEdit"];
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("Invalid record on line {line}: {source}")]
+    InvalidRecord {
+        line: usize,
+        source: serde_json::Error,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, SessionError>;
+
+/// A single line of a Claude Code session transcript.
+///
+/// The raw JSON is kept as is so that records can be written back unchanged;
+/// the accessors only read the fields the tool cares about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionRecord {
+    value: Value,
+}
+
+/// A `tool_use` block of an assistant message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolUse<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub input: &'a Value,
+}
+
+impl SessionRecord {
+    pub fn new(value: Value) -> Self {
+        Self { value }
+    }
+
+    pub fn parse(line: &str) -> std::result::Result<Self, serde_json::Error> {
+        serde_json::from_str(line).map(Self::new)
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    fn str_field(&self, name: &str) -> Option<&str> {
+        self.value.get(name).and_then(Value::as_str)
+    }
+
+    /// The record type, e.g. `user`, `assistant` or `summary`.
+    pub fn record_type(&self) -> Option<&str> {
+        self.str_field("type")
+    }
+
+    pub fn uuid(&self) -> Option<&str> {
+        self.str_field("uuid")
+    }
+
+    pub fn parent_uuid(&self) -> Option<&str> {
+        self.str_field("parentUuid")
+    }
+
+    pub fn session_id(&self) -> Option<&str> {
+        self.str_field("sessionId")
+    }
+
+    pub fn cwd(&self) -> Option<&str> {
+        self.str_field("cwd")
+    }
+
+    pub fn git_branch(&self) -> Option<&str> {
+        self.str_field("gitBranch")
+    }
+
+    pub fn is_sidechain(&self) -> bool {
+        self.value.get("isSidechain").and_then(Value::as_bool).unwrap_or(false)
+    }
+
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        let timestamp = self.str_field("timestamp")?;
+        DateTime::parse_from_rfc3339(timestamp).ok().map(|time| time.with_timezone(&Utc))
+    }
+
+    /// Model that produced an assistant message.
+    pub fn model(&self) -> Option<&str> {
+        self.value.pointer("/message/model").and_then(Value::as_str)
+    }
+
+    fn content(&self) -> Option<&Value> {
+        self.value.pointer("/message/content")
+    }
+
+    fn content_blocks<'a>(&'a self, block_type: &'a str) -> impl Iterator<Item = &'a Value> {
+        self.content()
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(move |block| block.get("type").and_then(Value::as_str) == Some(block_type))
+    }
+
+    fn joined_text(&self) -> Option<String> {
+        if let Some(text) = self.content().and_then(Value::as_str) {
+            return Some(text.to_string());
+        }
+
+        let text: Vec<&str> = self.content_blocks("text")
+            .filter_map(|block| block.get("text").and_then(Value::as_str))
+            .collect();
+        (!text.is_empty()).then(|| text.join("\n"))
+    }
+
+    /// Text the user typed, or `None` for tool results and meta messages.
+    pub fn user_prompt(&self) -> Option<String> {
+        let is_meta = self.value.get("isMeta").and_then(Value::as_bool).unwrap_or(false);
+        if self.record_type() != Some("user") || is_meta {
+            return None;
+        }
+        if self.content_blocks("tool_result").next().is_some() {
+            return None;
+        }
+        self.joined_text()
+    }
+
+    /// Text of an assistant reply, or `None` if the message has no text blocks.
+    pub fn assistant_text(&self) -> Option<String> {
+        if self.record_type() != Some("assistant") {
+            return None;
+        }
+        self.joined_text()
+    }
+
+    pub fn tool_uses(&self) -> Vec<ToolUse<'_>> {
+        if self.record_type() != Some("assistant") {
+            return Vec::new();
+        }
+
+        self.content_blocks("tool_use")
+            .filter_map(|block| {
+                Some(ToolUse {
+                    id: block.get("id").and_then(Value::as_str).unwrap_or_default(),
+                    name: block.get("name").and_then(Value::as_str)?,
+                    input: block.get("input").unwrap_or(&Value::Null),
+                })
+            })
+            .collect()
+    }
+
+    /// Files changed by edit tools in this record, relative to the session's
+    /// working directory when they are inside it.
+    pub fn edited_files(&self) -> Vec<String> {
+        self.tool_uses()
+            .into_iter()
+            .filter(|tool| EDIT_TOOLS.contains(&tool.name))
+            .filter_map(|tool| {
+                tool.input.get("file_path")
+                    .or_else(|| tool.input.get("notebook_path"))
+                    .and_then(Value::as_str)
+            })
+            .map(|file| {
+                self.cwd()
+                    .and_then(|cwd| Path::new(file).strip_prefix(cwd).ok())
+                    .map(|relative| relative.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| file.to_string())
+            })
+            .collect()
+    }
+}
+
+/// First line of `text`, cut to at most `max_chars` characters.
+pub fn shorten(text: &str, max_chars: usize) -> String {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    if line.chars().count() <= max_chars {
+        return line.to_string();
+    }
+
+    let mut shortened: String = line.chars().take(max_chars.saturating_sub(1)).collect();
+    shortened.push('…');
+    shortened
+}
+
+/// Parses a session transcript, failing on the first malformed line.
+pub fn parse_records(content: &str) -> Result<Vec<SessionRecord>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            SessionRecord::parse(line).map_err(|source| SessionError::InvalidRecord { line: index + 1, source })
+        })
+        .collect()
+}
+
+/// Parses a session transcript, skipping malformed lines such as a last line
+/// that was still being written when the file was copied.
+pub fn parse_records_lossy(content: &str) -> Vec<SessionRecord> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(index, line)| match SessionRecord::parse(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                log::warn!("Skipping invalid record on line {}: {}", index + 1, e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = r#"{"type":"user","uuid":"u1","parentUuid":null,"sessionId":"s1","cwd":"/repo","gitBranch":"main","timestamp":"2025-06-25T10:00:00.000Z","message":{"role":"user","content":"Add a diff command"}}"#;
+    const ASSISTANT: &str = r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","sessionId":"s1","cwd":"/repo","message":{"model":"claude-sonnet-4","role":"assistant","content":[{"type":"text","text":"Sure."},{"type":"tool_use","id":"t1","name":"Edit","input":{"file_path":"/repo/src/main.rs"}},{"type":"tool_use","id":"t2","name":"Bash","input":{"command":"cargo test"}}]}}"#;
+    const TOOL_RESULT: &str = r#"{"type":"user","uuid":"r1","parentUuid":"a1","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"ok"}]}}"#;
+
+    #[test]
+    fn test_user_prompt() {
+        let record = SessionRecord::parse(USER).unwrap();
+
+        assert_eq!(record.user_prompt().as_deref(), Some("Add a diff command"));
+        assert_eq!(record.git_branch(), Some("main"));
+        assert_eq!(record.timestamp().unwrap().to_rfc3339(), "2025-06-25T10:00:00+00:00");
+        assert!(SessionRecord::parse(TOOL_RESULT).unwrap().user_prompt().is_none());
+    }
+
+    #[test]
+    fn test_assistant_tool_uses() {
+        let record = SessionRecord::parse(ASSISTANT).unwrap();
+
+        assert_eq!(record.assistant_text().as_deref(), Some("Sure."));
+        assert_eq!(record.model(), Some("claude-sonnet-4"));
+        let names: Vec<_> = record.tool_uses().iter().map(|tool| tool.name).collect();
+        assert_eq!(names, vec!["Edit", "Bash"]);
+        assert_eq!(record.edited_files(), vec!["src/main.rs".to_string()]);
+    }
+
+    #[test]
+    fn test_shorten() {
+        assert_eq!(shorten("\n  short prompt\nsecond line", 20), "short prompt");
+        assert_eq!(shorten("a rather long prompt", 10), "a rather …");
+    }
+
+    #[test]
+    fn test_parse_records_reports_line() {
+        let content = format!("{}\n{}\n{{\"type\":\"us", USER, ASSISTANT);
+
+        match parse_records(&content) {
+            Err(SessionError::InvalidRecord { line, .. }) => assert_eq!(line, 3),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(parse_records_lossy(&content).len(), 2);
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::session::SessionRecord;
+use crate::store::Snapshot;
+
+/// Tool that spawns subagents with their own sidechain transcript.
+const TASK_TOOL: &str = "Task";
+
+/// Directory below a parent session that nested sidechains are stored in.
+pub const NESTED_DIR: &str = "sidechains";
+
+/// What `sync` does with sidechain transcripts of subagents.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SidechainMode {
+    /// Copy them like any other session file.
+    #[default]
+    Sync,
+    /// Store them below their parent session, in `<parent-id>/sidechains/`.
+    Nest,
+    /// Leave them out of the store.
+    Skip,
+}
+
+impl FromStr for SidechainMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sync" => Ok(SidechainMode::Sync),
+            "nest" => Ok(SidechainMode::Nest),
+            "skip" => Ok(SidechainMode::Skip),
+            _ => Err(format!("Unknown sidechain mode '{}' (expected sync, nest or skip)", s)),
+        }
+    }
+}
+
+impl fmt::Display for SidechainMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SidechainMode::Sync => "sync",
+            SidechainMode::Nest => "nest",
+            SidechainMode::Skip => "skip",
+        })
+    }
+}
+
+/// What a sidechain transcript says about where it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SidechainInfo {
+    /// Session id of the main session that spawned the subagent.
+    pub parent_session_id: String,
+    /// `parentUuid` of the first record, when the subagent recorded one.
+    pub parent_uuid: Option<String>,
+    /// First prompt the subagent received.
+    pub prompt: Option<String>,
+}
+
+/// A sidechain tied to its parent session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SidechainLink {
+    pub parent_session_id: String,
+    /// Path of the parent session in the same snapshot, if it is there.
+    pub parent_path: Option<PathBuf>,
+    /// id of the `Task` tool call that spawned the subagent.
+    pub tool_use_id: Option<String>,
+}
+
+/// Recognizes a sidechain transcript by its first record.
+///
+/// Only the first line is looked at, so a partial read of the file is enough.
+pub fn detect(content: &str) -> Option<SidechainInfo> {
+    let first = content.lines()
+        .filter(|line| !line.trim().is_empty())
+        .find_map(|line| SessionRecord::parse(line).ok().filter(|record| record.session_id().is_some()))?;
+
+    if !first.is_sidechain() {
+        return None;
+    }
+
+    Some(SidechainInfo {
+        parent_session_id: first.session_id()?.to_string(),
+        parent_uuid: first.parent_uuid().map(str::to_string),
+        prompt: first.user_prompt(),
+    })
+}
+
+/// Finds the `Task` tool call of the parent session that spawned the sidechain.
+///
+/// The record the sidechain's `parentUuid` points to is preferred; otherwise
+/// the call whose prompt matches the subagent's first prompt is used.
+pub fn find_spawning_tool_use(parent_content: &str, info: &SidechainInfo) -> Option<String> {
+    let records: Vec<SessionRecord> = parent_content.lines()
+        .filter_map(|line| SessionRecord::parse(line).ok())
+        .collect();
+    let task_calls = |record: &SessionRecord| -> Vec<(String, Option<String>)> {
+        record.tool_uses()
+            .into_iter()
+            .filter(|tool| tool.name == TASK_TOOL)
+            .map(|tool| (tool.id.to_string(), tool.input.get("prompt").and_then(Value::as_str).map(str::to_string)))
+            .collect()
+    };
+    let matches_prompt = |prompt: &Option<String>| prompt.is_some() && *prompt == info.prompt;
+
+    if let Some(parent_uuid) = &info.parent_uuid {
+        if let Some(record) = records.iter().find(|record| record.uuid() == Some(parent_uuid.as_str())) {
+            let calls = task_calls(record);
+            let by_prompt = calls.iter().find(|(_, prompt)| matches_prompt(prompt));
+            if let Some((id, _)) = by_prompt.or(if calls.len() == 1 { calls.first() } else { None }) {
+                return Some(id.clone());
+            }
+        }
+    }
+
+    records.iter()
+        .flat_map(task_calls)
+        .find(|(_, prompt)| matches_prompt(prompt))
+        .map(|(id, _)| id)
+}
+
+/// Ties every sidechain of `snapshot` to its parent session.
+pub fn link_sidechains(snapshot: &Snapshot) -> BTreeMap<PathBuf, SidechainLink> {
+    let mut sidechains = Vec::new();
+    let mut sessions: HashMap<String, &PathBuf> = HashMap::new();
+
+    for (path, content) in snapshot {
+        let content = String::from_utf8_lossy(content);
+        match detect(&content) {
+            Some(info) => sidechains.push((path, info)),
+            None => {
+                if let Some(stem) = path.file_stem() {
+                    sessions.insert(stem.to_string_lossy().into_owned(), path);
+                }
+            }
+        }
+    }
+
+    sidechains.into_iter()
+        .map(|(path, info)| {
+            let parent_path = sessions.get(&info.parent_session_id).map(|parent| (*parent).clone());
+            let tool_use_id = parent_path.as_ref()
+                .and_then(|parent| find_spawning_tool_use(&String::from_utf8_lossy(&snapshot[parent]), &info));
+
+            (path.clone(), SidechainLink {
+                parent_session_id: info.parent_session_id,
+                parent_path,
+                tool_use_id,
+            })
+        })
+        .collect()
+}
+
+/// Store-relative path of a nested sidechain: `<project>/<parent-id>/sidechains/<file>`.
+pub fn nested_path(relative_path: &Path, parent_session_id: &str) -> PathBuf {
+    let mut components = relative_path.components();
+    let project = components.next().map(|component| component.as_os_str()).unwrap_or_default();
+    let file_name = relative_path.file_name().unwrap_or_default();
+
+    PathBuf::from(project).join(parent_session_id).join(NESTED_DIR).join(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARENT: &str = concat!(
+        r#"{"type":"user","uuid":"u1","sessionId":"s1","isSidechain":false,"message":{"role":"user","content":"Explore the repo"}}"#, "\n",
+        r#"{"type":"assistant","uuid":"a1","parentUuid":"u1","sessionId":"s1","isSidechain":false,"message":{"role":"assistant","content":[{"type":"tool_use","id":"toolu_1","name":"Task","input":{"description":"Find tests","prompt":"Find all tests"}}]}}"#, "\n",
+    );
+    const SIDECHAIN: &str = concat!(
+        r#"{"type":"user","uuid":"x1","parentUuid":null,"sessionId":"s1","isSidechain":true,"message":{"role":"user","content":"Find all tests"}}"#, "\n",
+    );
+
+    #[test]
+    fn test_detect() {
+        assert!(detect(PARENT).is_none());
+
+        let info = detect(SIDECHAIN).unwrap();
+        assert_eq!(info.parent_session_id, "s1");
+        assert_eq!(info.prompt.as_deref(), Some("Find all tests"));
+    }
+
+    #[test]
+    fn test_link_sidechains() {
+        let snapshot = Snapshot::from([
+            (PathBuf::from("p/s1.jsonl"), PARENT.as_bytes().to_vec()),
+            (PathBuf::from("p/agent-1.jsonl"), SIDECHAIN.as_bytes().to_vec()),
+        ]);
+
+        let links = link_sidechains(&snapshot);
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[Path::new("p/agent-1.jsonl")], SidechainLink {
+            parent_session_id: "s1".to_string(),
+            parent_path: Some(PathBuf::from("p/s1.jsonl")),
+            tool_use_id: Some("toolu_1".to_string()),
+        });
+    }
+
+    #[test]
+    fn test_nested_path() {
+        assert_eq!(
+            nested_path(Path::new("-Users-yuta-project/agent-1.jsonl"), "s1"),
+            PathBuf::from("-Users-yuta-project/s1/sidechains/agent-1.jsonl")
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use git2::{Repository, Status, StatusOptions};
+use serde::Serialize;
+
+use crate::compression::logical_path;
+use crate::diff::new_records;
+use crate::session::parse_records_lossy;
+use crate::store::Snapshot;
+use crate::sync::is_session_file;
+
+/// How a session in the source differs from its copy in the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncState {
+    /// Only in the source; `sync` would copy it.
+    New,
+    /// The source has records the store lacks; `sync` would update it.
+    Modified,
+    /// Only in the store; `sync` keeps it.
+    Deleted,
+    /// Both have records the other lacks; `sync` would drop the store's.
+    Conflict,
+}
+
+impl SyncState {
+    /// One letter status for `status --short`.
+    pub fn letter(&self) -> char {
+        match self {
+            SyncState::New => 'A',
+            SyncState::Modified => 'M',
+            SyncState::Deleted => 'D',
+            SyncState::Conflict => 'C',
+        }
+    }
+
+    /// Label of the session in the `status` listing.
+    pub fn label(&self) -> &'static str {
+        match self {
+            SyncState::New => "new",
+            SyncState::Modified => "modified",
+            SyncState::Deleted => "deleted",
+            SyncState::Conflict => "conflict",
+        }
+    }
+
+    /// Whether `sync` would write the session.
+    pub fn is_pending(&self) -> bool {
+        !matches!(self, SyncState::Deleted)
+    }
+}
+
+/// How the stored copy of a session differs from the last commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GitState {
+    /// The change is in the index.
+    Staged,
+    /// The change is in the working tree only.
+    Unstaged,
+    /// The file is not known to git.
+    Untracked,
+}
+
+impl GitState {
+    /// One letter status for `status --short`.
+    pub fn letter(&self) -> char {
+        match self {
+            GitState::Staged => 'S',
+            GitState::Unstaged => 'U',
+            GitState::Untracked => '?',
+        }
+    }
+}
+
+/// A session that is not in sync between source, store and git.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileStatus {
+    pub path: PathBuf,
+    pub sync: Option<SyncState>,
+    pub git: Option<GitState>,
+}
+
+/// Compares the `source` sessions with the `stored` ones record by record.
+pub fn compare(source: &Snapshot, stored: &Snapshot) -> BTreeMap<PathBuf, SyncState> {
+    let paths: BTreeSet<&PathBuf> = source.keys().chain(stored.keys()).collect();
+    let mut states = BTreeMap::new();
+
+    for path in paths {
+        let state = match (source.get(path), stored.get(path)) {
+            (Some(source), Some(stored)) if source == stored => continue,
+            (Some(source), Some(stored)) => {
+                let source = parse_records_lossy(&String::from_utf8_lossy(source));
+                let stored = parse_records_lossy(&String::from_utf8_lossy(stored));
+                let source_ahead = !new_records(&stored, &source).is_empty();
+                let store_ahead = !new_records(&source, &stored).is_empty();
+                match (source_ahead, store_ahead) {
+                    (true, true) => SyncState::Conflict,
+                    // The store has everything, e.g. merged from another clone
+                    (false, true) => continue,
+                    // Same records, different bytes
+                    _ => SyncState::Modified,
+                }
+            }
+            (Some(_), None) => SyncState::New,
+            (None, Some(_)) => SyncState::Deleted,
+            (None, None) => continue,
+        };
+        states.insert(path.clone(), state);
+    }
+
+    states
+}
+
+/// The git state of the session files under `store_dir`, keyed by their
+/// logical path relative to it.
+pub fn git_states(repo: &Repository, store_dir: &Path) -> Result<BTreeMap<PathBuf, GitState>, git2::Error> {
+    let workdir = repo.workdir().ok_or_else(|| git2::Error::from_str("Repository has no working tree"))?;
+    let store_path = store_dir.strip_prefix(workdir).unwrap_or(store_dir);
+
+    let mut options = StatusOptions::new();
+    options.pathspec(store_path)
+        .include_untracked(true)
+        .recurse_untracked_dirs(true);
+
+    let mut states = BTreeMap::new();
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        let Some(path) = entry.path().map(PathBuf::from) else { continue };
+        let Ok(relative_path) = path.strip_prefix(store_path) else { continue };
+        if !is_session_file(relative_path) {
+            continue;
+        }
+
+        let status = entry.status();
+        let state = if status.intersects(Status::INDEX_NEW | Status::INDEX_MODIFIED | Status::INDEX_DELETED | Status::INDEX_RENAMED) {
+            GitState::Staged
+        } else if status.intersects(Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED) {
+            GitState::Unstaged
+        } else if status.contains(Status::WT_NEW) {
+            GitState::Untracked
+        } else {
+            continue;
+        };
+        states.insert(logical_path(relative_path), state);
+    }
+
+    Ok(states)
+}
+
+/// Joins the sync and git states into one list sorted by path.
+pub fn file_statuses(sync: &BTreeMap<PathBuf, SyncState>, git: &BTreeMap<PathBuf, GitState>) -> Vec<FileStatus> {
+    let paths: BTreeSet<&PathBuf> = sync.keys().chain(git.keys()).collect();
+    paths.into_iter()
+        .map(|path| FileStatus {
+            path: path.clone(),
+            sync: sync.get(path).copied(),
+            git: git.get(path).copied(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(uuid: &str) -> String {
+        format!(r#"{{"type":"user","uuid":"{}","message":{{"role":"user","content":"hi"}}}}"#, uuid)
+    }
+
+    fn session(uuids: &[&str]) -> Vec<u8> {
+        uuids.iter().map(|uuid| format!("{}\n", user(uuid))).collect::<String>().into_bytes()
+    }
+
+    #[test]
+    fn test_compare() {
+        let source = Snapshot::from([
+            (PathBuf::from("p/new.jsonl"), session(&["a"])),
+            (PathBuf::from("p/same.jsonl"), session(&["a"])),
+            (PathBuf::from("p/appended.jsonl"), session(&["a", "b"])),
+            (PathBuf::from("p/behind.jsonl"), session(&["a"])),
+            (PathBuf::from("p/forked.jsonl"), session(&["a", "b"])),
+        ]);
+        let stored = Snapshot::from([
+            (PathBuf::from("p/gone.jsonl"), session(&["a"])),
+            (PathBuf::from("p/same.jsonl"), session(&["a"])),
+            (PathBuf::from("p/appended.jsonl"), session(&["a"])),
+            (PathBuf::from("p/behind.jsonl"), session(&["a", "b"])),
+            (PathBuf::from("p/forked.jsonl"), session(&["a", "c"])),
+        ]);
+
+        let states = compare(&source, &stored);
+        assert_eq!(states, BTreeMap::from([
+            (PathBuf::from("p/appended.jsonl"), SyncState::Modified),
+            (PathBuf::from("p/forked.jsonl"), SyncState::Conflict),
+            (PathBuf::from("p/gone.jsonl"), SyncState::Deleted),
+            (PathBuf::from("p/new.jsonl"), SyncState::New),
+        ]));
+        assert!(!SyncState::Deleted.is_pending());
+
+        let git = BTreeMap::from([
+            (PathBuf::from("p/appended.jsonl"), GitState::Staged),
+            (PathBuf::from("p/old.jsonl"), GitState::Unstaged),
+        ]);
+        let statuses = file_statuses(&states, &git);
+        assert_eq!(statuses.len(), 5);
+        assert_eq!(statuses[0], FileStatus {
+            path: PathBuf::from("p/appended.jsonl"),
+            sync: Some(SyncState::Modified),
+            git: Some(GitState::Staged),
+        });
+        assert_eq!(statuses[4].sync, None);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use git2::{ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+
+use crate::compression::{decode, logical_path, read_session};
+use crate::encryption::Identities;
+use crate::filesystem::{self, EntryMetadata, FileSystem};
+use crate::manifest::sha256_hex;
+use crate::sync::is_session_file;
+use crate::trailers::existing_session_trailers;
+
+/// Location of the session store, relative to the repository root.
+pub const STORE_PATH: &str = ".claude/ccss_sessions";
+
+const LFS_POINTER_PREFIX: &[u8] = b"version https://git-lfs.github.com/spec/v1";
+
+/// Session files keyed by their path relative to a store (or source) root.
+pub type Snapshot = BTreeMap<PathBuf, Vec<u8>>;
+
+/// Returns the session store directory of `repo_dir`.
+pub fn store_dir(repo_dir: &Path) -> PathBuf {
+    repo_dir.join(".claude").join("ccss_sessions")
+}
+
+/// Reads `files` into a snapshot keyed by their path relative to `root`.
+///
+/// Compressed files are decompressed, encrypted ones decrypted with
+/// `identities`, and both keyed by their `.jsonl` path.
+pub fn read_snapshot<FS: FileSystem>(fs: &FS, root: &Path, files: &[EntryMetadata], identities: &Identities) -> filesystem::Result<Snapshot> {
+    let mut snapshot = Snapshot::new();
+
+    for file in files {
+        let relative_path = file.path.strip_prefix(root)
+            .map_err(|e| filesystem::FileSystemError::PathError(e.to_string()))?;
+        snapshot.insert(logical_path(relative_path), read_session(fs, &file.path, identities)?);
+    }
+
+    Ok(snapshot)
+}
+
+/// Reads every session file of the store in the working tree.
+pub fn read_store_snapshot<FS: FileSystem>(fs: &FS, store_dir: &Path, identities: &Identities) -> filesystem::Result<Snapshot> {
+    let files: Vec<_> = fs.walk_files(store_dir)?
+        .into_iter()
+        .filter(|entry| is_session_file(&entry.path))
+        .collect();
+
+    read_snapshot(fs, store_dir, &files, identities)
+}
+
+/// Reads every session file of the store as committed in `revision`.
+///
+/// Files stored through Git LFS are read from the local LFS object cache.
+pub fn read_revision_snapshot(repo: &Repository, revision: &str, identities: &Identities) -> Result<Snapshot> {
+    let commit = repo.revparse_single(revision)
+        .with_context(|| format!("Unknown revision: {}", revision))?
+        .peel_to_commit()
+        .with_context(|| format!("Revision is not a commit: {}", revision))?;
+    let root_tree = commit.tree()?;
+
+    let mut snapshot = Snapshot::new();
+    let store_entry = match root_tree.get_path(Path::new(STORE_PATH)) {
+        Ok(entry) => entry,
+        Err(_) => return Ok(snapshot),
+    };
+    let store_tree = store_entry.to_object(repo)?.peel_to_tree()
+        .with_context(|| format!("{} is not a directory in {}", STORE_PATH, revision))?;
+
+    let mut blobs = Vec::new();
+    store_tree.walk(TreeWalkMode::PreOrder, |parent, entry| {
+        if entry.kind() == Some(ObjectType::Blob) {
+            let path = Path::new(parent).join(entry.name().unwrap_or_default());
+            if is_session_file(&path) {
+                blobs.push((path, entry.id()));
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+
+    for (path, id) in blobs {
+        let blob = repo.find_blob(id)?;
+        let content = resolve_lfs_pointer(repo, blob.content())
+            .and_then(|content| Ok(decode(content, identities)?))
+            .with_context(|| format!("Failed to read {} in {}", path.display(), revision))?;
+        snapshot.insert(logical_path(&path), content);
+    }
+
+    Ok(snapshot)
+}
+
+/// Returns `true` if `content` is a Git LFS pointer rather than the file itself.
+pub fn is_lfs_pointer(content: &[u8]) -> bool {
+    content.starts_with(LFS_POINTER_PREFIX)
+}
+
+/// Replaces a Git LFS pointer with the object it points to.
+///
+/// Fails if the object is not in the local LFS object cache.
+pub fn resolve_lfs_pointer(repo: &Repository, content: &[u8]) -> Result<Vec<u8>> {
+    if !is_lfs_pointer(content) {
+        return Ok(content.to_vec());
+    }
+
+    let pointer = String::from_utf8_lossy(content);
+    let oid = pointer.lines()
+        .find_map(|line| line.strip_prefix("oid sha256:"))
+        .context("LFS pointer without oid")?
+        .trim();
+    // The oid becomes a path, so nothing but a SHA-256 digest is accepted
+    if oid.len() != 64 || !oid.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f')) {
+        anyhow::bail!("Invalid LFS oid: {}", oid);
+    }
+
+    fs::read(lfs_object_path(repo, oid))
+        .with_context(|| format!("LFS object {} is not available locally; run 'git lfs fetch'", oid))
+}
+
+/// Adds `content` to the local LFS object cache and returns the pointer to it,
+/// for content that goes into git where the LFS filter does not run, such as
+/// the result of a merge driver.
+pub fn write_lfs_object(repo: &Repository, content: &[u8]) -> Result<Vec<u8>> {
+    let oid = sha256_hex(content);
+    let object_path = lfs_object_path(repo, &oid);
+    if !object_path.exists() {
+        if let Some(parent) = object_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        fs::write(&object_path, content)
+            .with_context(|| format!("Failed to write LFS object {}", oid))?;
+    }
+
+    Ok(format!("version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize {}\n", oid, content.len()).into_bytes())
+}
+
+fn lfs_object_path(repo: &Repository, oid: &str) -> PathBuf {
+    repo.path().join("lfs").join("objects").join(&oid[..2]).join(&oid[2..4]).join(oid)
+}
+
+/// Session ids recorded in `Claude-Session` trailers of commits reachable from
+/// any local branch.
+pub fn committed_session_ids(repo: &Repository) -> Result<BTreeSet<String>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_glob("refs/heads/*")?;
+
+    let mut session_ids = BTreeSet::new();
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        session_ids.extend(existing_session_trailers(&String::from_utf8_lossy(commit.message_bytes())));
+    }
+
+    Ok(session_ids)
+}
@@ -0,0 +1,198 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+
+use crate::filesystem::{EntryMetadata, FileSystem, Result};
+use crate::session::SessionRecord;
+
+/// How much of a session file is read to decide whether it matches a filter.
+pub const PEEK_BYTES: usize = 64 * 1024;
+
+/// Restricts `sync` to some of the sessions.
+///
+/// Every criterion that is set has to match. The session's last activity is
+/// the file's modification time; everything else comes from the first
+/// records, read with [`peek`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionFilter {
+    /// Session ids (or id prefixes) to keep. A file matches by its name or by
+    /// the `sessionId` of its records, which keeps the sidechains of a session.
+    pub session_ids: Vec<String>,
+    /// Keep sessions active at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Keep sessions started at or before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Keep sessions started on this git branch.
+    pub git_branch: Option<String>,
+    /// Session ids (or id prefixes) to leave out, matched like `session_ids`.
+    pub private_ids: Vec<String>,
+}
+
+/// What the first records of a session say about it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionPeek {
+    pub session_id: Option<String>,
+    pub git_branch: Option<String>,
+    pub started: Option<DateTime<Utc>>,
+    /// Directory Claude Code ran in, the project the session belongs to.
+    pub cwd: Option<String>,
+}
+
+impl SessionPeek {
+    /// Reads the fields from the complete lines of `head`.
+    pub fn from_head(head: &str) -> Self {
+        let mut peek = Self::default();
+        let complete = head.rfind('\n').map_or("", |end| &head[..end]);
+
+        for record in complete.lines().filter_map(|line| SessionRecord::parse(line).ok()) {
+            if peek.session_id.is_none() {
+                peek.session_id = record.session_id().map(str::to_string);
+            }
+            if peek.git_branch.is_none() {
+                peek.git_branch = record.git_branch().map(str::to_string);
+            }
+            if peek.started.is_none() {
+                peek.started = record.timestamp();
+            }
+            if peek.cwd.is_none() {
+                peek.cwd = record.cwd().map(str::to_string);
+            }
+            if peek.session_id.is_some() && peek.git_branch.is_some() && peek.started.is_some() && peek.cwd.is_some() {
+                break;
+            }
+        }
+
+        peek
+    }
+}
+
+/// Reads the first [`PEEK_BYTES`] of a session file.
+pub fn peek<FS: FileSystem>(fs: &FS, path: &Path) -> Result<SessionPeek> {
+    let head = fs.read_head(path, PEEK_BYTES)?;
+    Ok(SessionPeek::from_head(&String::from_utf8_lossy(&head)))
+}
+
+impl SessionFilter {
+    pub fn is_empty(&self) -> bool {
+        self.session_ids.is_empty()
+            && self.since.is_none()
+            && self.until.is_none()
+            && self.git_branch.is_none()
+            && self.private_ids.is_empty()
+    }
+
+    /// Returns `true` if the session file `entry` passes the filter.
+    ///
+    /// The file is only read when its modification time and name do not
+    /// decide it.
+    pub fn matches<FS: FileSystem>(&self, fs: &FS, entry: &EntryMetadata) -> Result<bool> {
+        self.matches_with(&entry.path, entry.modified, || peek(fs, &entry.path))
+    }
+
+    /// Returns `true` if the session file at `path`, last modified at
+    /// `modified`, passes the filter. `peek` is only called when needed.
+    pub fn matches_with(&self, path: &Path, modified: SystemTime, peek: impl FnOnce() -> Result<SessionPeek>) -> Result<bool> {
+        if let Some(since) = self.since {
+            if DateTime::<Utc>::from(modified) < since {
+                return Ok(false);
+            }
+        }
+
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+        let id_matches = |id: &str| self.session_ids.iter().any(|wanted| id.starts_with(wanted.as_str()));
+        let is_private = |id: &str| self.private_ids.iter().any(|private| id.starts_with(private.as_str()));
+        if is_private(&stem) {
+            return Ok(false);
+        }
+        let name_matches = self.session_ids.is_empty() || id_matches(&stem);
+        if name_matches && self.until.is_none() && self.git_branch.is_none() && self.private_ids.is_empty() {
+            return Ok(true);
+        }
+
+        let peek = peek()?;
+        if !name_matches && !peek.session_id.as_deref().is_some_and(id_matches) {
+            return Ok(false);
+        }
+        // Sidechains are named after themselves but carry the session's id
+        if peek.session_id.as_deref().is_some_and(is_private) {
+            return Ok(false);
+        }
+        if let (Some(until), Some(started)) = (self.until, peek.started) {
+            if started > until {
+                return Ok(false);
+            }
+        }
+        if let Some(branch) = &self.git_branch {
+            if peek.git_branch.as_ref() != Some(branch) {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Parses a `--since` bound: an RFC 3339 time, or a date meaning its start (UTC).
+pub fn parse_since(value: &str) -> std::result::Result<DateTime<Utc>, String> {
+    parse_time_bound(value, NaiveTime::MIN)
+}
+
+/// Parses an `--until` bound: an RFC 3339 time, or a date meaning its end (UTC).
+pub fn parse_until(value: &str) -> std::result::Result<DateTime<Utc>, String> {
+    parse_time_bound(value, NaiveTime::from_hms_opt(23, 59, 59).unwrap_or(NaiveTime::MIN))
+}
+
+fn parse_time_bound(value: &str, time_of_day: NaiveTime) -> std::result::Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(time_of_day).and_utc())
+        .map_err(|_| format!("Invalid time '{}' (expected YYYY-MM-DD or an RFC 3339 time)", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockFileSystem;
+    use std::time::SystemTime;
+
+    const SESSION: &str = concat!(
+        r#"{"type":"summary","summary":"Fix the build"}"#, "\n",
+        r#"{"type":"user","uuid":"u1","sessionId":"abc123","gitBranch":"feature-x","timestamp":"2025-06-25T10:00:00Z","message":{"role":"user","content":"hi"}}"#, "\n",
+    );
+
+    #[test]
+    fn test_peek_ignores_partial_line() {
+        let peek = SessionPeek::from_head(&SESSION[..SESSION.len() - 20]);
+        assert_eq!(peek, SessionPeek::default());
+
+        let peek = SessionPeek::from_head(SESSION);
+        assert_eq!(peek.session_id.as_deref(), Some("abc123"));
+        assert_eq!(peek.git_branch.as_deref(), Some("feature-x"));
+        assert_eq!(peek.started, Some(parse_since("2025-06-25T10:00:00Z").unwrap()));
+    }
+
+    #[test]
+    fn test_matches() {
+        let fs = MockFileSystem::new();
+        let path = Path::new("/source/p/renamed.jsonl");
+        fs.add_file(path, SESSION.as_bytes().to_vec(), SystemTime::now());
+        let entry = fs.get_metadata(path).unwrap();
+
+        let matches = |filter: SessionFilter| filter.matches(&fs, &entry).unwrap();
+        assert!(matches(SessionFilter::default()));
+        assert!(matches(SessionFilter { session_ids: vec!["abc".to_string()], ..Default::default() }));
+        assert!(!matches(SessionFilter { session_ids: vec!["def".to_string()], ..Default::default() }));
+        assert!(matches(SessionFilter { git_branch: Some("feature-x".to_string()), ..Default::default() }));
+        assert!(!matches(SessionFilter { git_branch: Some("main".to_string()), ..Default::default() }));
+        assert!(matches(SessionFilter { until: Some(parse_until("2025-06-25").unwrap()), ..Default::default() }));
+        assert!(!matches(SessionFilter { until: Some(parse_until("2025-06-24").unwrap()), ..Default::default() }));
+        assert!(!matches(SessionFilter { since: Some(parse_since("2999-01-01").unwrap()), ..Default::default() }));
+        assert!(!matches(SessionFilter { private_ids: vec!["abc".to_string()], ..Default::default() }));
+        assert!(!matches(SessionFilter { private_ids: vec!["renamed".to_string()], ..Default::default() }));
+        assert!(matches(SessionFilter { private_ids: vec!["def".to_string()], ..Default::default() }));
+    }
+}
@@ -1,33 +1,156 @@
-use std::path::Path;
+mod filter;
+
+pub use filter::{parse_since, parse_until, peek, SessionFilter, SessionPeek, PEEK_BYTES};
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::time::SystemTime;
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::compression::{logical_path, StoreFormat};
+use crate::dedup::RecordIndex;
+use crate::encryption::Identities;
+use crate::filesystem::{EntryMetadata, FileSystem, FileSystemError, Result};
+use crate::index::{update_index, RedactionRules};
+use crate::manifest::Manifest;
+use crate::session::SessionRecord;
+use crate::sidechain::{self, SidechainMode};
+use crate::store::read_store_snapshot;
 
-use crate::filesystem::{FileSystem, FileSystemError, Result};
+/// Extension Claude Code uses for session transcripts.
+pub const SESSION_FILE_EXTENSION: &str = "jsonl";
+
+/// Returns `true` if `path` looks like a session transcript, compressed or not.
+pub fn is_session_file(path: &Path) -> bool {
+    logical_path(path).extension().is_some_and(|ext| ext == SESSION_FILE_EXTENSION)
+}
 
-#[derive(Debug, Clone)]
+fn first_timestamp(content: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    content.lines()
+        .filter_map(|line| SessionRecord::parse(line).ok())
+        .find_map(|record| record.timestamp())
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct SyncOptions {
     pub dry_run: bool,
     pub verbose: bool,
-}
-
-impl Default for SyncOptions {
-    fn default() -> Self {
-        Self {
-            dry_run: false,
-            verbose: false,
-        }
-    }
+    /// Replace records already stored in another session with references
+    pub deduplicate: bool,
+    /// What to do with sidechain transcripts of subagents
+    pub sidechains: SidechainMode,
+    /// Keep INDEX.md and sessions.json at the store root up to date
+    pub index: bool,
+    /// Only sync the sessions that match
+    pub filter: SessionFilter,
+    /// How session files are written to the store; with encryption, other
+    /// files are not synced at all
+    pub format: StoreFormat,
+    /// Identities to read the encrypted sessions of the store with, e.g. to
+    /// deduplicate against them
+    pub identities: Identities,
+    /// Secrets to keep out of the session index besides the built-in ones
+    pub redaction: RedactionRules,
+    /// Only these source files, e.g. the ones `watch` saw change; `None`
+    /// syncs every file of the project
+    pub changed_files: Option<BTreeSet<PathBuf>>,
 }
 
 #[derive(Debug, Default)]
 pub struct SyncResult {
     pub files_copied: usize,
     pub files_skipped: usize,
+    /// Session files left out by [`SyncOptions::filter`]
+    pub files_filtered: usize,
+    pub files_deduplicated: usize,
     pub directories_created: usize,
+    /// Whether INDEX.md and sessions.json were rewritten
+    pub index_updated: bool,
     pub errors: Vec<String>,
 }
 
+/// Exit code of `sync` when sessions are pending and were not synced:
+/// with [`SyncPolicy::Check`] or in a dry run. `status` exits with it when
+/// changes are pending.
+pub const EXIT_PENDING: i32 = 1;
+
+/// Exit code of `sync`, and of the other commands with exit codes of their
+/// own, when it could not run.
+pub const EXIT_ERROR: i32 = 2;
+
+/// Exit code of `sync` when sessions were synced with
+/// [`SyncPolicy::FixAndFail`], so that the commit is made again with them.
+pub const EXIT_SYNCED: i32 = 3;
+
+/// Exit code of `verify` and `doctor` when they found errors, e.g. a corrupt
+/// store.
+pub const EXIT_CHECK_FAILED: i32 = 4;
+
+/// How `sync` ends when sessions were pending, e.g. in a pre-commit hook.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncPolicy {
+    /// Change nothing; fail if sessions are pending.
+    Check,
+    /// Sync the sessions, then fail so that the commit is made again.
+    #[default]
+    FixAndFail,
+    /// Sync and stage the sessions, and succeed.
+    FixAndPass,
+}
+
+impl SyncPolicy {
+    /// Exit code of `sync` after `files_copied` session files were copied, or
+    /// would have been in a dry run.
+    pub fn exit_code(&self, files_copied: usize, dry_run: bool) -> i32 {
+        match self {
+            _ if files_copied == 0 => 0,
+            SyncPolicy::Check => EXIT_PENDING,
+            _ if dry_run => EXIT_PENDING,
+            SyncPolicy::FixAndFail => EXIT_SYNCED,
+            SyncPolicy::FixAndPass => 0,
+        }
+    }
+}
+
+/// Exit code of `sync --all` from the exit codes of each repository: the
+/// most severe of [`EXIT_ERROR`], [`EXIT_PENDING`] and [`EXIT_SYNCED`].
+pub fn combined_exit_code(codes: impl IntoIterator<Item = i32>) -> i32 {
+    let severity = |code: i32| match code {
+        EXIT_ERROR => 3,
+        EXIT_PENDING => 2,
+        EXIT_SYNCED => 1,
+        _ => 0,
+    };
+    codes.into_iter().max_by_key(|code| severity(*code)).filter(|code| severity(*code) > 0).unwrap_or(0)
+}
+
+impl FromStr for SyncPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "check" => Ok(SyncPolicy::Check),
+            "fix-and-fail" => Ok(SyncPolicy::FixAndFail),
+            "fix-and-pass" => Ok(SyncPolicy::FixAndPass),
+            _ => Err(format!("Unknown policy '{}' (expected check, fix-and-fail or fix-and-pass)", s)),
+        }
+    }
+}
+
+impl fmt::Display for SyncPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SyncPolicy::Check => "check",
+            SyncPolicy::FixAndFail => "fix-and-fail",
+            SyncPolicy::FixAndPass => "fix-and-pass",
+        })
+    }
+}
+
 pub struct SessionSyncer<FS: FileSystem> {
     filesystem: FS,
 }
@@ -57,26 +180,10 @@ impl<FS: FileSystem> SessionSyncer<FS> {
             }
         }
         
-        // Find directories that match the source prefix
-        let mut dirs_to_process = VecDeque::new();
+        let mut copied_files = Vec::new();
         
-        for current_dir in self.filesystem.list_directory(source_root_dir)? {
-            log::debug!("Processing directory: {}", current_dir.path.display());
-
-            if !current_dir.path.file_name().unwrap().to_string_lossy().starts_with(source_prefix){
-                // Skip directories that don't match the prefix
-                log::debug!("Skipping directory {}: does not match prefix {}", current_dir.path.display(), source_prefix);
-                continue;
-            }
-
-            if !current_dir.is_directory {
-                log::warn!("Skipping non-directory entry: {}", current_dir.path.display());
-                continue;
-            }
-            
-            // Add this directory to be processed
-            dirs_to_process.push_back(current_dir.path.clone());
-        }
+        // Find directories that match the source prefix
+        let mut dirs_to_process: VecDeque<_> = self.find_project_dirs(source_root_dir, source_prefix)?.into();
         
         // Process all directories recursively
         while let Some(current_dir) = dirs_to_process.pop_front() {
@@ -101,7 +208,7 @@ impl<FS: FileSystem> SessionSyncer<FS> {
                 
                 // Convert directory name format
                 log::debug!("relative path: {}", relative_path.display());
-                let target_path = target_dir.join(&relative_path);
+                let target_path = target_dir.join(relative_path);
                 
                 if entry.is_directory {
                     // Handle directory
@@ -118,6 +225,41 @@ impl<FS: FileSystem> SessionSyncer<FS> {
                     dirs_to_process.push_back(entry.path.clone());
                 } else {
                     // Handle file
+                    if options.changed_files.as_ref().is_some_and(|changed| !changed.contains(source_path)) {
+                        continue;
+                    }
+                    if !options.filter.is_empty() && is_session_file(source_path) {
+                        match options.filter.matches(&self.filesystem, &entry) {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                result.files_filtered += 1;
+                                info!("Filtered out: {}", source_path.display());
+                                continue;
+                            }
+                            Err(e) => {
+                                result.errors.push(format!("Error reading file {}: {}", source_path.display(), e));
+                                continue;
+                            }
+                        }
+                    }
+                    // Only sessions are encrypted; tool results and the like would be stored in plain text
+                    if options.format.encryption.is_some() && !is_session_file(source_path) {
+                        result.files_skipped += 1;
+                        info!("Skipped (not a session, store is encrypted): {}", source_path.display());
+                        continue;
+                    }
+                    let target_path = match self.session_target_path(source_path, relative_path, target_dir, options) {
+                        Ok(Some(target_path)) => target_path,
+                        Ok(None) => {
+                            result.files_skipped += 1;
+                            info!("Skipped (sidechain): {}", source_path.display());
+                            continue;
+                        }
+                        Err(e) => {
+                            result.errors.push(format!("Error reading file {}: {}", source_path.display(), e));
+                            continue;
+                        }
+                    };
                     match self.should_copy_file(source_path, &target_path) {
                         Ok(true) => {
                             // Check if parent directory needs to be created
@@ -131,7 +273,12 @@ impl<FS: FileSystem> SessionSyncer<FS> {
                             }
                             
                             if !options.dry_run {
-                                self.filesystem.copy_file(source_path, &target_path)?;
+                                if options.format.is_plain() || !is_session_file(source_path) {
+                                    self.filesystem.copy_file(source_path, &target_path)?;
+                                } else {
+                                    let content = options.format.encode(&self.filesystem.read_file(source_path)?)?;
+                                    self.filesystem.write_file(&target_path, &content)?;
+                                }
                                 
                                 // Update timestamp to mark as synced
                                 let now = SystemTime::now();
@@ -142,6 +289,7 @@ impl<FS: FileSystem> SessionSyncer<FS> {
                                 }
                             }
                             result.files_copied += 1;
+                            copied_files.push(target_path.clone());
                             info!("Copied: {} -> {}", source_path.display(), target_path.display());
                         }
                         Ok(false) => {
@@ -156,9 +304,189 @@ impl<FS: FileSystem> SessionSyncer<FS> {
             }
         }
         
+        if options.deduplicate && !options.dry_run {
+            let copied_sessions: Vec<PathBuf> = copied_files.iter()
+                .filter(|path| is_session_file(path))
+                .cloned()
+                .collect();
+            if !copied_sessions.is_empty() {
+                self.deduplicate_sessions(target_dir, &copied_sessions, options, &mut result)?;
+            }
+        }
+
+        if !options.dry_run && !copied_files.is_empty() {
+            self.update_manifest(target_dir, &copied_files, &mut result)?;
+        }
+
+        if options.index && !options.dry_run && options.format.encryption.is_some() {
+            result.errors.push("Not writing the session index: it would expose encrypted sessions".to_string());
+        } else if options.index && !options.dry_run {
+            let changed: Vec<PathBuf> = copied_files.iter()
+                .filter_map(|path| path.strip_prefix(target_dir).ok())
+                .map(Path::to_path_buf)
+                .collect();
+            match update_index(&self.filesystem, target_dir, &changed, &options.identities, &options.redaction) {
+                Ok(updated) => result.index_updated = updated,
+                Err(e) => result.errors.push(format!("Failed to update the session index: {}", e)),
+            }
+        }
+        
         Ok(result)
     }
+
+    /// Records the hashes of the files this sync wrote in the store manifest.
+    fn update_manifest(&self, target_dir: &Path, copied_files: &[PathBuf], result: &mut SyncResult) -> Result<()> {
+        let mut manifest = match Manifest::load(&self.filesystem, target_dir) {
+            Ok(manifest) => manifest.unwrap_or_default(),
+            Err(e) => {
+                result.errors.push(format!("Not updating the store manifest: {}", e));
+                return Ok(());
+            }
+        };
+
+        for path in copied_files {
+            let relative_path = path.strip_prefix(target_dir)
+                .map_err(|e| FileSystemError::PathError(e.to_string()))?;
+            manifest.record(relative_path, &self.filesystem.read_file(path)?);
+        }
+
+        if let Err(e) = manifest.save(&self.filesystem, target_dir) {
+            result.errors.push(format!("Failed to write the store manifest: {}", e));
+        }
+        Ok(())
+    }
+
+    /// Where a file goes in the store, or `None` if it is left out.
+    ///
+    /// Session files get the extensions of [`SyncOptions::format`], and
+    /// sidechain transcripts can end up elsewhere, depending on
+    /// [`SyncOptions::sidechains`].
+    fn session_target_path(&self, source_path: &Path, relative_path: &Path, target_dir: &Path, options: &SyncOptions) -> Result<Option<PathBuf>> {
+        if !is_session_file(source_path) {
+            return Ok(Some(target_dir.join(relative_path)));
+        }
+        let default_path = target_dir.join(options.format.stored_path(relative_path));
+        if options.sidechains == SidechainMode::Sync {
+            return Ok(Some(default_path));
+        }
+
+        let head = self.filesystem.read_head(source_path, PEEK_BYTES)?;
+        let info = match sidechain::detect(&String::from_utf8_lossy(&head)) {
+            Some(info) => info,
+            None => return Ok(Some(default_path)),
+        };
+
+        Ok(match options.sidechains {
+            SidechainMode::Skip => None,
+            SidechainMode::Nest => {
+                let nested_path = sidechain::nested_path(relative_path, &info.parent_session_id);
+                Some(target_dir.join(options.format.stored_path(&nested_path)))
+            }
+            SidechainMode::Sync => Some(default_path),
+        })
+    }
+
+    /// Replaces records of the copied sessions that are already stored in
+    /// other sessions of the store with references to them.
+    fn deduplicate_sessions(&self, target_dir: &Path, copied_sessions: &[PathBuf], options: &SyncOptions, result: &mut SyncResult) -> Result<()> {
+        let mut snapshot = read_store_snapshot(&self.filesystem, target_dir, &options.identities)?;
+
+        let mut copied: Vec<(PathBuf, String)> = copied_sessions.iter()
+            .filter_map(|path| {
+                let relative_path = logical_path(path.strip_prefix(target_dir).ok()?);
+                let content = snapshot.remove(&relative_path)?;
+                Some((relative_path, String::from_utf8_lossy(&content).into_owned()))
+            })
+            .collect();
+        let stored_paths: HashMap<PathBuf, &PathBuf> = copied_sessions.iter()
+            .filter_map(|path| Some((logical_path(path.strip_prefix(target_dir).ok()?), path)))
+            .collect();
+
+        // Older sessions keep their records, the sessions resumed from them refer to them
+        copied.sort_by_key(|(path, content)| (first_timestamp(content), path.clone()));
+
+        let mut index = RecordIndex::from_snapshot(&snapshot);
+        for (relative_path, content) in copied {
+            let content = match index.deduplicate(&relative_path, &content) {
+                Some(deduplicated) => {
+                    let target_path = stored_paths[&relative_path];
+                    let stored = options.format.encode(deduplicated.as_bytes())?;
+                    self.filesystem.write_file(target_path, &stored)?;
+                    result.files_deduplicated += 1;
+                    info!("Deduplicated: {}", target_path.display());
+                    deduplicated
+                }
+                None => content,
+            };
+            index.insert(&relative_path, &content);
+        }
+
+        Ok(())
+    }
     
+    /// Lists the Claude Code project directories under `source_root_dir` whose
+    /// encoded name starts with `source_prefix`.
+    pub fn find_project_dirs(&self, source_root_dir: &Path, source_prefix: &str) -> Result<Vec<PathBuf>> {
+        let mut project_dirs = Vec::new();
+
+        for current_dir in self.filesystem.list_directory(source_root_dir)? {
+            log::debug!("Processing directory: {}", current_dir.path.display());
+
+            if !current_dir.path.file_name().unwrap().to_string_lossy().starts_with(source_prefix){
+                // Skip directories that don't match the prefix
+                log::debug!("Skipping directory {}: does not match prefix {}", current_dir.path.display(), source_prefix);
+                continue;
+            }
+
+            if !current_dir.is_directory {
+                log::warn!("Skipping non-directory entry: {}", current_dir.path.display());
+                continue;
+            }
+
+            project_dirs.push(current_dir.path.clone());
+        }
+
+        project_dirs.sort();
+        Ok(project_dirs)
+    }
+
+    /// Lists the session transcripts (`*.jsonl`) that `sync` would pick up for
+    /// `source_prefix`, sorted by path.
+    pub fn find_session_files(&self, source_root_dir: &Path, source_prefix: &str) -> Result<Vec<EntryMetadata>> {
+        let mut sessions = Vec::new();
+
+        for project_dir in self.find_project_dirs(source_root_dir, source_prefix)? {
+            sessions.extend(
+                self.filesystem.walk_files(&project_dir)?
+                    .into_iter()
+                    .filter(|entry| is_session_file(&entry.path)),
+            );
+        }
+
+        Ok(sessions)
+    }
+
+    /// Lists the session transcripts that `sync` would store with `options`,
+    /// each with its logical path in the store: sessions the filter leaves
+    /// out and skipped sidechains are dropped, nested sidechains are moved.
+    pub fn find_synced_sessions(&self, source_root_dir: &Path, source_prefix: &str, options: &SyncOptions) -> Result<Vec<(EntryMetadata, PathBuf)>> {
+        let mut sessions = Vec::new();
+
+        for entry in self.find_session_files(source_root_dir, source_prefix)? {
+            if !options.filter.is_empty() && !options.filter.matches(&self.filesystem, &entry)? {
+                continue;
+            }
+            let relative_path = entry.path.strip_prefix(source_root_dir)
+                .map_err(|e| FileSystemError::PathError(e.to_string()))?
+                .to_path_buf();
+            if let Some(target_path) = self.session_target_path(&entry.path, &relative_path, Path::new(""), options)? {
+                sessions.push((entry, logical_path(&target_path)));
+            }
+        }
+
+        Ok(sessions)
+    }
+
     fn should_copy_file(&self, source: &Path, target: &Path) -> Result<bool> {
         if !self.filesystem.exists(target)? {
             return Ok(true);
@@ -180,6 +508,28 @@ mod tests {
     
 
     
+    #[test]
+    fn test_sync_policy_exit_code() {
+        for policy in [SyncPolicy::Check, SyncPolicy::FixAndFail, SyncPolicy::FixAndPass] {
+            assert_eq!(policy.exit_code(0, false), 0);
+            assert_eq!(policy.exit_code(2, true), EXIT_PENDING);
+            assert_eq!(policy.to_string().parse::<SyncPolicy>().unwrap(), policy);
+        }
+        assert_eq!(SyncPolicy::Check.exit_code(2, false), EXIT_PENDING);
+        assert_eq!(SyncPolicy::FixAndFail.exit_code(2, false), EXIT_SYNCED);
+        assert_eq!(SyncPolicy::FixAndPass.exit_code(2, false), 0);
+        assert!("fix".parse::<SyncPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_combined_exit_code() {
+        assert_eq!(combined_exit_code([]), 0);
+        assert_eq!(combined_exit_code([0, 0]), 0);
+        assert_eq!(combined_exit_code([0, EXIT_SYNCED, 0]), EXIT_SYNCED);
+        assert_eq!(combined_exit_code([EXIT_SYNCED, EXIT_PENDING]), EXIT_PENDING);
+        assert_eq!(combined_exit_code([EXIT_PENDING, EXIT_ERROR, EXIT_SYNCED]), EXIT_ERROR);
+    }
+
     #[test]
     fn test_should_copy_file() {
         let fs = MockFileSystem::new();
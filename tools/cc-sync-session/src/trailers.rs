@@ -0,0 +1,183 @@
+use std::collections::BTreeSet;
+
+/// Trailer key recording a Claude Code session that was active while a commit was written.
+pub const SESSION_TRAILER: &str = "Claude-Session";
+
+/// Line git puts in verbose commit messages; everything below it is discarded.
+const SCISSORS_MARKER: &str = "------------------------ >8 ------------------------";
+
+/// Returns `true` for the commit message sources whose text git generates and
+/// which therefore should not receive trailers.
+pub fn is_generated_message(commit_source: Option<&str>) -> bool {
+    matches!(commit_source, Some("merge") | Some("squash"))
+}
+
+/// Collects the session ids already recorded as `Claude-Session` trailers in `message`.
+pub fn existing_session_trailers(message: &str) -> BTreeSet<String> {
+    let (content, _) = split_comments(message);
+
+    content
+        .iter()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| parse_trailer(line))
+        .filter(|(key, _)| key.eq_ignore_ascii_case(SESSION_TRAILER))
+        .map(|(_, value)| value.to_string())
+        .collect()
+}
+
+/// Appends a `Claude-Session` trailer for every id in `session_ids` that is not
+/// already recorded in `message`.
+///
+/// Trailers join the existing trailer block when the message ends with one and
+/// start a new paragraph otherwise. Comment lines git added below the message
+/// are kept where they are.
+pub fn append_session_trailers(message: &str, session_ids: &[String]) -> String {
+    let existing = existing_session_trailers(message);
+    let mut missing = Vec::new();
+    for id in session_ids {
+        if !existing.contains(id) && !missing.contains(id) {
+            missing.push(id.clone());
+        }
+    }
+
+    if missing.is_empty() {
+        return message.to_string();
+    }
+
+    let (mut content, comments) = split_comments(message);
+    while content.last().is_some_and(|line| line.trim().is_empty()) {
+        content.pop();
+    }
+
+    let mut output = String::new();
+    for line in &content {
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    if !ends_with_trailer_block(&content) {
+        output.push('\n');
+    }
+
+    for id in &missing {
+        output.push_str(&format!("{}: {}\n", SESSION_TRAILER, id));
+    }
+
+    if !comments.is_empty() {
+        output.push('\n');
+        for line in comments {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+/// Splits a commit message into its content lines and the trailing block of
+/// comments (and blank lines) git adds for the editor.
+fn split_comments(message: &str) -> (Vec<&str>, Vec<&str>) {
+    let lines: Vec<&str> = message.lines().collect();
+
+    let mut end = lines
+        .iter()
+        .position(|line| line.starts_with('#') && line.contains(SCISSORS_MARKER))
+        .unwrap_or(lines.len());
+    while end > 0 && (lines[end - 1].starts_with('#') || lines[end - 1].trim().is_empty()) {
+        end -= 1;
+    }
+
+    let mut comments_start = end;
+    while comments_start < lines.len() && lines[comments_start].trim().is_empty() {
+        comments_start += 1;
+    }
+
+    (lines[..end].to_vec(), lines[comments_start..].to_vec())
+}
+
+fn ends_with_trailer_block(content: &[&str]) -> bool {
+    let paragraph_start = content
+        .iter()
+        .rposition(|line| line.trim().is_empty())
+        .map(|index| index + 1)
+        .unwrap_or(0);
+
+    // The subject line is never a trailer block on its own.
+    paragraph_start > 0 && content[paragraph_start..].iter().all(|line| parse_trailer(line).is_some())
+}
+
+fn parse_trailer(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    let is_token = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    let value = value.trim();
+
+    (is_token && !value.is_empty()).then_some((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_append_to_plain_message() {
+        let message = "Fix parser\n\nLonger description.\n";
+        let result = append_session_trailers(message, &ids(&["a1", "b2"]));
+
+        assert_eq!(
+            result,
+            "Fix parser\n\nLonger description.\n\nClaude-Session: a1\nClaude-Session: b2\n"
+        );
+    }
+
+    #[test]
+    fn test_append_joins_existing_trailer_block() {
+        let message = "Fix parser\n\nSigned-off-by: Someone <someone@example.com>\n";
+        let result = append_session_trailers(message, &ids(&["a1"]));
+
+        assert_eq!(
+            result,
+            "Fix parser\n\nSigned-off-by: Someone <someone@example.com>\nClaude-Session: a1\n"
+        );
+    }
+
+    #[test]
+    fn test_append_keeps_comment_block() {
+        let message = "Fix parser\n\n# Please enter the commit message for your changes.\n# On branch main\n";
+        let result = append_session_trailers(message, &ids(&["a1"]));
+
+        assert_eq!(
+            result,
+            "Fix parser\n\nClaude-Session: a1\n\n# Please enter the commit message for your changes.\n# On branch main\n"
+        );
+    }
+
+    #[test]
+    fn test_append_does_not_duplicate_on_amend() {
+        let message = "Fix parser\n\nClaude-Session: a1\n";
+        let result = append_session_trailers(message, &ids(&["a1", "b2"]));
+
+        assert_eq!(result, "Fix parser\n\nClaude-Session: a1\nClaude-Session: b2\n");
+        assert_eq!(append_session_trailers(&result, &ids(&["a1", "b2"])), result);
+    }
+
+    #[test]
+    fn test_existing_trailers_ignore_comments() {
+        let message = "Fix parser\n\nClaude-Session: a1\n# Claude-Session: b2\n";
+        let existing = existing_session_trailers(message);
+
+        assert_eq!(existing.into_iter().collect::<Vec<_>>(), vec!["a1".to_string()]);
+    }
+
+    #[test]
+    fn test_generated_messages() {
+        assert!(is_generated_message(Some("merge")));
+        assert!(is_generated_message(Some("squash")));
+        assert!(!is_generated_message(Some("message")));
+        assert!(!is_generated_message(Some("commit")));
+        assert!(!is_generated_message(None));
+    }
+}
@@ -0,0 +1,622 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::widgets::ListState;
+
+use crate::index::RedactionRules;
+use crate::list::ListedSession;
+use crate::render::{self, RenderedRecord};
+use crate::sidechain;
+use crate::status::{self, SyncState};
+use crate::store::Snapshot;
+use crate::sync::{SessionFilter, SessionPeek};
+
+mod ui;
+
+pub use ui::draw;
+
+/// A session of the source, the store or both.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuiSession {
+    /// Path relative to the source and store roots: `<encoded-project>/<id>.jsonl`.
+    pub path: PathBuf,
+    pub listed: ListedSession,
+    /// The transcript in Claude Code's projects.
+    pub source: Option<String>,
+    /// The transcript in the store.
+    pub stored: Option<String>,
+    /// How the source differs from the store; `None` when they agree.
+    pub state: Option<SyncState>,
+    pub pinned: bool,
+    pub private: bool,
+}
+
+impl TuiSession {
+    /// The transcript shown in the conversation pane, the source's if it has one.
+    pub fn content(&self) -> &str {
+        self.source.as_deref().or(self.stored.as_deref()).unwrap_or_default()
+    }
+
+    /// Whether `sync` would write the session.
+    pub fn is_pending(&self) -> bool {
+        !self.private && self.state.is_some_and(|state| state.is_pending())
+    }
+}
+
+/// Pairs up the `source` and `stored` sessions that pass `filter`, latest
+/// first. Subagent sidechains are left out.
+///
+/// `modified` has the last write time of each file; `pinned` and `private`
+/// are the session ids (or prefixes) of `[retention] pinned` and
+/// `[filter] private`. First prompts are redacted with `rules`.
+pub fn collect_sessions(
+    source: &Snapshot,
+    stored: &Snapshot,
+    modified: &BTreeMap<PathBuf, SystemTime>,
+    filter: &SessionFilter,
+    pinned: &[String],
+    private: &[String],
+    rules: &RedactionRules,
+) -> Vec<TuiSession> {
+    let states = status::compare(source, stored);
+    let paths: BTreeSet<&PathBuf> = source.keys().chain(stored.keys()).collect();
+    let mut sessions = Vec::new();
+
+    for path in paths {
+        let source = source.get(path).map(|content| String::from_utf8_lossy(content).into_owned());
+        let stored = stored.get(path).map(|content| String::from_utf8_lossy(content).into_owned());
+        let content = source.as_deref().or(stored.as_deref()).unwrap_or_default();
+        if sidechain::detect(content).is_some() {
+            continue;
+        }
+        let modified = modified.get(path).copied().unwrap_or(UNIX_EPOCH);
+        let matches = filter.matches_with(path, modified, || Ok(SessionPeek::from_head(content)));
+        if !matches.unwrap_or(false) {
+            continue;
+        }
+
+        let listed = ListedSession::new(path, content, modified, None, rules);
+        let marked = |ids: &[String]| ids.iter().any(|id| listed.id.starts_with(id.as_str()));
+        sessions.push(TuiSession {
+            path: path.clone(),
+            state: states.get(path).copied(),
+            pinned: marked(pinned),
+            private: marked(private),
+            listed,
+            source,
+            stored,
+        });
+    }
+
+    sessions.sort_by_key(|session| Reverse(session.listed.entry.started));
+    sessions
+}
+
+/// Which sessions the list shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StateFilter {
+    #[default]
+    All,
+    /// Sessions `sync` would write.
+    Pending,
+    /// Sessions in the store.
+    Stored,
+    Pinned,
+    Private,
+}
+
+impl StateFilter {
+    /// The filter after this one, for cycling through them.
+    fn next(self) -> Self {
+        match self {
+            StateFilter::All => StateFilter::Pending,
+            StateFilter::Pending => StateFilter::Stored,
+            StateFilter::Stored => StateFilter::Pinned,
+            StateFilter::Pinned => StateFilter::Private,
+            StateFilter::Private => StateFilter::All,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            StateFilter::All => "all",
+            StateFilter::Pending => "pending",
+            StateFilter::Stored => "stored",
+            StateFilter::Pinned => "pinned",
+            StateFilter::Private => "private",
+        }
+    }
+
+    fn matches(&self, session: &TuiSession) -> bool {
+        match self {
+            StateFilter::All => true,
+            StateFilter::Pending => session.is_pending(),
+            StateFilter::Stored => session.stored.is_some(),
+            StateFilter::Pinned => session.pinned,
+            StateFilter::Private => session.private,
+        }
+    }
+}
+
+/// Something the TUI asks the command to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Quit,
+    /// Read the source and the store again.
+    Reload,
+    SetPinned { id: String, pinned: bool },
+    SetPrivate { id: String, private: bool },
+    /// Sync the session into the store.
+    Sync { id: String },
+    /// Copy the stored session into Claude Code's projects, replacing the
+    /// copy there if `force`.
+    Restore { id: String, force: bool },
+    /// Write the session to a bundle, taking it from the store if it is not
+    /// in the source.
+    Export { id: String, from_store: bool, path: PathBuf },
+}
+
+/// Pane that has the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Sessions,
+    Conversation,
+}
+
+/// Line being typed at the bottom of the screen.
+#[derive(Debug, Clone, PartialEq)]
+enum Input {
+    /// The search box; the list follows it as it is typed.
+    Search,
+    /// File name of an export.
+    Export(String),
+    /// Restoring would replace a session in the projects.
+    ConfirmRestore(String),
+}
+
+/// Where to scroll the conversation to once it is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Jump {
+    ToolCursor,
+    NextMatch,
+    PreviousMatch,
+}
+
+/// The rendered transcripts of the selected session.
+#[derive(Debug)]
+struct Conversation {
+    session: usize,
+    records: Vec<RenderedRecord>,
+    source: Vec<RenderedRecord>,
+    stored: Vec<RenderedRecord>,
+}
+
+/// State of the TUI between two key presses.
+#[derive(Debug)]
+pub struct App {
+    sessions: Vec<TuiSession>,
+    /// Indexes into `sessions` of the listed sessions.
+    visible: Vec<usize>,
+    list_state: ListState,
+    state_filter: StateFilter,
+    query: String,
+    /// Lowercase transcripts searched by the search box, rendered on demand.
+    search_texts: Vec<Option<String>>,
+    input: Option<Input>,
+    focus: Focus,
+    compare: bool,
+    expand_all: bool,
+    /// Tool calls and results unfolded in the conversation, by their index in it.
+    expanded: BTreeSet<usize>,
+    tool_cursor: Option<usize>,
+    scroll: usize,
+    jump: Option<Jump>,
+    conversation: Option<Conversation>,
+    message: Option<String>,
+}
+
+impl App {
+    pub fn new(sessions: Vec<TuiSession>) -> Self {
+        let mut app = App {
+            sessions: Vec::new(),
+            visible: Vec::new(),
+            list_state: ListState::default(),
+            state_filter: StateFilter::default(),
+            query: String::new(),
+            search_texts: Vec::new(),
+            input: None,
+            focus: Focus::Sessions,
+            compare: false,
+            expand_all: false,
+            expanded: BTreeSet::new(),
+            tool_cursor: None,
+            scroll: 0,
+            jump: None,
+            conversation: None,
+            message: None,
+        };
+        app.set_sessions(sessions);
+        app
+    }
+
+    /// Replaces the sessions, e.g. after an action, keeping the selected one.
+    pub fn set_sessions(&mut self, sessions: Vec<TuiSession>) {
+        let selected = self.selected().map(|session| session.listed.id.clone());
+        self.search_texts = vec![None; sessions.len()];
+        self.sessions = sessions;
+        self.conversation = None;
+        self.apply_filters();
+        if let Some(position) = selected.and_then(|id| self.visible.iter().position(|&index| self.sessions[index].listed.id == id)) {
+            self.list_state.select(Some(position));
+        }
+    }
+
+    /// Shows `message` at the bottom of the screen until the next key press.
+    pub fn set_message(&mut self, message: impl Into<String>) {
+        self.message = Some(message.into());
+    }
+
+    pub fn selected(&self) -> Option<&TuiSession> {
+        let position = self.list_state.selected()?;
+        self.visible.get(position).map(|&index| &self.sessions[index])
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        self.visible.get(self.list_state.selected()?).copied()
+    }
+
+    fn apply_filters(&mut self) {
+        let query = self.query.to_lowercase();
+        let mut visible = Vec::new();
+        for (index, session) in self.sessions.iter().enumerate() {
+            if !self.state_filter.matches(session) {
+                continue;
+            }
+            if !query.is_empty() {
+                let text = self.search_texts[index].get_or_insert_with(|| search_text(session));
+                if !text.contains(&query) {
+                    continue;
+                }
+            }
+            visible.push(index);
+        }
+
+        let selected = self.selected_index();
+        self.visible = visible;
+        let position = selected.and_then(|selected| self.visible.iter().position(|&index| index == selected));
+        self.list_state.select(position.or(if self.visible.is_empty() { None } else { Some(0) }));
+        self.selection_changed();
+    }
+
+    fn selection_changed(&mut self) {
+        if self.conversation.as_ref().map(|conversation| conversation.session) != self.selected_index() {
+            self.expanded.clear();
+            self.tool_cursor = None;
+            self.scroll = 0;
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let position = self.list_state.selected().unwrap_or(0) as isize + delta;
+        self.list_state.select(Some(position.clamp(0, self.visible.len() as isize - 1) as usize));
+        self.selection_changed();
+    }
+
+    /// The rendered transcripts of the selected session, rendered when it changed.
+    fn conversation(&mut self) -> Option<&Conversation> {
+        let index = self.selected_index()?;
+        if self.conversation.as_ref().map(|conversation| conversation.session) != Some(index) {
+            let session = &self.sessions[index];
+            self.conversation = Some(Conversation {
+                session: index,
+                records: render::render_records(session.content()),
+                source: session.source.as_deref().map(render::render_records).unwrap_or_default(),
+                stored: session.stored.as_deref().map(render::render_records).unwrap_or_default(),
+            });
+        }
+        self.conversation.as_ref()
+    }
+
+    fn tool_count(&mut self) -> usize {
+        self.conversation()
+            .map(|conversation| conversation.records.iter().flat_map(|record| &record.parts).filter(|part| part.is_tool()).count())
+            .unwrap_or(0)
+    }
+
+    fn move_tool_cursor(&mut self, delta: isize) {
+        let count = self.tool_count();
+        if count == 0 {
+            self.set_message("No tool calls in this session");
+            return;
+        }
+        let cursor = match self.tool_cursor {
+            Some(cursor) => (cursor as isize + delta).clamp(0, count as isize - 1) as usize,
+            None if delta < 0 => count - 1,
+            None => 0,
+        };
+        self.tool_cursor = Some(cursor);
+        self.jump = Some(Jump::ToolCursor);
+    }
+
+    fn is_expanded(&self, tool: usize) -> bool {
+        self.expand_all != self.expanded.contains(&tool)
+    }
+
+    /// Handles a key press, returning what the command has to do for it.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        self.message = None;
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Some(Action::Quit);
+        }
+        if let Some(input) = self.input.take() {
+            return self.handle_input(input, key);
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return Some(Action::Quit),
+            KeyCode::Esc if !self.query.is_empty() => {
+                self.query.clear();
+                self.apply_filters();
+            }
+            KeyCode::Esc | KeyCode::Left if self.focus == Focus::Conversation => self.focus = Focus::Sessions,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Sessions => Focus::Conversation,
+                    Focus::Conversation => Focus::Sessions,
+                };
+            }
+            KeyCode::Enter | KeyCode::Right if self.focus == Focus::Sessions => self.focus = Focus::Conversation,
+            KeyCode::Char('j') | KeyCode::Down => match self.focus {
+                Focus::Sessions => self.move_selection(1),
+                Focus::Conversation => self.move_tool_cursor(1),
+            },
+            KeyCode::Char('k') | KeyCode::Up => match self.focus {
+                Focus::Sessions => self.move_selection(-1),
+                Focus::Conversation => self.move_tool_cursor(-1),
+            },
+            KeyCode::Char('g') | KeyCode::Home => {
+                self.list_state.select(if self.visible.is_empty() { None } else { Some(0) });
+                self.selection_changed();
+            }
+            KeyCode::Char('G') | KeyCode::End => self.move_selection(isize::MAX / 2),
+            KeyCode::Enter | KeyCode::Char(' ') if self.focus == Focus::Conversation => {
+                if let Some(cursor) = self.tool_cursor {
+                    if !self.expanded.remove(&cursor) {
+                        self.expanded.insert(cursor);
+                    }
+                    self.jump = Some(Jump::ToolCursor);
+                } else {
+                    self.move_tool_cursor(1);
+                }
+            }
+            KeyCode::PageDown | KeyCode::Char('J') => self.scroll += 10,
+            KeyCode::PageUp | KeyCode::Char('K') => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Char('t') => {
+                self.expand_all = !self.expand_all;
+                self.expanded.clear();
+            }
+            KeyCode::Char('c') => self.compare = !self.compare,
+            KeyCode::Char('f') => {
+                self.state_filter = self.state_filter.next();
+                self.apply_filters();
+            }
+            KeyCode::Char('/') => {
+                self.input = Some(Input::Search);
+            }
+            KeyCode::Char('n') => self.jump = Some(Jump::NextMatch),
+            KeyCode::Char('N') => self.jump = Some(Jump::PreviousMatch),
+            KeyCode::Char('R') => return Some(Action::Reload),
+            KeyCode::Char('p') => {
+                let session = self.selected()?;
+                return Some(Action::SetPinned { id: session.listed.id.clone(), pinned: !session.pinned });
+            }
+            KeyCode::Char('x') => {
+                let session = self.selected()?;
+                return Some(Action::SetPrivate { id: session.listed.id.clone(), private: !session.private });
+            }
+            KeyCode::Char('s') => {
+                let session = self.selected()?;
+                if session.private {
+                    self.set_message("The session is private; press x to sync it again");
+                } else if session.source.is_none() {
+                    self.set_message("The session is not in Claude Code's projects");
+                } else if !session.is_pending() {
+                    self.set_message("The session is already synced");
+                } else {
+                    return Some(Action::Sync { id: session.listed.id.clone() });
+                }
+            }
+            KeyCode::Char('r') => {
+                let session = self.selected()?;
+                match (&session.source, &session.stored) {
+                    (_, None) => self.set_message("The session is not in the store"),
+                    (Some(source), Some(stored)) if source == stored => self.set_message("The session is already in Claude Code's projects"),
+                    (Some(_), Some(_)) => {
+                        let id = session.listed.id.clone();
+                        self.input = Some(Input::ConfirmRestore(id));
+                    }
+                    (None, Some(_)) => return Some(Action::Restore { id: session.listed.id.clone(), force: false }),
+                }
+            }
+            KeyCode::Char('e') => {
+                let session = self.selected()?;
+                self.input = Some(Input::Export(format!("{}.tar.zst", session.listed.id)));
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn handle_input(&mut self, input: Input, key: KeyEvent) -> Option<Action> {
+        match input {
+            Input::Search => {
+                match key.code {
+                    KeyCode::Esc => self.query.clear(),
+                    KeyCode::Enter => {
+                        self.apply_filters();
+                        return None;
+                    }
+                    KeyCode::Backspace => {
+                        self.query.pop();
+                    }
+                    KeyCode::Char(c) => self.query.push(c),
+                    _ => {}
+                }
+                self.apply_filters();
+                if key.code != KeyCode::Esc {
+                    self.input = Some(Input::Search);
+                }
+            }
+            Input::Export(mut path) => match key.code {
+                KeyCode::Esc => {}
+                KeyCode::Enter if !path.trim().is_empty() => {
+                    let session = self.selected()?;
+                    return Some(Action::Export {
+                        id: session.listed.id.clone(),
+                        from_store: session.source.is_none(),
+                        path: PathBuf::from(path.trim()),
+                    });
+                }
+                KeyCode::Backspace => {
+                    path.pop();
+                    self.input = Some(Input::Export(path));
+                }
+                KeyCode::Char(c) => {
+                    path.push(c);
+                    self.input = Some(Input::Export(path));
+                }
+                _ => self.input = Some(Input::Export(path)),
+            },
+            Input::ConfirmRestore(id) => {
+                if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                    return Some(Action::Restore { id, force: true });
+                }
+                self.set_message("Restore cancelled");
+            }
+        }
+        None
+    }
+}
+
+/// What the search box looks in: the id, the branch and the transcript.
+fn search_text(session: &TuiSession) -> String {
+    let mut text = session.listed.id.clone();
+    text.push('\n');
+    text.push_str(session.listed.entry.git_branch.as_deref().unwrap_or_default());
+    text.push('\n');
+    text.push_str(&render::render_transcript(session.content()));
+    text.to_lowercase()
+}
+
+/// For each record of `records`, whether `other` lacks it. Records are
+/// matched by `uuid`, records without one by what they show.
+fn only_in(records: &[RenderedRecord], other: &[RenderedRecord]) -> Vec<bool> {
+    let uuids: BTreeSet<&str> = other.iter().filter_map(|record| record.uuid.as_deref()).collect();
+    records.iter()
+        .map(|record| match &record.uuid {
+            Some(uuid) => !uuids.contains(uuid.as_str()),
+            None => !other.iter().any(|theirs| theirs.uuid.is_none() && theirs.parts == record.parts),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(uuid: &str, session_id: &str, timestamp: &str, prompt: &str) -> String {
+        format!(
+            r#"{{"type":"user","uuid":"{}","sessionId":"{}","timestamp":"{}","message":{{"role":"user","content":"{}"}}}}"#,
+            uuid, session_id, timestamp, prompt
+        )
+    }
+
+    pub(super) fn sessions() -> Vec<TuiSession> {
+        let first = format!("{}\n", user("u1", "aaa", "2025-06-01T10:00:00Z", "Fix the build"));
+        let more = format!("{}{}\n", first, user("u2", "aaa", "2025-06-01T10:05:00Z", "Thanks"));
+        let source = Snapshot::from([
+            (PathBuf::from("p/aaa.jsonl"), more.into_bytes()),
+            (PathBuf::from("p/bbb.jsonl"), format!("{}\n", user("u3", "bbb", "2025-06-02T10:00:00Z", "Add a test")).into_bytes()),
+            (PathBuf::from("p/agent-1.jsonl"), concat!(
+                r#"{"type":"user","isSidechain":true,"sessionId":"aaa","message":{"role":"user","content":"Search"}}"#,
+                "\n",
+            ).as_bytes().to_vec()),
+        ]);
+        let stored = Snapshot::from([
+            (PathBuf::from("p/aaa.jsonl"), first.into_bytes()),
+            (PathBuf::from("p/ccc.jsonl"), format!("{}\n", user("u4", "ccc", "2025-05-01T10:00:00Z", "Old work")).into_bytes()),
+        ]);
+        collect_sessions(&source, &stored, &BTreeMap::new(), &SessionFilter::default(), &["ccc".to_string()], &["bb".to_string()], &RedactionRules::default())
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_collect_sessions() {
+        let sessions = sessions();
+        let summary: Vec<_> = sessions.iter()
+            .map(|session| (session.listed.id.as_str(), session.state, session.pinned, session.private, session.is_pending()))
+            .collect();
+        assert_eq!(summary, vec![
+            ("bbb", Some(SyncState::New), false, true, false),
+            ("aaa", Some(SyncState::Modified), false, false, true),
+            ("ccc", Some(SyncState::Deleted), true, false, false),
+        ]);
+    }
+
+    #[test]
+    fn test_filters_and_actions() {
+        let mut app = App::new(sessions());
+        assert_eq!(app.selected().map(|session| session.listed.id.as_str()), Some("bbb"));
+
+        app.handle_key(key(KeyCode::Char('f')));
+        assert_eq!(app.visible, vec![1]);
+        assert_eq!(app.handle_key(key(KeyCode::Char('s'))), Some(Action::Sync { id: "aaa".to_string() }));
+        app.handle_key(key(KeyCode::Char('f')));
+        assert_eq!(app.visible, vec![1, 2]);
+
+        // Restoring over a newer copy in the projects asks first
+        assert_eq!(app.handle_key(key(KeyCode::Char('r'))), None);
+        assert_eq!(app.handle_key(key(KeyCode::Char('y'))), Some(Action::Restore { id: "aaa".to_string(), force: true }));
+        app.handle_key(key(KeyCode::Char('j')));
+        assert_eq!(app.handle_key(key(KeyCode::Char('r'))), Some(Action::Restore { id: "ccc".to_string(), force: false }));
+        assert_eq!(app.handle_key(key(KeyCode::Char('p'))), Some(Action::SetPinned { id: "ccc".to_string(), pinned: false }));
+
+        app.handle_key(key(KeyCode::Char('e')));
+        app.handle_key(key(KeyCode::Backspace));
+        assert_eq!(app.handle_key(key(KeyCode::Enter)), Some(Action::Export {
+            id: "ccc".to_string(),
+            from_store: true,
+            path: PathBuf::from("ccc.tar.zs"),
+        }));
+
+        app.state_filter = StateFilter::Private;
+        app.handle_key(key(KeyCode::Char('f')));
+        assert_eq!(app.visible, vec![0, 1, 2]);
+        app.handle_key(key(KeyCode::Char('/')));
+        for c in "THANKS".chars() {
+            app.handle_key(key(KeyCode::Char(c)));
+        }
+        assert_eq!(app.visible, vec![1]);
+        app.handle_key(key(KeyCode::Enter));
+        app.handle_key(key(KeyCode::Esc));
+        assert_eq!(app.visible, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_only_in() {
+        let sessions = sessions();
+        let source = render::render_records(sessions[1].source.as_deref().unwrap());
+        let stored = render::render_records(sessions[1].stored.as_deref().unwrap());
+        assert_eq!(only_in(&source, &stored), vec![false, true]);
+        assert_eq!(only_in(&stored, &source), vec![false]);
+    }
+}
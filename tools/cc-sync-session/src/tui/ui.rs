@@ -0,0 +1,349 @@
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, Paragraph};
+use ratatui::Frame;
+
+use crate::render::{self, Part, RenderOptions, RenderedRecord};
+
+use super::{only_in, App, Focus, Input, Jump, TuiSession};
+
+const HELP: &str = "j/k move  tab switch pane  enter open/fold  t tools  c compare  / search  f filter  \
+                    p pin  x private  s sync  r restore  e export  R reload  q quit";
+
+/// Lines kept above a search match scrolled to.
+const MATCH_CONTEXT: usize = 2;
+
+/// A line of a transcript pane.
+#[derive(Debug, Clone, PartialEq)]
+struct PaneLine {
+    text: String,
+    kind: LineKind,
+    /// Index of the record the line belongs to.
+    record: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Heading,
+    Text,
+    /// A line of the tool call or result with this index in the conversation.
+    Tool(usize),
+    Blank,
+}
+
+/// Lays out `records` in lines at most `width` characters wide. Tool calls
+/// and results are shown in full if `expanded` says so for their index.
+fn pane_lines(records: &[RenderedRecord], expanded: &dyn Fn(usize) -> bool, width: usize) -> Vec<PaneLine> {
+    let mut lines = Vec::new();
+    let mut tool = 0;
+
+    for (record_index, record) in records.iter().enumerate() {
+        for part in &record.parts {
+            let mut text = String::new();
+            let kind = match part {
+                Part::Heading(_) => LineKind::Heading,
+                Part::Text(_) => LineKind::Text,
+                Part::ToolUse { .. } | Part::ToolResult(_) => {
+                    tool += 1;
+                    LineKind::Tool(tool - 1)
+                }
+            };
+            let options = RenderOptions { expand_tools: matches!(kind, LineKind::Tool(index) if expanded(index)) };
+            render::render_part(part, &options, &mut text);
+            for line in text.lines() {
+                for chunk in wrap(line, width) {
+                    lines.push(PaneLine { text: chunk, kind, record: record_index });
+                }
+            }
+        }
+        lines.push(PaneLine { text: String::new(), kind: LineKind::Blank, record: record_index });
+    }
+
+    lines
+}
+
+/// Splits `line` into pieces of at most `width` characters.
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if chars.is_empty() || width == 0 {
+        return vec![line.to_string()];
+    }
+    chars.chunks(width).map(|chunk| chunk.iter().collect()).collect()
+}
+
+/// `text` in `style`, with the case-insensitive matches of `query` marked.
+fn highlight(text: &str, query: &str, style: Style) -> Line<'static> {
+    let lower = text.to_lowercase();
+    // Lowercasing can change byte offsets outside ASCII; such lines are not marked
+    if query.is_empty() || lower.len() != text.len() {
+        return Line::from(Span::styled(text.to_string(), style));
+    }
+
+    let query = query.to_lowercase();
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while let Some(found) = lower[start..].find(&query) {
+        let found = start + found;
+        spans.push(Span::styled(text[start..found].to_string(), style));
+        spans.push(Span::styled(text[found..found + query.len()].to_string(), style.bg(Color::Yellow).fg(Color::Black)));
+        start = found + query.len();
+    }
+    spans.push(Span::styled(text[start..].to_string(), style));
+    Line::from(spans)
+}
+
+fn line_style(line: &PaneLine) -> Style {
+    match line.kind {
+        LineKind::Heading if line.text.starts_with("== User") => Style::new().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        LineKind::Heading if line.text.starts_with("== Assistant") => Style::new().fg(Color::Green).add_modifier(Modifier::BOLD),
+        LineKind::Heading => Style::new().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+        LineKind::Tool(_) => Style::new().fg(Color::DarkGray),
+        LineKind::Text | LineKind::Blank => Style::new(),
+    }
+}
+
+fn border_style(focused: bool) -> Style {
+    if focused {
+        Style::new().fg(Color::Cyan)
+    } else {
+        Style::new()
+    }
+}
+
+/// Draws the TUI, scrolling the conversation to where the last key asked.
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [main, footer] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    let [list_area, conversation_area] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+
+    draw_sessions(frame, app, list_area);
+    if app.compare {
+        draw_compare(frame, app, conversation_area);
+    } else {
+        draw_conversation(frame, app, conversation_area);
+    }
+    draw_footer(frame, app, footer);
+}
+
+fn session_row(session: &TuiSession) -> ListItem<'static> {
+    let state = if session.private {
+        Span::styled("x", Style::new().fg(Color::Red))
+    } else {
+        match session.state {
+            Some(state) if state.is_pending() => Span::styled(state.letter().to_string(), Style::new().fg(Color::Yellow)),
+            Some(state) => Span::styled(state.letter().to_string(), Style::new().fg(Color::DarkGray)),
+            None => Span::raw(" "),
+        }
+    };
+    let pin = if session.pinned { "*" } else { " " };
+    let entry = &session.listed.entry;
+    let started = entry.started
+        .map(|started| started.with_timezone(&chrono::Local).format("%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| " ".repeat(11));
+    let id: String = session.listed.id.chars().take(8).collect();
+
+    ListItem::new(Line::from(vec![
+        state,
+        Span::styled(pin, Style::new().fg(Color::Yellow)),
+        Span::raw(format!(" {:<8} ", id)),
+        Span::styled(started, Style::new().fg(Color::DarkGray)),
+        Span::styled(format!(" {}", entry.git_branch.as_deref().unwrap_or("-")), Style::new().fg(Color::Blue)),
+        Span::raw(format!(" {}", entry.first_prompt.as_deref().unwrap_or_default().replace('\n', " "))),
+    ]))
+}
+
+fn draw_sessions(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app.visible.iter().map(|&index| session_row(&app.sessions[index])).collect();
+    let mut title = format!(" Sessions {}/{} · {} ", app.visible.len(), app.sessions.len(), app.state_filter.label());
+    if !app.query.is_empty() {
+        title.push_str(&format!("· /{} ", app.query));
+    }
+
+    let list = List::new(items)
+        .block(Block::bordered().title(title).border_style(border_style(app.focus == Focus::Sessions)))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, &mut app.list_state);
+}
+
+fn draw_conversation(frame: &mut Frame, app: &mut App, area: Rect) {
+    let block = Block::bordered().border_style(border_style(app.focus == Focus::Conversation));
+    let Some(session) = app.selected() else {
+        frame.render_widget(Paragraph::new("No sessions").block(block.title(" Conversation ")), area);
+        return;
+    };
+    let title = format!(
+        " {} · {} ",
+        session.path.display(),
+        if session.source.is_some() { "projects" } else { "store" }
+    );
+
+    let width = area.width.saturating_sub(2) as usize;
+    let height = area.height.saturating_sub(2) as usize;
+    let expanded: Vec<bool> = (0..app.tool_count()).map(|tool| app.is_expanded(tool)).collect();
+    let records = app.conversation().map(|conversation| conversation.records.as_slice()).unwrap_or_default();
+    let lines = pane_lines(records, &|tool| expanded.get(tool).copied().unwrap_or(false), width);
+
+    let query = app.query.to_lowercase();
+    match app.jump.take() {
+        Some(Jump::ToolCursor) => {
+            if let Some(position) = lines.iter().position(|line| Some(line.kind) == app.tool_cursor.map(LineKind::Tool)) {
+                let last = lines.iter().rposition(|line| line.kind == lines[position].kind).unwrap_or(position);
+                if position < app.scroll {
+                    app.scroll = position;
+                } else if last >= app.scroll + height {
+                    app.scroll = (last + 1).saturating_sub(height).min(position);
+                }
+            }
+        }
+        Some(jump) if !query.is_empty() => {
+            let matches: Vec<usize> = lines.iter().enumerate()
+                .filter(|(_, line)| line.text.to_lowercase().contains(&query))
+                .map(|(index, _)| index)
+                .collect();
+            let current = app.scroll + MATCH_CONTEXT;
+            let found = match jump {
+                Jump::NextMatch => matches.iter().find(|&&index| index > current).or(matches.first()),
+                _ => matches.iter().rev().find(|&&index| index < current).or(matches.last()),
+            };
+            match found {
+                Some(&index) => app.scroll = index.saturating_sub(MATCH_CONTEXT),
+                None => app.set_message(format!("No match for '{}' in this session", app.query)),
+            }
+        }
+        Some(_) => app.set_message("Search with / first"),
+        None => {}
+    }
+    app.scroll = app.scroll.min(lines.len().saturating_sub(height));
+
+    let cursor = app.tool_cursor.filter(|_| app.focus == Focus::Conversation).map(LineKind::Tool);
+    let text: Vec<Line> = lines.iter()
+        .skip(app.scroll)
+        .take(height)
+        .map(|line| {
+            let mut style = line_style(line);
+            if Some(line.kind) == cursor {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            highlight(&line.text, &query, style)
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(text).block(block.title(title)), area);
+}
+
+fn draw_compare(frame: &mut Frame, app: &mut App, area: Rect) {
+    let [left, right] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(area);
+    let style = border_style(app.focus == Focus::Conversation);
+    let width = left.width.saturating_sub(4) as usize;
+    let height = left.height.saturating_sub(2) as usize;
+
+    let expand_all = app.expand_all;
+    let scroll = app.scroll;
+    let query = app.query.to_lowercase();
+    let Some(conversation) = app.conversation() else {
+        frame.render_widget(Paragraph::new("No sessions").block(Block::bordered().border_style(style)), area);
+        return;
+    };
+    let sides = [
+        (" Pending (projects) ", &conversation.source, &conversation.stored, Color::Green, "not in the projects", left),
+        (" Stored ", &conversation.stored, &conversation.source, Color::Yellow, "not in the store", right),
+    ];
+
+    let mut longest = 0;
+    let mut panes = Vec::new();
+    for (title, records, other, color, missing, area) in sides {
+        let block = Block::bordered().title(title).border_style(style);
+        if records.is_empty() {
+            panes.push((Paragraph::new(format!("The session is {}", missing)).block(block), area));
+            continue;
+        }
+        // Records the other side lacks are marked with '+'
+        let unique = only_in(records, other);
+        let lines = pane_lines(records, &|_| expand_all, width);
+        longest = longest.max(lines.len());
+        let text: Vec<Line> = lines.iter()
+            .skip(scroll)
+            .take(height)
+            .map(|line| {
+                let (gutter, style) = if unique[line.record] && line.kind != LineKind::Blank {
+                    ("+ ", line_style(line).fg(color))
+                } else {
+                    ("  ", line_style(line))
+                };
+                let mut highlighted = highlight(&line.text, &query, style);
+                highlighted.spans.insert(0, Span::styled(gutter, Style::new().fg(color)));
+                highlighted
+            })
+            .collect();
+        panes.push((Paragraph::new(text).block(block), area));
+    }
+
+    for (pane, area) in panes {
+        frame.render_widget(pane, area);
+    }
+    app.scroll = app.scroll.min(longest.saturating_sub(height));
+}
+
+fn draw_footer(frame: &mut Frame, app: &App, area: Rect) {
+    let line = match &app.input {
+        Some(Input::Search) => Line::from(format!("/{}", app.query)),
+        Some(Input::Export(path)) => Line::from(format!("Export to: {}", path)),
+        Some(Input::ConfirmRestore(_)) => Line::from(Span::styled(
+            "Replace the session in Claude Code's projects with the stored one? (y/n)",
+            Style::new().fg(Color::Yellow),
+        )),
+        None => match &app.message {
+            Some(message) => Line::from(message.as_str()),
+            None => Line::from(Span::styled(HELP, Style::new().fg(Color::DarkGray))),
+        },
+    };
+    frame.render_widget(Paragraph::new(line), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    #[test]
+    fn test_pane_lines() {
+        let content = [
+            r#"{"type":"user","uuid":"u1","message":{"role":"user","content":"Run the tests"}}"#,
+            r#"{"type":"assistant","uuid":"a1","message":{"role":"assistant","content":[{"type":"tool_use","id":"t1","name":"Bash","input":{"command":"cargo test"}}]}}"#,
+        ].join("\n");
+        let records = render::render_records(&content);
+
+        let folded = pane_lines(&records, &|_| false, 10);
+        let texts: Vec<_> = folded.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, vec!["== User", "Run the te", "sts", "", "== Assista", "nt", "  -> Bash:", " cargo tes", "t", ""]);
+        assert_eq!(folded[6].kind, LineKind::Tool(0));
+
+        let expanded = pane_lines(&records, &|tool| tool == 0, 80);
+        let texts: Vec<_> = expanded.iter().skip(4).map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, vec!["  -> Bash", "     {", "       \"command\": \"cargo test\"", "     }", ""]);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let line = highlight("Run the Tests", "test", Style::new());
+        let spans: Vec<_> = line.spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(spans, vec!["Run the ", "Test", "s"]);
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut app = App::new(super::super::tests::sessions());
+        let mut terminal = Terminal::new(TestBackend::new(120, 20)).unwrap();
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("Sessions 3/3 · all"));
+        assert!(screen.contains("Add a test"));
+        assert!(screen.contains("p/bbb.jsonl · projects"));
+
+        app.compare = true;
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("Pending (projects)"));
+        assert!(screen.contains("The session is not in the store"));
+    }
+}
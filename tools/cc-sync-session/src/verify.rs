@@ -0,0 +1,449 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use git2::Repository;
+use serde::Serialize;
+
+use crate::compression::{decode, logical_path};
+use crate::dedup::{self, has_references};
+use crate::encryption::Identities;
+use crate::filesystem::FileSystem;
+use crate::gitattributes;
+use crate::index::{INDEX_JSON_FILE, INDEX_MARKDOWN_FILE};
+use crate::layout::{Layout, StoreDescriptor, GITKEEP_FILE, STORE_DESCRIPTOR_FILE};
+use crate::namespace::list_namespaces;
+use crate::manifest::{manifest_key, Manifest, ManifestEntry, MANIFEST_FILE};
+use crate::session::SessionRecord;
+use crate::store::Snapshot;
+use crate::sync::is_session_file;
+
+/// Files at the store root that belong to the tool rather than to sessions.
+pub const STORE_METADATA_FILES: &[&str] = &[
+    GITKEEP_FILE,
+    STORE_DESCRIPTOR_FILE,
+    MANIFEST_FILE,
+    INDEX_JSON_FILE,
+    INDEX_MARKDOWN_FILE,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    /// A line of a session file is not valid JSON.
+    InvalidRecord,
+    /// The last line is cut off, usually because the file was copied while
+    /// Claude Code was writing it.
+    TruncatedLastLine,
+    /// The file does not match the hash recorded in the manifest.
+    HashMismatch,
+    /// The manifest lists a file that is not in the store.
+    MissingFile,
+    /// The store has a file the manifest does not list.
+    UnexpectedFile,
+    /// The store has no manifest to check hashes against.
+    NoManifest,
+    /// The manifest itself cannot be read.
+    InvalidManifest,
+    /// A deduplicated session refers to records that are not in the store.
+    BrokenReference,
+    /// `.gitattributes` lacks an expected line or a driver is not configured.
+    Gitattributes,
+    /// The store descriptor is missing (a legacy store) or cannot be read.
+    Descriptor,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub check: Check,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, check: Check, path: Option<&Path>, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            check,
+            path: path.map(Path::to_path_buf),
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct VerifyReport {
+    pub files_checked: usize,
+    pub findings: Vec<Finding>,
+}
+
+impl VerifyReport {
+    pub fn errors(&self) -> usize {
+        self.findings.iter().filter(|finding| finding.severity == Severity::Error).count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.findings.iter().filter(|finding| finding.severity == Severity::Warning).count()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.errors() == 0
+    }
+}
+
+/// Checks that every line of a session file is a JSON record.
+pub fn check_jsonl(relative_path: &Path, content: &[u8]) -> Vec<Finding> {
+    let content = String::from_utf8_lossy(content);
+    let lines: Vec<&str> = content.lines().collect();
+    let mut findings = Vec::new();
+
+    for (index, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Err(e) = SessionRecord::parse(line) {
+            let is_last = index + 1 == lines.len();
+            if is_last && !content.ends_with('\n') {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    Check::TruncatedLastLine,
+                    Some(relative_path),
+                    format!("Last line ({}) is truncated; sync again once the session is idle", index + 1),
+                ));
+            } else {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    Check::InvalidRecord,
+                    Some(relative_path),
+                    format!("Line {} is not a valid record: {}", index + 1, e),
+                ));
+            }
+        }
+    }
+
+    findings
+}
+
+/// Compares the store files with the manifest.
+pub fn check_manifest(manifest: &Manifest, files: &Snapshot) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for (path, content) in files {
+        match manifest.get(path) {
+            Some(entry) if *entry == ManifestEntry::for_content(content) => {}
+            Some(_) => findings.push(Finding::new(
+                Severity::Error,
+                Check::HashMismatch,
+                Some(path),
+                "Content does not match the manifest",
+            )),
+            None => findings.push(Finding::new(
+                Severity::Warning,
+                Check::UnexpectedFile,
+                Some(path),
+                "File is not listed in the manifest",
+            )),
+        }
+    }
+
+    let present: BTreeSet<String> = files.keys().map(|path| manifest_key(path)).collect();
+    for key in manifest.files.keys().filter(|key| !present.contains(*key)) {
+        findings.push(Finding::new(
+            Severity::Error,
+            Check::MissingFile,
+            Some(Path::new(key)),
+            "File listed in the manifest is missing from the store",
+        ));
+    }
+
+    findings
+}
+
+/// Checks that references of deduplicated sessions resolve.
+pub fn check_references(sessions: &Snapshot) -> Vec<Finding> {
+    let read_session = |path: &Path| {
+        sessions.get(path).map(|content| String::from_utf8_lossy(content).into_owned())
+    };
+
+    sessions.iter()
+        .filter_map(|(path, content)| {
+            let content = String::from_utf8_lossy(content);
+            if !has_references(&content) {
+                return None;
+            }
+            dedup::expand(&content, &read_session)
+                .err()
+                .map(|e| Finding::new(Severity::Error, Check::BrokenReference, Some(path), e.to_string()))
+        })
+        .collect()
+}
+
+/// Checks that `.gitattributes` routes the store through Git LFS.
+pub fn check_gitattributes(content: Option<&str>) -> Vec<Finding> {
+    let content = content.unwrap_or_default();
+    if gitattributes::contains_line(content, gitattributes::LFS_LINE) {
+        return Vec::new();
+    }
+
+    vec![Finding::new(
+        Severity::Warning,
+        Check::Gitattributes,
+        Some(Path::new(".gitattributes")),
+        format!("Missing '{}'; run 'cc-sync-session init'", gitattributes::LFS_LINE),
+    )]
+}
+
+/// Checks that drivers named in `.gitattributes` are configured in git.
+pub fn check_drivers(repo: &Repository, gitattributes_content: &str) -> Vec<Finding> {
+    let config = match repo.config() {
+        Ok(config) => config,
+        Err(_) => return Vec::new(),
+    };
+    let drivers = [
+        (gitattributes::DIFF_DRIVER_LINE, format!("diff.{}.textconv", gitattributes::DIFF_DRIVER), "--diff-driver"),
+        (gitattributes::MERGE_DRIVER_LINE, format!("merge.{}.driver", gitattributes::MERGE_DRIVER), "--merge-driver"),
+    ];
+
+    drivers.into_iter()
+        .filter(|(line, key, _)| gitattributes::contains_line(gitattributes_content, line) && config.get_string(key).is_err())
+        .map(|(_, key, flag)| Finding::new(
+            Severity::Warning,
+            Check::Gitattributes,
+            Some(Path::new(".gitattributes")),
+            format!("Driver is used in .gitattributes but {} is not set; run 'cc-sync-session init {}'", key, flag),
+        ))
+        .collect()
+}
+
+/// Runs every store check on the working tree of `repo_dir`.
+///
+/// The namespaces of a namespaced store are checked as stores of their own,
+/// with paths reported relative to the store root. Git configuration is not
+/// looked at; see [`check_drivers`].
+pub fn verify_store<FS: FileSystem>(fs: &FS, repo_dir: &Path, identities: &Identities) -> VerifyReport {
+    let store_dir = crate::store::store_dir(repo_dir);
+    let mut report = VerifyReport::default();
+
+    let layout = match StoreDescriptor::load(fs, &store_dir) {
+        Ok(Some(descriptor)) => descriptor.layout,
+        Ok(None) => {
+            report.findings.push(Finding::new(
+                Severity::Warning,
+                Check::Descriptor,
+                None,
+                format!("No {} in the store; run 'cc-sync-session migrate' to version it", STORE_DESCRIPTOR_FILE),
+            ));
+            Layout::Encoded
+        }
+        Err(e) => {
+            report.findings.push(Finding::new(
+                Severity::Error,
+                Check::Descriptor,
+                Some(Path::new(STORE_DESCRIPTOR_FILE)),
+                e.to_string(),
+            ));
+            Layout::Encoded
+        }
+    };
+
+    match layout {
+        Layout::Encoded => {
+            let files = verify_files(fs, &store_dir, identities);
+            report.files_checked += files.files_checked;
+            report.findings.extend(files.findings);
+        }
+        Layout::Namespaced => match list_namespaces(fs, &store_dir) {
+            Ok(namespaces) => {
+                for namespace in namespaces {
+                    let files = verify_files(fs, &store_dir.join(namespace.path()), identities);
+                    report.files_checked += files.files_checked;
+                    report.findings.extend(files.findings.into_iter().map(|mut finding| {
+                        finding.path = Some(namespace.path().join(finding.path.unwrap_or_default()));
+                        finding
+                    }));
+                }
+            }
+            Err(e) => report.findings.push(Finding::new(
+                Severity::Error,
+                Check::MissingFile,
+                Some(&store_dir),
+                format!("Cannot read the store: {}", e),
+            )),
+        },
+    }
+
+    let gitattributes_content = fs.read_file(&repo_dir.join(".gitattributes"))
+        .ok()
+        .map(|content| String::from_utf8_lossy(&content).into_owned());
+    report.findings.extend(check_gitattributes(gitattributes_content.as_deref()));
+
+    report
+}
+
+/// Checks the files, sessions and manifest of a store of the encoded layout.
+fn verify_files<FS: FileSystem>(fs: &FS, store_dir: &Path, identities: &Identities) -> VerifyReport {
+    let mut report = VerifyReport::default();
+
+    let entries = match fs.walk_files(store_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            report.findings.push(Finding::new(
+                Severity::Error,
+                Check::MissingFile,
+                Some(store_dir),
+                format!("Cannot read the store: {}", e),
+            ));
+            return report;
+        }
+    };
+
+    let mut files = Snapshot::new();
+    for entry in entries {
+        let relative_path = entry.path.strip_prefix(store_dir).unwrap_or(&entry.path).to_path_buf();
+        if STORE_METADATA_FILES.iter().any(|name| relative_path == Path::new(name)) {
+            continue;
+        }
+        match fs.read_file(&entry.path) {
+            Ok(content) => {
+                files.insert(relative_path, content);
+            }
+            Err(e) => report.findings.push(Finding::new(
+                Severity::Error,
+                Check::InvalidRecord,
+                Some(&relative_path),
+                format!("Cannot read file: {}", e),
+            )),
+        }
+    }
+    report.files_checked = files.len();
+
+    let mut sessions = Snapshot::new();
+    for (path, content) in files.iter().filter(|(path, _)| is_session_file(path)) {
+        match decode(content.clone(), identities) {
+            Ok(content) => {
+                report.findings.extend(check_jsonl(path, &content));
+                sessions.insert(logical_path(path), content);
+            }
+            Err(e) => report.findings.push(Finding::new(
+                Severity::Error,
+                Check::InvalidRecord,
+                Some(path),
+                format!("Cannot decompress file: {}", e),
+            )),
+        }
+    }
+    report.findings.extend(check_references(&sessions));
+
+    match Manifest::load(fs, store_dir) {
+        Ok(Some(manifest)) => report.findings.extend(check_manifest(&manifest, &files)),
+        Ok(None) => report.findings.push(Finding::new(
+            Severity::Warning,
+            Check::NoManifest,
+            None,
+            format!("No {} in the store; it is written by the next sync", MANIFEST_FILE),
+        )),
+        Err(e) => report.findings.push(Finding::new(
+            Severity::Error,
+            Check::InvalidManifest,
+            Some(Path::new(MANIFEST_FILE)),
+            e.to_string(),
+        )),
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockFileSystem;
+    use std::time::SystemTime;
+
+    const RECORD: &str = r#"{"type":"user","uuid":"u1","message":{"role":"user","content":"hi"}}"#;
+
+    #[test]
+    fn test_check_jsonl() {
+        let path = Path::new("p/s1.jsonl");
+
+        assert!(check_jsonl(path, format!("{}\n", RECORD).as_bytes()).is_empty());
+
+        let truncated = check_jsonl(path, format!("{}\n{{\"type\":\"us", RECORD).as_bytes());
+        assert_eq!(truncated.len(), 1);
+        assert_eq!(truncated[0].check, Check::TruncatedLastLine);
+
+        let invalid = check_jsonl(path, format!("oops\n{}\n", RECORD).as_bytes());
+        assert_eq!(invalid[0].check, Check::InvalidRecord);
+    }
+
+    #[test]
+    fn test_verify_store() {
+        let fs = MockFileSystem::new();
+        let repo_dir = Path::new("/repo");
+        let store_dir = crate::store::store_dir(repo_dir);
+        fs.add_directory(&store_dir);
+        fs.add_directory(store_dir.join("p"));
+
+        let good = format!("{}\n", RECORD).into_bytes();
+        crate::layout::init_store(&fs, &store_dir, crate::config::StoreMode::Worktree).unwrap();
+        fs.add_file(store_dir.join("p/good.jsonl"), good.clone(), SystemTime::now());
+        fs.add_file(store_dir.join("p/changed.jsonl"), good.clone(), SystemTime::now());
+        fs.add_file(store_dir.join("p/extra.jsonl"), good.clone(), SystemTime::now());
+        fs.add_file(repo_dir.join(".gitattributes"), format!("{}\n", gitattributes::LFS_LINE).into_bytes(), SystemTime::now());
+
+        let mut manifest = Manifest::default();
+        manifest.record(Path::new("p/good.jsonl"), &good);
+        manifest.record(Path::new("p/changed.jsonl"), b"something else");
+        manifest.record(Path::new("p/gone.jsonl"), &good);
+        manifest.save(&fs, &store_dir).unwrap();
+
+        let report = verify_store(&fs, repo_dir, &Identities::default());
+        let checks: Vec<_> = report.findings.iter()
+            .map(|finding| (finding.check, finding.path.clone().unwrap()))
+            .collect();
+
+        assert_eq!(report.files_checked, 3);
+        assert_eq!(checks, vec![
+            (Check::HashMismatch, PathBuf::from("p/changed.jsonl")),
+            (Check::UnexpectedFile, PathBuf::from("p/extra.jsonl")),
+            (Check::MissingFile, PathBuf::from("p/gone.jsonl")),
+        ]);
+        assert_eq!(report.errors(), 2);
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_verify_namespaced_store() {
+        let fs = MockFileSystem::new();
+        let repo_dir = Path::new("/repo");
+        let store_dir = crate::store::store_dir(repo_dir);
+        for dir in ["", "alice", "alice/laptop", "alice/laptop/p", "bob", "bob/desktop", "bob/desktop/p"] {
+            fs.add_directory(store_dir.join(dir));
+        }
+        crate::layout::init_store_with_layout(&fs, &store_dir, crate::config::StoreMode::Worktree, Layout::Namespaced).unwrap();
+        fs.add_file(repo_dir.join(".gitattributes"), format!("{}\n", gitattributes::LFS_LINE).into_bytes(), SystemTime::now());
+
+        let good = format!("{}\n", RECORD).into_bytes();
+        let mut manifest = Manifest::default();
+        manifest.record(Path::new("p/s1.jsonl"), &good);
+        for namespace in ["alice/laptop", "bob/desktop"] {
+            fs.add_file(store_dir.join(namespace).join("p/s1.jsonl"), good.clone(), SystemTime::now());
+            manifest.save(&fs, &store_dir.join(namespace)).unwrap();
+        }
+        fs.add_file(store_dir.join("bob/desktop/p/s2.jsonl"), good.clone(), SystemTime::now());
+
+        let report = verify_store(&fs, repo_dir, &Identities::default());
+        assert_eq!(report.files_checked, 3);
+        let checks: Vec<_> = report.findings.iter()
+            .map(|finding| (finding.check, finding.path.clone().unwrap()))
+            .collect();
+        assert_eq!(checks, vec![(Check::UnexpectedFile, PathBuf::from("bob/desktop/p/s2.jsonl"))]);
+    }
+}
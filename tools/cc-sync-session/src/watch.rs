@@ -0,0 +1,162 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use notify::event::{EventKind, ModifyKind};
+use notify::Event;
+
+/// Collects changed paths into batches, so a burst of appends to a session
+/// is synced once.
+///
+/// A batch is ready when no change arrived for the quiet period, or when its
+/// first change is older than the maximum delay, so a session that is written
+/// to without pause is still synced now and then.
+#[derive(Debug, Clone)]
+pub struct Debouncer {
+    quiet: Duration,
+    max_delay: Duration,
+    pending: BTreeSet<PathBuf>,
+    first_change: Option<Instant>,
+    last_change: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn new(quiet: Duration, max_delay: Duration) -> Self {
+        Self {
+            quiet,
+            max_delay: max_delay.max(quiet),
+            pending: BTreeSet::new(),
+            first_change: None,
+            last_change: None,
+        }
+    }
+
+    pub fn add(&mut self, path: PathBuf, now: Instant) {
+        self.pending.insert(path);
+        self.first_change.get_or_insert(now);
+        self.last_change = Some(now);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// How long to wait for more changes before the batch is ready; `None`
+    /// when nothing is pending.
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        let (first, last) = (self.first_change?, self.last_change?);
+        let due = (last + self.quiet).min(first + self.max_delay);
+        Some(due.saturating_duration_since(now))
+    }
+
+    /// Takes the pending paths if the batch is ready.
+    pub fn take_ready(&mut self, now: Instant) -> Option<BTreeSet<PathBuf>> {
+        if self.timeout(now)? > Duration::ZERO {
+            return None;
+        }
+        Some(self.take())
+    }
+
+    /// Takes the pending paths whether the batch is ready or not.
+    pub fn take(&mut self) -> BTreeSet<PathBuf> {
+        self.first_change = None;
+        self.last_change = None;
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// Paths whose content an event may have changed. Reads, metadata changes
+/// and removals are left out: `sync` never removes from the store.
+pub fn changed_paths(event: &Event) -> &[PathBuf] {
+    match event.kind {
+        EventKind::Create(_) => &event.paths,
+        EventKind::Modify(ModifyKind::Metadata(_)) => &[],
+        // The last path of a rename is where the file is now
+        EventKind::Modify(ModifyKind::Name(_)) => event.paths.last().map(std::slice::from_ref).unwrap_or(&[]),
+        EventKind::Modify(_) => &event.paths,
+        _ => &[],
+    }
+}
+
+/// A systemd user unit running `watch` for `repo_dir` with the executable at `exe`.
+pub fn systemd_unit(exe: &Path, repo_dir: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Sync Claude Code sessions into {repo}\n\
+         \n\
+         [Service]\n\
+         Type=simple\n\
+         ExecStart={exe} watch --repo-dir {repo_arg}\n\
+         Restart=on-failure\n\
+         RestartSec=10\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        repo = repo_dir.display(),
+        exe = systemd_quote(&exe.to_string_lossy()),
+        repo_arg = systemd_quote(&repo_dir.to_string_lossy()),
+    )
+}
+
+/// Quotes a word of a systemd `ExecStart=` line if it needs it.
+fn systemd_quote(word: &str) -> String {
+    if !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || "\"'\\;$%".contains(c)) {
+        return word.to_string();
+    }
+    let escaped = word.replace('\\', "\\\\").replace('"', "\\\"").replace('$', "$$").replace('%', "%%");
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, MetadataKind, RenameMode};
+
+    #[test]
+    fn test_debouncer() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_secs(2), Duration::from_secs(10));
+        assert_eq!(debouncer.timeout(start), None);
+        assert_eq!(debouncer.take_ready(start), None);
+
+        // Appends keep coming every second: the batch waits for a quiet period...
+        for second in 0..5 {
+            debouncer.add(PathBuf::from("/p/s1.jsonl"), start + Duration::from_secs(second));
+        }
+        debouncer.add(PathBuf::from("/p/s2.jsonl"), start + Duration::from_secs(4));
+        assert_eq!(debouncer.timeout(start + Duration::from_secs(5)), Some(Duration::from_secs(1)));
+        assert_eq!(debouncer.take_ready(start + Duration::from_secs(5)), None);
+
+        let batch = debouncer.take_ready(start + Duration::from_secs(6)).unwrap();
+        assert_eq!(batch.len(), 2);
+        assert!(debouncer.is_empty());
+
+        // ...but not longer than the maximum delay
+        for second in 0..12 {
+            debouncer.add(PathBuf::from("/p/s1.jsonl"), start + Duration::from_secs(100 + second));
+        }
+        assert!(debouncer.take_ready(start + Duration::from_secs(110)).is_some());
+    }
+
+    #[test]
+    fn test_changed_paths() {
+        let path = PathBuf::from("/p/s1.jsonl");
+        let event = |kind| Event::new(kind).add_path(path.clone());
+        assert_eq!(changed_paths(&event(EventKind::Create(CreateKind::File))), std::slice::from_ref(&path));
+        assert_eq!(changed_paths(&event(EventKind::Modify(ModifyKind::Data(DataChange::Content)))), std::slice::from_ref(&path));
+        assert!(changed_paths(&event(EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)))).is_empty());
+        assert!(changed_paths(&event(EventKind::Remove(notify::event::RemoveKind::File))).is_empty());
+
+        let rename = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from("/p/.tmp"))
+            .add_path(path.clone());
+        assert_eq!(changed_paths(&rename), [path]);
+    }
+
+    #[test]
+    fn test_systemd_unit() {
+        let unit = systemd_unit(Path::new("/usr/bin/cc-sync-session"), Path::new("/home/me/my repo"));
+        assert!(unit.contains("ExecStart=/usr/bin/cc-sync-session watch --repo-dir \"/home/me/my repo\"\n"));
+        assert!(unit.contains("WantedBy=default.target"));
+    }
+}
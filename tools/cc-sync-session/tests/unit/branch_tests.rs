@@ -0,0 +1,200 @@
+use cc_sync_session::branch::{BranchCheckout, BranchStore, MergeResult};
+use cc_sync_session::encryption::Identities;
+use cc_sync_session::index::RedactionRules;
+use git2::{Repository, Signature};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const SESSION: &str = "-repo/session.jsonl";
+
+fn record(uuid: &str, parent: Option<&str>, text: &str) -> String {
+    let parent = parent.map(|parent| format!("\"{}\"", parent)).unwrap_or_else(|| "null".to_string());
+    format!(
+        r#"{{"type":"user","uuid":"{}","parentUuid":{},"timestamp":"2025-06-25T10:00:00Z","message":{{"role":"user","content":"{}"}}}}"#,
+        uuid, parent, text
+    )
+}
+
+fn transcript(records: &[String]) -> String {
+    records.iter().map(|record| format!("{}\n", record)).collect()
+}
+
+/// A bare remote and `count` clones of it, each with an initial commit.
+fn setup(count: usize) -> (TempDir, PathBuf, Vec<PathBuf>) {
+    let dir = TempDir::new().unwrap();
+    let remote = dir.path().join("remote.git");
+    Repository::init_bare(&remote).unwrap();
+
+    let clones = (0..count)
+        .map(|i| {
+            let path = dir.path().join(format!("clone{}", i));
+            let repo = Repository::init(&path).unwrap();
+            repo.remote("origin", remote.to_str().unwrap()).unwrap();
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Test").unwrap();
+            config.set_str("user.email", "test@example.com").unwrap();
+
+            fs::write(path.join("README.md"), "code\n").unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("README.md")).unwrap();
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature = Signature::now("Test", "test@example.com").unwrap();
+            repo.commit(Some("HEAD"), &signature, &signature, "Initial commit", &tree, &[]).unwrap();
+            path
+        })
+        .collect();
+
+    (dir, remote, clones)
+}
+
+fn store(path: &Path) -> BranchStore {
+    BranchStore::new(Repository::open(path).unwrap(), "ccss/sessions")
+}
+
+fn write_session(checkout: &BranchCheckout, content: &str) {
+    let path = checkout.store_dir().join(SESSION);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+fn read_session(path: &Path) -> String {
+    let checkout = BranchCheckout::new(store(path)).unwrap();
+    fs::read_to_string(checkout.store_dir().join(SESSION)).unwrap()
+}
+
+#[test]
+fn test_commit_leaves_working_branch_alone() {
+    let (_dir, _remote, clones) = setup(1);
+    let repo = Repository::open(&clones[0]).unwrap();
+    let head = repo.head().unwrap().target().unwrap();
+
+    let checkout = BranchCheckout::new(store(&clones[0])).unwrap();
+    write_session(&checkout, &transcript(&[record("a", None, "hello")]));
+    let commit = checkout.commit("Sync 1 session files").unwrap().unwrap();
+
+    // Nothing changed, nothing to commit
+    assert_eq!(checkout.commit("Sync 0 session files").unwrap(), None);
+
+    assert_eq!(repo.head().unwrap().target().unwrap(), head);
+    assert_eq!(repo.head().unwrap().shorthand(), Some("master"));
+    assert!(repo.statuses(None).unwrap().is_empty());
+    assert!(!clones[0].join(".claude").exists());
+
+    let branch = repo.find_branch("ccss/sessions", git2::BranchType::Local).unwrap();
+    assert_eq!(branch.get().target(), Some(commit));
+    let stored = repo.find_commit(commit).unwrap();
+    assert_eq!(stored.parent_count(), 0);
+    assert!(stored.tree().unwrap().get_path(Path::new(".claude/ccss_sessions/-repo/session.jsonl")).is_ok());
+}
+
+#[test]
+fn test_check_out_keeps_commit_times() {
+    let (_dir, _remote, clones) = setup(1);
+    let checkout = BranchCheckout::new(store(&clones[0])).unwrap();
+    write_session(&checkout, &transcript(&[record("a", None, "hello")]));
+    checkout.commit("Sync").unwrap();
+
+    let checkout = BranchCheckout::new(store(&clones[0])).unwrap();
+    let modified = fs::metadata(checkout.store_dir().join(SESSION)).unwrap().modified().unwrap();
+    let commit_time = store(&clones[0]).head().unwrap().unwrap().time().seconds();
+    let seconds = modified.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64;
+    assert_eq!(seconds, commit_time + 1);
+}
+
+#[test]
+fn test_pull_fast_forwards_and_merges() {
+    let (_dir, _remote, clones) = setup(2);
+    let (alice, bob) = (&clones[0], &clones[1]);
+    let base = [record("a", None, "hello")];
+
+    // Alice starts the branch and pushes it
+    let checkout = BranchCheckout::new(store(alice)).unwrap();
+    write_session(&checkout, &transcript(&base));
+    checkout.commit("Sync").unwrap();
+    store(alice).push("origin").unwrap();
+
+    // Bob has no branch yet and takes Alice's
+    let bob_store = store(bob);
+    let theirs = bob_store.fetch("origin").unwrap().unwrap();
+    assert_eq!(bob_store.merge(theirs, None, &Identities::default(), &RedactionRules::default()).unwrap(), MergeResult::FastForward(theirs));
+    assert_eq!(bob_store.merge(theirs, None, &Identities::default(), &RedactionRules::default()).unwrap(), MergeResult::UpToDate);
+
+    // Both continue the session
+    let checkout = BranchCheckout::new(store(alice)).unwrap();
+    write_session(&checkout, &transcript(&[base[0].clone(), record("b", Some("a"), "from alice")]));
+    checkout.commit("Sync").unwrap();
+    store(alice).push("origin").unwrap();
+
+    let checkout = BranchCheckout::new(store(bob)).unwrap();
+    write_session(&checkout, &transcript(&[base[0].clone(), record("c", Some("a"), "from bob")]));
+    checkout.commit("Sync").unwrap();
+
+    // Bob is behind the remote and has to pull first
+    assert!(store(bob).push("origin").is_err());
+
+    let bob_store = store(bob);
+    let theirs = bob_store.fetch("origin").unwrap().unwrap();
+    let MergeResult::Merged { commit, merged_files, conflicting_files } = bob_store.merge(theirs, None, &Identities::default(), &RedactionRules::default()).unwrap() else {
+        panic!("expected a merge commit");
+    };
+    assert_eq!(merged_files, vec![PathBuf::from(SESSION)]);
+    assert!(conflicting_files.is_empty());
+    assert_eq!(bob_store.repo().find_commit(commit).unwrap().parent_count(), 2);
+
+    let merged = read_session(bob);
+    assert!(merged.contains("from alice"));
+    assert!(merged.contains("from bob"));
+    store(bob).push("origin").unwrap();
+
+    // Alice is now behind and fast-forwards to the merge
+    let alice_store = store(alice);
+    let theirs = alice_store.fetch("origin").unwrap().unwrap();
+    assert_eq!(alice_store.merge(theirs, None, &Identities::default(), &RedactionRules::default()).unwrap(), MergeResult::FastForward(commit));
+    assert_eq!(read_session(alice), merged);
+}
+
+#[test]
+fn test_fetch_missing_branch() {
+    let (_dir, _remote, clones) = setup(1);
+    assert_eq!(store(&clones[0]).fetch("origin").unwrap(), None);
+    assert!(store(&clones[0]).fetch("nope").is_err());
+}
+
+#[test]
+fn test_merge_rebuilds_namespace_manifests() {
+    let (_dir, _remote, clones) = setup(2);
+    let (alice, bob) = (&clones[0], &clones[1]);
+
+    let checkout = BranchCheckout::new(store(alice)).unwrap();
+    fs::write(checkout.store_dir().join(".ccss.toml"), "version = 2\nlayout = \"namespaced\"\nmode = \"branch\"\n").unwrap();
+    checkout.commit("Initialize").unwrap();
+    store(alice).push("origin").unwrap();
+    let bob_store = store(bob);
+    let theirs = bob_store.fetch("origin").unwrap().unwrap();
+    bob_store.merge(theirs, None, &Identities::default(), &RedactionRules::default()).unwrap();
+
+    // Each side syncs into its own namespace, with its own manifest
+    for (clone, author) in [(alice, "alice"), (bob, "bob")] {
+        let checkout = BranchCheckout::new(store(clone)).unwrap();
+        let namespace = checkout.store_dir().join(author).join("laptop");
+        fs::create_dir_all(namespace.join("-repo")).unwrap();
+        fs::write(namespace.join(SESSION), transcript(&[record(author, None, author)])).unwrap();
+        fs::write(namespace.join(".ccss-manifest.json"), "{}").unwrap();
+        checkout.commit("Sync").unwrap();
+    }
+    store(alice).push("origin").unwrap();
+
+    let bob_store = store(bob);
+    let theirs = bob_store.fetch("origin").unwrap().unwrap();
+    assert!(matches!(bob_store.merge(theirs, None, &Identities::default(), &RedactionRules::default()).unwrap(), MergeResult::Merged { .. }));
+
+    let checkout = BranchCheckout::new(store(bob)).unwrap();
+    for author in ["alice", "bob"] {
+        let manifest = fs::read_to_string(checkout.store_dir().join(author).join("laptop/.ccss-manifest.json")).unwrap();
+        assert!(manifest.contains("session.jsonl"), "{}", manifest);
+        assert!(!manifest.contains(author), "{}", manifest);
+    }
+    assert!(!checkout.store_dir().join(".ccss-manifest.json").exists());
+}
@@ -1 +1,3 @@
+mod branch_tests;
+mod store_tests;
 mod sync_tests;
\ No newline at end of file
@@ -0,0 +1,150 @@
+use cc_sync_session::compression::{Compression, StoreFormat};
+use cc_sync_session::encryption::{Encryption, Identities};
+use cc_sync_session::merge::merge_transcripts;
+use cc_sync_session::store::{is_lfs_pointer, read_revision_snapshot, resolve_lfs_pointer, write_lfs_object, STORE_PATH};
+use git2::{Repository, Signature};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn record(uuid: &str, text: &str) -> String {
+    format!(
+        r#"{{"type":"user","uuid":"{}","timestamp":"2025-06-25T10:00:00Z","message":{{"role":"user","content":"{}"}}}}"#,
+        uuid, text
+    ) + "\n"
+}
+
+/// Commits `files`, given relative to the repository, on HEAD.
+fn commit(repo: &Repository, files: &[(&str, &[u8])], message: &str) {
+    let workdir = repo.workdir().unwrap();
+    let mut index = repo.index().unwrap();
+    for (path, content) in files {
+        let full_path = workdir.join(path);
+        fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+        fs::write(&full_path, content).unwrap();
+        index.add_path(Path::new(path)).unwrap();
+    }
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("Test", "test@example.com").unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parent.iter().collect::<Vec<_>>()).unwrap();
+}
+
+#[test]
+fn test_read_revision_snapshot() {
+    let dir = TempDir::new().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    commit(&repo, &[("README.md", b"code\n")], "Initial commit");
+    // No store yet
+    assert!(read_revision_snapshot(&repo, "HEAD", &Identities::default()).unwrap().is_empty());
+
+    let plain = record("a", "plain");
+    let compressed = record("b", "compressed");
+    let tracked = record("c", "in lfs");
+    let zstd = StoreFormat { compression: Compression::Zstd, ..Default::default() }.encode(compressed.as_bytes()).unwrap();
+    let pointer = write_lfs_object(&repo, tracked.as_bytes()).unwrap();
+    let store_file = |path: &str| format!("{}/-repo/{}", STORE_PATH, path);
+    commit(&repo, &[
+        (&store_file("s1.jsonl"), plain.as_bytes()),
+        (&store_file("s2.jsonl.zst"), &zstd),
+        (&store_file("s3.jsonl"), &pointer),
+        (&store_file("s1/tool-results/toolu_1.txt"), b"output\n"),
+    ], "Add sessions");
+    commit(&repo, &[(&store_file("s1.jsonl"), (plain.clone() + &record("d", "later")).as_bytes())], "Update a session");
+
+    let snapshot = read_revision_snapshot(&repo, "HEAD~1", &Identities::default()).unwrap();
+    let session = |path: &str| String::from_utf8(snapshot[&PathBuf::from(path)].clone()).unwrap();
+    assert_eq!(snapshot.len(), 3);
+    assert_eq!(session("-repo/s1.jsonl"), plain);
+    assert_eq!(session("-repo/s2.jsonl"), compressed);
+    assert_eq!(session("-repo/s3.jsonl"), tracked);
+
+    let head = read_revision_snapshot(&repo, "HEAD", &Identities::default()).unwrap();
+    assert!(String::from_utf8_lossy(&head[Path::new("-repo/s1.jsonl")]).contains("later"));
+
+    assert!(read_revision_snapshot(&repo, "no-such-revision", &Identities::default()).is_err());
+}
+
+#[test]
+fn test_read_revision_snapshot_encrypted() {
+    let dir = TempDir::new().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let identity = age::x25519::Identity::generate();
+    let format = StoreFormat {
+        encryption: Some(Encryption::new(&[identity.to_public().to_string()]).unwrap()),
+        ..Default::default()
+    };
+    let session = record("a", "secret");
+    let path = format!("{}/-repo/s1.jsonl.age", STORE_PATH);
+    commit(&repo, &[(&path, &format.encode(session.as_bytes()).unwrap())], "Add a session");
+
+    let snapshot = read_revision_snapshot(&repo, "HEAD", &Identities::new(vec![identity])).unwrap();
+    assert_eq!(snapshot[Path::new("-repo/s1.jsonl")], session.into_bytes());
+    assert!(read_revision_snapshot(&repo, "HEAD", &Identities::default()).is_err());
+}
+
+#[test]
+fn test_lfs_pointers_round_trip_through_a_merge() {
+    let dir = TempDir::new().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+
+    // What the merge driver is handed for an LFS-tracked store
+    let base = record("a", "hello");
+    let ours = base.clone() + &record("b", "from alice");
+    let theirs = base.clone() + &record("c", "from bob");
+    let pointers: Vec<Vec<u8>> = [&base, &ours, &theirs].iter()
+        .map(|content| write_lfs_object(&repo, content.as_bytes()).unwrap())
+        .collect();
+    assert!(pointers.iter().all(|pointer| is_lfs_pointer(pointer)));
+    assert!(String::from_utf8_lossy(&pointers[1]).contains(&format!("size {}\n", ours.len())));
+
+    let resolved: Vec<String> = pointers.iter()
+        .map(|pointer| String::from_utf8(resolve_lfs_pointer(&repo, pointer).unwrap()).unwrap())
+        .collect();
+    assert_eq!(resolved, vec![base, ours, theirs]);
+
+    // The result goes back as a pointer to a new object
+    let merged = merge_transcripts(&resolved[0], &resolved[1], &resolved[2]).unwrap();
+    let pointer = write_lfs_object(&repo, merged.content.as_bytes()).unwrap();
+    assert!(is_lfs_pointer(&pointer));
+    assert_eq!(write_lfs_object(&repo, merged.content.as_bytes()).unwrap(), pointer);
+    let merged_content = String::from_utf8(resolve_lfs_pointer(&repo, &pointer).unwrap()).unwrap();
+    assert!(merged_content.contains("from alice") && merged_content.contains("from bob"));
+
+    // Content that is not a pointer is passed through
+    assert_eq!(resolve_lfs_pointer(&repo, b"{}\n").unwrap(), b"{}\n");
+}
+
+#[test]
+fn test_lfs_pointer_without_local_object() {
+    let dir = TempDir::new().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+
+    let pointer = write_lfs_object(&repo, record("a", "not fetched").as_bytes()).unwrap();
+    fs::remove_dir_all(repo.path().join("lfs")).unwrap();
+
+    let error = resolve_lfs_pointer(&repo, &pointer).unwrap_err();
+    assert!(format!("{:#}", error).contains("git lfs fetch"), "{:#}", error);
+    assert!(resolve_lfs_pointer(&repo, b"version https://git-lfs.github.com/spec/v1\nsize 3\n").is_err());
+}
+
+#[test]
+fn test_lfs_pointer_with_malformed_oid() {
+    let dir = TempDir::new().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    fs::write(dir.path().join("secret"), "not a session\n").unwrap();
+
+    let valid = "a".repeat(64);
+    for oid in [
+        "ab/../../../secret".to_string(),
+        "é\u{fffd}xyz".to_string(),
+        valid.to_uppercase(),
+        valid[..63].to_string(),
+        "abc".to_string(),
+    ] {
+        let pointer = format!("version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 14\n", oid);
+        let error = resolve_lfs_pointer(&repo, pointer.as_bytes()).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid LFS oid"), "{}: {:#}", oid, error);
+    }
+}
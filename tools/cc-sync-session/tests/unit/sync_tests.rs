@@ -1,5 +1,17 @@
 use cc_sync_session::{FileSystem, mock::MockFileSystem};
-use cc_sync_session::sync::{SessionSyncer, SyncOptions};
+use cc_sync_session::compression::{is_compressed, Compression, StoreFormat};
+use cc_sync_session::config::StoreMode;
+use cc_sync_session::encryption::{decrypt_with, is_encrypted, Encryption, Identities};
+use cc_sync_session::dedup::expand_snapshot;
+use cc_sync_session::sidechain::SidechainMode;
+use cc_sync_session::gitattributes::LFS_LINE;
+use cc_sync_session::index::{RedactionRules, INDEX_JSON_FILE};
+use cc_sync_session::layout::init_store;
+use cc_sync_session::manifest::{Manifest, MANIFEST_FILE};
+use cc_sync_session::status::compare;
+use cc_sync_session::store::{read_store_snapshot, store_dir, Snapshot};
+use cc_sync_session::sync::{SessionFilter, SessionSyncer, SyncOptions};
+use cc_sync_session::verify::{verify_store, Check};
 use std::path::Path;
 use std::time::{Duration, SystemTime};
 
@@ -31,12 +43,12 @@ fn test_sync_new_files() {
     // Verify results
     assert_eq!(result.files_copied, 2);
     assert_eq!(result.files_skipped, 0);
-    assert_eq!(result.directories_created, 2); // Users/yuta/project and Users/yuta/project/subdir
+    assert_eq!(result.directories_created, 2); // -Users-yuta-project and -Users-yuta-project/subdir
     assert_eq!(result.errors.len(), 0);
     
     // Verify files were copied correctly
-    let expected_file1 = target_dir.join("Users/yuta/project").join("file1.txt");
-    let expected_file2 = target_dir.join("Users/yuta/project").join("subdir").join("file2.txt");
+    let expected_file1 = target_dir.join("-Users-yuta-project").join("file1.txt");
+    let expected_file2 = target_dir.join("-Users-yuta-project").join("subdir").join("file2.txt");
     
     assert!(fs.exists(&expected_file1).unwrap());
     assert!(fs.exists(&expected_file2).unwrap());
@@ -64,10 +76,8 @@ fn test_sync_skip_up_to_date_files() {
     fs.add_file(&source_file, vec![1, 2, 3], old_time);
     
     // Add target file with newer timestamp
-    let target_file = target_dir.join("Users/yuta/project").join("file.txt");
-    fs.add_directory(target_dir.join("Users"));
-    fs.add_directory(target_dir.join("Users/yuta"));
-    fs.add_directory(target_dir.join("Users/yuta/project"));
+    let target_file = target_dir.join("-Users-yuta-project").join("file.txt");
+    fs.add_directory(target_dir.join("-Users-yuta-project"));
     fs.add_file(&target_file, vec![1, 2, 3], SystemTime::now());
     
     // Run sync
@@ -96,10 +106,8 @@ fn test_sync_update_newer_files() {
     fs.add_file(&source_file, vec![7, 8, 9], SystemTime::now());
     
     // Add target file with older timestamp
-    let target_file = target_dir.join("Users/yuta/project").join("file.txt");
-    fs.add_directory(target_dir.join("Users"));
-    fs.add_directory(target_dir.join("Users/yuta"));
-    fs.add_directory(target_dir.join("Users/yuta/project"));
+    let target_file = target_dir.join("-Users-yuta-project").join("file.txt");
+    fs.add_directory(target_dir.join("-Users-yuta-project"));
     
     let old_time = SystemTime::now() - Duration::from_secs(3600);
     fs.add_file(&target_file, vec![1, 2, 3], old_time);
@@ -134,6 +142,7 @@ fn test_sync_dry_run() {
     let options = SyncOptions {
         dry_run: true,
         verbose: false,
+        ..Default::default()
     };
     let result = syncer.sync(source_dir, "-Users-yuta-", target_dir, &options).unwrap();
     
@@ -142,7 +151,7 @@ fn test_sync_dry_run() {
     assert_eq!(result.directories_created, 1);
     
     // Verify file was NOT actually copied
-    let expected_file = target_dir.join("Users/yuta/project").join("file.txt");
+    let expected_file = target_dir.join("-Users-yuta-project").join("file.txt");
     assert!(!fs.exists(&expected_file).unwrap());
 }
 
@@ -167,7 +176,7 @@ fn test_sync_with_github_domain() {
     let result = syncer.sync(source_dir, "-Users-yuta-", target_dir, &options).unwrap();
     
     // Verify correct path conversion
-    let expected_file = target_dir.join("Users/yuta/github.com/project").join("file.txt");
+    let expected_file = target_dir.join("-Users-yuta-github.com-project").join("file.txt");
     assert!(fs.exists(&expected_file).unwrap());
     assert_eq!(result.files_copied, 1);
 }
@@ -202,12 +211,382 @@ fn test_sync_nested_directories() {
     
     // Verify all files and directories were created
     assert_eq!(result.files_copied, 4);
-    assert_eq!(result.directories_created, 4); // Users/yuta/project, src, src/lib, tests
+    assert_eq!(result.directories_created, 4); // -Users-yuta-project, src, src/lib, tests
     
     // Verify structure
-    let target_base = target_dir.join("Users/yuta/project");
+    let target_base = target_dir.join("-Users-yuta-project");
     assert!(fs.exists(&target_base.join("README.md")).unwrap());
     assert!(fs.exists(&target_base.join("src").join("main.rs")).unwrap());
     assert!(fs.exists(&target_base.join("src").join("lib").join("mod.rs")).unwrap());
     assert!(fs.exists(&target_base.join("tests").join("test.rs")).unwrap());
-}
\ No newline at end of file
+}
+#[test]
+fn test_find_session_files() {
+    let fs = MockFileSystem::new();
+    let syncer = SessionSyncer::new(fs.clone());
+
+    let source_dir = Path::new("/source");
+    fs.add_directory(source_dir);
+    fs.add_directory(source_dir.join("-Users-yuta-project"));
+    fs.add_directory(source_dir.join("-Users-yuta-project-sub"));
+    fs.add_directory(source_dir.join("-Users-other-project"));
+
+    fs.add_file(source_dir.join("-Users-yuta-project").join("a1.jsonl"), vec![1], SystemTime::now());
+    fs.add_file(source_dir.join("-Users-yuta-project").join("notes.txt"), vec![2], SystemTime::now());
+    fs.add_file(source_dir.join("-Users-yuta-project-sub").join("b2.jsonl"), vec![3], SystemTime::now());
+    fs.add_file(source_dir.join("-Users-other-project").join("c3.jsonl"), vec![4], SystemTime::now());
+
+    let sessions = syncer.find_session_files(source_dir, "-Users-yuta-project").unwrap();
+    let paths: Vec<_> = sessions.into_iter().map(|session| session.path).collect();
+
+    assert_eq!(paths, vec![
+        source_dir.join("-Users-yuta-project").join("a1.jsonl"),
+        source_dir.join("-Users-yuta-project-sub").join("b2.jsonl"),
+    ]);
+}
+
+#[test]
+fn test_sync_deduplicates_resumed_session() {
+    let fs = MockFileSystem::new();
+    let syncer = SessionSyncer::new(fs.clone());
+
+    let source_dir = Path::new("/source");
+    let target_dir = Path::new("/target");
+    let project = source_dir.join("-Users-yuta-project");
+
+    fs.add_directory(source_dir);
+    fs.add_directory(target_dir);
+    fs.add_directory(&project);
+
+    let record = |uuid: &str, session: &str, second: u32| {
+        format!(
+            r#"{{"type":"user","uuid":"{}","sessionId":"{}","timestamp":"2025-06-25T10:00:{:02}Z","message":{{"role":"user","content":"hi"}}}}"#,
+            uuid, session, second
+        )
+    };
+    let original = format!("{}\n{}\n", record("a", "s1", 0), record("b", "s1", 1));
+    let resumed = format!("{}\n{}\n{}\n", record("a", "s2", 0), record("b", "s2", 1), record("c", "s2", 2));
+
+    fs.add_file(project.join("s1.jsonl"), original.clone().into_bytes(), SystemTime::now());
+    fs.add_file(project.join("s2.jsonl"), resumed.clone().into_bytes(), SystemTime::now());
+
+    let options = SyncOptions {
+        deduplicate: true,
+        ..Default::default()
+    };
+    let result = syncer.sync(source_dir, "-Users-yuta-", target_dir, &options).unwrap();
+
+    assert_eq!(result.files_copied, 2);
+    assert_eq!(result.files_deduplicated, 1);
+
+    let stored_dir = target_dir.join("-Users-yuta-project");
+    assert_eq!(fs.get_file_content(&stored_dir.join("s1.jsonl")).unwrap(), original.into_bytes());
+    assert_eq!(String::from_utf8(fs.get_file_content(&stored_dir.join("s2.jsonl")).unwrap()).unwrap().lines().count(), 2);
+
+    let snapshot = read_store_snapshot(&fs, target_dir, &Identities::default()).unwrap();
+    let expanded = expand_snapshot(&snapshot).unwrap();
+    assert_eq!(expanded[Path::new("-Users-yuta-project/s2.jsonl")], resumed.into_bytes());
+}
+
+#[test]
+fn test_sync_sidechain_modes() {
+    let parent = br#"{"type":"user","uuid":"u1","sessionId":"s1","isSidechain":false,"message":{"role":"user","content":"hi"}}"#.to_vec();
+    let sidechain = br#"{"type":"user","uuid":"x1","sessionId":"s1","isSidechain":true,"message":{"role":"user","content":"find tests"}}"#.to_vec();
+
+    for (mode, expected) in [
+        (SidechainMode::Sync, Some("-Users-yuta-project/agent-1.jsonl")),
+        (SidechainMode::Nest, Some("-Users-yuta-project/s1/sidechains/agent-1.jsonl")),
+        (SidechainMode::Skip, None),
+    ] {
+        let fs = MockFileSystem::new();
+        let syncer = SessionSyncer::new(fs.clone());
+
+        let source_dir = Path::new("/source");
+        let target_dir = Path::new("/target");
+        let project = source_dir.join("-Users-yuta-project");
+        fs.add_directory(source_dir);
+        fs.add_directory(target_dir);
+        fs.add_directory(&project);
+        fs.add_file(project.join("s1.jsonl"), parent.clone(), SystemTime::now());
+        fs.add_file(project.join("agent-1.jsonl"), sidechain.clone(), SystemTime::now());
+
+        let options = SyncOptions {
+            sidechains: mode,
+            ..Default::default()
+        };
+        let result = syncer.sync(source_dir, "-Users-yuta-", target_dir, &options).unwrap();
+
+        assert!(fs.exists(&target_dir.join("-Users-yuta-project/s1.jsonl")).unwrap());
+        let sidechain_files: Vec<_> = fs.list_all_files()
+            .into_iter()
+            .filter(|path| path.starts_with(target_dir) && path.ends_with("agent-1.jsonl"))
+            .collect();
+        match expected {
+            Some(expected) => {
+                assert_eq!(sidechain_files, vec![target_dir.join(expected)], "mode {}", mode);
+                assert_eq!(result.files_copied, 2);
+            }
+            None => {
+                assert!(sidechain_files.is_empty(), "mode {}", mode);
+                assert_eq!(result.files_copied, 1);
+                assert_eq!(result.files_skipped, 1);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_sync_writes_manifest_for_verify() {
+    let fs = MockFileSystem::new();
+    let syncer = SessionSyncer::new(fs.clone());
+
+    let source_dir = Path::new("/source");
+    let repo_dir = Path::new("/repo");
+    let target_dir = store_dir(repo_dir);
+    let project = source_dir.join("-Users-yuta-project");
+    fs.add_directory(source_dir);
+    init_store(&fs, &target_dir, StoreMode::Worktree).unwrap();
+    fs.add_directory(&project);
+    fs.add_file(repo_dir.join(".gitattributes"), format!("{}\n", LFS_LINE).into_bytes(), SystemTime::now());
+    fs.add_file(
+        project.join("s1.jsonl"),
+        concat!(r#"{"type":"user","uuid":"u1","sessionId":"s1","message":{"role":"user","content":"hi"}}"#, "\n").as_bytes().to_vec(),
+        SystemTime::now(),
+    );
+
+    syncer.sync(source_dir, "-Users-yuta-", &target_dir, &SyncOptions::default()).unwrap();
+
+    let manifest = Manifest::load(&fs, &target_dir).unwrap().unwrap();
+    assert!(manifest.get(Path::new("-Users-yuta-project/s1.jsonl")).is_some());
+    let report = verify_store(&fs, repo_dir, &Identities::default());
+    assert!(report.findings.is_empty(), "{:?}", report.findings);
+
+    // Edited in the store behind the tool's back
+    fs.add_file(target_dir.join("-Users-yuta-project/s1.jsonl"), b"{\"type\":\"us".to_vec(), SystemTime::now());
+    let checks: Vec<_> = verify_store(&fs, repo_dir, &Identities::default()).findings.into_iter().map(|finding| finding.check).collect();
+    assert_eq!(checks, vec![Check::TruncatedLastLine, Check::HashMismatch]);
+}
+
+#[test]
+fn test_sync_filters_by_branch_and_session() {
+    let session = |id: &str, branch: &str| {
+        format!(
+            "{{\"type\":\"user\",\"uuid\":\"u-{}\",\"sessionId\":\"{}\",\"gitBranch\":\"{}\",\"message\":{{\"role\":\"user\",\"content\":\"hi\"}}}}\n",
+            id, id, branch
+        ).into_bytes()
+    };
+
+    for (filter, expected) in [
+        (SessionFilter { git_branch: Some("feature-x".to_string()), ..Default::default() }, vec!["s1.jsonl", "s3.jsonl"]),
+        (SessionFilter { session_ids: vec!["s3".to_string()], ..Default::default() }, vec!["s3.jsonl"]),
+        (SessionFilter { session_ids: vec!["s1".to_string()], git_branch: Some("main".to_string()), ..Default::default() }, vec![]),
+    ] {
+        let fs = MockFileSystem::new();
+        let syncer = SessionSyncer::new(fs.clone());
+
+        let source_dir = Path::new("/source");
+        let target_dir = Path::new("/target");
+        let project = source_dir.join("-Users-yuta-project");
+        fs.add_directory(source_dir);
+        fs.add_directory(target_dir);
+        fs.add_directory(&project);
+        fs.add_file(project.join("s1.jsonl"), session("s1", "feature-x"), SystemTime::now());
+        fs.add_file(project.join("s2.jsonl"), session("s2", "main"), SystemTime::now());
+        fs.add_file(project.join("s3.jsonl"), session("s3", "feature-x"), SystemTime::now());
+
+        let options = SyncOptions {
+            filter: filter.clone(),
+            ..Default::default()
+        };
+        let result = syncer.sync(source_dir, "-Users-yuta-", target_dir, &options).unwrap();
+
+        let synced: Vec<String> = read_store_snapshot(&fs, target_dir, &Identities::default()).unwrap()
+            .keys()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(synced, expected, "{:?}", filter);
+        assert_eq!(result.files_filtered, 3 - expected.len());
+    }
+}
+
+#[test]
+fn test_sync_compressed_store() {
+    let fs = MockFileSystem::new();
+    let syncer = SessionSyncer::new(fs.clone());
+
+    let source_dir = Path::new("/source");
+    let target_dir = Path::new("/target");
+    let project = source_dir.join("-Users-yuta-project");
+    fs.add_directory(source_dir);
+    fs.add_directory(target_dir);
+    fs.add_directory(&project);
+
+    let record = |uuid: &str, session: &str| {
+        format!(r#"{{"type":"user","uuid":"{}","sessionId":"{}","message":{{"role":"user","content":"hi"}}}}"#, uuid, session)
+    };
+    let original = format!("{}\n{}\n", record("a", "s1"), record("b", "s1"));
+    let resumed = format!("{}\n{}\n{}\n", record("a", "s2"), record("b", "s2"), record("c", "s2"));
+    let old_time = SystemTime::now() - Duration::from_secs(60);
+    fs.add_file(project.join("s1.jsonl"), original.clone().into_bytes(), old_time);
+    fs.add_file(project.join("s2.jsonl"), resumed.clone().into_bytes(), SystemTime::now());
+
+    let options = SyncOptions {
+        format: StoreFormat { compression: Compression::Zstd, ..Default::default() },
+        deduplicate: true,
+        ..Default::default()
+    };
+    let result = syncer.sync(source_dir, "-Users-yuta-", target_dir, &options).unwrap();
+    assert_eq!(result.files_copied, 2);
+    assert_eq!(result.files_deduplicated, 1);
+
+    let stored = target_dir.join("-Users-yuta-project/s2.jsonl.zst");
+    assert!(is_compressed(&fs.get_file_content(&stored).unwrap()));
+    assert!(!fs.exists(&target_dir.join("-Users-yuta-project/s2.jsonl")).unwrap());
+
+    let snapshot = read_store_snapshot(&fs, target_dir, &Identities::default()).unwrap();
+    let expanded = expand_snapshot(&snapshot).unwrap();
+    assert_eq!(expanded[Path::new("-Users-yuta-project/s1.jsonl")], original.into_bytes());
+    assert_eq!(expanded[Path::new("-Users-yuta-project/s2.jsonl")], resumed.into_bytes());
+
+    // Change detection compares the source with the compressed copy
+    let result = syncer.sync(source_dir, "-Users-yuta-", target_dir, &options).unwrap();
+    assert_eq!(result.files_copied, 0);
+    assert_eq!(result.files_skipped, 2);
+}
+
+#[test]
+fn test_sync_encrypted_store() {
+    let fs = MockFileSystem::new();
+    let syncer = SessionSyncer::new(fs.clone());
+
+    let source_dir = Path::new("/source");
+    let target_dir = Path::new("/target");
+    let project = source_dir.join("-Users-yuta-project");
+    fs.add_directory(source_dir);
+    fs.add_directory(target_dir);
+    fs.add_directory(&project);
+
+    let session = b"{\"type\":\"user\",\"uuid\":\"a\",\"sessionId\":\"s1\",\"message\":{\"role\":\"user\",\"content\":\"secret\"}}\n".to_vec();
+    fs.add_file(project.join("s1.jsonl"), session.clone(), SystemTime::now());
+    fs.add_directory(project.join("s1"));
+    fs.add_directory(project.join("s1/tool-results"));
+    fs.add_file(project.join("s1/tool-results/toolu_1.txt"), b"secret tool output\n".to_vec(), SystemTime::now());
+
+    let identity = age::x25519::Identity::generate();
+    let options = SyncOptions {
+        format: StoreFormat {
+            compression: Compression::Zstd,
+            encryption: Some(Encryption::new(&[identity.to_public().to_string()]).unwrap()),
+        },
+        index: true,
+        ..Default::default()
+    };
+    let result = syncer.sync(source_dir, "-Users-yuta-", target_dir, &options).unwrap();
+    assert_eq!((result.files_copied, result.files_skipped), (1, 1));
+    // The index would list prompts in plain text
+    assert!(!result.index_updated);
+    assert_eq!(result.errors.len(), 1);
+
+    let stored = fs.get_file_content(&target_dir.join("-Users-yuta-project/s1.jsonl.zst.age")).unwrap();
+    assert!(is_encrypted(&stored));
+    let decrypted = decrypt_with(&stored, &[identity]).unwrap();
+    assert_eq!(zstd::decode_all(decrypted.as_slice()).unwrap(), session);
+
+    // Nothing but the manifest of hashes is readable without an identity
+    for file in fs.walk_files(target_dir).unwrap() {
+        if file.path.file_name().is_some_and(|name| name == MANIFEST_FILE) {
+            continue;
+        }
+        assert!(is_encrypted(&fs.get_file_content(&file.path).unwrap()), "{} is not encrypted", file.path.display());
+    }
+}
+
+#[test]
+fn test_sync_only_changed_files() {
+    let fs = MockFileSystem::new();
+    let syncer = SessionSyncer::new(fs.clone());
+    let source_dir = Path::new("/source");
+    let target_dir = Path::new("/target");
+    let project = source_dir.join("-repo");
+    fs.add_directory(source_dir);
+    fs.add_directory(target_dir);
+    fs.add_directory(&project);
+    fs.add_file(project.join("s1.jsonl"), b"{}\n".to_vec(), SystemTime::now());
+    fs.add_file(project.join("s2.jsonl"), b"{}\n".to_vec(), SystemTime::now());
+
+    let options = SyncOptions {
+        changed_files: Some([project.join("s2.jsonl")].into()),
+        ..SyncOptions::default()
+    };
+    let result = syncer.sync(source_dir, "-repo", target_dir, &options).unwrap();
+
+    assert_eq!((result.files_copied, result.files_skipped), (1, 0));
+    assert!(!fs.exists(&target_dir.join("-repo/s1.jsonl")).unwrap());
+    assert!(fs.exists(&target_dir.join("-repo/s2.jsonl")).unwrap());
+}
+
+#[test]
+fn test_sync_redacts_index_with_options_rules() {
+    let fs = MockFileSystem::new();
+    let syncer = SessionSyncer::new(fs.clone());
+    let source_dir = Path::new("/source");
+    let target_dir = Path::new("/target");
+    let project = source_dir.join("-repo");
+    fs.add_directory(source_dir);
+    fs.add_directory(target_dir);
+    fs.add_directory(&project);
+    let session = r#"{"type":"user","uuid":"a","sessionId":"s1","message":{"role":"user","content":"ssh db.internal please"}}"#;
+    fs.add_file(project.join("s1.jsonl"), format!("{}\n", session).into_bytes(), SystemTime::now());
+
+    let options = SyncOptions {
+        index: true,
+        redaction: RedactionRules { words: vec!["db.internal".to_string()], ..Default::default() },
+        ..SyncOptions::default()
+    };
+    let result = syncer.sync(source_dir, "-repo", target_dir, &options).unwrap();
+    assert!(result.index_updated);
+
+    let index = String::from_utf8(fs.get_file_content(&target_dir.join(INDEX_JSON_FILE)).unwrap()).unwrap();
+    assert!(index.contains("ssh [redacted] please"));
+    assert!(!index.contains("db.internal"));
+}
+
+#[test]
+fn test_find_synced_sessions_matches_the_store() {
+    let fs = MockFileSystem::new();
+    let syncer = SessionSyncer::new(fs.clone());
+    let source_dir = Path::new("/source");
+    let target_dir = Path::new("/target");
+    let project = source_dir.join("-repo");
+    fs.add_directory(source_dir);
+    fs.add_directory(target_dir);
+    fs.add_directory(&project);
+
+    let record = |session: &str, sidechain: bool, branch: &str| {
+        format!(
+            r#"{{"type":"user","uuid":"{}-1","sessionId":"{}","isSidechain":{},"gitBranch":"{}","message":{{"role":"user","content":"hi"}}}}"#,
+            session, session, sidechain, branch
+        ) + "\n"
+    };
+    fs.add_file(project.join("s1.jsonl"), record("s1", false, "main").into_bytes(), SystemTime::now());
+    fs.add_file(project.join("agent-1.jsonl"), record("s1", true, "main").into_bytes(), SystemTime::now());
+    fs.add_file(project.join("s2.jsonl"), record("s2", false, "feature").into_bytes(), SystemTime::now());
+
+    let options = SyncOptions {
+        sidechains: SidechainMode::Nest,
+        filter: SessionFilter { git_branch: Some("main".to_string()), ..Default::default() },
+        format: StoreFormat { compression: Compression::Zstd, ..Default::default() },
+        ..Default::default()
+    };
+    let sessions = syncer.find_synced_sessions(source_dir, "-repo", &options).unwrap();
+    let stored_paths: Vec<&Path> = sessions.iter().map(|(_, path)| path.as_path()).collect();
+    assert_eq!(stored_paths, vec![Path::new("-repo/s1/sidechains/agent-1.jsonl"), Path::new("-repo/s1.jsonl")]);
+
+    // Once synced, the sessions are exactly what the store has
+    syncer.sync(source_dir, "-repo", target_dir, &options).unwrap();
+    let source: Snapshot = sessions.into_iter()
+        .map(|(entry, path)| (path, fs.get_file_content(&entry.path).unwrap()))
+        .collect();
+    let stored = expand_snapshot(&read_store_snapshot(&fs, target_dir, &Identities::default()).unwrap()).unwrap();
+    assert!(compare(&source, &stored).is_empty());
+}